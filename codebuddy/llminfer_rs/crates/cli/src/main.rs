@@ -0,0 +1,158 @@
+//! Offline next-chunk experiments: replays a target token sequence against a
+//! reference the way speculative decoding would and prints acceptance statistics.
+//!
+//! ```text
+//! llminfer-cli <reference> <target> [--chunk-size N] [--algorithm NAME]
+//!              [--matcher NAME] [--min-match-len N] [--max-mismatches N]
+//!              [--global-reanchor-after N] [--verbose]
+//! ```
+//!
+//! Token files hold integer token ids separated by whitespace and/or commas;
+//! surrounding `[`/`]` are ignored, so JSON arrays work too.
+
+use std::cmp::max;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use diff::{simulate_with_options, NextChunkOptions, SimulationReport};
+
+
+const USAGE: &str = "usage: llminfer-cli <reference> <target> [--chunk-size N] [--algorithm NAME] \
+[--matcher NAME] [--min-match-len N] [--max-mismatches N] [--global-reanchor-after N] [--verbose]";
+
+/// Parsed command line.
+#[derive(Debug)]
+struct Args {
+    reference: String,
+    target: String,
+    chunk_size: usize,
+    options: NextChunkOptions,
+    verbose: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut files = Vec::new();
+    let mut chunk_size = 80;
+    let mut options = NextChunkOptions::default();
+    let mut verbose = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{name} needs a value"));
+        let number = |name: &str, value: String| value.parse::<usize>().map_err(|e| format!("{name}: {e}"));
+        match arg.as_str() {
+            "--chunk-size" => chunk_size = number("--chunk-size", value("--chunk-size")?)?,
+            "--algorithm" => options.algorithm = value("--algorithm")?.parse().map_err(|e| format!("{e}"))?,
+            "--matcher" => options.matcher = value("--matcher")?.parse().map_err(|e| format!("{e}"))?,
+            "--min-match-len" => options.min_match_len = number("--min-match-len", value("--min-match-len")?)?,
+            "--max-mismatches" => options.max_mismatches = number("--max-mismatches", value("--max-mismatches")?)?,
+            "--global-reanchor-after" => {
+                options.global_reanchor_after = Some(number("--global-reanchor-after", value("--global-reanchor-after")?)?)
+            }
+            "--verbose" | "-v" => verbose = true,
+            flag if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+            _ => files.push(arg),
+        }
+    }
+    let [reference, target]: [String; 2] = files.try_into().map_err(|_| "expected a reference and a target file".to_owned())?;
+    Ok(Args { reference, target, chunk_size, options, verbose })
+}
+
+fn parse_tokens(text: &str) -> Result<Vec<i32>, String> {
+    text.split(|c: char| c.is_whitespace() || matches!(c, ',' | '[' | ']'))
+        .filter(|token| !token.is_empty())
+        .map(|token| token.parse().map_err(|e| format!("bad token {token:?}: {e}")))
+        .collect()
+}
+
+fn read_tokens(path: &str) -> Result<Vec<i32>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    parse_tokens(&text).map_err(|e| format!("{path}: {e}"))
+}
+
+/// Prints each step of `report`: where in the target it predicted from,
+/// the predicted and the accepted tokens.
+fn print_steps(report: &SimulationReport) {
+    let mut current_idx = 0;
+    for (iteration, step) in report.steps.iter().enumerate() {
+        println!("iteration {}: at {current_idx}, predicted {}, accepted {}", iteration + 1, step.predicted, step.accepted);
+        current_idx += max(step.accepted, 1);
+    }
+}
+
+fn print_summary(report: &SimulationReport, reference_len: usize, target_len: usize, chunk_size: usize) {
+    let ratio = |num: usize, den: usize| if den == 0 { 0.0 } else { num as f64 / den as f64 };
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let mut sorted: Vec<Duration> = report.steps.iter().map(|step| step.latency).collect();
+    let total: Duration = sorted.iter().sum();
+    sorted.sort_unstable();
+    let p99 = sorted.get((sorted.len() * 99 / 100).min(sorted.len().saturating_sub(1))).copied().unwrap_or_default();
+    let predictions = report.steps.iter().filter(|step| step.predicted > 0).count();
+    let (predicted, accepted) = (report.total_predicted(), report.total_accepted());
+
+    println!("reference tokens:   {reference_len}");
+    println!("target tokens:      {target_len}");
+    println!("chunk size:         {chunk_size}");
+    println!("iterations:         {}", report.iterations());
+    println!("predictions:        {predictions}");
+    println!("predicted tokens:   {predicted}");
+    println!("accepted tokens:    {accepted} ({:.1}% of target)", 100.0 * ratio(accepted, target_len));
+    println!("acceptance rate:    {:.1}% of predicted", 100.0 * report.acceptance_rate());
+    println!("accepted / call:    {:.2}", ratio(accepted, report.iterations()));
+    println!("first call:         {:.3} ms", report.steps.first().map_or(0.0, |step| ms(step.latency)));
+    let mean = ms(total) / max(sorted.len(), 1) as f64;
+    let slowest = sorted.last().copied().map_or(0.0, ms);
+    println!("mean / p99 / max:   {mean:.3} / {:.3} / {slowest:.3} ms", ms(p99));
+    println!("total:              {:.3} ms", ms(total));
+}
+
+fn run() -> Result<(), String> {
+    let args = parse_args(std::env::args().skip(1))?;
+    let reference = read_tokens(&args.reference)?;
+    let target = read_tokens(&args.target)?;
+    let report = simulate_with_options(&reference, &target, args.chunk_size, args.options);
+    if args.verbose {
+        print_steps(&report);
+    }
+    print_summary(&report, reference.len(), target.len(), args.chunk_size);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}\n{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_tokens("[1, 2,3]\n4 -5").unwrap(), vec![1, 2, 3, 4, -5]);
+        assert!(parse_tokens("1 x").is_err());
+
+        let args = parse_args(["a.txt", "--chunk-size", "16", "b.txt", "--algorithm", "myers"].map(String::from)).unwrap();
+        assert_eq!((args.reference.as_str(), args.target.as_str(), args.chunk_size), ("a.txt", "b.txt", 16));
+        assert_eq!(args.options.algorithm.as_str(), "myers");
+        assert!(parse_args(["a.txt"].map(String::from)).is_err());
+        assert!(parse_args(["a", "b", "--bogus"].map(String::from)).is_err());
+        assert!(parse_args(["a", "b", "--chunk-size"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_simulate() {
+        let reference: Vec<i32> = (0..100).collect();
+        let mut target = reference.clone();
+        target[50] = -1;
+        let report = simulate_with_options(&reference, &target, 20, NextChunkOptions::default());
+        assert_eq!(report.total_accepted(), 98);
+        assert!(report.total_predicted() >= report.total_accepted());
+    }
+}
@@ -0,0 +1,139 @@
+use super::nextchunk::PredictionResult;
+
+
+/// Estimated acceptance of a predicted chunk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AcceptanceEstimate {
+    /// Probability that each predicted token is accepted given the previous one was.
+    pub per_token: f64,
+    /// Expected number of leading tokens of the chunk the verifier accepts.
+    pub expected_len: usize,
+}
+
+/// Estimates how much of a predicted chunk will be accepted, so callers can
+/// decide how many speculative tokens are worth submitting.
+///
+/// Combines three signals into a per-token acceptance probability:
+/// * the length of the match the prediction continues from (short matches are
+///   often accidental);
+/// * the local edit density: the fraction of the last `horizon` tokens of `b`
+///   that differ from `a` when aligned at the prediction start;
+/// * the historical acceptance ratio fed through [`AcceptanceEstimator::record`].
+#[derive(Debug, Clone)]
+pub struct AcceptanceEstimator {
+    /// Exponential moving average of accepted / predicted.
+    history: f64,
+    /// Weight of the newest observation in `history`.
+    smoothing: f64,
+    /// Number of trailing `b` tokens inspected for the edit density.
+    horizon: usize,
+}
+
+/// Match length at which the match-length confidence reaches 1/2.
+const HALF_CONFIDENCE_MATCH_LEN: f64 = 4.0;
+
+impl Default for AcceptanceEstimator {
+    fn default() -> Self {
+        Self::new(0.2, 32)
+    }
+}
+
+impl AcceptanceEstimator {
+    /// `smoothing` is the weight (0..=1) of each new observation in the
+    /// acceptance history; `horizon` the number of trailing `b` tokens used
+    /// for the edit density.
+    pub fn new(smoothing: f64, horizon: usize) -> Self {
+        AcceptanceEstimator { history: 0.5, smoothing: smoothing.clamp(0.0, 1.0), horizon: horizon.max(1) }
+    }
+
+    /// Records that `accepted` of `predicted` speculative tokens were accepted.
+    pub fn record(&mut self, predicted: usize, accepted: usize) {
+        if predicted == 0 {
+            return;
+        }
+        let ratio = accepted.min(predicted) as f64 / predicted as f64;
+        self.history += self.smoothing * (ratio - self.history);
+    }
+
+    /// Current acceptance history (moving average of accepted / predicted).
+    pub fn history(&self) -> f64 {
+        self.history
+    }
+
+    /// Estimates the acceptance of `result`, predicted from `a` for `b`.
+    pub fn estimate<T: Eq>(&self, a: &[T], b: &[T], result: &PredictionResult<'_, T>) -> AcceptanceEstimate {
+        let Some(start) = result.start.filter(|_| !result.tokens.is_empty()) else {
+            return AcceptanceEstimate { per_token: 0.0, expected_len: 0 };
+        };
+        let match_len = result.match_len as f64;
+        let confidence = if start == 0 && b.is_empty() {
+            // Predicting the start of `a` for an empty `b`: nothing to go on but history
+            1.0
+        } else {
+            match_len / (match_len + HALF_CONFIDENCE_MATCH_LEN)
+        };
+        let density = local_edit_density(&a[..start.min(a.len())], b, self.horizon);
+        let per_token = (self.history * confidence * (1.0 - density)).clamp(0.0, 1.0);
+        AcceptanceEstimate { per_token, expected_len: expected_run(per_token, result.tokens.len()) }
+    }
+}
+
+/// Fraction of the last `horizon` tokens of `b` that differ from the tail of
+/// `a_prefix` when both are right-aligned; 0 for an empty `b`.
+fn local_edit_density<T: Eq>(a_prefix: &[T], b: &[T], horizon: usize) -> f64 {
+    let n = horizon.min(b.len());
+    if n == 0 {
+        return 0.0;
+    }
+    let a_tail = a_prefix.iter().rev().map(Some).chain(std::iter::repeat(None));
+    let mismatches = b.iter().rev().take(n).zip(a_tail).filter(|(x, y)| Some(*x) != *y).count();
+    mismatches as f64 / n as f64
+}
+
+/// Expected number of leading successes among `n` tokens each accepted with
+/// probability `p` given its predecessor was: `p + p^2 + ... + p^n`.
+fn expected_run(p: f64, n: usize) -> usize {
+    let expected = if p >= 1.0 { n as f64 } else { p * (1.0 - p.powi(n as i32)) / (1.0 - p) };
+    (expected.round() as usize).min(n)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::StreamNextChunk;
+
+    #[test]
+    fn test_estimate() {
+        let a: Vec<i32> = (0..100).collect();
+        let streamer = StreamNextChunk::new(&a);
+        let mut estimator = AcceptanceEstimator::default();
+
+        let clean: Vec<i32> = (0..40).collect();
+        let edited: Vec<i32> = (0..40).map(|t| if t % 3 == 0 { -t } else { t }).chain([40]).collect();
+        let clean_est = estimator.estimate(&a, &clean, &streamer.next_chunk_with_info(&clean, 8));
+        let edited_est = estimator.estimate(&a, &edited, &streamer.next_chunk_with_info(&edited, 8));
+        assert!(clean_est.per_token > edited_est.per_token);
+        assert!(clean_est.expected_len >= edited_est.expected_len);
+
+        for _ in 0..20 {
+            estimator.record(8, 8);
+        }
+        assert!(estimator.history() > 0.95);
+        let est = estimator.estimate(&a, &clean, &streamer.next_chunk_with_info(&clean, 8));
+        assert!(est.expected_len > clean_est.expected_len && est.expected_len <= 8);
+
+        let miss = streamer.next_chunk_with_info(&[-1, -2], 8);
+        assert_eq!(estimator.estimate(&a, &[-1, -2], &miss).expected_len, 0);
+    }
+
+    #[test]
+    fn test_helpers() {
+        assert_eq!(local_edit_density(&[1, 2, 3], &[9, 2, 3], 3), 1.0 / 3.0);
+        assert_eq!(local_edit_density(&[3], &[1, 2, 3], 2), 0.5);
+        assert_eq!(expected_run(1.0, 5), 5);
+        assert_eq!(expected_run(0.0, 5), 0);
+        assert_eq!(expected_run(0.5, 10), 1);
+    }
+}
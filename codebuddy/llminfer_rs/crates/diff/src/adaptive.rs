@@ -0,0 +1,136 @@
+use std::hash::Hash;
+
+use super::nextchunk::StreamNextChunk;
+
+
+/// Wraps a [`StreamNextChunk`] and picks the chunk size from how much of
+/// previous predictions was accepted.
+///
+/// After each verification, report the outcome through
+/// [`AdaptiveChunker::record`]: a fully accepted chunk doubles the chunk size,
+/// one with less than half of its tokens accepted halves it. The size always
+/// stays within the caller's `[min_chunk, max_chunk]` bounds.
+pub struct AdaptiveChunker<T: Eq + Hash> {
+    streamer: StreamNextChunk<T>,
+    min_chunk: usize,
+    max_chunk: usize,
+    chunk_size: usize,
+    predicted: usize,
+    accepted: usize,
+}
+
+impl<T: Eq + Hash + Copy + Send + Sync> AdaptiveChunker<T> {
+    /// Starts at `min_chunk`; `max_chunk` is raised to `min_chunk` if smaller.
+    pub fn new(streamer: StreamNextChunk<T>, min_chunk: usize, max_chunk: usize) -> Self {
+        let min_chunk = min_chunk.max(1);
+        AdaptiveChunker {
+            streamer,
+            min_chunk,
+            max_chunk: max_chunk.max(min_chunk),
+            chunk_size: min_chunk,
+            predicted: 0,
+            accepted: 0,
+        }
+    }
+
+    /// The wrapped streamer.
+    pub fn streamer(&self) -> &StreamNextChunk<T> {
+        &self.streamer
+    }
+
+    /// The chunk size the next prediction will use.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Total tokens predicted and accepted over all recorded calls.
+    pub fn totals(&self) -> (usize, usize) {
+        (self.predicted, self.accepted)
+    }
+
+    /// Predicts the next chunk following `current_b` with the current chunk size.
+    pub fn next_chunk(&self, current_b: &[T]) -> &[T] {
+        self.streamer.next_chunk(current_b, self.chunk_size)
+    }
+
+    /// Appends newly generated tokens to the wrapped streamer.
+    pub fn append(&mut self, new_tokens: &[T]) {
+        self.streamer.append(new_tokens)
+    }
+
+    /// Predicts the next chunk for the appended tokens with the current chunk size.
+    pub fn predict(&mut self) -> &[T] {
+        let chunk_size = self.chunk_size;
+        self.streamer.predict(chunk_size)
+    }
+
+    /// Records that `accepted` of `predicted` tokens were accepted and
+    /// adjusts the chunk size for the next prediction.
+    pub fn record(&mut self, predicted: usize, accepted: usize) {
+        let accepted = accepted.min(predicted);
+        self.predicted += predicted;
+        self.accepted += accepted;
+        if predicted == 0 {
+            return;
+        }
+        if accepted == predicted {
+            self.chunk_size = self.chunk_size.saturating_mul(2).min(self.max_chunk);
+        } else if accepted * 2 < predicted {
+            self.chunk_size = (self.chunk_size / 2).max(self.min_chunk);
+        }
+    }
+
+    /// Verifies `predicted` against `actual` with
+    /// [`StreamNextChunk::verify_and_advance`] and records the outcome.
+    /// Returns the number of accepted tokens.
+    pub fn verify_and_advance(&mut self, predicted: &[T], actual: &[T]) -> usize {
+        let accepted = self.streamer.verify_and_advance(predicted, actual);
+        self.record(predicted.len(), accepted);
+        accepted
+    }
+
+    /// Forgets the appended tokens, the totals and the learned chunk size.
+    pub fn reset(&mut self) {
+        self.streamer.reset();
+        self.chunk_size = self.min_chunk;
+        self.predicted = 0;
+        self.accepted = 0;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_chunk_size() {
+        let a: Vec<i32> = (0..100).collect();
+        let mut chunker = AdaptiveChunker::new(StreamNextChunk::new(&a), 2, 10);
+        assert_eq!(chunker.next_chunk(&[0, 1]), &[2, 3]);
+        chunker.record(2, 2);
+        assert_eq!(chunker.chunk_size(), 4);
+        chunker.record(4, 4);
+        chunker.record(8, 8);
+        assert_eq!(chunker.chunk_size(), 10);
+        assert_eq!(chunker.next_chunk(&[0, 1]).len(), 10);
+        chunker.record(10, 7);
+        assert_eq!(chunker.chunk_size(), 10);
+        chunker.record(10, 1);
+        assert_eq!(chunker.chunk_size(), 5);
+        chunker.record(5, 0);
+        chunker.record(2, 0);
+        assert_eq!(chunker.chunk_size(), 2);
+        assert_eq!(chunker.totals(), (41, 22));
+
+        chunker.append(&[5, 6]);
+        assert_eq!(chunker.predict(), &[7, 8]);
+        chunker.reset();
+        assert_eq!((chunker.chunk_size(), chunker.totals()), (2, (0, 0)));
+
+        let predicted = chunker.predict().to_vec();
+        assert_eq!(chunker.verify_and_advance(&predicted, &[0, 1]), 2);
+        assert_eq!((chunker.chunk_size(), chunker.totals()), (4, (2, 2)));
+        assert_eq!(chunker.predict(), &[2, 3, 4, 5]);
+    }
+}
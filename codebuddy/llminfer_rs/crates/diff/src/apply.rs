@@ -0,0 +1,106 @@
+use std::hash::Hash;
+use std::ops::Range;
+
+use super::changes::diff_changes;
+use super::options::DiffAlgorithm;
+
+
+/// One edit of an edit script: replace `a[before]` with `replacement`, which
+/// ends up at `after` in the reconstructed `b`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Edit<T> {
+    pub before: Range<u32>,
+    pub after: Range<u32>,
+    pub replacement: Vec<T>,
+}
+
+/// Why an edit script couldn't be applied.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ApplyError {
+    #[error("edit {index}: before range {before:?} overlaps or precedes the previous edit")]
+    Unordered { index: usize, before: Range<u32> },
+    #[error("edit {index}: before range {before:?} is out of bounds for a sequence of length {len}")]
+    OutOfBounds { index: usize, before: Range<u32>, len: usize },
+    #[error("edit {index}: replacement has {actual} tokens but after range {after:?} spans {expected}")]
+    ReplacementLength { index: usize, after: Range<u32>, expected: usize, actual: usize },
+    #[error("edit {index}: after range starts at {expected} in the output, not {actual}")]
+    AfterOffset { index: usize, expected: usize, actual: u32 },
+}
+
+/// Builds the edit script turning `a` into `b`.
+pub fn edit_script<T: Eq + Hash + Copy>(a: &[T], b: &[T], algorithm: DiffAlgorithm) -> Vec<Edit<T>> {
+    diff_changes(a, b, algorithm)
+        .into_iter()
+        .map(|(before, after)| {
+            let replacement = b[after.start as usize..after.end as usize].to_vec();
+            Edit { before, after, replacement }
+        })
+        .collect()
+}
+
+/// Applies `edits` to `a`, reconstructing `b`.
+///
+/// Edits must be sorted by `before` and not overlap, and each `after` range
+/// must match where its replacement lands in the output; anything else is
+/// reported as an [`ApplyError`] naming the offending edit.
+pub fn apply_edits<T: Clone>(a: &[T], edits: &[Edit<T>]) -> Result<Vec<T>, ApplyError> {
+    let mut out = Vec::with_capacity(a.len());
+    let mut pos = 0usize;
+    for (index, edit) in edits.iter().enumerate() {
+        let (start, end) = (edit.before.start as usize, edit.before.end as usize);
+        if start < pos || end < start {
+            return Err(ApplyError::Unordered { index, before: edit.before.clone() });
+        }
+        if end > a.len() {
+            return Err(ApplyError::OutOfBounds { index, before: edit.before.clone(), len: a.len() });
+        }
+        if edit.replacement.len() != edit.after.len() {
+            return Err(ApplyError::ReplacementLength {
+                index,
+                after: edit.after.clone(),
+                expected: edit.after.len(),
+                actual: edit.replacement.len(),
+            });
+        }
+
+        out.extend_from_slice(&a[pos..start]);
+        if out.len() != edit.after.start as usize {
+            return Err(ApplyError::AfterOffset { index, expected: out.len(), actual: edit.after.start });
+        }
+        out.extend_from_slice(&edit.replacement);
+        pos = end;
+    }
+    out.extend_from_slice(&a[pos..]);
+    Ok(out)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let a = [1, 2, 3, 4, 5, 6];
+        let b = [0, 1, 3, 4, 9, 9, 6, 7];
+        let edits = edit_script(&a, &b, DiffAlgorithm::default());
+        assert_eq!(apply_edits(&a, &edits).unwrap(), b);
+        assert_eq!(apply_edits(&a, &[]).unwrap(), a);
+    }
+
+    #[test]
+    fn test_invalid_edits() {
+        let a = [1, 2, 3];
+        let edit = |before: Range<u32>, after: Range<u32>, replacement: Vec<i32>| Edit { before, after, replacement };
+
+        let err = apply_edits(&a, &[edit(1..2, 1..1, vec![]), edit(0..1, 0..0, vec![])]).unwrap_err();
+        assert_eq!(err, ApplyError::Unordered { index: 1, before: 0..1 });
+        let err = apply_edits(&a, &[edit(2..4, 2..2, vec![])]).unwrap_err();
+        assert!(matches!(err, ApplyError::OutOfBounds { len: 3, .. }));
+        let err = apply_edits(&a, &[edit(0..1, 0..2, vec![7])]).unwrap_err();
+        assert_eq!(err.to_string(), "edit 0: replacement has 1 tokens but after range 0..2 spans 2");
+        let err = apply_edits(&a, &[edit(1..2, 0..1, vec![7])]).unwrap_err();
+        assert_eq!(err, ApplyError::AfterOffset { index: 0, expected: 1, actual: 0 });
+    }
+}
@@ -0,0 +1,175 @@
+use std::hash::Hash;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::cancel::{CancellationToken, Cancelled};
+use super::nextchunk::StreamNextChunk;
+use super::options::NextChunkOptions;
+
+
+/// Owns one [`StreamNextChunk`] per stream and predicts for many streams at once.
+///
+/// With the `parallel` feature the per-stream diffs run on the rayon thread
+/// pool; without it they run sequentially with the same results.
+pub struct BatchNextChunk<T: Eq + Hash> {
+    streamers: Vec<StreamNextChunk<T>>,
+}
+
+impl<T: Eq + Hash + Copy + Send + Sync> BatchNextChunk<T> {
+    /// Creates a batch with one stream per reference, all using `options`.
+    pub fn new(references: Vec<Vec<T>>, options: NextChunkOptions) -> Self {
+        let streamers = references
+            .into_iter()
+            .map(|a| StreamNextChunk::with_options(a, options.clone()))
+            .collect();
+        BatchNextChunk { streamers }
+    }
+
+    /// Adds a stream and returns its index.
+    pub fn push(&mut self, streamer: StreamNextChunk<T>) -> usize {
+        self.streamers.push(streamer);
+        self.streamers.len() - 1
+    }
+
+    /// Number of streams in the batch.
+    pub fn len(&self) -> usize {
+        self.streamers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streamers.is_empty()
+    }
+
+    /// The streamer for stream `index`, if any.
+    pub fn get(&self, index: usize) -> Option<&StreamNextChunk<T>> {
+        self.streamers.get(index)
+    }
+
+    /// Predicts the next chunk for every stream; `bs[i]` is the sequence
+    /// received so far on stream `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bs.len()` differs from the number of streams.
+    pub fn next_chunk_batch(&self, bs: &[&[T]], chunk_size: usize) -> Vec<&[T]> {
+        assert_eq!(bs.len(), self.streamers.len(), "expected one sequence per stream");
+        #[cfg(feature = "parallel")]
+        let chunks = self
+            .streamers
+            .par_iter()
+            .zip(bs.par_iter())
+            .map(|(streamer, b)| streamer.next_chunk(b, chunk_size))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let chunks = self
+            .streamers
+            .iter()
+            .zip(bs)
+            .map(|(streamer, b)| streamer.next_chunk(b, chunk_size))
+            .collect();
+        chunks
+    }
+
+    /// Predicts the next chunk for a subset of streams, given as
+    /// `(stream index, sequence received so far)` pairs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a stream index is out of range.
+    pub fn next_chunk_batch_at(&self, requests: &[(usize, &[T])], chunk_size: usize) -> Vec<&[T]> {
+        #[cfg(feature = "parallel")]
+        let chunks = requests
+            .par_iter()
+            .map(|&(index, b)| self.streamers[index].next_chunk(b, chunk_size))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let chunks = requests
+            .iter()
+            .map(|&(index, b)| self.streamers[index].next_chunk(b, chunk_size))
+            .collect();
+        chunks
+    }
+
+    /// Same as [`BatchNextChunk::next_chunk_batch`], but gives up with
+    /// [`Cancelled`] once `cancel` is cancelled: streams not started yet are
+    /// skipped and those in flight start no further diff.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bs.len()` differs from the number of streams.
+    pub fn next_chunk_batch_cancellable(
+        &self,
+        bs: &[&[T]],
+        chunk_size: usize,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<&[T]>, Cancelled> {
+        assert_eq!(bs.len(), self.streamers.len(), "expected one sequence per stream");
+        let requests: Vec<(usize, &[T])> = bs.iter().copied().enumerate().collect();
+        self.next_chunk_batch_at_cancellable(&requests, chunk_size, cancel)
+    }
+
+    /// Same as [`BatchNextChunk::next_chunk_batch_at`], but gives up with
+    /// [`Cancelled`] once `cancel` is cancelled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a stream index is out of range.
+    pub fn next_chunk_batch_at_cancellable(
+        &self,
+        requests: &[(usize, &[T])],
+        chunk_size: usize,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<&[T]>, Cancelled> {
+        #[cfg(feature = "parallel")]
+        let chunks = requests
+            .par_iter()
+            .map(|&(index, b)| self.streamers[index].next_chunk_cancellable(b, chunk_size, cancel))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let chunks = requests
+            .iter()
+            .map(|&(index, b)| self.streamers[index].next_chunk_cancellable(b, chunk_size, cancel))
+            .collect();
+        chunks
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_next_chunk_batch() {
+        let references: Vec<Vec<i32>> = (0..8).map(|i| (i * 100..i * 100 + 50).collect()).collect();
+        let batch = BatchNextChunk::new(references.clone(), NextChunkOptions::default());
+        assert_eq!(batch.len(), 8);
+
+        let bs: Vec<&[i32]> = references.iter().enumerate().map(|(i, a)| &a[..i + 1]).collect();
+        let chunks = batch.next_chunk_batch(&bs, 2);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(*chunk, &references[i][i + 1..i + 3]);
+        }
+
+        let b = [300, 301, 302];
+        let chunks = batch.next_chunk_batch_at(&[(3, &b[..]), (0, &[])], 2);
+        assert_eq!(chunks, vec![&[303, 304][..], &[0, 1][..]]);
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let references: Vec<Vec<i32>> = (0..4).map(|i| (i * 100..i * 100 + 50).collect()).collect();
+        let batch = BatchNextChunk::new(references.clone(), NextChunkOptions::default());
+        let bs: Vec<&[i32]> = references.iter().map(|a| &a[..3]).collect();
+
+        let cancel = CancellationToken::new();
+        let chunks = batch.next_chunk_batch_cancellable(&bs, 2, &cancel).unwrap();
+        assert_eq!(chunks, batch.next_chunk_batch(&bs, 2));
+
+        cancel.clone().cancel();
+        assert_eq!(batch.next_chunk_batch_cancellable(&bs, 2, &cancel), Err(Cancelled));
+        assert_eq!(batch.next_chunk_batch_at_cancellable(&[(1, bs[1])], 2, &cancel), Err(Cancelled));
+        assert_eq!(batch.get(0).unwrap().stats().calls, 2);
+    }
+}
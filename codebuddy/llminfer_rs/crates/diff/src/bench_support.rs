@@ -0,0 +1,165 @@
+//! Synthetic reference/target token pairs for benchmarks and acceptance-rate
+//! regression checks.
+//!
+//! The target is the reference after the kind of changes a model makes when
+//! rewriting a file: scattered token edits, bursts of newly written tokens and
+//! moved blocks. Everything is drawn from a seeded generator, so a
+//! [`WorkloadConfig`] always yields the same [`Workload`].
+
+use std::cmp::min;
+
+
+/// Shape of a generated [`Workload`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorkloadConfig {
+    /// Number of tokens in the reference.
+    pub reference_len: usize,
+    /// Token ids are drawn from `0..vocab_size`, low ids more often, as in
+    /// tokenized code.
+    pub vocab_size: u32,
+    /// Probability (0..=1) that a reference token is substituted, deleted or
+    /// preceded by an inserted token, in equal parts.
+    pub edit_rate: f64,
+    /// Probability per reference token that a burst of new tokens is inserted before it.
+    pub burst_rate: f64,
+    /// Longest insertion burst.
+    pub max_burst_len: usize,
+    /// Number of blocks moved elsewhere in the target.
+    pub reorderings: usize,
+    /// Longest moved block.
+    pub max_reorder_len: usize,
+    pub seed: u64,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        WorkloadConfig {
+            reference_len: 4096,
+            vocab_size: 32_000,
+            edit_rate: 0.01,
+            burst_rate: 0.002,
+            max_burst_len: 64,
+            reorderings: 1,
+            max_reorder_len: 256,
+            seed: 0,
+        }
+    }
+}
+
+/// A generated reference and the target the model "writes" from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workload {
+    pub reference: Vec<u32>,
+    pub target: Vec<u32>,
+}
+
+/// Generates the workload described by `config`.
+pub fn generate(config: &WorkloadConfig) -> Workload {
+    let mut rng = Rng(config.seed);
+    let vocab_size = config.vocab_size.max(1);
+
+    let mut reference = Vec::with_capacity(config.reference_len);
+    while reference.len() < config.reference_len {
+        // Code repeats itself: now and then copy a recent span instead of new tokens
+        if reference.len() >= 16 && rng.chance(0.05) {
+            let len = 4 + rng.below(12);
+            let start = reference.len() - 16 + rng.below(16 - min(len, 16) + 1);
+            let end = min(start + len, reference.len());
+            reference.extend_from_within(start..end);
+        } else {
+            reference.push(rng.token(vocab_size));
+        }
+    }
+    reference.truncate(config.reference_len);
+
+    let mut moved = reference.clone();
+    for _ in 0..config.reorderings {
+        if moved.len() < 2 || config.max_reorder_len == 0 {
+            break;
+        }
+        let len = 1 + rng.below(min(config.max_reorder_len, moved.len() / 2));
+        let start = rng.below(moved.len() - len + 1);
+        let block: Vec<u32> = moved.drain(start..start + len).collect();
+        let to = rng.below(moved.len() + 1);
+        moved.splice(to..to, block);
+    }
+
+    let mut target = Vec::with_capacity(moved.len());
+    for &token in &moved {
+        if config.max_burst_len > 0 && rng.chance(config.burst_rate) {
+            let len = 1 + rng.below(config.max_burst_len);
+            target.extend((0..len).map(|_| rng.token(vocab_size)));
+        }
+        if rng.chance(config.edit_rate) {
+            match rng.below(3) {
+                0 => target.push(rng.token(vocab_size)),
+                1 => continue,
+                _ => target.extend([rng.token(vocab_size), token]),
+            }
+        } else {
+            target.push(token);
+        }
+    }
+    Workload { reference, target }
+}
+
+/// splitmix64: seedable and, unlike library generators, guaranteed to give
+/// the same stream forever, so recorded numbers stay comparable.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..1`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `0..n`, `n > 0`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        self.unit() < p
+    }
+
+    /// Skewed towards low ids, like frequent tokens of a BPE vocabulary.
+    fn token(&mut self, vocab_size: u32) -> u32 {
+        let u = self.unit();
+        ((u * u * vocab_size as f64) as u32).min(vocab_size - 1)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simulate;
+
+    #[test]
+    fn test_generate() {
+        let config = WorkloadConfig { reference_len: 2000, seed: 42, ..Default::default() };
+        let workload = generate(&config);
+        assert_eq!(workload.reference.len(), 2000);
+        assert_eq!(workload, generate(&config));
+        assert_ne!(workload, generate(&WorkloadConfig { seed: 43, ..config }));
+        assert!(workload.reference.iter().all(|&t| t < config.vocab_size));
+
+        let unchanged = WorkloadConfig { edit_rate: 0.0, burst_rate: 0.0, reorderings: 0, ..config };
+        let workload = generate(&unchanged);
+        assert_eq!(workload.reference, workload.target);
+
+        let rate = |edit_rate| {
+            let workload = generate(&WorkloadConfig { reference_len: 500, edit_rate, ..config });
+            simulate(&workload.reference, &workload.target, 16).acceptance_rate()
+        };
+        assert!(rate(0.001) > rate(0.05));
+    }
+}
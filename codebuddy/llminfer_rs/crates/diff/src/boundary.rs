@@ -0,0 +1,40 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+
+/// Pairs of tokens a tokenizer may emit for one another where a chunk
+/// starts, e.g. `" def"` and `"def"` when a merge crossed the boundary.
+///
+/// When the last token of `b` keeps it from anchoring but is equivalent to
+/// the token of `a` that would follow the rest of `b`, that token of `a` is
+/// taken as already generated and the prediction starts after it.
+/// Equivalence is symmetric and only applies to that one boundary token.
+#[derive(Debug, Clone, Default)]
+pub struct BoundaryEquivalence<T> {
+    pairs: HashSet<(T, T)>,
+}
+
+impl<T: Eq + Hash + Copy> BoundaryEquivalence<T> {
+    /// Table of the given `(x, y)` pairs, each making `x` and `y` equivalent.
+    pub fn new(pairs: impl IntoIterator<Item = (T, T)>) -> Self {
+        BoundaryEquivalence { pairs: pairs.into_iter().flat_map(|(x, y)| [(x, y), (y, x)]).collect() }
+    }
+
+    /// Whether `b_token` stands in for `a_token` at the boundary.
+    pub fn equivalent(&self, b_token: T, a_token: T) -> bool {
+        b_token == a_token || self.pairs.contains(&(b_token, a_token))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_equivalent() {
+        let table = BoundaryEquivalence::new([(1, 10), (2, 20)]);
+        assert!(table.equivalent(1, 10) && table.equivalent(10, 1) && table.equivalent(3, 3));
+        assert!(!table.equivalent(1, 20));
+    }
+}
@@ -0,0 +1,31 @@
+use std::mem::size_of;
+
+
+/// Approximate heap bytes of a hash map holding `len` entries of type `E`:
+/// the entries and a control byte per slot, at the 7/8 maximum load factor.
+pub(crate) fn map_bytes<E>(len: usize) -> usize {
+    len * (size_of::<E>() + 1) * 8 / 7
+}
+
+/// Memory held by the interning tables and indexes a
+/// [`crate::StreamNextChunk`] builds over `a`, against its
+/// [`crate::NextChunkOptions::memory_budget`]. Sizes are estimates and
+/// don't include `a` itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryUsage {
+    /// The budget in bytes, `None` when unbounded.
+    pub budget: Option<usize>,
+    /// Bytes held now.
+    pub used: usize,
+    /// Bytes of the tables matching can't do without (the interned `a`, or
+    /// the suffix automaton or suffix array backend).
+    pub required: usize,
+    /// Whether an optional index (the rolling hash locating windows, the
+    /// block index, the suffix automaton of `longest_match_at`) was left out
+    /// to stay within the budget.
+    pub degraded: bool,
+    /// Whether even the required tables exceed the budget, so every diff is
+    /// restricted to a window of `a` instead of running over all of it.
+    pub windowed_only: bool,
+}
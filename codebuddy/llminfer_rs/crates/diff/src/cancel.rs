@@ -0,0 +1,48 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+
+/// Handle to abort predictions in flight, e.g. when the client that asked
+/// for them disconnected.
+///
+/// Clones share the flag: keep one to [`CancellationToken::cancel`] and
+/// pass another to the cancellable calls, which start no further diff once
+/// it is set and return [`Cancelled`]. A diff already running isn't
+/// interrupted. Once cancelled, a token stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the calls using this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Error of a prediction aborted through its [`CancellationToken`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("prediction cancelled")]
+pub struct Cancelled;
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cancel() {
+        let token = CancellationToken::new();
+        let shared = token.clone();
+        assert!(!shared.is_cancelled());
+        token.cancel();
+        assert!(shared.is_cancelled() && token.is_cancelled());
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem::size_of;
+
+use super::budget::map_bytes;
+use super::rolling::slice_hash;
+
+
+/// Tokens hashed into a chain: shorter matches aren't found.
+const KEY_LEN: usize = 4;
+/// Most chained positions compared per lookup, as zlib bounds its chains.
+const MAX_CHAIN: usize = 64;
+
+/// LZ77-style hash chains over a reference sequence.
+///
+/// The hash of every run of 4 tokens of `a` heads a chain of the positions
+/// it ends at, most recent first. Looking up `b` walks the chain of its last
+/// 4 tokens and extends each candidate backwards; appending to `a` is O(1)
+/// per token. Only 4 bytes per token plus one map entry per distinct run,
+/// but matches shorter than 4 tokens are missed. The chains don't own `a`:
+/// each lookup takes it.
+pub struct HashChain {
+    /// Hash of a run -> end of its last occurrence in `a`.
+    head: HashMap<u64, u32>,
+    /// End of a run -> end of the previous run with the same hash, 0 for none.
+    prev: Vec<u32>,
+}
+
+impl HashChain {
+    pub fn new<T: Hash>(a: &[T]) -> Self {
+        let mut chain = HashChain { head: HashMap::new(), prev: vec![0] };
+        chain.extend(a);
+        chain
+    }
+
+    /// Chains the runs ending past the tokens already indexed, after `a` grew.
+    pub fn extend<T: Hash>(&mut self, a: &[T]) {
+        for end in self.prev.len()..=a.len() {
+            let prev = match end.checked_sub(KEY_LEN) {
+                Some(start) => self.head.insert(slice_hash(&a[start..end]), end as u32).unwrap_or(0),
+                None => 0,
+            };
+            self.prev.push(prev);
+        }
+    }
+
+    /// Approximate heap bytes held by the chains.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        map_bytes::<(u64, u32)>(self.head.len()) + self.prev.len() * size_of::<u32>()
+    }
+
+    /// Number of tokens indexed.
+    pub fn len(&self) -> usize {
+        self.prev.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The longest suffix of `b` found in `a` through the chain of its last
+    /// 4 tokens, as `(pos, match_len)` with `pos` the offset in `a` right
+    /// after it, the earliest on ties; `None` when `b`'s last 4 tokens
+    /// don't occur in `a`. Only the most recent positions of a long chain
+    /// are compared.
+    pub fn longest_suffix_match<T: Eq + Hash>(&self, a: &[T], b: &[T]) -> Option<(usize, usize)> {
+        let key = b.get(b.len().checked_sub(KEY_LEN)?..)?;
+        let mut end = *self.head.get(&slice_hash(key))? as usize;
+        let mut best = None;
+        for _ in 0..MAX_CHAIN {
+            if end == 0 {
+                break;
+            }
+            let match_len = a[..end].iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+            // Shorter only on a hash collision
+            if match_len >= KEY_LEN && best.is_none_or(|(_, best_len)| match_len >= best_len) {
+                best = Some((end, match_len));
+            }
+            end = self.prev[end] as usize;
+        }
+        best
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_longest_suffix_match() {
+        let a = vec![1, 2, 3, 4, 5, 9, 2, 3, 4, 5, 6, 1, 2, 3];
+        let mut chain = HashChain::new(&a);
+        assert_eq!(chain.len(), a.len());
+        assert_eq!(chain.longest_suffix_match(&a, &[7, 2, 3, 4, 5]), Some((5, 4)));
+        assert_eq!(chain.longest_suffix_match(&a, &[9, 2, 3, 4, 5]), Some((10, 5)));
+        assert_eq!(chain.longest_suffix_match(&a, &[8, 3, 4, 5, 6]), Some((11, 4)));
+        // Too short, or absent
+        assert_eq!(chain.longest_suffix_match(&a, &[4, 5]), None);
+        assert_eq!(chain.longest_suffix_match(&a, &[3, 4, 5, 7]), None);
+
+        let mut a = a;
+        a.extend([9, 2, 3, 4, 5, 7]);
+        chain.extend(&a);
+        assert_eq!(chain.len(), a.len());
+        assert_eq!(chain.longest_suffix_match(&a, &[3, 4, 5, 7]), Some((20, 4)));
+        assert_eq!(chain.longest_suffix_match(&a, &[1, 2, 3, 9, 2]), Some((16, 5)));
+    }
+}
@@ -0,0 +1,34 @@
+use std::hash::Hash;
+use std::ops::Range;
+
+use imara_diff::intern::InternedInput;
+
+use super::options::DiffAlgorithm;
+use super::sink::ChangeRangeCollector;
+use super::source::TokenSlice;
+
+
+/// Diffs `a` against `b` and returns the raw edits as `(range_in_a, range_in_b)`
+/// pairs, in order. Pure insertions have an empty `a` range, pure deletions an
+/// empty `b` range.
+pub fn diff_changes<T: Eq + Hash + Copy>(a: &[T], b: &[T], algorithm: DiffAlgorithm) -> Vec<(Range<u32>, Range<u32>)> {
+    let input = InternedInput::new(TokenSlice(a), TokenSlice(b));
+    imara_diff::diff(algorithm.into(), &input, ChangeRangeCollector::default())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff_changes() {
+        let a = [1, 2, 3, 4, 5];
+        let b = [1, 9, 3, 4, 5, 6];
+        for algorithm in [DiffAlgorithm::Histogram, DiffAlgorithm::Myers] {
+            assert_eq!(diff_changes(&a, &b, algorithm), vec![(1..2, 1..2), (5..5, 5..6)]);
+        }
+        assert!(diff_changes(&a, &a, DiffAlgorithm::default()).is_empty());
+        assert_eq!(diff_changes(&a, &[], DiffAlgorithm::default()), vec![(0..5, 0..0)]);
+    }
+}
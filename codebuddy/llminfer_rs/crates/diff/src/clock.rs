@@ -0,0 +1,7 @@
+//! The clock timing calls: `std::time::Instant` panics on
+//! wasm32-unknown-unknown, where `web-time` reads `performance.now()` instead.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::Instant;
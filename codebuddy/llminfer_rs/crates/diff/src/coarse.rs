@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use imara_diff::{diff_with_tokens, intern::Token};
+
+use super::budget::map_bytes;
+use super::options::DiffAlgorithm;
+use super::rolling::{for_each_kgram, leading_power, slice_hash};
+use super::sink::MatchCollector;
+
+
+/// Hashes of the aligned `block_len`-token blocks of the interned `a`, so
+/// the full diff of a huge reference can first diff block hashes to find
+/// the region `b` ends in, and only diff tokens there.
+///
+/// `b` is scanned with a rolling hash for runs equal to some block of `a`,
+/// so its blocks don't have to be aligned with those of `a`: any run of `b`
+/// following `a` for `2 * block_len - 1` tokens contains a whole block.
+pub(crate) struct BlockIndex {
+    block_len: usize,
+    /// `BASE^(block_len-1)` for the rolling hash over `b`.
+    high: u64,
+    /// Id of each block of `a`; equal blocks share an id.
+    blocks: Vec<Token>,
+    /// Block hash -> its id and the first block with that hash.
+    ids: HashMap<u64, (Token, usize)>,
+}
+
+fn ids(tokens: &[Token]) -> Vec<u32> {
+    tokens.iter().map(|token| token.0).collect()
+}
+
+impl BlockIndex {
+    pub(crate) fn new(a: &[Token], block_len: usize) -> Self {
+        assert!(block_len > 0);
+        let mut index = BlockIndex { block_len, high: leading_power(block_len), blocks: Vec::new(), ids: HashMap::new() };
+        index.extend(a);
+        index
+    }
+
+    /// Hashes the blocks of `a` completed since the last call.
+    pub(crate) fn extend(&mut self, a: &[Token]) {
+        let k = self.block_len;
+        for block in self.blocks.len()..a.len() / k {
+            let next = Token(self.ids.len() as u32);
+            let (id, _) = *self.ids.entry(slice_hash(&ids(&a[block * k..(block + 1) * k]))).or_insert((next, block));
+            self.blocks.push(id);
+        }
+    }
+
+    /// Approximate heap bytes held by the index.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.blocks.len() * 4 + map_bytes::<(u64, (Token, usize))>(self.ids.len())
+    }
+
+    /// Upper bound of [`BlockIndex::heap_bytes`] over `a_len` tokens, before
+    /// building the index.
+    pub(crate) fn estimate_bytes(a_len: usize, block_len: usize) -> usize {
+        let blocks = a_len / block_len.max(1);
+        blocks * 4 + map_bytes::<(u64, (Token, usize))>(blocks)
+    }
+
+    /// Narrows the diff of `b` against all of `a` to `(a_start, a_end,
+    /// b_start)`: from the last block of `b` the block diff lines up with
+    /// `a` to the end of `b`, with room in `a` for as many tokens again as
+    /// follow that block in `b`. `None` when no block of `b` lines up.
+    pub(crate) fn narrow(&self, a: &[Token], b: &[Token], algorithm: DiffAlgorithm) -> Option<(usize, usize, usize)> {
+        let k = self.block_len;
+        // Blocks of `a` found in `b`, with where they end in `b`
+        let mut found: Vec<(Token, usize)> = Vec::new();
+        let mut next = 0;
+        for_each_kgram(&ids(b), k, self.high, |start, hash| {
+            let Some(&(id, block)) = self.ids.get(&hash).filter(|_| start >= next) else { return };
+            if a[block * k..(block + 1) * k] == b[start..start + k] {
+                found.push((id, start + k));
+                next = start + k;
+            }
+        });
+        if found.is_empty() {
+            return None;
+        }
+
+        let b_blocks: Vec<Token> = found.iter().map(|&(id, _)| id).collect();
+        let sink = MatchCollector::new(self.blocks.len() as u32, b_blocks.len() as u32);
+        let matches = diff_with_tokens(algorithm.into(), &self.blocks, &b_blocks, self.ids.len() as u32, sink);
+        let (a_range, b_range) = matches.last()?;
+        let block = a_range.end as usize - 1;
+        let b_end = found[b_range.end as usize - 1].1;
+        let a_end = (block + 1) * k + 2 * (b.len() - b_end) + k;
+        Some((block * k, a_end.min(a.len()), b_end - k))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokens(ids: impl IntoIterator<Item = u32>) -> Vec<Token> {
+        ids.into_iter().map(Token).collect()
+    }
+
+    #[test]
+    fn test_narrow() {
+        let a = tokens((0..1000).map(|i| i % 700));
+        let index = BlockIndex::new(&a, 16);
+
+        // `b` copies a[300..500] with an insertion, off the block grid
+        let b = tokens((300..400).chain([5000, 5001]).chain(400..500));
+        let (a_start, a_end, b_start) = index.narrow(&a, &b, DiffAlgorithm::Histogram).unwrap();
+        assert_eq!(a_start % 16, 0);
+        assert!(a_start <= 480 && (500..=600).contains(&a_end), "{a_start}..{a_end}");
+        assert_eq!(&b[b_start..b_start + 16], &a[a_start..a_start + 16]);
+
+        // `b` jumped ahead in `a`: only its last lined up block counts
+        let b = tokens((0..200).chain(600..650));
+        assert_eq!(index.narrow(&a, &b, DiffAlgorithm::Histogram), Some((624, 676, 224)));
+
+        assert_eq!(index.narrow(&a, &tokens(0..10), DiffAlgorithm::Histogram), None);
+        assert_eq!(index.narrow(&a, &tokens(5000..5100), DiffAlgorithm::Histogram), None);
+    }
+}
@@ -0,0 +1,174 @@
+use std::borrow::Cow;
+use std::hash::Hash;
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::options::DiffAlgorithm;
+use super::sequencematch::matching_blocks;
+
+
+/// Where a run of a [`DeltaTokens`] comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    /// Starting at this offset of the base.
+    Base(usize),
+    /// Starting at this offset of the inserted tokens.
+    Inserted(usize),
+}
+
+/// A token sequence stored as a token-level delta against a shared base
+/// document: runs copied from the base plus the tokens inserted between them.
+///
+/// Many references that are small edits of the same file then cost little
+/// more than their edits, while slices are reconstructed on demand (borrowed
+/// from the base when they don't cross an edit). The streamers index the whole
+/// reference, so materialize it with [`DeltaTokens::to_vec`] to predict from
+/// it; the delta is for references at rest, e.g. of idle sessions.
+#[derive(Debug, Clone)]
+pub struct DeltaTokens<T> {
+    base: Arc<[T]>,
+    /// Offset in the sequence where each run starts, increasing.
+    starts: Vec<usize>,
+    sources: Vec<Source>,
+    inserted: Vec<T>,
+    len: usize,
+}
+
+impl<T: Eq + Hash + Copy> DeltaTokens<T> {
+    /// Encodes `tokens` against `base`, reusing the runs the diff matches.
+    pub fn encode(base: Arc<[T]>, tokens: &[T]) -> Self {
+        let mut delta = DeltaTokens { base, starts: Vec::new(), sources: Vec::new(), inserted: Vec::new(), len: tokens.len() };
+        let mut covered = 0;
+        for (i, j, n) in matching_blocks(&delta.base, tokens, DiffAlgorithm::Histogram) {
+            if j > covered {
+                delta.push(covered, Source::Inserted(delta.inserted.len()));
+                delta.inserted.extend_from_slice(&tokens[covered..j]);
+            }
+            if n > 0 {
+                delta.push(j, Source::Base(i));
+            }
+            covered = j + n;
+        }
+        delta
+    }
+}
+
+impl<T: Copy> DeltaTokens<T> {
+    fn push(&mut self, start: usize, source: Source) {
+        self.starts.push(start);
+        self.sources.push(source);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The shared base document.
+    pub fn base(&self) -> &Arc<[T]> {
+        &self.base
+    }
+
+    /// Bytes held by this delta besides the shared base.
+    pub fn heap_size(&self) -> usize {
+        self.starts.capacity() * size_of::<usize>()
+            + self.sources.capacity() * size_of::<Source>()
+            + self.inserted.capacity() * size_of::<T>()
+    }
+
+    /// Run `run` as a slice of the base or of the inserted tokens.
+    fn run(&self, run: usize) -> &[T] {
+        let end = self.starts.get(run + 1).copied().unwrap_or(self.len);
+        let len = end - self.starts[run];
+        match self.sources[run] {
+            Source::Base(start) => &self.base[start..start + len],
+            Source::Inserted(start) => &self.inserted[start..start + len],
+        }
+    }
+
+    /// Index of the run containing offset `pos`.
+    fn run_at(&self, pos: usize) -> usize {
+        self.starts.partition_point(|&start| start <= pos) - 1
+    }
+
+    pub fn get(&self, pos: usize) -> Option<T> {
+        (pos < self.len).then(|| {
+            let run = self.run_at(pos);
+            self.run(run)[pos - self.starts[run]]
+        })
+    }
+
+    /// The tokens in `range`, borrowed when they lie in a single run.
+    ///
+    /// # Panics
+    ///
+    /// When `range` is out of bounds, like slice indexing.
+    pub fn slice(&self, range: Range<usize>) -> Cow<'_, [T]> {
+        assert!(range.start <= range.end && range.end <= self.len, "range {range:?} out of bounds of {}", self.len);
+        if range.is_empty() {
+            return Cow::Borrowed(&[]);
+        }
+        let first = self.run_at(range.start);
+        let offset = range.start - self.starts[first];
+        let run = &self.run(first)[offset..];
+        if range.len() <= run.len() {
+            return Cow::Borrowed(&run[..range.len()]);
+        }
+        let mut tokens = Vec::with_capacity(range.len());
+        tokens.extend_from_slice(run);
+        for run in first + 1..self.starts.len() {
+            let run = self.run(run);
+            let needed = range.len() - tokens.len();
+            tokens.extend_from_slice(&run[..needed.min(run.len())]);
+            if tokens.len() == range.len() {
+                break;
+            }
+        }
+        Cow::Owned(tokens)
+    }
+
+    /// All tokens, e.g. to create a streamer from them.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.slice(0..self.len).into_owned()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delta_round_trip() {
+        let base: Arc<[i32]> = (0..1000).collect();
+        let mut edited: Vec<i32> = (0..1000).collect();
+        edited.splice(100..110, [-1, -2, -3]);
+        edited.drain(500..600);
+        edited.splice(0..0, [-7]);
+        edited.extend([-8, -9]);
+
+        let delta = DeltaTokens::encode(base.clone(), &edited);
+        assert_eq!(delta.len(), edited.len());
+        assert_eq!(delta.to_vec(), edited);
+        assert!((0..edited.len()).all(|i| delta.get(i) == Some(edited[i])));
+        assert_eq!(delta.get(edited.len()), None);
+        for range in [0..0, 0..1, 1..50, 50..200, 90..800, 0..edited.len(), edited.len() - 3..edited.len()] {
+            assert_eq!(delta.slice(range.clone()), &edited[range]);
+        }
+        // Within one copied run the slice borrows the base
+        assert!(matches!(delta.slice(10..50), Cow::Borrowed(_)));
+        assert!(delta.heap_size() < edited.len() * size_of::<i32>() / 10);
+    }
+
+    #[test]
+    fn test_delta_edge_cases() {
+        let base: Arc<[i32]> = Arc::from([1, 2, 3]);
+        assert!(DeltaTokens::encode(base.clone(), &[]).is_empty());
+        assert_eq!(DeltaTokens::encode(base.clone(), &[7, 8]).to_vec(), [7, 8]);
+        assert_eq!(DeltaTokens::encode(Arc::from([]), &[7, 8]).to_vec(), [7, 8]);
+        assert_eq!(DeltaTokens::encode(base, &[1, 2, 3]).slice(0..3), Cow::Borrowed(&[1, 2, 3][..]));
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::nextchunk::{StreamNextChunk, StreamNextChunkBytes};
+use super::options::NextChunkOptions;
+
+
+/// The bytes each token id of a tokenizer decodes to.
+///
+/// Also tokenizes bytes back, greedily taking the longest token at each
+/// position. That agrees with the tokenizer's own encoding on most text but
+/// not all of it, as BPE merges are ordered by rank rather than by length.
+#[derive(Debug, Clone)]
+pub struct Vocab<T> {
+    bytes: HashMap<T, Vec<u8>>,
+    ids: HashMap<Vec<u8>, T>,
+    /// Length of the longest token.
+    max_len: usize,
+}
+
+impl<T: Eq + Hash + Copy> Vocab<T> {
+    /// Vocabulary of the given `(id, bytes)` entries. Of several ids decoding
+    /// to the same bytes, tokenizing picks the first.
+    pub fn new(entries: impl IntoIterator<Item = (T, Vec<u8>)>) -> Self {
+        let mut vocab = Vocab { bytes: HashMap::new(), ids: HashMap::new(), max_len: 0 };
+        for (id, bytes) in entries {
+            vocab.max_len = vocab.max_len.max(bytes.len());
+            if !bytes.is_empty() {
+                vocab.ids.entry(bytes.clone()).or_insert(id);
+            }
+            vocab.bytes.insert(id, bytes);
+        }
+        vocab
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The bytes of `id`, `None` for ids outside the vocabulary.
+    pub fn token_bytes(&self, id: T) -> Option<&[u8]> {
+        self.bytes.get(&id).map(Vec::as_slice)
+    }
+
+    /// Concatenated bytes of `tokens`; ids outside the vocabulary decode to nothing.
+    pub fn detokenize(&self, tokens: &[T]) -> Vec<u8> {
+        tokens.iter().filter_map(|&id| self.token_bytes(id)).flatten().copied().collect()
+    }
+
+    /// Greedy longest-match tokenization of `bytes`, stopping before the
+    /// first byte no token starts with.
+    pub fn tokenize(&self, bytes: &[u8]) -> Vec<T> {
+        self.tokenize_at_most(bytes, usize::MAX)
+    }
+
+    /// [`Vocab::tokenize`], stopping after `max_tokens`.
+    fn tokenize_at_most(&self, mut bytes: &[u8], max_tokens: usize) -> Vec<T> {
+        let mut tokens = Vec::new();
+        while !bytes.is_empty() && tokens.len() < max_tokens {
+            let longest = (1..=self.max_len.min(bytes.len()))
+                .rev()
+                .find_map(|len| Some((len, *self.ids.get(&bytes[..len])?)));
+            let Some((len, id)) = longest else {
+                break;
+            };
+            tokens.push(id);
+            bytes = &bytes[len..];
+        }
+        tokens
+    }
+}
+
+/// Next-chunk prediction matching on the bytes tokens decode to instead of
+/// their ids.
+///
+/// The reference may come from another tokenizer (e.g. an older version of
+/// the current one) or be plain bytes: `current_b` is decoded with the
+/// current [`Vocab`] and matched as bytes, and predicted bytes are tokenized
+/// again with that vocabulary (see [`Vocab::tokenize`] for the caveats).
+pub struct DetokenizedNextChunk<T> {
+    vocab: Vocab<T>,
+    streamer: StreamNextChunkBytes,
+}
+
+impl<T: Eq + Hash + Copy> DetokenizedNextChunk<T> {
+    /// Predicts from the bytes `a`, e.g. the original file content.
+    pub fn new(a: Vec<u8>, vocab: Vocab<T>) -> Self {
+        Self::with_options(a, vocab, NextChunkOptions::default())
+    }
+
+    pub fn with_options(a: Vec<u8>, vocab: Vocab<T>, options: NextChunkOptions) -> Self {
+        DetokenizedNextChunk { vocab, streamer: StreamNextChunk::with_options(a, options) }
+    }
+
+    /// Predicts from `a` tokenized with `a_vocab`, e.g. a previous tokenizer version.
+    pub fn from_tokens<U: Eq + Hash + Copy>(a: &[U], a_vocab: &Vocab<U>, vocab: Vocab<T>, options: NextChunkOptions) -> Self {
+        Self::with_options(a_vocab.detokenize(a), vocab, options)
+    }
+
+    /// The reference as bytes.
+    pub fn reference(&self) -> &[u8] {
+        self.streamer.reference()
+    }
+
+    /// The vocabulary `current_b` is decoded and predictions tokenized with.
+    pub fn vocab(&self) -> &Vocab<T> {
+        &self.vocab
+    }
+
+    /// Predicts up to `max_bytes` bytes of the reference following `current_b`.
+    pub fn next_chunk_bytes(&self, current_b: &[T], max_bytes: usize) -> &[u8] {
+        self.streamer.next_chunk(&self.vocab.detokenize(current_b), max_bytes)
+    }
+
+    /// Predicts the next `chunk_size` tokens following `current_b`, tokenized
+    /// with the current vocabulary.
+    pub fn next_chunk(&self, current_b: &[T], chunk_size: usize) -> Vec<T> {
+        // No token is longer than `max_len`, so one token's worth of extra bytes
+        // keeps the last token of the chunk from being cut short
+        let max_bytes = chunk_size.saturating_add(1).saturating_mul(self.vocab.max_len.max(1));
+        let bytes = self.next_chunk_bytes(current_b, max_bytes);
+        self.vocab.tokenize_at_most(bytes, chunk_size)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vocab(words: &[&str]) -> Vocab<u32> {
+        Vocab::new(words.iter().enumerate().map(|(id, word)| (id as u32, word.as_bytes().to_vec())))
+    }
+
+    #[test]
+    fn test_vocab() {
+        let vocab = vocab(&["a", "b", "ab", "abc", " "]);
+        assert_eq!(vocab.tokenize(b"abcab a"), [3, 2, 4, 0]);
+        assert_eq!(vocab.tokenize(b"ab?b"), [2]);
+        assert_eq!(vocab.detokenize(&[3, 4, 99, 1]), b"abc b");
+    }
+
+    #[test]
+    fn test_detokenized_next_chunk() {
+        // The reference was tokenized with an older vocabulary lacking " def"
+        let old = vocab(&["fn", " ", "def", "(", ")", ":", "\n", "x"]);
+        let current = vocab(&["fn", " def", " ", "def", "(", ")", ":", "\n", "x"]);
+        let a = [1, 2, 3, 7, 4, 6, 1, 2, 3, 4, 5];
+        let detok = DetokenizedNextChunk::from_tokens(&a, &old, current.clone(), NextChunkOptions::default());
+        assert_eq!(detok.reference(), b" def(x)\n def():");
+        // The current tokenizer emits " def" as one token, which no id of `a` matches
+        let b = current.tokenize(b"fn def(x)\n def");
+        assert_eq!(b, [0, 1, 4, 8, 5, 7, 1]);
+        assert_eq!(detok.next_chunk_bytes(&b, 3), b"():");
+        assert_eq!(detok.next_chunk(&b, 2), [4, 5]);
+        assert_eq!(detok.next_chunk(&b, 10), [4, 5, 6]);
+    }
+}
@@ -0,0 +1,108 @@
+use std::hash::Hash;
+
+use super::changes::diff_changes;
+use super::options::DiffAlgorithm;
+
+
+/// Token-level edit distance: the number of tokens the diff deletes from `a`
+/// plus the number it inserts from `b`.
+///
+/// Exact (minimal insert/delete distance) for [`DiffAlgorithm::MyersMinimal`];
+/// the heuristic algorithms may overestimate slightly.
+pub fn edit_distance<T: Eq + Hash + Copy>(a: &[T], b: &[T], algorithm: DiffAlgorithm) -> usize {
+    diff_changes(a, b, algorithm)
+        .iter()
+        .map(|(before, after)| before.len() + after.len())
+        .sum()
+}
+
+/// The minimal insert/delete distance between `a` and `b` if it is at most
+/// `max`, `None` otherwise.
+///
+/// Skips the common prefix/suffix and runs a DP restricted to the band of
+/// width `2 * max + 1`, giving up as soon as a whole row exceeds `max`: cost
+/// is O(len * max), cheap enough to gate decisions on.
+pub fn edit_distance_within<T: Eq>(a: &[T], b: &[T], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    // Band cell `idx` of row `i` holds the distance between a[..i] and b[..j]
+    // for j = i + idx - max; anything beyond `max` is saturated to `inf`.
+    let inf = max + 1;
+    let width = 2 * max + 1;
+    let mut prev = vec![inf; width];
+    let mut cur = vec![inf; width];
+    for j in 0..=max.min(b.len()) {
+        prev[j + max] = j;
+    }
+    for i in 1..=a.len() {
+        let mut row_min = inf;
+        for idx in 0..width {
+            cur[idx] = inf;
+            let Some(j) = (i + idx).checked_sub(max).filter(|&j| j <= b.len()) else { continue };
+            let dist = if j == 0 {
+                i
+            } else if a[i - 1] == b[j - 1] {
+                prev[idx]
+            } else {
+                let up = prev.get(idx + 1).copied().unwrap_or(inf);
+                let left = if idx > 0 { cur[idx - 1] } else { inf };
+                1 + up.min(left)
+            };
+            cur[idx] = dist.min(inf);
+            row_min = row_min.min(cur[idx]);
+        }
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    let dist = prev[b.len() + max - a.len()];
+    (dist <= max).then_some(dist)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        let a = [1, 2, 3, 4, 5];
+        let b = [0, 1, 2, 9, 4, 5, 6];
+        assert_eq!(edit_distance(&a, &b, DiffAlgorithm::MyersMinimal), 4);
+        assert_eq!(edit_distance(&a, &a, DiffAlgorithm::default()), 0);
+    }
+
+    #[test]
+    fn test_edit_distance_within() {
+        let a = [1, 2, 3, 4, 5];
+        let b = [0, 1, 2, 9, 4, 5, 6];
+        assert_eq!(edit_distance_within(&a, &b, 4), Some(4));
+        assert_eq!(edit_distance_within(&a, &b, 10), Some(4));
+        assert_eq!(edit_distance_within(&a, &b, 3), None);
+        assert_eq!(edit_distance_within(&a, &a, 0), Some(0));
+        assert_eq!(edit_distance_within(&a, &[], 4), None);
+        assert_eq!(edit_distance_within::<i32>(&[], &[], 0), Some(0));
+
+        // agrees with the exact diff on pseudo-random input
+        let mut seed = 7u32;
+        let mut next = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % 4
+        };
+        for _ in 0..50 {
+            let a: Vec<u32> = (0..12).map(|_| next()).collect();
+            let b: Vec<u32> = (0..10).map(|_| next()).collect();
+            let exact = edit_distance(&a, &b, DiffAlgorithm::MyersMinimal);
+            assert_eq!(edit_distance_within(&a, &b, 30), Some(exact));
+            assert_eq!(edit_distance_within(&a, &b, exact), Some(exact));
+            assert_eq!(edit_distance_within(&a, &b, exact - 1), None);
+        }
+    }
+}
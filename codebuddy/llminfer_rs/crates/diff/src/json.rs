@@ -0,0 +1,109 @@
+use std::hash::Hash;
+
+use serde::Serialize;
+
+use super::options::DiffAlgorithm;
+use super::sequencematch::opcodes;
+use super::sink::OpTag;
+
+
+/// One run of the edit script with the tokens it covers.
+///
+/// `op` is never [`OpTag::Replace`]: replacements are split into a delete of
+/// `a[a_start..a_end]` followed by an insert of `b[b_start..b_end]`. `tokens`
+/// come from `a` for equal and deleted runs and from `b` for inserted ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffHunk<'a, T> {
+    pub op: OpTag,
+    pub a_start: u32,
+    pub a_end: u32,
+    pub b_start: u32,
+    pub b_end: u32,
+    pub tokens: &'a [T],
+}
+
+#[derive(Serialize)]
+struct DiffDocument<'a, T> {
+    algorithm: DiffAlgorithm,
+    a_len: usize,
+    b_len: usize,
+    hunks: Vec<DiffHunk<'a, T>>,
+}
+
+/// Equal/insert/delete hunks turning `a` into `b`, in order.
+pub fn diff_hunks<'a, T: Eq + Hash + Copy>(a: &'a [T], b: &'a [T], algorithm: DiffAlgorithm) -> Vec<DiffHunk<'a, T>> {
+    let hunk = |op, a_start, a_end, b_start, b_end, tokens| DiffHunk { op, a_start, a_end, b_start, b_end, tokens };
+    let mut hunks = Vec::new();
+    for op in opcodes(a, b, algorithm) {
+        let (a_tokens, b_tokens) = (&a[op.a.start as usize..op.a.end as usize], &b[op.b.start as usize..op.b.end as usize]);
+        match op.tag {
+            OpTag::Equal => hunks.push(hunk(OpTag::Equal, op.a.start, op.a.end, op.b.start, op.b.end, a_tokens)),
+            OpTag::Insert => hunks.push(hunk(OpTag::Insert, op.a.start, op.a.end, op.b.start, op.b.end, b_tokens)),
+            OpTag::Delete => hunks.push(hunk(OpTag::Delete, op.a.start, op.a.end, op.b.start, op.b.end, a_tokens)),
+            OpTag::Replace => {
+                // The delete consumes nothing of `b`, the insert nothing of `a`
+                hunks.push(hunk(OpTag::Delete, op.a.start, op.a.end, op.b.start, op.b.start, a_tokens));
+                hunks.push(hunk(OpTag::Insert, op.a.end, op.a.end, op.b.start, op.b.end, b_tokens));
+            }
+        }
+    }
+    hunks
+}
+
+/// Diff of `a` and `b` as a JSON document:
+///
+/// ```json
+/// {"algorithm": "histogram", "a_len": 3, "b_len": 3, "hunks": [
+///   {"op": "equal", "a_start": 0, "a_end": 2, "b_start": 0, "b_end": 2, "tokens": [1, 2]},
+///   {"op": "delete", "a_start": 2, "a_end": 3, "b_start": 2, "b_end": 2, "tokens": [3]},
+///   {"op": "insert", "a_start": 3, "a_end": 3, "b_start": 2, "b_end": 3, "tokens": [4]}]}
+/// ```
+///
+/// See [`diff_hunks`] for the hunk layout.
+pub fn diff_json<T: Eq + Hash + Copy + Serialize>(a: &[T], b: &[T], algorithm: DiffAlgorithm) -> String {
+    let document = DiffDocument { algorithm, a_len: a.len(), b_len: b.len(), hunks: diff_hunks(a, b, algorithm) };
+    // Token payloads are plain values and the keys are strings, so this can't fail
+    serde_json::to_string(&document).expect("diff document serializes")
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff_hunks() {
+        let a = [1, 2, 3, 5];
+        let b = [1, 2, 4, 5, 6];
+        let hunks = diff_hunks(&a, &b, DiffAlgorithm::default());
+        let ops: Vec<_> = hunks.iter().map(|h| (h.op, h.a_start..h.a_end, h.b_start..h.b_end, h.tokens.to_vec())).collect();
+        assert_eq!(
+            ops,
+            vec![
+                (OpTag::Equal, 0..2, 0..2, vec![1, 2]),
+                (OpTag::Delete, 2..3, 2..2, vec![3]),
+                (OpTag::Insert, 3..3, 2..3, vec![4]),
+                (OpTag::Equal, 3..4, 3..4, vec![5]),
+                (OpTag::Insert, 4..4, 4..5, vec![6]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_json() {
+        let json: serde_json::Value = serde_json::from_str(&diff_json(&[1, 2, 3], &[1, 2, 4], DiffAlgorithm::Myers)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "algorithm": "myers",
+                "a_len": 3,
+                "b_len": 3,
+                "hunks": [
+                    {"op": "equal", "a_start": 0, "a_end": 2, "b_start": 0, "b_end": 2, "tokens": [1, 2]},
+                    {"op": "delete", "a_start": 2, "a_end": 3, "b_start": 2, "b_end": 2, "tokens": [3]},
+                    {"op": "insert", "a_start": 3, "a_end": 3, "b_start": 2, "b_end": 3, "tokens": [4]},
+                ],
+            })
+        );
+    }
+}
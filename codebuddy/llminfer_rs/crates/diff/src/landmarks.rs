@@ -0,0 +1,166 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::mem::size_of;
+use std::ops::Range;
+
+use super::budget::map_bytes;
+
+
+/// Aho–Corasick automaton over distinctive "landmark" runs of a reference
+/// sequence.
+///
+/// Fed `b` one token at a time, it reports in O(1) amortized per token when
+/// `b` just reached a landmark, so a stream that diverged from `a` can be
+/// re-anchored right at the landmark's end without diffing.
+pub struct LandmarkAutomaton<T> {
+    nodes: Vec<Node<T>>,
+    /// The ranges of `a` indexed, in the order given.
+    landmarks: Vec<Range<usize>>,
+}
+
+struct Node<T> {
+    next: HashMap<T, usize>,
+    /// Node of the longest proper suffix of this node's tokens in the trie.
+    fail: usize,
+    /// Longest landmark ending at this node: its own, or one reached
+    /// through the failure links.
+    output: Option<usize>,
+}
+
+impl<T> Node<T> {
+    fn new() -> Self {
+        Node { next: HashMap::new(), fail: ROOT, output: None }
+    }
+}
+
+/// Position in the automaton after feeding some tokens of `b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LandmarkCursor {
+    node: usize,
+}
+
+const ROOT: usize = 0;
+
+impl<T: Eq + Hash + Copy> LandmarkAutomaton<T> {
+    /// Builds the automaton over the `landmarks` ranges of `a`. Empty ranges
+    /// never match; of landmarks with the same tokens, the first is reported.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a range is out of bounds of `a`.
+    pub fn new(a: &[T], landmarks: impl IntoIterator<Item = Range<usize>>) -> Self {
+        let landmarks: Vec<Range<usize>> = landmarks.into_iter().collect();
+        let mut nodes = vec![Node::new()];
+        for (i, range) in landmarks.iter().enumerate() {
+            if range.is_empty() {
+                continue;
+            }
+            let mut node = ROOT;
+            for &token in &a[range.clone()] {
+                node = match nodes[node].next.get(&token) {
+                    Some(&next) => next,
+                    None => {
+                        nodes.push(Node::new());
+                        let next = nodes.len() - 1;
+                        nodes[node].next.insert(token, next);
+                        next
+                    }
+                };
+            }
+            nodes[node].output.get_or_insert(i);
+        }
+
+        // Failure links breadth-first, so a node's are set before its children's
+        let mut queue: VecDeque<usize> = nodes[ROOT].next.values().copied().collect();
+        while let Some(node) = queue.pop_front() {
+            let children: Vec<(T, usize)> = nodes[node].next.iter().map(|(&token, &child)| (token, child)).collect();
+            for (token, child) in children {
+                let fail = goto(&nodes, nodes[node].fail, token);
+                nodes[child].fail = fail;
+                if nodes[child].output.is_none() {
+                    nodes[child].output = nodes[fail].output;
+                }
+                queue.push_back(child);
+            }
+        }
+        LandmarkAutomaton { nodes, landmarks }
+    }
+
+    /// Approximate heap bytes held by the automaton.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        // Every node but the root is the target of one transition
+        self.nodes.len() * size_of::<Node<T>>()
+            + map_bytes::<(T, usize)>(self.nodes.len() - 1)
+            + self.landmarks.len() * size_of::<Range<usize>>()
+    }
+
+    /// The ranges of `a` indexed, in the order given.
+    pub fn landmarks(&self) -> &[Range<usize>] {
+        &self.landmarks
+    }
+
+    /// Feeds one token of `b`.
+    pub fn step(&self, cursor: LandmarkCursor, token: T) -> LandmarkCursor {
+        LandmarkCursor { node: goto(&self.nodes, cursor.node, token) }
+    }
+
+    /// Index in [`LandmarkAutomaton::landmarks`] of the longest landmark the
+    /// tokens fed so far end with, if any.
+    pub fn reached(&self, cursor: LandmarkCursor) -> Option<usize> {
+        self.nodes[cursor.node].output
+    }
+
+    /// Every `(end, landmark)` reached while feeding `b` from the start:
+    /// `end` is the offset in `b` right after the landmark, the longest one
+    /// reported when several end there.
+    pub fn scan(&self, b: &[T]) -> Vec<(usize, usize)> {
+        let mut cursor = LandmarkCursor::default();
+        b.iter()
+            .enumerate()
+            .filter_map(|(i, &token)| {
+                cursor = self.step(cursor, token);
+                Some((i + 1, self.reached(cursor)?))
+            })
+            .collect()
+    }
+}
+
+/// Follows the transition on `token` from `node`, falling back along the
+/// failure links; the root when no suffix continues with `token`.
+fn goto<T: Eq + Hash>(nodes: &[Node<T>], mut node: usize, token: T) -> usize {
+    loop {
+        if let Some(&next) = nodes[node].next.get(&token) {
+            return next;
+        }
+        if node == ROOT {
+            return ROOT;
+        }
+        node = nodes[node].fail;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scan() {
+        //            0  1  2  3  4  5  6  7  8  9
+        let a = vec![10, 11, 12, 13, 20, 21, 11, 12, 30, 31];
+        let automaton = LandmarkAutomaton::new(&a, [0..4, 6..8, 4..6, 1..3, 9..9]);
+        assert_eq!(automaton.landmarks().len(), 5);
+        // [11, 12] is both landmarks 1 and 3: the first is reported
+        assert_eq!(automaton.scan(&[5, 11, 12, 7, 20, 21]), [(3, 1), (6, 2)]);
+        // Landmark 1 is a suffix of the partial landmark 0, then landmark 0 completes
+        assert_eq!(automaton.scan(&[10, 11, 12, 13, 10, 11]), [(3, 1), (4, 0)]);
+        assert!(automaton.scan(&[13, 31, 12]).is_empty());
+
+        let mut cursor = LandmarkCursor::default();
+        for token in [9, 20] {
+            cursor = automaton.step(cursor, token);
+            assert_eq!(automaton.reached(cursor), None);
+        }
+        assert_eq!(automaton.reached(automaton.step(cursor, 21)), Some(2));
+    }
+}
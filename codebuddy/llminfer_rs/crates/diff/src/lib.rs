@@ -1,13 +1,105 @@
+/// Emits a `tracing` event when the `tracing` feature is enabled, and
+/// compiles to nothing otherwise.
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
+}
 
+mod acceptance;
+mod adaptive;
+mod apply;
+mod batch;
+pub mod bench_support;
+mod boundary;
+mod budget;
+mod cancel;
+mod chain;
+mod changes;
+mod clock;
+mod coarse;
+mod delta;
+mod detok;
+mod distance;
+#[cfg(feature = "json")]
+mod json;
+mod landmarks;
+mod lookahead;
+mod memo;
+mod merge;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod multiref;
 mod nextchunk;
+mod ngram;
+mod normalize;
+mod npy;
+mod options;
+mod prefix;
+mod reference;
+mod rolling;
+mod sa;
+mod sam;
+mod scratch;
+mod sequencematch;
+mod sessions;
+mod simulate;
 // mod printhelper;
 mod sink;
 mod source;
+mod state;
+mod stats;
+mod store;
+mod text;
+#[cfg(feature = "tokenizers")]
+mod tokenize;
+mod tree;
+mod words;
 
 // mod test_nextchunk;
 
 // mod similar_diff;
-// mod sequencematch;
 
 
-pub use nextchunk::StreamNextChunk;
+pub use acceptance::{AcceptanceEstimate, AcceptanceEstimator};
+pub use adaptive::AdaptiveChunker;
+pub use apply::{apply_edits, edit_script, ApplyError, Edit};
+pub use batch::BatchNextChunk;
+pub use boundary::BoundaryEquivalence;
+pub use budget::MemoryUsage;
+pub use cancel::{CancellationToken, Cancelled};
+pub use chain::HashChain;
+pub use changes::diff_changes;
+pub use delta::DeltaTokens;
+pub use detok::{DetokenizedNextChunk, Vocab};
+pub use distance::{edit_distance, edit_distance_within};
+#[cfg(feature = "json")]
+pub use json::{diff_hunks, diff_json, DiffHunk};
+pub use landmarks::{LandmarkAutomaton, LandmarkCursor};
+pub use lookahead::NgramPool;
+pub use merge::{merge3, Conflict, Merge3};
+pub use multiref::{MultiRefPrediction, MultiRefStreamNextChunk};
+pub use nextchunk::{OwnedPredictionResult, PredictionCursor, PredictionHook, PredictionResult, StreamNextChunk, StreamNextChunkBytes};
+#[cfg(feature = "mmap")]
+pub use mmap::{MappedTokens, MmapError};
+pub use ngram::NgramNextChunk;
+pub use normalize::Normalizer;
+pub use npy::{parse_npy, read_npy, NpyArray, NpyError};
+pub use options::{AnchorStrategy, DiffAlgorithm, EscalationPolicy, FallbackPolicy, MatcherBackend, NextChunkOptions, ParseOptionError};
+pub use reference::SharedTokens;
+pub use sa::SuffixArray;
+pub use sam::{SamCursor, SuffixAutomaton};
+pub use sequencematch::{find_matches, lcs, matching_blocks, opcodes, similarity, Lcs};
+pub use sessions::{Evicted, Eviction, SessionLimits, SessionPool};
+pub use simulate::{simulate, simulate_with_options, SimulationReport, SimulationStep};
+pub use sink::{ChangeRangeCollector, MatchCollector, MatchWeights, OpTag, Opcode, OpcodeCollector, ScoredMatch, ScoredMatchCollector, UnifiedDiffSink};
+pub use source::{I32Slice, TokenSlice, U8Slice, WordSource, WordSplit};
+pub use state::StateError;
+pub use stats::{CallAnchor, CallStats, MatchLengthHistogram, NextChunkStats};
+pub use store::ReferenceStore;
+pub use text::{unified_diff, TextDiff};
+#[cfg(feature = "tokenizers")]
+pub use tokenize::TokenizerError;
+pub use tree::{LabeledTokenTree, TokenTree, TreeVerification};
+pub use words::WordNextChunk;
@@ -0,0 +1,149 @@
+use std::cmp::min;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use super::tree::TokenTree;
+
+
+/// Pool of n-grams for lookahead (Jacobi) decoding, harvested from the
+/// reference and from the text generated so far.
+///
+/// Keys are runs of `key_len` tokens, each mapping to up to `max_per_key`
+/// distinct continuations of at most `continuation_len` tokens seen after it,
+/// most recently inserted first. Unlike [`crate::StreamNextChunk`] and
+/// [`crate::NgramNextChunk`], which only predict from the reference, the pool
+/// keeps learning from verified output, so it still has candidates when the
+/// model leaves the reference but repeats itself. The number of keys grows
+/// with the distinct text inserted; [`NgramPool::clear`] drops them.
+#[derive(Debug, Clone)]
+pub struct NgramPool<T> {
+    key_len: usize,
+    continuation_len: usize,
+    max_per_key: usize,
+    /// Key -> continuations, none a prefix of another, most recent first.
+    pool: HashMap<Vec<T>, VecDeque<Vec<T>>>,
+}
+
+impl<T: Eq + Hash + Copy> NgramPool<T> {
+    /// An empty pool.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key_len`, `continuation_len` or `max_per_key` is 0.
+    pub fn new(key_len: usize, continuation_len: usize, max_per_key: usize) -> Self {
+        assert!(
+            key_len > 0 && continuation_len > 0 && max_per_key > 0,
+            "key_len, continuation_len and max_per_key must be at least 1"
+        );
+        NgramPool { key_len, continuation_len, max_per_key, pool: HashMap::new() }
+    }
+
+    /// A pool seeded with the n-grams of `reference`.
+    pub fn with_reference(reference: &[T], key_len: usize, continuation_len: usize, max_per_key: usize) -> Self {
+        let mut pool = Self::new(key_len, continuation_len, max_per_key);
+        pool.insert(reference);
+        pool
+    }
+
+    /// Tokens of the end of `b` looked up.
+    pub fn key_len(&self) -> usize {
+        self.key_len
+    }
+
+    /// Longest continuation kept per key.
+    pub fn continuation_len(&self) -> usize {
+        self.continuation_len
+    }
+
+    /// Most continuations kept per key.
+    pub fn max_per_key(&self) -> usize {
+        self.max_per_key
+    }
+
+    /// Number of continuations in the pool.
+    pub fn len(&self) -> usize {
+        self.pool.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pool.is_empty()
+    }
+
+    /// Forgets every n-gram.
+    pub fn clear(&mut self) {
+        self.pool.clear();
+    }
+
+    /// Harvests the n-grams of `tokens`, e.g. a window of output that passed
+    /// verification. N-grams spanning two insertions are only seen when the
+    /// windows overlap by `key_len + continuation_len - 1` tokens.
+    ///
+    /// A continuation extending one already kept for its key replaces it,
+    /// and one that is a prefix of a kept one just refreshes that one; when
+    /// a key has `max_per_key` continuations, the least recent is dropped.
+    pub fn insert(&mut self, tokens: &[T]) {
+        for start in 0..tokens.len().saturating_sub(self.key_len) {
+            let (key, rest) = tokens[start..].split_at(self.key_len);
+            self.insert_ngram(key, &rest[..min(rest.len(), self.continuation_len)]);
+        }
+    }
+
+    fn insert_ngram(&mut self, key: &[T], continuation: &[T]) {
+        let continuations = self.pool.entry(key.to_vec()).or_default();
+        // At most one kept continuation is a prefix of, or extends, this one
+        let related = continuations.iter().position(|c| c.starts_with(continuation) || continuation.starts_with(c));
+        let kept = match related.and_then(|i| continuations.remove(i)) {
+            Some(existing) if existing.len() >= continuation.len() => existing,
+            _ => continuation.to_vec(),
+        };
+        continuations.push_front(kept);
+        continuations.truncate(self.max_per_key);
+    }
+
+    /// Up to `k` continuations of the last `key_len` tokens of `current_b`,
+    /// most recently inserted first; none when `current_b` is shorter.
+    pub fn candidates(&self, current_b: &[T], k: usize) -> Vec<&[T]> {
+        let Some(start) = current_b.len().checked_sub(self.key_len) else {
+            return Vec::new();
+        };
+        self.pool
+            .get(&current_b[start..])
+            .map_or_else(Vec::new, |continuations| continuations.iter().take(k).map(Vec::as_slice).collect())
+    }
+
+    /// The [`NgramPool::candidates`] merged into a prefix tree, for
+    /// tree-attention verification.
+    pub fn candidate_tree(&self, current_b: &[T], k: usize) -> TokenTree<T> {
+        TokenTree::from_candidates(self.candidates(current_b, k))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let mut pool = NgramPool::with_reference(&[1, 2, 3, 4, 1, 2, 5, 6], 2, 3, 2);
+        assert_eq!(pool.candidates(&[9, 1, 2], 4), [&[5, 6][..], &[3, 4, 1]]);
+        assert_eq!(pool.candidates(&[9, 1, 2], 1), [&[5, 6]]);
+        assert!(pool.candidates(&[2], 4).is_empty() && pool.candidates(&[7, 7], 4).is_empty());
+
+        // Verified output extends a continuation and adds new ones
+        pool.insert(&[1, 2, 5, 6, 7]);
+        assert_eq!(pool.candidates(&[1, 2], 4), [&[5, 6, 7][..], &[3, 4, 1]]);
+        pool.insert(&[1, 2, 8]);
+        assert_eq!(pool.candidates(&[1, 2], 4), [&[8][..], &[5, 6, 7]]);
+        // A prefix of a kept continuation only refreshes it
+        pool.insert(&[1, 2, 5]);
+        assert_eq!(pool.candidates(&[1, 2], 4), [&[5, 6, 7][..], &[8]]);
+
+        let tree = pool.candidate_tree(&[1, 2], 4);
+        assert_eq!((tree.tokens, tree.parents), (vec![5, 8, 6, 7], vec![-1, -1, 0, 2]));
+        assert!(!pool.is_empty());
+        pool.clear();
+        assert!(pool.is_empty());
+        assert_eq!(pool.len(), 0);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::sync::{Mutex, PoisonError};
+
+use super::rolling::slice_hash;
+
+
+/// Number of calls [`AnchorMemo`] remembers.
+const CAPACITY: usize = 8;
+/// Trailing tokens of `b` hashed into the key.
+const TAIL_LEN: usize = 32;
+
+/// Where recent stateless calls anchored, keyed by the length of their `b`
+/// and a rolling hash of its tail.
+///
+/// During generation each `next_chunk` call passes the previous call's `b`
+/// plus a few new tokens, so the remembered anchor and a check that the new
+/// tokens keep following `a` replace a re-diff of the already matched prefix.
+/// Besides the last diffed calls it keeps the latest anchored one, memo hits
+/// included, so that check only covers the tokens appended since.
+#[derive(Debug, Default)]
+pub(crate) struct AnchorMemo(Mutex<Entries>);
+
+#[derive(Debug, Default)]
+struct Entries {
+    diffed: VecDeque<Entry>,
+    latest: Option<Entry>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    b_len: usize,
+    tail: u64,
+    /// Offset in `a` aligned with the end of that `b`.
+    pos: usize,
+    match_len: usize,
+}
+
+/// Hash of the last tokens of `b`, which with its length tells a call's `b` apart.
+pub(crate) fn tail_hash<T: Hash>(b: &[T]) -> u64 {
+    slice_hash(&b[b.len().saturating_sub(TAIL_LEN)..])
+}
+
+impl AnchorMemo {
+    /// The longest remembered prefix of `b`, as `(b_len, pos, match_len)`.
+    /// The latest call only counts when `b` is longer: one as long appended
+    /// nothing, so it was edited or retried.
+    pub(crate) fn lookup<T: Hash>(&self, b: &[T]) -> Option<(usize, usize, usize)> {
+        let entries = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let latest = entries.latest.filter(|latest| latest.b_len < b.len());
+        entries
+            .diffed
+            .iter()
+            .chain(&latest)
+            .filter(|entry| entry.b_len <= b.len() && entry.tail == tail_hash(&b[..entry.b_len]))
+            .max_by_key(|entry| entry.b_len)
+            .map(|entry| (entry.b_len, entry.pos, entry.match_len))
+    }
+
+    /// Remembers that the diff of `b` anchored at `pos` via a match of
+    /// `match_len` tokens.
+    pub(crate) fn store<T: Hash>(&self, b: &[T], pos: usize, match_len: usize) {
+        let mut entries = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if entries.diffed.len() == CAPACITY {
+            entries.diffed.pop_front();
+        }
+        entries.diffed.push_back(Entry { b_len: b.len(), tail: tail_hash(b), pos, match_len });
+    }
+
+    /// Remembers the latest call, whose `b` anchored at `pos` via a match of
+    /// `match_len` tokens, replacing the previous one.
+    pub(crate) fn store_latest<T: Hash>(&self, b: &[T], pos: usize, match_len: usize) {
+        let mut entries = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.latest = Some(Entry { b_len: b.len(), tail: tail_hash(b), pos, match_len });
+    }
+
+    pub(crate) fn clear(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Entries::default();
+    }
+}
+
+
+/// The latest confirmed alignment of a stateless call's `b` with `a`, as
+/// `(b_len, pos)`: the first `b_len` tokens of `b` ended at offset `pos`.
+#[derive(Debug, Default)]
+pub(crate) struct LastAnchor(Mutex<Option<(usize, usize)>>);
+
+impl LastAnchor {
+    pub(crate) fn get(&self) -> Option<(usize, usize)> {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn set(&self, b_len: usize, pos: usize) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some((b_len, pos));
+    }
+
+    pub(crate) fn clear(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+
+/// What the latest stateless call predicted, as the `(start, end)` range of `a`.
+#[derive(Debug, Default)]
+pub(crate) struct LastPrediction(Mutex<Option<(usize, usize)>>);
+
+impl LastPrediction {
+    pub(crate) fn get(&self) -> Option<(usize, usize)> {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn set(&self, start: usize, end: usize) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some((start, end));
+    }
+
+    pub(crate) fn clear(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_anchor_memo() {
+        let memo = AnchorMemo::default();
+        let b: Vec<i32> = (0..100).collect();
+        memo.store(&b[..40], 45, 40);
+        memo.store(&b[..60], 65, 60);
+        assert_eq!(memo.lookup(&b), Some((60, 65, 60)));
+        assert_eq!(memo.lookup(&b[..50]), Some((40, 45, 40)));
+        assert_eq!(memo.lookup(&[7; 100]), None);
+        for len in 0..CAPACITY {
+            memo.store(&b[..len], len, len);
+        }
+        assert_eq!(memo.lookup(&b), Some((CAPACITY - 1, CAPACITY - 1, CAPACITY - 1)));
+        memo.clear();
+        assert_eq!(memo.lookup(&b), None);
+    }
+
+    #[test]
+    fn test_latest() {
+        let memo = AnchorMemo::default();
+        let b: Vec<i32> = (0..100).collect();
+        memo.store(&b[..40], 45, 40);
+        memo.store_latest(&b[..60], 65, 60);
+        assert_eq!(memo.lookup(&b), Some((60, 65, 60)));
+        memo.store_latest(&b[..70], 75, 70);
+        assert_eq!(memo.lookup(&b), Some((70, 75, 70)));
+        // Not a continuation of the latest call
+        assert_eq!(memo.lookup(&b[..70]), Some((40, 45, 40)));
+        assert_eq!(memo.lookup(&b[..65]), Some((40, 45, 40)));
+        memo.clear();
+        assert_eq!(memo.lookup(&b), None);
+    }
+}
@@ -0,0 +1,137 @@
+use std::hash::Hash;
+use std::ops::Range;
+
+use super::changes::diff_changes;
+use super::options::DiffAlgorithm;
+
+
+/// Region where `ours` and `theirs` changed the same part of `base` differently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Conflict<T> {
+    /// The conflicting region of `base`.
+    pub base: Range<u32>,
+    /// Where the region ended up in [`Merge3::merged`], which holds the `ours` side there.
+    pub output: Range<u32>,
+    pub ours: Vec<T>,
+    pub theirs: Vec<T>,
+}
+
+/// Result of [`merge3`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Merge3<T> {
+    /// The merged sequence; conflicting regions hold the `ours` side.
+    pub merged: Vec<T>,
+    pub conflicts: Vec<Conflict<T>>,
+}
+
+impl<T> Merge3<T> {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+type Change = (Range<u32>, Range<u32>);
+
+/// Tokens `side` puts in place of `base[lo..hi]`, given its changes inside that region.
+fn side_content<'a, T>(changes: &[Change], side: &'a [T], base: &'a [T], lo: u32, hi: u32) -> &'a [T] {
+    let (Some(first), Some(last)) = (changes.first(), changes.last()) else {
+        return &base[lo as usize..hi as usize];
+    };
+    let start = first.1.start - (first.0.start - lo);
+    let end = last.1.end + (hi - last.0.end);
+    &side[start as usize..end as usize]
+}
+
+/// Three-way merge of `ours` and `theirs`, both derived from `base`.
+///
+/// Each side is diffed against `base`. Changes touching the same region of
+/// `base` are merged when only one side changed it or both made the same
+/// change, and reported as a [`Conflict`] otherwise.
+pub fn merge3<T: Eq + Hash + Copy>(base: &[T], ours: &[T], theirs: &[T], algorithm: DiffAlgorithm) -> Merge3<T> {
+    let ours_changes = diff_changes(base, ours, algorithm);
+    let theirs_changes = diff_changes(base, theirs, algorithm);
+
+    let mut merged = Vec::with_capacity(base.len().max(ours.len()));
+    let mut conflicts = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut pos = 0u32;
+    loop {
+        // Start a group at whichever change comes first in `base`
+        let (lo, mut hi) = match (ours_changes.get(i), theirs_changes.get(j)) {
+            (None, None) => break,
+            (Some(o), Some(t)) if t.0.start < o.0.start => (t.0.start, t.0.end),
+            (Some(o), _) => (o.0.start, o.0.end),
+            (None, Some(t)) => (t.0.start, t.0.end),
+        };
+        // Grow it while changes from either side touch the region
+        let (i0, j0) = (i, j);
+        loop {
+            if let Some(o) = ours_changes.get(i).filter(|o| o.0.start <= hi) {
+                hi = hi.max(o.0.end);
+                i += 1;
+            } else if let Some(t) = theirs_changes.get(j).filter(|t| t.0.start <= hi) {
+                hi = hi.max(t.0.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        merged.extend_from_slice(&base[pos as usize..lo as usize]);
+        let ours_side = side_content(&ours_changes[i0..i], ours, base, lo, hi);
+        let theirs_side = side_content(&theirs_changes[j0..j], theirs, base, lo, hi);
+        if i0 == i || ours_side == theirs_side {
+            merged.extend_from_slice(theirs_side);
+        } else if j0 == j {
+            merged.extend_from_slice(ours_side);
+        } else {
+            let start = merged.len() as u32;
+            merged.extend_from_slice(ours_side);
+            conflicts.push(Conflict {
+                base: lo..hi,
+                output: start..merged.len() as u32,
+                ours: ours_side.to_vec(),
+                theirs: theirs_side.to_vec(),
+            });
+        }
+        pos = hi;
+    }
+    merged.extend_from_slice(&base[pos as usize..]);
+
+    Merge3 { merged, conflicts }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clean_merge() {
+        let base = [1, 2, 3, 4, 5, 6, 7, 8];
+        let ours = [1, 20, 3, 4, 5, 6, 7, 8];
+        let theirs = [1, 2, 3, 4, 5, 6, 70, 8, 9];
+        let merge = merge3(&base, &ours, &theirs, DiffAlgorithm::default());
+        assert!(merge.is_clean());
+        assert_eq!(merge.merged, [1, 20, 3, 4, 5, 6, 70, 8, 9]);
+
+        // identical edits on both sides merge cleanly
+        let merge = merge3(&base, &ours, &ours, DiffAlgorithm::default());
+        assert_eq!((merge.merged.as_slice(), merge.is_clean()), (&ours[..], true));
+    }
+
+    #[test]
+    fn test_conflict() {
+        let base = [1, 2, 3, 4, 5];
+        let ours = [1, 2, 30, 4, 5];
+        let theirs = [1, 2, 31, 32, 4, 5, 6];
+        let merge = merge3(&base, &ours, &theirs, DiffAlgorithm::default());
+        assert_eq!(merge.merged, [1, 2, 30, 4, 5, 6]);
+        assert_eq!(
+            merge.conflicts,
+            vec![Conflict { base: 2..3, output: 2..3, ours: vec![30], theirs: vec![31, 32] }]
+        );
+    }
+}
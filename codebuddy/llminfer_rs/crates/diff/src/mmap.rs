@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use memmap2::Mmap;
+
+use super::nextchunk::StreamNextChunk;
+use super::options::NextChunkOptions;
+
+
+/// Error from [`MappedTokens::open`] and [`StreamNextChunk::from_file`].
+#[derive(Debug, thiserror::Error)]
+pub enum MmapError {
+    #[error("failed to map {path:?}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("{path:?} is {len} bytes, not a whole number of i32 tokens")]
+    Length { path: PathBuf, len: u64 },
+}
+
+/// A binary file of little-endian i32 tokens, memory-mapped read-only.
+///
+/// The pages are shared with the OS page cache, so instances built on the
+/// same file (or the same `Arc<MappedTokens>`) cost no extra resident memory.
+/// The file must not be modified while mapped.
+pub struct MappedTokens {
+    // Big-endian targets can't read the file in place
+    #[cfg(target_endian = "little")]
+    map: Option<Mmap>,
+    #[cfg(target_endian = "big")]
+    tokens: Vec<i32>,
+}
+
+impl MappedTokens {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MmapError> {
+        let path = path.as_ref();
+        let io_error = |source| MmapError::Io { path: path.to_owned(), source };
+        let file = File::open(path).map_err(io_error)?;
+        let len = file.metadata().map_err(io_error)?.len();
+        if len % 4 != 0 {
+            return Err(MmapError::Length { path: path.to_owned(), len });
+        }
+        // Empty files can't be mapped on every platform
+        // SAFETY: the mapping is read-only; the caller keeps the file unmodified
+        let map = (len > 0).then(|| unsafe { Mmap::map(&file) }).transpose().map_err(io_error)?;
+        #[cfg(target_endian = "little")]
+        return Ok(MappedTokens { map });
+        #[cfg(target_endian = "big")]
+        return Ok(MappedTokens {
+            tokens: map.as_deref().unwrap_or_default().chunks_exact(4).map(|b| i32::from_le_bytes(b.try_into().unwrap())).collect(),
+        });
+    }
+}
+
+impl AsRef<[i32]> for MappedTokens {
+    #[cfg(target_endian = "little")]
+    fn as_ref(&self) -> &[i32] {
+        match &self.map {
+            // SAFETY: mappings are page aligned and the length is a multiple
+            // of 4, checked in `open`; any bit pattern is a valid i32
+            Some(map) => unsafe { std::slice::from_raw_parts(map.as_ptr().cast::<i32>(), map.len() / 4) },
+            None => &[],
+        }
+    }
+
+    #[cfg(target_endian = "big")]
+    fn as_ref(&self) -> &[i32] {
+        &self.tokens
+    }
+}
+
+impl StreamNextChunk<i32> {
+    /// Predicts from the binary file of little-endian i32 tokens at `path`,
+    /// memory-mapped instead of copied into a `Vec`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, MmapError> {
+        Self::from_file_with_options(path, NextChunkOptions::default())
+    }
+
+    /// Like [`StreamNextChunk::from_file`] with non-default tunables.
+    pub fn from_file_with_options(path: impl AsRef<Path>, options: NextChunkOptions) -> Result<Self, MmapError> {
+        Ok(Self::with_shared_reference(Arc::new(MappedTokens::open(path)?), options))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_tokens(name: &str, tokens: &[i32]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("llminfer-mmap-{}-{name}", std::process::id()));
+        std::fs::write(&path, tokens.iter().flat_map(|t| t.to_le_bytes()).collect::<Vec<u8>>()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_from_file() {
+        let a: Vec<i32> = (0..1000).map(|i| i % 97 - 40).collect();
+        let path = write_tokens("tokens", &a);
+        let streamer = StreamNextChunk::from_file(&path).unwrap();
+        assert_eq!(streamer.reference(), a);
+        assert_eq!(streamer.next_chunk(&a[..300], 5), &a[300..305]);
+
+        let mapped = Arc::new(MappedTokens::open(&path).unwrap());
+        let mut shared = StreamNextChunk::with_shared_reference(mapped.clone(), NextChunkOptions::default());
+        shared.extend_reference(&[7, 8]);
+        assert_eq!(shared.reference().len(), 1002);
+        assert_eq!((*mapped).as_ref(), a);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_from_file_errors() {
+        let empty = write_tokens("empty", &[]);
+        assert!(StreamNextChunk::from_file(&empty).unwrap().reference().is_empty());
+        std::fs::write(&empty, [1, 2, 3]).unwrap();
+        assert!(matches!(StreamNextChunk::from_file(&empty), Err(MmapError::Length { len: 3, .. })));
+        std::fs::remove_file(&empty).unwrap();
+        assert!(matches!(StreamNextChunk::from_file(&empty), Err(MmapError::Io { .. })));
+    }
+}
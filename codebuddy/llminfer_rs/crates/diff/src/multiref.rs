@@ -0,0 +1,108 @@
+use std::hash::Hash;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::nextchunk::{PredictionResult, StreamNextChunk};
+use super::options::NextChunkOptions;
+
+
+/// Predicts from whichever of several candidate references best matches `b`.
+///
+/// `current_b` is diffed against every reference (in parallel with the
+/// `parallel` feature) and the prediction anchored on the longest match wins.
+pub struct MultiRefStreamNextChunk<T: Eq + Hash> {
+    streamers: Vec<StreamNextChunk<T>>,
+}
+
+/// A prediction together with the index of the reference it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MultiRefPrediction<'a, T> {
+    /// Index of the winning reference, `None` when no reference could predict.
+    pub reference: Option<usize>,
+    pub result: PredictionResult<'a, T>,
+}
+
+impl<T: Eq + Hash + Copy + Send + Sync> MultiRefStreamNextChunk<T> {
+    pub fn new(references: Vec<Vec<T>>, options: NextChunkOptions) -> Self {
+        let streamers = references
+            .into_iter()
+            .map(|a| StreamNextChunk::with_options(a, options.clone()))
+            .collect();
+        MultiRefStreamNextChunk { streamers }
+    }
+
+    /// Number of candidate references.
+    pub fn len(&self) -> usize {
+        self.streamers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streamers.is_empty()
+    }
+
+    /// The candidate reference at `index`, if any.
+    pub fn reference(&self, index: usize) -> Option<&[T]> {
+        self.streamers.get(index).map(StreamNextChunk::reference)
+    }
+
+    /// Predicts the next chunk from the best-matching reference.
+    pub fn next_chunk(&self, current_b: &[T], chunk_size: usize) -> MultiRefPrediction<'_, T> {
+        #[cfg(feature = "parallel")]
+        let results: Vec<PredictionResult<'_, T>> = self
+            .streamers
+            .par_iter()
+            .map(|streamer| streamer.next_chunk_with_info(current_b, chunk_size))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let results: Vec<PredictionResult<'_, T>> = self
+            .streamers
+            .iter()
+            .map(|streamer| streamer.next_chunk_with_info(current_b, chunk_size))
+            .collect();
+
+        // Longest anchoring match wins; earlier references win ties
+        let best = results
+            .into_iter()
+            .enumerate()
+            .filter(|(_, result)| !result.tokens.is_empty())
+            .max_by(|(i, x), (j, y)| x.match_len.cmp(&y.match_len).then(j.cmp(i)));
+
+        match best {
+            Some((index, result)) => MultiRefPrediction { reference: Some(index), result },
+            None => MultiRefPrediction { reference: None, result: PredictionResult::empty() },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_best_reference_wins() {
+        let references = vec![
+            vec![1, 2, 3, 4, 5, 6],
+            vec![9, 2, 3, 4, 7, 8],
+            vec![0, 1, 2, 3, 4, 10, 11],
+        ];
+        let multi = MultiRefStreamNextChunk::new(references, NextChunkOptions::default());
+        assert_eq!(multi.len(), 3);
+
+        // both 0 and 2 match [1, 2, 3, 4], reference 1 only [2, 3, 4]; earliest wins the tie
+        let prediction = multi.next_chunk(&[1, 2, 3, 4], 2);
+        assert_eq!(prediction.reference, Some(0));
+        assert_eq!(prediction.result.tokens, &[5, 6]);
+
+        let prediction = multi.next_chunk(&[0, 1, 2, 3, 4], 2);
+        assert_eq!(prediction.reference, Some(2));
+        assert_eq!(prediction.result.tokens, &[10, 11]);
+        assert_eq!(prediction.result.match_len, 5);
+
+        let prediction = multi.next_chunk(&[1, 2, 99], 2);
+        assert_eq!(prediction.reference, None);
+        assert!(prediction.result.tokens.is_empty());
+    }
+}
@@ -1,51 +1,850 @@
 
 use std::cmp::{min, max};
-use std::time::Instant;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::mem::size_of;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use imara_diff::{diff_with_tokens, intern::Token};
+
+use super::boundary::BoundaryEquivalence;
+use super::budget::{map_bytes, MemoryUsage};
+use super::cancel::{CancellationToken, Cancelled};
+use super::chain::HashChain;
+use super::clock::Instant;
+use super::coarse::BlockIndex;
+use super::landmarks::{LandmarkAutomaton, LandmarkCursor};
+use super::memo::{tail_hash, AnchorMemo, LastAnchor, LastPrediction};
+use super::normalize::{normalized, Normalizer};
+use super::options::{AnchorStrategy, DiffAlgorithm, FallbackPolicy, MatcherBackend, NextChunkOptions};
+use super::prefix::common_prefix_len;
+use super::reference::{Reference, SharedTokens};
+use super::rolling::RollingHashIndex;
+use super::sa::SuffixArray;
+use super::sam::{SamCursor, SuffixAutomaton};
+use super::scratch::{Scratch, ScratchBuf, ScratchPool};
+use super::sink::{MatchCollector, MatchWeights};
+use super::state::SavedState;
+use super::stats::{CallAnchor, CallStats, MatchLengthHistogram, MatchLengthRecorder, NextChunkStats, StatsRecorder};
+use super::tree::TokenTree;
+
+/// Below this many tokens of 'a', [`NextChunkOptions::autojunk`] doesn't apply.
+const AUTOJUNK_MIN_LEN: usize = 200;
+/// Below this many tokens of 'a', [`NextChunkOptions::coarse_block_len`] doesn't apply.
+const COARSE_MIN_LEN: usize = 100_000;
+/// With a [`NextChunkOptions::deadline`] and no [`NextChunkOptions::diff_segment_len`],
+/// diffs are segmented at this many tokens of 'a' so the deadline bounds each.
+const DEADLINE_SEGMENT_LEN: usize = 4096;
+
+
+
+/// Predicts the next chunk of a reference sequence `a` from the tokens
+/// generated so far.
+///
+/// The token type is generic so tokenizers emitting u32/i64 ids don't have to
+/// be cast to i32 first.
+///
+/// Two calling styles are supported:
+/// * stateless: pass the whole `current_b` to [`StreamNextChunk::next_chunk`] each step;
+/// * stateful: feed only the new tokens with [`StreamNextChunk::append`] and call
+///   [`StreamNextChunk::predict`], which keeps the anchor in `a` between calls.
+pub struct StreamNextChunk<T: Eq + Hash> {
+    a: Reference<T>,
+    window_size: usize, // Store calculated window size
+    options: NextChunkOptions,
+    backend: Backend<T>,
+    anchor_index: Option<RollingHashIndex>, // Locates the 'a' window when windowing can apply
+    /// Block hashes of `a` narrowing full diffs, see [`NextChunkOptions::coarse_block_len`].
+    coarse_index: Option<BlockIndex>,
+    /// Suffix automaton over `a` for [`StreamNextChunk::longest_match_at`]
+    /// with the diff backend and for [`StreamNextChunk::next_chunk_candidates`],
+    /// built on first use.
+    suffix_index: OnceLock<SuffixAutomaton<T>>,
+    /// An optional index was left out to fit [`NextChunkOptions::memory_budget`].
+    degraded: bool,
+    /// The required tables alone exceed [`NextChunkOptions::memory_budget`],
+    /// so diffs never run over all of `a`.
+    windowed_only: bool,
+    state: IncrementalState<T>, // Only used by the stateful append/predict API
+    stats: StatsRecorder,
+    match_lengths: MatchLengthRecorder,
+    normalizer: Option<Normalizer<T>>,
+    /// `a` in canonical form, matched instead of `a` when there is a normalizer.
+    keys: Option<Vec<T>>,
+    /// Anchors of recent stateless calls (diff backend only).
+    memo: AnchorMemo,
+    /// Where the last stateless call anchored, to place windows when the
+    /// tail of `b` can't be located in `a`.
+    last_anchor: LastAnchor,
+    /// What the last stateless call predicted, see
+    /// [`NextChunkOptions::continuation_check_len`].
+    last_prediction: LastPrediction,
+    /// Consecutive stateless windowed calls that predicted nothing, see
+    /// [`NextChunkOptions::global_reanchor_after`].
+    window_miss_streak: AtomicUsize,
+    /// Called with every prediction, see [`StreamNextChunk::set_hook`].
+    hook: Option<PredictionHook<T>>,
+    /// See [`StreamNextChunk::set_boundary_equivalence`].
+    boundary: Option<BoundaryEquivalence<T>>,
+    /// See [`StreamNextChunk::set_junk_tokens`].
+    junk: HashSet<T>,
+    /// See [`StreamNextChunk::set_landmarks`].
+    landmarks: Option<LandmarkAutomaton<T>>,
+    /// Buffers of the interned `b` and the diff matches, kept allocated
+    /// between calls.
+    scratch: Scratch,
+    /// Set by [`StreamNextChunk::from_text`] to tokenize text passed to `next_chunk_text`.
+    #[cfg(feature = "tokenizers")]
+    pub(crate) tokenizer: Option<std::sync::Arc<tokenizers::Tokenizer>>,
+}
 
-use pyo3::Bound;
-use pyo3::prelude::*;
-use pyo3::types::PyList;
+/// Callback invoked with each prediction and the [`CallStats`] of its call.
+pub type PredictionHook<T> = Arc<dyn Fn(&PredictionResult<'_, T>, &CallStats) + Send + Sync>;
 
-use imara_diff::{
-    diff,
-    intern::{InternedInput, TokenSource}, // Import TokenSource trait
-    Algorithm,
-};
+/// Predicts over raw bytes (e.g. UTF-8 text for models with byte-level
+/// vocabularies), with no cast to a wider token type.
+pub type StreamNextChunkBytes = StreamNextChunk<u8>;
+
+/// Index over `a` built at construction for the configured [`MatcherBackend`].
+enum Backend<T: Eq + Hash> {
+    Diff(InternedReference<T>),
+    SuffixAutomaton(SuffixAutomaton<T>),
+    SuffixArray(SuffixArray<T>),
+    HashChain(HashChain),
+}
 
-use super::source::I32Slice;
-use super::sink::MatchCollector;
+/// `a` interned once at construction, so each diff only interns `b`.
+/// Windows diff sub-slices of `tokens`.
+struct InternedReference<T: Eq + Hash> {
+    tokens: Vec<Token>,
+    ids: HashMap<T, Token>,
+    /// Occurrences in `a` of each id, to tell junk tokens.
+    counts: Vec<u32>,
+}
 
+impl<T: Eq + Hash + Copy> InternedReference<T> {
+    fn new(a: &[T]) -> Self {
+        let mut interned = InternedReference { tokens: Vec::new(), ids: HashMap::new(), counts: Vec::new() };
+        interned.rebuild(a);
+        interned
+    }
 
+    /// Interns tokens appended to `a`; returns whether any of them were new.
+    fn extend(&mut self, tokens: &[T]) -> bool {
+        let known = self.ids.len();
+        let ids = &mut self.ids;
+        self.tokens.extend(tokens.iter().map(|&t| {
+            let next = Token(ids.len() as u32);
+            *ids.entry(t).or_insert(next)
+        }));
+        self.count(self.tokens.len() - tokens.len());
+        self.ids.len() > known
+    }
 
-#[pyclass(module = "stream_chunk_py")]
-pub struct StreamNextChunk {
-    a: Vec<i32>,
-    window_size: usize, // Store calculated window size
-    min_window_threshold: usize, // Minimum window size to activate windowing
-    a_window_factor: usize, // How much larger the 'a' window should be (e.g., 3x)
+    /// Counts the occurrences of `tokens[from..]`.
+    fn count(&mut self, from: usize) {
+        self.counts.resize(self.ids.len(), 0);
+        for token in &self.tokens[from..] {
+            self.counts[token.0 as usize] += 1;
+        }
+    }
+
+    /// Re-interns a new `a`, reusing the allocations.
+    fn rebuild(&mut self, a: &[T]) {
+        self.tokens.clear();
+        self.ids.clear();
+        self.counts.clear();
+        let ids = &mut self.ids;
+        self.tokens.extend(a.iter().map(|&t| {
+            let next = Token(ids.len() as u32);
+            *ids.entry(t).or_insert(next)
+        }));
+        self.count(0);
+    }
+
+    /// Id of a `b` token. Tokens absent from `a` can't match anything, so
+    /// they all share one extra id instead of growing the table per call.
+    fn id(&self, token: &T) -> Token {
+        self.ids.get(token).copied().unwrap_or(Token(self.ids.len() as u32))
+    }
+
+    fn intern(&self, b: &[T]) -> Vec<Token> {
+        b.iter().map(|t| self.id(t)).collect()
+    }
+
+    /// Like [`InternedReference::intern`], into a scratch buffer.
+    fn intern_into<'s>(&self, b: &[T], mut out: ScratchBuf<'s, Token>) -> ScratchBuf<'s, Token> {
+        out.extend(b.iter().map(|t| self.id(t)));
+        out
+    }
+
+    fn num_tokens(&self) -> u32 {
+        self.ids.len() as u32 + 1
+    }
+
+    /// Approximate heap bytes of the tables.
+    fn heap_bytes(&self) -> usize {
+        (self.tokens.len() + self.counts.len()) * 4 + map_bytes::<(T, Token)>(self.ids.len())
+    }
+}
+
+/// What the diffs of a call share besides the token slices.
+#[derive(Clone, Copy)]
+struct DiffContext<'a> {
+    algorithm: DiffAlgorithm,
+    num_tokens: u32,
+    max_mismatches: usize,
+    /// Occurrences in `a` of each id and the count above which a token is
+    /// junk, when [`NextChunkOptions::autojunk`] applies.
+    junk: Option<(&'a [u32], u32)>,
+    /// Occurrences in `a` of each id and the length of `a`, to weigh rare
+    /// matches for [`AnchorStrategy::RecencyWeighted`].
+    counts: (&'a [u32], usize),
+    lengths: &'a MatchLengthRecorder,
+    /// Buffers to collect the matching blocks into.
+    matches: &'a ScratchPool<(Range<u32>, Range<u32>)>,
+    /// When to stop diffing further segments, and the previous anchor.
+    scope: CallScope<'a>,
+    /// Which matching block the prediction is anchored on.
+    strategy: AnchorStrategy,
+    weights: MatchWeights,
+}
+
+impl DiffContext<'_> {
+    fn is_junk(&self, token: Token) -> bool {
+        self.junk.is_some_and(|(counts, limit)| counts.get(token.0 as usize).is_some_and(|&count| count > limit))
+    }
+}
+
+/// What the diffs of one call share: the last confirmed `(b_len, pos)`
+/// alignment, and when the call stops starting diffs (past the deadline of
+/// [`NextChunkOptions::deadline`] or once its [`CancellationToken`] is cancelled).
+#[derive(Clone, Copy, Default)]
+struct CallScope<'a> {
+    last_anchor: Option<(usize, usize)>,
+    /// The caller's own last confirmed alignment, placing the windows the
+    /// rolling hash can't: from its cursor, or of the stateful stream. Other
+    /// callers' calls on the instance would misplace them.
+    window_anchor: Option<(usize, usize)>,
+    deadline: Option<Instant>,
+    cancel: Option<&'a CancellationToken>,
+    /// Where the caller's earlier call left `b`, see [`StreamNextChunk::next_chunk_from`].
+    cursor: Option<&'a PredictionCursor>,
+}
+
+impl CallScope<'_> {
+    /// Whether the call should stop diffing. Marks it as timed out once the
+    /// deadline has passed.
+    fn stopped(&self, call: &mut CallStats) -> bool {
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            call.timed_out = true;
+        }
+        call.timed_out || self.cancel.is_some_and(CancellationToken::is_cancelled)
+    }
+}
+
+/// State kept between calls by the stateful `append`/`predict` API.
+struct IncrementalState<T: Eq + Hash> {
+    /// All tokens appended so far.
+    b: Vec<T>,
+    /// `b` in canonical form, only kept when there is a normalizer.
+    b_keys: Vec<T>,
+    /// `b` interned against `a` (diff backend only).
+    b_tokens: Vec<Token>,
+    /// Offset in `a` aligned with the end of `b`, i.e. where the continuation starts.
+    /// `None` when the last appended tokens diverged from `a` and a re-diff is needed.
+    anchor: Option<usize>,
+    /// Length of the match in `a` ending at `anchor`.
+    match_len: usize,
+    /// Last confirmed `(b_len, pos)` alignment, kept while `anchor` is stale
+    /// to place the re-diff window.
+    confirmed: Option<(usize, usize)>,
+    /// Suffix automaton position after all of `b` (suffix automaton backend only).
+    sam_cursor: SamCursor,
+    /// Landmark automaton position after all of `b`, see [`StreamNextChunk::set_landmarks`].
+    landmark_cursor: LandmarkCursor,
+    /// Consecutive windowed `predict` calls that predicted nothing (atomic
+    /// only to be updated while the prediction borrows the streamer).
+    window_miss_streak: AtomicUsize,
+}
+
+impl<T: Eq + Hash> IncrementalState<T> {
+    fn new() -> Self {
+        IncrementalState {
+            b: Vec::new(),
+            b_keys: Vec::new(),
+            b_tokens: Vec::new(),
+            // An empty `b` is aligned with the start of `a`
+            anchor: Some(0),
+            match_len: 0,
+            confirmed: None,
+            sam_cursor: SamCursor::default(),
+            landmark_cursor: LandmarkCursor::default(),
+            window_miss_streak: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Which part of `a` `b` windowing restricted the diff to.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    applied: bool,
+    a_start: usize,
+    a_end: usize,
+    b_start: usize,
+}
+
+impl Window {
+    /// All of `a` and `b`, unwindowed.
+    fn full(a_len: usize) -> Self {
+        Window { applied: false, a_start: 0, a_end: a_len, b_start: 0 }
+    }
+}
+
+/// Outcome of matching `b` against `a`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Anchor {
+    /// The end of `b` is aligned with offset `pos` in `a`, via a match of
+    /// `match_len` tokens; predict from here.
+    At { pos: usize, match_len: usize },
+    /// Nothing usable to anchor on; predict the start of `a`.
+    StartOfA,
+    /// The full diff found no match at all.
+    NoMatch,
+    /// Cannot confidently predict.
+    Miss,
+    /// No anchor; predict from `pos` as the fallback policy says.
+    Fallback { pos: usize },
+}
+
+impl From<Anchor> for CallAnchor {
+    fn from(anchor: Anchor) -> Self {
+        match anchor {
+            Anchor::At { pos, match_len } => CallAnchor::At { pos, match_len },
+            Anchor::StartOfA => CallAnchor::StartOfA,
+            Anchor::NoMatch => CallAnchor::NoMatch,
+            // Fallbacks are only resolved after matching
+            Anchor::Miss | Anchor::Fallback { .. } => CallAnchor::Miss,
+        }
+    }
+}
+
+/// A predicted chunk together with where in `a` it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PredictionResult<'a, T> {
+    /// The predicted tokens, borrowed from `a`.
+    pub tokens: &'a [T],
+    /// Offset in `a` of the first predicted token, `None` when no anchor was found.
+    pub start: Option<usize>,
+    /// Length of the match in `a` the prediction continues from (0 when
+    /// predicting the start of `a` without a match).
+    pub match_len: usize,
+    /// Whether the diff was restricted to a window of `a`/`b`.
+    pub windowed: bool,
+}
+
+impl<T> PredictionResult<'_, T> {
+    pub(crate) fn empty() -> Self {
+        PredictionResult { tokens: &[], start: None, match_len: 0, windowed: false }
+    }
+}
+
+impl<T: Clone> PredictionResult<'_, T> {
+    /// Copies the predicted tokens out of `a`, e.g. to log or replay the result.
+    pub fn into_owned(self) -> OwnedPredictionResult<T> {
+        OwnedPredictionResult {
+            tokens: self.tokens.to_vec(),
+            start: self.start,
+            match_len: self.match_len,
+            windowed: self.windowed,
+        }
+    }
+}
+
+/// [`PredictionResult`] owning its tokens, so it can outlive the streamer
+/// and be deserialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedPredictionResult<T> {
+    pub tokens: Vec<T>,
+    pub start: Option<usize>,
+    pub match_len: usize,
+    pub windowed: bool,
+}
+
+/// Where a [`StreamNextChunk::next_chunk_from`] call left its `b` in `a`,
+/// handed back to the next call to resume from there.
+///
+/// Unlike the anchors the streamer remembers itself, cursors belong to the
+/// caller: branchy callers (beam search, retries) keep one per branch over
+/// the same shared streamer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PredictionCursor {
+    /// Length of the `b` of the call.
+    pub b_len: usize,
+    /// Hash of the tail of that `b`, so a cursor of another branch with a
+    /// `b` as long isn't followed.
+    tail: u64,
+    /// Offset in `a` aligned with the end of that `b`, `None` when it
+    /// couldn't be anchored.
+    pub pos: Option<usize>,
+    /// Length of the match in `a` ending at `pos`.
+    pub match_len: usize,
+    /// Calls along this branch, this one included.
+    pub calls: u64,
+    /// Of those, calls that had to diff.
+    pub diff_calls: u64,
+    /// Tokens predicted along this branch.
+    pub predicted_tokens: u64,
 }
 
-impl StreamNextChunk {
+impl<T: Eq + Hash + Copy> StreamNextChunk<T> {
     /// Creates a new StreamNextChunk instance.
     ///
     /// # Arguments
     ///
     /// * `a` - The reference sequence (like the original file content).
-    pub fn new(a_slice: &[i32]) -> Self {
-        // Calculate window size based on 'a' length (similar to python)
-        // Avoid division by zero for empty 'a'
-        let a = a_slice.to_vec();
-        let window_size = if a.is_empty() { 0 } else { max(1, a.len() / 15) };
-        // Use reasonable defaults or make them configurable
-        let min_window_threshold = 100;
-        let a_window_factor = 3;
-
-        StreamNextChunk {
+    pub fn new(a_slice: &[T]) -> Self {
+        Self::from_vec(a_slice.to_vec())
+    }
+
+    /// Creates a new StreamNextChunk instance taking ownership of `a`.
+    pub fn from_vec(a: Vec<T>) -> Self {
+        Self::with_options(a, NextChunkOptions::default())
+    }
+
+    /// Creates a new StreamNextChunk instance with non-default tunables.
+    pub fn with_options(a: Vec<T>, options: NextChunkOptions) -> Self {
+        Self::build(Reference::Owned(a), options, None)
+    }
+
+    /// Creates a new StreamNextChunk instance matching tokens in the canonical
+    /// form given by `normalizer`, while still predicting the original tokens of `a`.
+    pub fn with_normalizer(a: Vec<T>, options: NextChunkOptions, normalizer: Normalizer<T>) -> Self {
+        Self::build(Reference::Owned(a), options, Some(normalizer))
+    }
+
+    /// Creates a new StreamNextChunk instance reading `a` from shared storage
+    /// in place, e.g. a [`MappedTokens`](crate::MappedTokens) file used by
+    /// many instances. The reference is only copied if it's later modified.
+    pub fn with_shared_reference(a: SharedTokens<T>, options: NextChunkOptions) -> Self {
+        Self::build(Reference::Shared(a), options, None)
+    }
+
+    /// Creates a new StreamNextChunk instance predicting from `a` without
+    /// copying it, so sessions over the same reference can share one buffer.
+    /// The reference is only copied if it's later modified.
+    pub fn with_shared_slice(a: Arc<[T]>, options: NextChunkOptions) -> Self {
+        Self::build(Reference::Slice(a), options, None)
+    }
+
+    fn build(a: Reference<T>, options: NextChunkOptions, normalizer: Option<Normalizer<T>>) -> Self {
+        let window_size = window_size(a.len());
+        let keys = normalizer.as_ref().map(|normalizer| normalizer.normalize_all(&a));
+        let a_keys = keys.as_deref().unwrap_or(&a);
+        let backend = match options.matcher {
+            MatcherBackend::Diff => Backend::Diff(InternedReference::new(a_keys)),
+            MatcherBackend::SuffixAutomaton => Backend::SuffixAutomaton(SuffixAutomaton::new(a_keys)),
+            MatcherBackend::SuffixArray => Backend::SuffixArray(SuffixArray::new(a_keys)),
+            MatcherBackend::HashChain => Backend::HashChain(HashChain::new(a_keys)),
+        };
+
+        let mut streamer = StreamNextChunk {
             a,
             window_size,
-            min_window_threshold,
-            a_window_factor,
+            options,
+            backend,
+            anchor_index: None,
+            coarse_index: None,
+            suffix_index: OnceLock::new(),
+            degraded: false,
+            windowed_only: false,
+            state: IncrementalState::new(),
+            stats: StatsRecorder::default(),
+            match_lengths: MatchLengthRecorder::default(),
+            normalizer,
+            keys,
+            memo: AnchorMemo::default(),
+            last_anchor: LastAnchor::default(),
+            last_prediction: LastPrediction::default(),
+            window_miss_streak: AtomicUsize::new(0),
+            hook: None,
+            boundary: None,
+            junk: HashSet::new(),
+            landmarks: None,
+            scratch: Scratch::default(),
+            #[cfg(feature = "tokenizers")]
+            tokenizer: None,
+        };
+        streamer.fit_indexes();
+        streamer
+    }
+
+    /// The reference sequence this instance predicts from.
+    pub fn reference(&self) -> &[T] {
+        &self.a
+    }
+
+    /// Replaces the reference with `new_a`, e.g. for the next request on a
+    /// hot path, keeping the options, normalizer, statistics and allocations.
+    ///
+    /// The window size and indexes are recomputed for `new_a` and the stateful
+    /// stream starts over as with [`StreamNextChunk::reset`].
+    pub fn set_reference(&mut self, new_a: &[T]) {
+        self.a.assign(new_a);
+        self.reindex();
+    }
+
+    /// Like [`StreamNextChunk::set_reference`], but takes ownership of `new_a`
+    /// and hands back the previous reference, whose buffer is left untouched
+    /// (or copied out of shared storage).
+    pub fn replace_reference(&mut self, new_a: Vec<T>) -> Vec<T> {
+        let old = std::mem::replace(&mut self.a, Reference::Owned(new_a));
+        self.reindex();
+        old.into_vec()
+    }
+
+    /// Moves an owned reference behind an `Arc`, so that
+    /// [`StreamNextChunk::shared_reference`] can hand it out. Modifying the
+    /// reference afterwards copies it, as with [`StreamNextChunk::with_shared_slice`].
+    pub fn share_reference(&mut self) {
+        if let Reference::Owned(tokens) = &mut self.a {
+            self.a = Reference::Slice(Arc::from(std::mem::take(tokens)));
+        }
+    }
+
+    /// A handle on the reference's tokens that keeps them alive and unchanged
+    /// whatever happens to this instance, e.g. for zero-copy views outliving
+    /// it; `None` when the reference is owned, see [`StreamNextChunk::share_reference`].
+    pub fn shared_reference(&self) -> Option<SharedTokens<T>>
+    where
+        T: Send + Sync + 'static,
+    {
+        match &self.a {
+            Reference::Owned(_) => None,
+            Reference::Slice(tokens) => Some(Arc::new(tokens.clone())),
+            Reference::Shared(tokens) => Some(tokens.clone()),
+        }
+    }
+
+    /// Appends `tokens` to the reference, e.g. as earlier parts of a document
+    /// are regenerated, updating the interning, suffix automaton and anchor
+    /// index incrementally instead of rebuilding them. The
+    /// [`MatcherBackend::SuffixArray`] index is the exception: it's rebuilt
+    /// over all of `a`, in O(n log² n) per call.
+    ///
+    /// Positions in `a` don't move, so the stateful stream carries on.
+    pub fn extend_reference(&mut self, tokens: &[T]) {
+        let old_len = self.a.len();
+        self.a.to_mut().extend_from_slice(tokens);
+        if let (Some(normalizer), Some(keys)) = (&self.normalizer, &mut self.keys) {
+            keys.extend(tokens.iter().map(|&t| normalizer.normalize(t)));
+        }
+        self.window_size = window_size(self.a.len());
+        let a_keys = self.keys.as_deref().unwrap_or(&self.a);
+        let b_keys = if self.normalizer.is_some() { &self.state.b_keys } else { &self.state.b };
+        match &mut self.backend {
+            Backend::Diff(interned) => {
+                // `b` tokens absent from the old `a` all had the id new tokens may take now
+                if interned.extend(&a_keys[old_len..]) {
+                    self.state.b_tokens = interned.intern(b_keys);
+                }
+            }
+            Backend::SuffixAutomaton(sam) => {
+                sam.extend(&a_keys[old_len..]);
+                self.state.sam_cursor = sam.match_suffix(b_keys);
+            }
+            // Appending to `a` prepends to the reversed text the array sorts
+            Backend::SuffixArray(sa) => *sa = SuffixArray::new(a_keys),
+            Backend::HashChain(chain) => chain.extend(a_keys),
+        }
+        if let Some(sam) = self.suffix_index.get_mut() {
+            sam.extend(&a_keys[old_len..]);
+        }
+        if let Some(index) = &mut self.anchor_index {
+            index.extend(a_keys, old_len);
+        }
+        if let (Some(index), Backend::Diff(interned)) = (&mut self.coarse_index, &self.backend) {
+            index.extend(&interned.tokens);
+        }
+        // `a` may just have grown long enough for windowing or the block
+        // index, or out of the memory budget
+        self.fit_indexes();
+        // A longer match may exist in the new tokens
+        self.memo.clear();
+    }
+
+    /// What [`StreamNextChunk::save`] persists.
+    pub(crate) fn saved_state(&self) -> SavedState<T> {
+        SavedState {
+            options: self.options.clone(),
+            a: self.a.to_vec(),
+            b: self.state.b.clone(),
+            anchor: self.state.anchor,
+            match_len: self.state.match_len,
+            confirmed: self.state.confirmed,
+            last_anchor: self.last_anchor.get(),
+            stats: self.stats.snapshot(),
+        }
+    }
+
+    /// Rebuilds an instance from [`StreamNextChunk::saved_state`].
+    pub(crate) fn restore(saved: SavedState<T>, normalizer: Option<Normalizer<T>>) -> Self {
+        let mut streamer = Self::build(Reference::Owned(saved.a), saved.options, normalizer);
+        // Replaying the appends rebuilds the interned / automaton state of `b`
+        streamer.append(&saved.b);
+        streamer.state.anchor = saved.anchor;
+        streamer.state.match_len = saved.match_len;
+        streamer.state.confirmed = saved.confirmed;
+        if let Some((b_len, pos)) = saved.last_anchor {
+            streamer.last_anchor.set(b_len, pos);
         }
+        streamer.stats.restore(NextChunkStats { last: None, ..saved.stats });
+        streamer
+    }
+
+    /// Rebuilds everything derived from `a` after it was replaced.
+    fn reindex(&mut self) {
+        if let (Some(normalizer), Some(keys)) = (&self.normalizer, &mut self.keys) {
+            keys.clear();
+            keys.extend(self.a.iter().map(|&t| normalizer.normalize(t)));
+        }
+        self.window_size = window_size(self.a.len());
+        let a_keys = self.keys.as_deref().unwrap_or(&self.a);
+        match &mut self.backend {
+            Backend::Diff(interned) => interned.rebuild(a_keys),
+            Backend::SuffixAutomaton(sam) => *sam = SuffixAutomaton::new(a_keys),
+            Backend::SuffixArray(sa) => *sa = SuffixArray::new(a_keys),
+            Backend::HashChain(chain) => *chain = HashChain::new(a_keys),
+        }
+        (self.anchor_index, self.coarse_index, self.suffix_index) = (None, None, OnceLock::new());
+        // Ranges of the old `a`
+        self.landmarks = None;
+        self.fit_indexes();
+        self.memo.clear();
+        self.last_anchor.clear();
+        self.last_prediction.clear();
+        self.window_miss_streak.store(0, Ordering::Relaxed);
+        self.reset();
+    }
+
+    /// Builds the optional indexes over `a` that apply and fit the memory
+    /// budget, and drops the built ones that no longer fit, see
+    /// [`NextChunkOptions::memory_budget`].
+    fn fit_indexes(&mut self) {
+        let budget = self.options.memory_budget.unwrap_or(usize::MAX);
+        let required = self.required_bytes();
+        self.windowed_only = required > budget;
+        let (mut room, mut degraded) = (budget.saturating_sub(required), false);
+        // Takes `bytes` out of the room left, or flags the index left out
+        let mut fits = |bytes: usize| {
+            let fits = bytes <= room;
+            if fits {
+                room -= bytes;
+            }
+            degraded |= !fits;
+            fits
+        };
+        let a_keys = self.keys.as_deref().unwrap_or(&self.a);
+        // Windows placed by length alone drift, so the rolling hash index goes first
+        self.anchor_index = match (self.anchor_index.take(), anchor_hash_len(&self.backend, self.window_size, &self.options)) {
+            (Some(index), Some(_)) => fits(index.heap_bytes()).then_some(index),
+            (None, Some(k)) => fits(RollingHashIndex::estimate_bytes(a_keys.len())).then(|| RollingHashIndex::new(a_keys, k)),
+            (_, None) => None,
+        };
+        self.coarse_index = match (self.coarse_index.take(), &self.backend, coarse_block_len(&self.backend, &self.options)) {
+            (Some(index), _, Some(_)) => fits(index.heap_bytes()).then_some(index),
+            (None, Backend::Diff(interned), Some(block_len)) => fits(BlockIndex::estimate_bytes(interned.tokens.len(), block_len))
+                .then(|| BlockIndex::new(&interned.tokens, block_len)),
+            _ => None,
+        };
+        if self.suffix_index.get().is_some_and(|sam| !fits(sam.heap_bytes())) {
+            self.suffix_index = OnceLock::new();
+        }
+        self.degraded = degraded;
+    }
+
+    /// Bytes of the tables matching can't do without.
+    fn required_bytes(&self) -> usize {
+        let keys = self.keys.as_ref().map_or(0, |keys| keys.len() * size_of::<T>());
+        let landmarks = self.landmarks.as_ref().map_or(0, |landmarks| landmarks.heap_bytes());
+        keys + landmarks + match &self.backend {
+            Backend::Diff(interned) => interned.heap_bytes(),
+            Backend::SuffixAutomaton(sam) => sam.heap_bytes(),
+            Backend::SuffixArray(sa) => sa.heap_bytes(),
+            Backend::HashChain(chain) => chain.heap_bytes(),
+        }
+    }
+
+    /// Memory held by the interning tables and indexes over `a`, against
+    /// [`NextChunkOptions::memory_budget`].
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let required = self.required_bytes();
+        let optional = self.anchor_index.as_ref().map_or(0, |index| index.heap_bytes())
+            + self.coarse_index.as_ref().map_or(0, |index| index.heap_bytes())
+            + self.suffix_index.get().map_or(0, |sam| sam.heap_bytes());
+        MemoryUsage {
+            budget: self.options.memory_budget,
+            used: required + optional,
+            required,
+            degraded: self.degraded,
+            windowed_only: self.windowed_only,
+        }
+    }
+
+    /// The tunables this instance was created with.
+    pub fn options(&self) -> &NextChunkOptions {
+        &self.options
+    }
+
+    /// The normalizer tokens are matched through, if any.
+    pub fn normalizer(&self) -> Option<&Normalizer<T>> {
+        self.normalizer.as_ref()
+    }
+
+    /// `a` as matched: in canonical form when there is a normalizer.
+    fn a_keys(&self) -> &[T] {
+        self.keys.as_deref().unwrap_or(&self.a)
+    }
+
+    /// How the diffs of a call against `interned` are run.
+    fn diff_context<'a>(
+        &'a self,
+        interned: &'a InternedReference<T>,
+        algorithm: DiffAlgorithm,
+        scope: CallScope<'a>,
+    ) -> DiffContext<'a> {
+        let junk = self
+            .options
+            .autojunk
+            .filter(|_| self.a.len() >= AUTOJUNK_MIN_LEN)
+            .map(|fraction| (interned.counts.as_slice(), (fraction * self.a.len() as f64) as u32));
+        DiffContext {
+            algorithm,
+            num_tokens: interned.num_tokens(),
+            max_mismatches: self.options.max_mismatches,
+            junk,
+            counts: (interned.counts.as_slice(), interned.tokens.len()),
+            lengths: &self.match_lengths,
+            matches: &self.scratch.matches,
+            scope,
+            strategy: self.options.anchor_strategy,
+            weights: self.options.anchor_weights,
+        }
+    }
+
+    /// The appended `b` as matched.
+    fn appended_keys(&self) -> &[T] {
+        match self.normalizer {
+            Some(_) => &self.state.b_keys,
+            None => &self.state.b,
+        }
+    }
+
+    /// Calls `hook` after every prediction (stateless or stateful) with its
+    /// result and [`CallStats`], e.g. to feed logs or metrics from the hot path.
+    /// Runs on the predicting thread, so it should be cheap. `None` removes it.
+    pub fn set_hook(&mut self, hook: Option<PredictionHook<T>>) {
+        self.hook = hook;
+    }
+
+    /// Changes what subsequent calls predict when `b` can't be anchored.
+    pub fn set_fallback(&mut self, fallback: Option<FallbackPolicy>) {
+        self.options.fallback = fallback;
+    }
+
+    /// Lets a last token of `b` that keeps it from anchoring match its
+    /// equivalent in `a`, e.g. a token the tokenizer merged across the chunk
+    /// boundary; the prediction then skips that token of `a`. `None` removes it.
+    /// Not persisted by [`StreamNextChunk::save`].
+    pub fn set_boundary_equivalence(&mut self, boundary: Option<BoundaryEquivalence<T>>) {
+        self.boundary = boundary;
+    }
+
+    /// The boundary equivalence table, if any.
+    pub fn boundary_equivalence(&self) -> Option<&BoundaryEquivalence<T>> {
+        self.boundary.as_ref()
+    }
+
+    /// Tokens of `a` never to anchor on or predict across, e.g. EOS, padding
+    /// or other special tokens: a match of only junk tokens doesn't anchor
+    /// (and falls back as a miss would), and predictions stop before the
+    /// next junk token of `a`. Empty by default. Not persisted by
+    /// [`StreamNextChunk::save`].
+    pub fn set_junk_tokens(&mut self, tokens: impl IntoIterator<Item = T>) {
+        self.junk = tokens.into_iter().collect();
+    }
+
+    /// The tokens set by [`StreamNextChunk::set_junk_tokens`].
+    pub fn junk_tokens(&self) -> &HashSet<T> {
+        &self.junk
+    }
+
+    /// Tracks distinctive runs of `a` (e.g. function signatures), given as
+    /// ranges: once the stateful stream diverged, reaching the end of one
+    /// re-anchors [`StreamNextChunk::append`] right after it, without
+    /// re-diffing. An Aho–Corasick automaton scans the appended tokens
+    /// online. No ranges remove it. Kept by
+    /// [`StreamNextChunk::extend_reference`], dropped when the reference is
+    /// replaced, and not persisted by [`StreamNextChunk::save`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a range is out of bounds of `a`.
+    pub fn set_landmarks(&mut self, landmarks: impl IntoIterator<Item = Range<usize>>) {
+        let a_keys = self.keys.as_deref().unwrap_or(&self.a);
+        let automaton = LandmarkAutomaton::new(a_keys, landmarks);
+        let b_keys = if self.normalizer.is_some() { &self.state.b_keys } else { &self.state.b };
+        self.state.landmark_cursor = b_keys.iter().fold(LandmarkCursor::default(), |cursor, &t| automaton.step(cursor, t));
+        self.landmarks = (!automaton.landmarks().is_empty()).then_some(automaton);
+    }
+
+    /// The ranges of `a` set by [`StreamNextChunk::set_landmarks`].
+    pub fn landmarks(&self) -> &[Range<usize>] {
+        self.landmarks.as_ref().map(|landmarks| landmarks.landmarks()).unwrap_or_default()
+    }
+
+    /// Changes the diff algorithm used by subsequent calls.
+    pub fn set_algorithm(&mut self, algorithm: DiffAlgorithm) {
+        self.options.algorithm = algorithm;
+        self.memo.clear();
+    }
+
+    /// Timings and windowing decisions of the prediction calls so far.
+    pub fn stats(&self) -> NextChunkStats {
+        self.stats.snapshot()
+    }
+
+    /// Diagnostics of the most recent prediction call: whether it was
+    /// windowed, the bounds of the diffed windows and the anchor matching
+    /// ended on, e.g. to tell why a prediction came back empty.
+    pub fn last_call_info(&self) -> Option<CallStats> {
+        self.stats.last()
+    }
+
+    /// Lengths of the matching blocks found by the most recent diff.
+    pub fn last_match_lengths(&self) -> MatchLengthHistogram {
+        self.match_lengths.last()
+    }
+
+    /// Lengths of the matching blocks found by all diffs since the last
+    /// [`StreamNextChunk::reset_stats`].
+    pub fn match_lengths(&self) -> MatchLengthHistogram {
+        self.match_lengths.session()
+    }
+
+    /// Clears the statistics returned by [`StreamNextChunk::stats`] and
+    /// [`StreamNextChunk::match_lengths`].
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+        self.match_lengths.reset();
     }
 
 
@@ -60,177 +859,1083 @@ impl StreamNextChunk {
     /// # Returns
     ///
     /// A slice referencing the predicted next chunk within the original `a`.
-    pub fn next_chunk(&self, current_b: &[i32], chunk_size: usize) -> &[i32] {
-        let result = self._next_chunk(current_b, chunk_size);
-        result
+    pub fn next_chunk(&self, current_b: &[T], chunk_size: usize) -> &[T] {
+        self._next_chunk(current_b, chunk_size, self.options.algorithm)
+    }
+
+    /// Same as [`StreamNextChunk::next_chunk`] but diffs with `algorithm`
+    /// instead of the configured one, e.g. to benchmark Myers on short windows.
+    pub fn next_chunk_with_algorithm(&self, current_b: &[T], chunk_size: usize, algorithm: DiffAlgorithm) -> &[T] {
+        self._next_chunk(current_b, chunk_size, algorithm)
+    }
+
+    /// Same as [`StreamNextChunk::next_chunk`] with `out.len()` as the chunk
+    /// size, but copies the prediction into `out`, e.g. a draft buffer reused
+    /// every decoding step. Returns the number of tokens written.
+    pub fn next_chunk_into(&self, current_b: &[T], out: &mut [T]) -> usize {
+        let chunk = self.next_chunk(current_b, out.len());
+        out[..chunk.len()].copy_from_slice(chunk);
+        chunk.len()
+    }
+
+    /// Nested predictions of each of `depths` tokens (e.g. 8, 32 and 128)
+    /// from the same anchor, in the order of `depths`, so the caller can pick
+    /// how deep to speculate without matching `current_b` again. Each is a
+    /// prefix of the deepest one.
+    pub fn next_chunk_depths(&self, current_b: &[T], depths: &[usize]) -> Vec<&[T]> {
+        let Some(&max_depth) = depths.iter().max() else {
+            return Vec::new();
+        };
+        let deepest = self.next_chunk(current_b, max_depth);
+        depths.iter().map(|&depth| &deepest[..min(depth, deepest.len())]).collect()
+    }
+
+    /// Same as [`StreamNextChunk::next_chunk`], but gives up with [`Cancelled`]
+    /// once `cancel` is cancelled, before or during the call.
+    pub fn next_chunk_cancellable(
+        &self,
+        current_b: &[T],
+        chunk_size: usize,
+        cancel: &CancellationToken,
+    ) -> Result<&[T], Cancelled> {
+        if cancel.is_cancelled() {
+            return Err(Cancelled);
+        }
+        let (result, _) = self.predict_from(current_b, chunk_size, self.options.algorithm, Some(cancel), None);
+        if cancel.is_cancelled() {
+            return Err(Cancelled);
+        }
+        Ok(result.tokens)
     }
 
-    fn _next_chunk(&self, current_b: &[i32], chunk_size: usize) -> &[i32] {
+    /// Same as [`StreamNextChunk::next_chunk`], but also reports where in `a`
+    /// the prediction came from.
+    pub fn next_chunk_with_info(&self, current_b: &[T], chunk_size: usize) -> PredictionResult<'_, T> {
+        self.predict_from(current_b, chunk_size, self.options.algorithm, None, None).0
+    }
+
+    /// Same as [`StreamNextChunk::next_chunk_with_info`], resuming from
+    /// `cursor`, returned by an earlier call on a prefix of `current_b`: only
+    /// the tokens appended since are checked against `a` where it left off,
+    /// and `current_b` is matched as usual when they don't follow it.
+    /// Also returns the cursor to pass to the next call of the same branch.
+    pub fn next_chunk_from(
+        &self,
+        current_b: &[T],
+        chunk_size: usize,
+        cursor: Option<&PredictionCursor>,
+    ) -> (PredictionResult<'_, T>, PredictionCursor) {
+        let (result, call) = self.predict_from(current_b, chunk_size, self.options.algorithm, None, cursor);
+        let previous = cursor.copied().unwrap_or_default();
+        let pos = match call.anchor {
+            CallAnchor::At { .. } | CallAnchor::StartOfA => result.start,
+            _ => None,
+        };
+        let next = PredictionCursor {
+            b_len: current_b.len(),
+            tail: tail_hash(current_b),
+            pos,
+            match_len: result.match_len,
+            calls: previous.calls + 1,
+            diff_calls: previous.diff_calls + u64::from(!call.fast_path),
+            predicted_tokens: previous.predicted_tokens + result.tokens.len() as u64,
+        };
+        (result, next)
+    }
+
+    /// Up to `k` distinct continuations of `current_b`, for tree-based
+    /// speculative decoding where several candidates are verified at once.
+    ///
+    /// Every position of `a` the tail of `current_b` matches is a candidate;
+    /// they're ranked by match length, longest first, ties going to the
+    /// earlier position. Candidates predicting the same tokens as a better
+    /// ranked one are dropped, as are matches shorter than `min_match_len`
+    /// or of only junk tokens. The positions come from a suffix automaton
+    /// over `a`, built on first use with the diff, suffix array and hash
+    /// chain backends; none are returned when it wouldn't fit the memory budget.
+    pub fn next_chunk_candidates(&self, current_b: &[T], chunk_size: usize, k: usize) -> Vec<PredictionResult<'_, T>> {
+        if self.a.is_empty() || chunk_size == 0 || k == 0 {
+            return Vec::new();
+        }
+        if current_b.is_empty() {
+            return vec![self.result(Anchor::StartOfA, false, chunk_size)];
+        }
+        let Some(sam) = self.suffix_automaton() else { return Vec::new() };
+        let b = normalized(self.normalizer.as_ref(), current_b);
+        let mut anchors = sam.occurrences(sam.match_suffix(&b), max(1, self.options.min_match_len));
+        // Matches ending at the end of `a` leave nothing to predict
+        anchors.retain(|&(pos, _)| pos < self.a.len());
+        anchors.sort_by_key(|&(pos, match_len)| (std::cmp::Reverse(match_len), pos));
+        let mut seen = HashSet::new();
+        anchors
+            .into_iter()
+            .map(|(pos, match_len)| self.result(Anchor::At { pos, match_len }, false, chunk_size))
+            .filter(|candidate| !candidate.tokens.is_empty() && seen.insert(candidate.tokens))
+            .take(k)
+            .collect()
+    }
+
+    /// The [`StreamNextChunk::next_chunk_candidates`] merged into a prefix
+    /// tree, for tree-attention speculation.
+    pub fn next_chunk_tree(&self, current_b: &[T], chunk_size: usize, k: usize) -> TokenTree<T> {
+        TokenTree::from_candidates(self.next_chunk_candidates(current_b, chunk_size, k).iter().map(|c| c.tokens))
+    }
+
+    fn _next_chunk(&self, current_b: &[T], chunk_size: usize, algorithm: DiffAlgorithm) -> &[T] {
+        self.predict_from(current_b, chunk_size, algorithm, None, None).0.tokens
+    }
+
+    /// Predicts and records the call, returning its [`CallStats`] too.
+    fn predict_from(
+        &self,
+        current_b: &[T],
+        chunk_size: usize,
+        algorithm: DiffAlgorithm,
+        cancel: Option<&CancellationToken>,
+        cursor: Option<&PredictionCursor>,
+    ) -> (PredictionResult<'_, T>, CallStats) {
+        let started = Instant::now();
+        let mut call = CallStats { fast_path: true, ..Default::default() };
+        let deadline = self.options.deadline.map(|deadline| started + deadline);
+        let window_anchor = cursor.and_then(|cursor| self.cursor_alignment(cursor, current_b));
+        let scope = CallScope { last_anchor: self.last_anchor.get(), window_anchor, deadline, cancel, cursor };
+        let result = self.predict_from_timed(current_b, chunk_size, algorithm, scope, &mut call);
+        if let (Some(pos), 1..) = (result.start, result.match_len) {
+            self.last_anchor.set(current_b.len(), pos);
+            // Keyed like the diffed calls `stateless_anchor` stores. A windowed
+            // match may be cut short by the window, as there: the position holds
+            // and resumed calls only undercount the match
+            if self.options.memoize_anchors {
+                self.memo.store_latest(&normalized(self.normalizer.as_ref(), current_b), pos, result.match_len);
+            }
+        }
+        if let Some(start) = result.start {
+            self.last_prediction.set(start, start + result.tokens.len());
+        }
+        call.wall = started.elapsed();
+        call.windowed = result.windowed;
+        call.chunk_len = result.tokens.len();
+        // The diffs a cancelled call skipped say nothing about how well the windows work
+        if !cancel.is_some_and(CancellationToken::is_cancelled) {
+            if call.windowed && call.chunk_len == 0 {
+                self.window_miss_streak.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.window_miss_streak.store(0, Ordering::Relaxed);
+            }
+        }
+        self.stats.record(call);
+        if let Some(hook) = &self.hook {
+            hook(&result, &call);
+        }
+        (result, call)
+    }
+
+    fn predict_from_timed(
+        &self,
+        current_b: &[T],
+        chunk_size: usize,
+        algorithm: DiffAlgorithm,
+        scope: CallScope<'_>,
+        call: &mut CallStats,
+    ) -> PredictionResult<'_, T> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("next_chunk", b_len = current_b.len(), chunk_size).entered();
         if self.a.is_empty() || chunk_size == 0 {
-            return &[];
+            return PredictionResult::empty();
         }
+        let (mut anchor, windowed) = self.stateless_anchor(current_b, algorithm, scope, call);
+        if anchor == Anchor::Miss {
+            anchor = self.boundary_anchor(current_b, algorithm, scope, call).unwrap_or(anchor);
+        }
+        call.anchor = anchor.into();
+        let anchor = self.fallback(anchor, current_b.len(), scope.last_anchor);
+        self.result(anchor, windowed, chunk_size)
+    }
 
-        // --- Determine if windowing should be applied ---
-        let apply_windowing = !current_b.is_empty()
-            && self.window_size > 0 // Avoid windowing if window size is zero
-            && self.window_size >= self.min_window_threshold // Only window if size is significant
-            && current_b.len() >= self.window_size;
-
-        let a_slice: &[i32]; // The slice of 'a' to diff against
-        let b_slice: &[i32]; // The slice of 'b' to use for diffing
-        let a_slice_start_offset: usize; // Start index of a_slice within self.a
-
-        if apply_windowing {
-            // Calculate slices for windowed diff
-            let trim_len = current_b.len() - self.window_size;
-            b_slice = &current_b[trim_len..];
-
-            // Calculate 'a' window bounds (similar to python logic)
-            // Start 'a' window potentially before the corresponding 'b' start point
-            let a_lower_bound = trim_len.saturating_sub(self.window_size);
-            // Make 'a' window larger to provide context
-            let a_upper_bound = min(self.a.len(), a_lower_bound + self.window_size * self.a_window_factor);
-            // Ensure lower bound isn't past upper bound (can happen with short 'a')
-            let a_lower_bound_final = min(a_lower_bound, a_upper_bound);
-
-            a_slice = &self.a[a_lower_bound_final..a_upper_bound];
-            a_slice_start_offset = a_lower_bound_final; // Remember the offset
-
-            // println!( // Debugging window info
-            //     "Windowing: b_len={}, trim_len={}, b_slice_len={}, a_offset={}, a_slice_len={}",
-            //     current_b.len(), trim_len, b_slice.len(), a_slice_start_offset, a_slice.len()
-            // );
+    /// Matches `current_b` against `a` from scratch, starting no diff once
+    /// `scope` stops. Also returns whether windowing was applied.
+    fn stateless_anchor(
+        &self,
+        current_b: &[T],
+        algorithm: DiffAlgorithm,
+        scope: CallScope<'_>,
+        call: &mut CallStats,
+    ) -> (Anchor, bool) {
+        if current_b.is_empty() {
+            return (Anchor::StartOfA, false);
+        }
+        if let Some(anchor) = scope.cursor.and_then(|cursor| self.cursor_anchor(cursor, current_b)) {
+            trace_event!(trace, ?anchor, "b extends the cursor's, skipping the diff");
+            call.cursor_hit = true;
+            return (anchor, false);
+        }
+        let b_keys = &*normalized(self.normalizer.as_ref(), current_b);
+        let interned = match &self.backend {
+            Backend::Diff(interned) => interned,
+            Backend::SuffixAutomaton(sam) => return (sam_anchor(sam, sam.match_suffix(b_keys)), false),
+            Backend::SuffixArray(sa) => return (suffix_match_anchor(sa.longest_suffix_match(b_keys)), false),
+            Backend::HashChain(chain) => {
+                return (suffix_match_anchor(chain.longest_suffix_match(self.a_keys(), b_keys)), false)
+            }
+        };
+        // Fast path: `b` is still an exact copy of the start of `a`, no diff needed
+        if common_prefix_len(self.a_keys(), b_keys) == b_keys.len() {
+            trace_event!(trace, "b is a prefix of a, skipping the diff");
+            return (Anchor::At { pos: b_keys.len(), match_len: b_keys.len() }, false);
+        }
+        if let Some(anchor) = self.memoized_anchor(b_keys) {
+            trace_event!(trace, ?anchor, "b extends a remembered call, skipping the diff");
+            call.memo_hit = true;
+            return (anchor, false);
+        }
+        if let Some(anchor) = self.continuation_anchor(b_keys) {
+            trace_event!(trace, ?anchor, "b continues the previous prediction, skipping the diff");
+            call.continuation_hit = true;
+            return (anchor, false);
+        }
 
-        } else {
-            // Use full slices if not windowing
-            a_slice = self.a.as_slice();
-            b_slice = current_b;
-            a_slice_start_offset = 0;
+        let window = self.window(b_keys, scope.window_anchor);
+        let b_slice = &b_keys[window.b_start..]; // The slice of 'b' to use for diffing
+
+        if b_slice.is_empty() {
+            // Standard case: b is truly empty, predict start of a.
+            // (Windowing never trims b down to nothing since window_size > 0.)
+            return (Anchor::StartOfA, window.applied);
         }
 
         // --- Perform diff on the selected slices (either full or windowed) ---
-        let source_a = I32Slice(a_slice);
-        let source_b = I32Slice(b_slice);
-
-        let a_len = source_a.estimate_tokens(); // Length of the slice being diffed
-        let b_len = source_b.estimate_tokens(); // Length of the slice being diffed
-
-        // Handle case where b_slice might be empty after trimming but current_b wasn't
-        if b_len == 0 && !current_b.is_empty() {
-             // If b_slice is empty (e.g., windowing trimmed everything),
-             // we can't find matches based on it. Predict from the start of 'a'.
-             // This might need refinement depending on desired behavior.
-             // A safer bet might be to return empty, assuming the state is unusual.
-             // Let's stick to the original "no match" behavior for now:
-             let end = min(chunk_size, self.a.len());
-             return &self.a[0..end];
-        } else if b_len == 0 && current_b.is_empty() {
-             // Standard case: b is truly empty, predict start of a
-             let end = min(chunk_size, self.a.len());
-             return &self.a[0..end];
-        }
-
-
-        let input = InternedInput::<i32>::new(source_a, source_b);
-        // Pass the lengths of the *slices* being diffed to the collector
-        let sink = MatchCollector::new(a_len, b_len);
-        let matches = diff(Algorithm::Histogram, &input, sink);
-
-        // --- Process matches ---
-        if matches.is_empty() {
-            // No matches found *within the diffed slices*.
-            if apply_windowing {
-                // If windowing was active and found no match, it's hard to predict.
-                // Maybe the match lies outside the window. Returning empty is safest.
-                // Alternatively, could try predicting from a_slice_start_offset + window_size?
-                // Let's return empty for now.
-                 return &[];
+        // `a` is already interned; only the (windowed) `b` is interned per call
+        let interning = Instant::now();
+        let b_tokens = interned.intern_into(b_slice, self.scratch.tokens.take());
+        call.intern = interning.elapsed();
+        call.fast_path = false;
+
+        let diffing = Instant::now();
+        let (mut anchor, matches, mut window) = self.diff_window(interned, &b_tokens, window, algorithm, scope, call);
+        call.matches = matches;
+        trace_event!(debug, ?anchor, matches, windowed = window.applied, "diffed b against a");
+        if anchor == Anchor::Miss && window.applied {
+            if let Some(escalated) = self.escalate(interned, b_keys, algorithm, diffing, scope, call) {
+                (anchor, window) = escalated;
+            }
+        }
+        if anchor == Anchor::Miss && window.applied {
+            let streak = self.window_miss_streak.load(Ordering::Relaxed);
+            if let Some(reanchored) = self.global_reanchor(interned, b_keys, algorithm, streak, scope, call) {
+                (anchor, window) = reanchored;
+            }
+        }
+        let search = anchor == Anchor::Miss && window.applied && self.options.multi_window_search;
+        let anchor = if search && !scope.stopped(call) {
+            self.search_windows(interned, &b_tokens, window, algorithm, scope, call).unwrap_or(anchor)
+        } else {
+            anchor
+        };
+        call.diff = diffing.elapsed();
+        (call.a_window, call.b_window_start) = (Some((window.a_start, window.a_end)), window.b_start);
+        if let (true, Anchor::At { pos, match_len }) = (self.options.memoize_anchors, anchor) {
+            self.memo.store(b_keys, pos, match_len);
+        }
+        (anchor, window.applied)
+    }
+
+    /// The longest suffix of the last `suffix_len` tokens of `current_b`
+    /// that occurs in `a`, as `(pos, match_len)` with `pos` the offset in `a`
+    /// right after its first occurrence; `None` when the last token doesn't
+    /// occur in `a` at all. Produces no chunk and leaves the statistics alone,
+    /// e.g. to decide whether speculating is worth it this step.
+    ///
+    /// With the diff backend the first call builds a suffix automaton over
+    /// `a`, or returns `None` when it wouldn't fit the memory budget.
+    pub fn longest_match_at(&self, current_b: &[T], suffix_len: usize) -> Option<(usize, usize)> {
+        let tail = &current_b[current_b.len().saturating_sub(suffix_len)..];
+        let tail = normalized(self.normalizer.as_ref(), tail);
+        let sam = match &self.backend {
+            Backend::SuffixArray(sa) => return sa.longest_suffix_match(&tail),
+            Backend::HashChain(chain) => return chain.longest_suffix_match(self.a_keys(), &tail),
+            Backend::SuffixAutomaton(_) | Backend::Diff(_) => self.suffix_automaton()?,
+        };
+        let cursor = sam.match_suffix(&tail);
+        Some((sam.end_of_match(cursor)?, cursor.match_len))
+    }
+
+    /// The backend's suffix automaton, or [`StreamNextChunk::suffix_index`]
+    /// built on first use; `None` when that wouldn't fit the memory budget.
+    fn suffix_automaton(&self) -> Option<&SuffixAutomaton<T>> {
+        if let Backend::SuffixAutomaton(sam) = &self.backend {
+            return Some(sam);
+        }
+        let usage = self.memory_usage();
+        let needed = usage.used + SuffixAutomaton::<T>::estimate_bytes(self.a.len());
+        if self.suffix_index.get().is_none() && usage.budget.is_some_and(|budget| needed > budget) {
+            trace_event!(debug, needed, "the suffix automaton doesn't fit the memory budget");
+            return None;
+        }
+        Some(self.suffix_index.get_or_init(|| SuffixAutomaton::new(self.a_keys())))
+    }
+
+    /// Anchors `b` without its last token, which then has to be equivalent
+    /// to the next token of `a` under the boundary table to be skipped over.
+    fn boundary_anchor(
+        &self,
+        b: &[T],
+        algorithm: DiffAlgorithm,
+        scope: CallScope<'_>,
+        call: &mut CallStats,
+    ) -> Option<Anchor> {
+        let boundary = self.boundary.as_ref()?;
+        let (&last, rest) = b.split_last()?;
+        let (pos, match_len) = match self.stateless_anchor(rest, algorithm, scope, call).0 {
+            Anchor::At { pos, match_len } => (pos, match_len),
+            Anchor::StartOfA => (0, 0),
+            _ => return None,
+        };
+        let skipped = *self.a.get(pos)?;
+        if !boundary.equivalent(last, skipped) {
+            return None;
+        }
+        call.boundary_skip = true;
+        trace_event!(debug, pos, "matched the last token of b through the boundary table");
+        Some(Anchor::At { pos: pos + 1, match_len: match_len + 1 })
+    }
+
+    /// Anchor of a remembered call whose `b` the given one extends, when the
+    /// tokens added since keep following `a`.
+    fn memoized_anchor(&self, current_b: &[T]) -> Option<Anchor> {
+        if !self.options.memoize_anchors {
+            return None;
+        }
+        let (b_len, pos, match_len) = self.memo.lookup(current_b)?;
+        self.followed_anchor(pos, match_len, &current_b[b_len..])
+    }
+
+    /// The anchor past `added`, in canonical form, when those tokens follow
+    /// `a` from `pos`, where a match of `match_len` tokens ended.
+    fn followed_anchor(&self, pos: usize, match_len: usize, added: &[T]) -> Option<Anchor> {
+        let a_keys = self.a_keys();
+        (common_prefix_len(&a_keys[min(pos, a_keys.len())..], added) == added.len())
+            .then(|| Anchor::At { pos: pos + added.len(), match_len: match_len + added.len() })
+    }
+
+    /// Anchors `b` by checking only its tokens past `cursor` against `a`
+    /// where the cursor left off, when `b` extends the cursor's.
+    fn cursor_anchor(&self, cursor: &PredictionCursor, current_b: &[T]) -> Option<Anchor> {
+        let (b_len, pos) = self.cursor_alignment(cursor, current_b)?;
+        let added = normalized(self.normalizer.as_ref(), &current_b[b_len..]);
+        self.followed_anchor(pos, cursor.match_len, &added)
+    }
+
+    /// The `(b_len, pos)` alignment `cursor` confirmed, when `b` extends the cursor's.
+    fn cursor_alignment(&self, cursor: &PredictionCursor, current_b: &[T]) -> Option<(usize, usize)> {
+        (tail_hash(current_b.get(..cursor.b_len)?) == cursor.tail).then_some((cursor.b_len, cursor.pos?))
+    }
+
+    /// Anchors right after the last [`NextChunkOptions::continuation_check_len`]
+    /// tokens of `b` when `a` has them just before a position the previous
+    /// call predicted: tried from one past its end (the verifier's extra
+    /// token after a fully accepted chunk) back to its start.
+    fn continuation_anchor(&self, current_b: &[T]) -> Option<Anchor> {
+        let k = self.options.continuation_check_len;
+        if k == 0 || current_b.len() < k {
+            return None;
+        }
+        let (start, end) = self.last_prediction.get()?;
+        let (a_keys, tail) = (self.a_keys(), &current_b[current_b.len() - k..]);
+        let pos = (max(start, k)..=min(end + 1, a_keys.len())).rev().find(|&pos| a_keys[pos - k..pos] == *tail)?;
+        let match_len = a_keys[..pos].iter().rev().zip(current_b.iter().rev()).take_while(|(x, y)| x == y).count();
+        Some(Anchor::At { pos, match_len })
+    }
+
+    /// Diffs `b_slice` against the other windows of `a` (same size as `missed`,
+    /// overlapping by one `window_size`) and keeps the anchor with the longest
+    /// match, preferring windows closer to `missed` on ties.
+    fn search_windows(
+        &self,
+        interned: &InternedReference<T>,
+        b_tokens: &[Token],
+        missed: Window,
+        algorithm: DiffAlgorithm,
+        scope: CallScope<'_>,
+        call: &mut CallStats,
+    ) -> Option<Anchor> {
+        let window_len = missed.a_end - missed.a_start;
+        let step = max(1, window_len.saturating_sub(self.window_size));
+        let windows: Vec<Window> = (0..self.a.len())
+            .step_by(step)
+            .filter(|&a_start| a_start != missed.a_start)
+            .map(|a_start| Window { a_start, a_end: min(self.a.len(), a_start + window_len), ..missed })
+            .collect();
+
+        let (a_tokens, context) = (interned.tokens.as_slice(), self.diff_context(interned, algorithm, scope));
+        let diff_window = |window: &Window| {
+            let a_tokens = &a_tokens[window.a_start..window.a_end];
+            let (anchor, matches) = anchor_from_diff(&context, a_tokens, b_tokens, *window);
+            let key = match anchor {
+                Anchor::At { match_len, .. } => Some((match_len, std::cmp::Reverse(window.a_start.abs_diff(missed.a_start)))),
+                _ => None,
+            };
+            (key, anchor, matches)
+        };
+
+        #[cfg(feature = "parallel")]
+        let outcomes: Vec<_> = windows.par_iter().map(diff_window).collect();
+        #[cfg(not(feature = "parallel"))]
+        let outcomes: Vec<_> = windows.iter().map(diff_window).collect();
+        call.windows_searched = windows.len();
+        call.matches += outcomes.iter().map(|(_, _, matches)| matches).sum::<usize>();
+        let best = outcomes
+            .into_iter()
+            .filter_map(|(key, anchor, _)| Some((key?, anchor)))
+            .max_by_key(|(key, _)| *key)
+            .map(|(_, anchor)| anchor);
+        trace_event!(debug, windows = windows.len(), anchor = ?best, "searched the other windows of a");
+        best
+    }
+
+    /// Appends newly generated tokens to the internally tracked `b`.
+    ///
+    /// While the tokens keep following `a` from the current anchor this is
+    /// O(new tokens); a divergence only marks the anchor stale so the next
+    /// [`StreamNextChunk::predict`] re-diffs.
+    pub fn append(&mut self, new_tokens: &[T]) {
+        let keys = normalized(self.normalizer.as_ref(), new_tokens);
+        let a = self.keys.as_deref().unwrap_or(&self.a);
+        let state = &mut self.state;
+        if let Some(pos) = state.anchor {
+            // Hot path: the new tokens continue `a` right at the anchor
+            let rest = &a[min(pos, a.len())..];
+            if common_prefix_len(rest, &keys) == keys.len() {
+                state.anchor = Some(pos + new_tokens.len());
+                state.match_len += new_tokens.len();
+                state.confirmed = Some((state.b.len() + new_tokens.len(), pos + new_tokens.len()));
             } else {
-                // Not windowing, and no matches found at all. Predict start of 'a'.
-                let end = min(chunk_size, self.a.len());
-                return &self.a[0..end];
+                state.anchor = None;
+            }
+        }
+        if let Some(automaton) = &self.landmarks {
+            // Last landmark reached, as (tokens of `keys` up to its end, landmark)
+            let mut reached = None;
+            for (i, &t) in keys.iter().enumerate() {
+                state.landmark_cursor = automaton.step(state.landmark_cursor, t);
+                if let Some(landmark) = automaton.reached(state.landmark_cursor) {
+                    reached = Some((i + 1, landmark));
+                }
+            }
+            if let (None, Some((consumed, landmark))) = (state.anchor, reached) {
+                // Diverged: re-anchor on the landmark if `b` still follows `a` after it
+                let range = &automaton.landmarks()[landmark];
+                let after = &keys[consumed..];
+                if common_prefix_len(&a[range.end..], after) == after.len() {
+                    let pos = range.end + after.len();
+                    trace_event!(debug, landmark, pos, "reached a landmark, re-anchoring without a diff");
+                    state.anchor = Some(pos);
+                    state.match_len = range.len() + after.len();
+                    state.confirmed = Some((state.b.len() + new_tokens.len(), pos));
+                }
+            }
+        }
+        state.b.extend_from_slice(new_tokens);
+        if self.normalizer.is_some() {
+            state.b_keys.extend_from_slice(&keys);
+        }
+        match &self.backend {
+            Backend::Diff(interned) => state.b_tokens.extend(keys.iter().map(|t| interned.id(t))),
+            Backend::SuffixAutomaton(sam) => {
+                state.sam_cursor = keys.iter().fold(state.sam_cursor, |cursor, &t| sam.step(cursor, t));
+            }
+            // Looked up from scratch by each re-anchoring
+            Backend::SuffixArray(_) | Backend::HashChain(_) => {}
+        }
+    }
+
+    /// Predicts the next chunk for the tokens fed through [`StreamNextChunk::append`].
+    ///
+    /// Equivalent to `next_chunk(b, chunk_size)` on the accumulated `b`, but
+    /// reuses the anchor from the previous call while `b` keeps following `a`.
+    pub fn predict(&mut self, chunk_size: usize) -> &[T] {
+        self.predict_with_info(chunk_size).tokens
+    }
+
+    /// Same as [`StreamNextChunk::predict`], but also reports where in `a`
+    /// the prediction came from.
+    pub fn predict_with_info(&mut self, chunk_size: usize) -> PredictionResult<'_, T> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("predict", b_len = self.state.b.len(), chunk_size).entered();
+        let started = Instant::now();
+        let mut call = CallStats { fast_path: true, ..Default::default() };
+        let anchor = (!self.a.is_empty() && chunk_size > 0).then(|| self.stateful_anchor(&mut call));
+        call.wall = started.elapsed();
+        call.windowed = anchor.is_some_and(|(_, windowed)| windowed);
+        call.anchor = anchor.map_or(CallAnchor::Skipped, |(anchor, _)| anchor.into());
+        let result = match anchor {
+            Some((anchor, windowed)) => {
+                let anchor = self.fallback(anchor, self.state.b.len(), self.state.confirmed);
+                self.result(anchor, windowed, chunk_size)
             }
+            None => PredictionResult::empty(),
+        };
+        call.chunk_len = result.tokens.len();
+        if call.windowed && call.chunk_len == 0 {
+            self.state.window_miss_streak.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.state.window_miss_streak.store(0, Ordering::Relaxed);
+        }
+        self.stats.record(call);
+        if let Some(hook) = &self.hook {
+            hook(&result, &call);
+        }
+        result
+    }
+
+    /// Anchor for the appended `b`, re-diffing only when the held anchor is stale.
+    fn stateful_anchor(&mut self, call: &mut CallStats) -> (Anchor, bool) {
+        if self.state.b.is_empty() {
+            return (Anchor::StartOfA, false);
+        }
+        if let Some(pos) = self.state.anchor {
+            return (Anchor::At { pos, match_len: self.state.match_len }, false);
+        }
+
+        let (mut anchor, windowed) = self.reanchor(call);
+        if anchor == Anchor::Miss {
+            let confirmed = self.state.confirmed;
+            let scope = CallScope { last_anchor: confirmed, window_anchor: confirmed, ..Default::default() };
+            anchor = self.boundary_anchor(&self.state.b, self.options.algorithm, scope, call).unwrap_or(anchor);
         }
+        if let Anchor::At { pos, match_len } = anchor {
+            self.state.anchor = Some(pos);
+            self.state.match_len = match_len;
+            self.state.confirmed = Some((self.state.b.len(), pos));
+        }
+        (anchor, windowed)
+    }
 
-        // Get the last match found within the diffed slices
-        if let Some(last_match) = matches.last() {
-            let (last_match_a_range, last_match_b_range) = last_match;
+    /// Checks `predicted` against `actual`, the tokens the model generated at
+    /// those positions, and advances the stateful stream: the accepted prefix
+    /// is [`StreamNextChunk::append`]ed, or the first `actual` token when
+    /// nothing was accepted. Returns the number of accepted tokens, which also
+    /// add up in [`StreamNextChunk::stats`].
+    pub fn verify_and_advance(&mut self, predicted: &[T], actual: &[T]) -> usize {
+        let accepted = common_prefix_len(predicted, actual);
+        self.stats.record_verification(predicted.len(), accepted);
+        let advance = min(max(accepted, 1), actual.len());
+        self.append(&actual[..advance]);
+        accepted
+    }
 
-            // Check if the end of the last match in b_slice aligns with the end of b_slice
-            let current_matched = last_match_b_range.end == b_len;
+    /// The tokens fed through [`StreamNextChunk::append`] so far.
+    pub fn appended(&self) -> &[T] {
+        &self.state.b
+    }
 
-            if !current_matched {
-                // b_slice (or current_b if not windowing) ends mid-change or after the last match.
-                // Cannot confidently predict.
-                return &[];
+    /// Forgets all appended tokens, starting a new stateful stream over the same `a`.
+    pub fn reset(&mut self) {
+        self.state.b.clear();
+        self.state.anchor = Some(0);
+        self.state.match_len = 0;
+        self.state.confirmed = None;
+        self.state.sam_cursor = SamCursor::default();
+        self.state.landmark_cursor = LandmarkCursor::default();
+        self.state.b_keys.clear();
+        self.state.b_tokens.clear();
+        *self.state.window_miss_streak.get_mut() = 0;
+    }
+
+    /// Re-diffs the accumulated `b` against `a`; both are already interned.
+    /// Also returns whether windowing was applied.
+    fn reanchor(&self, call: &mut CallStats) -> (Anchor, bool) {
+        let interned = match &self.backend {
+            Backend::Diff(interned) => interned,
+            Backend::SuffixAutomaton(sam) => return (sam_anchor(sam, self.state.sam_cursor), false),
+            Backend::SuffixArray(sa) => return (suffix_match_anchor(sa.longest_suffix_match(self.appended_keys())), false),
+            Backend::HashChain(chain) => {
+                return (suffix_match_anchor(chain.longest_suffix_match(self.a_keys(), self.appended_keys())), false)
             }
+        };
+        let confirmed = self.state.confirmed;
+        let scope = CallScope { last_anchor: confirmed, window_anchor: confirmed, ..Default::default() };
+        let window = self.window(self.appended_keys(), scope.window_anchor);
+        if window.b_start == self.state.b.len() {
+            return (Anchor::StartOfA, window.applied);
+        }
 
-            // Calculate the offset *within the a_slice* immediately after the last match
-            let unmatched_offset_in_a_slice = last_match_a_range.end as usize;
+        let b_tokens = &self.state.b_tokens[window.b_start..];
+        let diffing = Instant::now();
+        let (mut anchor, matches, mut window) = self.diff_window(interned, b_tokens, window, self.options.algorithm, scope, call);
+        (call.matches, call.fast_path) = (matches, false);
+        trace_event!(debug, ?anchor, matches, windowed = window.applied, "re-diffed the appended b against a");
+        if anchor == Anchor::Miss && window.applied {
+            let b = self.appended_keys();
+            if let Some(escalated) = self.escalate(interned, b, self.options.algorithm, diffing, scope, call) {
+                (anchor, window) = escalated;
+            }
+        }
+        if anchor == Anchor::Miss && window.applied {
+            let (b, streak) = (self.appended_keys(), self.state.window_miss_streak.load(Ordering::Relaxed));
+            if let Some(reanchored) = self.global_reanchor(interned, b, self.options.algorithm, streak, scope, call) {
+                (anchor, window) = reanchored;
+            }
+        }
+        call.diff = diffing.elapsed();
+        (call.a_window, call.b_window_start) = (Some((window.a_start, window.a_end)), window.b_start);
+        (anchor, window.applied)
+    }
 
-            // --- Crucial: Convert offset back to the original self.a coordinate system ---
-            let unmatched_offset_in_original_a = a_slice_start_offset + unmatched_offset_in_a_slice;
+    /// Diffs all of `b` against all of `a` once `streak` consecutive windowed
+    /// calls predicted nothing, as configured by
+    /// [`NextChunkOptions::global_reanchor_after`].
+    fn global_reanchor(
+        &self,
+        interned: &InternedReference<T>,
+        b: &[T],
+        algorithm: DiffAlgorithm,
+        streak: usize,
+        scope: CallScope<'_>,
+        call: &mut CallStats,
+    ) -> Option<(Anchor, Window)> {
+        if streak < self.options.global_reanchor_after? || self.windowed_only || scope.stopped(call) {
+            return None;
+        }
+        let b_tokens = interned.intern_into(b, self.scratch.tokens.take());
+        let full = Window::full(self.a.len());
+        let (anchor, matches, window) = self.diff_window(interned, &b_tokens, full, algorithm, scope, call);
+        call.global_reanchor = true;
+        call.matches += matches;
+        trace_event!(debug, ?anchor, matches, streak, "re-anchored against all of a after repeated window misses");
+        Some((anchor, window))
+    }
+
+    /// Retries a windowed miss with the wider windows of the escalation
+    /// policy, then the full diff, until one anchors or the policy's latency
+    /// budget since `started` is spent or `scope` stops. Returns the last
+    /// attempt, if any.
+    fn escalate(
+        &self,
+        interned: &InternedReference<T>,
+        b: &[T],
+        algorithm: DiffAlgorithm,
+        started: Instant,
+        scope: CallScope<'_>,
+        call: &mut CallStats,
+    ) -> Option<(Anchor, Window)> {
+        let policy = self.options.escalation.as_ref()?;
+        let sizes = policy.factors.iter().map(|&factor| Some(self.window_size.saturating_mul(factor)));
+        let mut last = None;
+        for size in sizes.chain((policy.full_diff && !self.windowed_only).then_some(None)) {
+            if started.elapsed() >= policy.budget || scope.stopped(call) {
+                trace_event!(debug, escalations = call.escalations, "escalation budget spent");
+                break;
+            }
+            let window = match size {
+                Some(size) => self.window_sized(b, scope.window_anchor, size),
+                None => Window::full(self.a.len()),
+            };
+            let b_tokens = interned.intern_into(&b[window.b_start..], self.scratch.tokens.take());
+            let (anchor, matches, window) = self.diff_window(interned, &b_tokens, window, algorithm, scope, call);
+            call.escalations += 1;
+            call.matches += matches;
+            trace_event!(debug, ?anchor, matches, windowed = window.applied, "escalated the window");
+            last = Some((anchor, window));
+            // A window as large as `b` is the full diff already
+            if anchor != Anchor::Miss || !window.applied {
+                break;
+            }
+        }
+        last
+    }
 
-            // Check if we've already matched past the end of the original 'a'
-            if unmatched_offset_in_original_a >= self.a.len() {
-                return &[]; // Nothing more to predict
+    /// Diffs `b_tokens` (`b` from `window.b_start`) against the window of
+    /// `a`. A diff over all of `a` is first narrowed by the block index when
+    /// there is one, see [`NextChunkOptions::coarse_block_len`]. A miss
+    /// without diffing once `scope` stops. Also returns the window
+    /// actually diffed.
+    fn diff_window(
+        &self,
+        interned: &InternedReference<T>,
+        b_tokens: &[Token],
+        window: Window,
+        algorithm: DiffAlgorithm,
+        scope: CallScope<'_>,
+        call: &mut CallStats,
+    ) -> (Anchor, usize, Window) {
+        if scope.stopped(call) {
+            trace_event!(debug, timed_out = call.timed_out, "out of time or cancelled, skipping the diff");
+            return (Anchor::Miss, 0, window);
+        }
+        let coarse = self.coarse_index.as_ref().filter(|_| !window.applied);
+        let (window, b_tokens) = match coarse.and_then(|index| index.narrow(&interned.tokens, b_tokens, algorithm)) {
+            Some((a_start, a_end, b_start)) => {
+                trace_event!(debug, a_start, a_end, b_start, "narrowed the full diff by block hashes");
+                call.coarse = true;
+                (Window { a_start, a_end, b_start, ..window }, &b_tokens[b_start..])
             }
+            None => (window, b_tokens),
+        };
+        let context = self.diff_context(interned, algorithm, scope);
+        let segment_len = self
+            .options
+            .diff_segment_len
+            .or(scope.deadline.map(|_| DEADLINE_SEGMENT_LEN))
+            .filter(|&len| len > 0 && window.a_end - window.a_start > len);
+        let (anchor, matches) = match segment_len {
+            Some(segment_len) => diff_segments(&context, &interned.tokens, b_tokens, window, segment_len, call),
+            None => anchor_from_diff(&context, &interned.tokens[window.a_start..window.a_end], b_tokens, window),
+        };
+        (anchor, matches, window)
+    }
 
-            // Calculate the end index for the next chunk slice in the original 'a'
-            let end_offset_in_original_a = min(unmatched_offset_in_original_a + chunk_size, self.a.len());
+    /// Decides which part of `a`/`b` to diff for `b`, given the last
+    /// confirmed `(b_len, pos)` alignment if there is one.
+    fn window(&self, b: &[T], last_anchor: Option<(usize, usize)>) -> Window {
+        self.window_sized(b, last_anchor, self.window_size)
+    }
 
-            // Return the slice from the *original* self.a
-            return &self.a[unmatched_offset_in_original_a..end_offset_in_original_a];
+    /// [`StreamNextChunk::window`] with a `window_size`-token window of `b`.
+    fn window_sized(&self, b: &[T], last_anchor: Option<(usize, usize)>, window_size: usize) -> Window {
+        let b_len = b.len();
+        // --- Determine if windowing should be applied ---
+        let apply_windowing = b_len > 0
+            && window_size > 0 // Avoid windowing if window size is zero
+            // Over the memory budget even a short 'b' is diffed against a window
+            && (self.windowed_only
+                || (window_size >= self.options.min_window_threshold // Only window if size is significant
+                    && b_len >= window_size));
+
+        if !apply_windowing {
+            // Use full slices if not windowing
+            return Window::full(self.a.len());
+        }
 
-        } else {
-            // Should be covered by matches.is_empty(), but handle defensively.
-            return &[];
+        // Calculate slices for windowed diff
+        let trim_len = b_len.saturating_sub(window_size);
+
+        // Where in 'a' the 'b' window starts: located via the rolling hash when
+        // the tail of 'b' shares a k-gram with 'a', else shifted like the caller's last
+        // confirmed anchor (so tokens inserted before it don't drift the
+        // window), else assume equal offsets
+        let b_window_start_in_a = self
+            .anchor_index
+            .as_ref()
+            .and_then(|index| index.locate(self.a_keys(), b, trim_len))
+            .map(|(b_end, a_end)| (a_end + trim_len).saturating_sub(b_end))
+            .or_else(|| {
+                let (anchor_b_len, pos) = last_anchor.filter(|&(anchor_b_len, _)| anchor_b_len <= b_len)?;
+                Some(min((pos + trim_len).saturating_sub(anchor_b_len), self.a.len()))
+            })
+            .unwrap_or(trim_len);
+
+        // Calculate 'a' window bounds (similar to python logic)
+        // Start 'a' window potentially before the corresponding 'b' start point
+        let a_lower_bound = b_window_start_in_a.saturating_sub(window_size);
+        // Make 'a' window larger to provide context
+        let a_upper_bound = min(self.a.len(), a_lower_bound + window_size * self.options.a_window_factor);
+        // Ensure lower bound isn't past upper bound (can happen with short 'a')
+        let a_lower_bound_final = min(a_lower_bound, a_upper_bound);
+
+        trace_event!(
+            debug,
+            b_len,
+            trim_len,
+            a_offset = a_lower_bound_final,
+            a_slice_len = a_upper_bound - a_lower_bound_final,
+            "windowing the diff"
+        );
+
+        Window { applied: true, a_start: a_lower_bound_final, a_end: a_upper_bound, b_start: trim_len }
+    }
+
+    /// Where to predict from when `anchor` didn't line `b` up with `a`, as
+    /// the fallback policy says; `last_anchor` is the last confirmed
+    /// `(b_len, pos)` alignment.
+    fn fallback(&self, anchor: Anchor, b_len: usize, last_anchor: Option<(usize, usize)>) -> Anchor {
+        let unmatched = match anchor {
+            Anchor::At { pos, match_len } => match_len < self.options.min_match_len || self.junk_match(pos, match_len),
+            Anchor::NoMatch | Anchor::Miss => true,
+            Anchor::StartOfA | Anchor::Fallback { .. } => false,
+        };
+        let Some(policy) = self.options.fallback.filter(|_| unmatched) else {
+            return if anchor == Anchor::NoMatch { Anchor::StartOfA } else { anchor };
+        };
+        trace_event!(debug, ?anchor, %policy, "falling back");
+        match policy {
+            FallbackPolicy::Empty => Anchor::Miss,
+            FallbackPolicy::StartOfA => Anchor::StartOfA,
+            FallbackPolicy::LastPosition => match last_anchor {
+                Some((anchor_b_len, pos)) if anchor_b_len <= b_len => Anchor::Fallback { pos: pos + b_len - anchor_b_len },
+                _ => Anchor::Miss,
+            },
+            FallbackPolicy::Offset(pos) => Anchor::Fallback { pos },
+        }
+    }
+
+    /// Whether the match of `match_len` tokens ending before `pos` is only
+    /// junk tokens.
+    fn junk_match(&self, pos: usize, match_len: usize) -> bool {
+        let matched = &self.a[min(pos, self.a.len()).saturating_sub(match_len)..min(pos, self.a.len())];
+        !self.junk.is_empty() && !matched.is_empty() && matched.iter().all(|t| self.junk.contains(t))
+    }
+
+    /// Slices the predicted chunk out of the original `a`.
+    fn result(&self, anchor: Anchor, windowed: bool, chunk_size: usize) -> PredictionResult<'_, T> {
+        let (start, match_len) = match anchor {
+            // A short accidental match would predict from the wrong region of `a`
+            Anchor::At { pos, match_len } if match_len < self.options.min_match_len || self.junk_match(pos, match_len) => {
+                return PredictionResult { windowed, ..PredictionResult::empty() }
+            }
+            Anchor::At { pos, match_len } => (pos, match_len),
+            Anchor::StartOfA => (0, 0),
+            Anchor::Fallback { pos } => (pos, 0),
+            Anchor::NoMatch | Anchor::Miss => return PredictionResult { windowed, ..PredictionResult::empty() },
+        };
+        // Check if we've already matched past the end of the original 'a'
+        // (then there is nothing more to predict)
+        let start = min(start, self.a.len());
+        // Calculate the end index for the next chunk slice in the original 'a'
+        let mut end = min(start + chunk_size, self.a.len());
+        if !self.junk.is_empty() {
+            end = self.a[start..end].iter().position(|t| self.junk.contains(t)).map_or(end, |len| start + len);
+        }
+        PredictionResult {
+            tokens: &self.a[start..end],
+            start: Some(start),
+            match_len,
+            windowed,
         }
     }
 }
 
 
+/// Size of the `b` window for a reference of `a_len` tokens (similar to python);
+/// 0 for an empty reference.
+fn window_size(a_len: usize) -> usize {
+    if a_len == 0 { 0 } else { max(1, a_len / 15) }
+}
 
+/// Length of the k-grams of the rolling hash index locating the `a` window,
+/// when windowing can apply.
+fn anchor_hash_len<T: Eq + Hash>(backend: &Backend<T>, window_size: usize, options: &NextChunkOptions) -> Option<usize> {
+    let can_window = matches!(backend, Backend::Diff(_)) && window_size > 0 && window_size >= options.min_window_threshold;
+    Some(options.anchor_hash_len).filter(|&k| can_window && k > 0)
+}
 
+/// Block length of the block hash index over `a`, when
+/// [`NextChunkOptions::coarse_block_len`] applies.
+fn coarse_block_len<T: Eq + Hash>(backend: &Backend<T>, options: &NextChunkOptions) -> Option<usize> {
+    let long_enough = matches!(backend, Backend::Diff(interned) if interned.tokens.len() >= COARSE_MIN_LEN);
+    options.coarse_block_len.filter(|&block_len| long_enough && block_len > 0)
+}
 
-#[pymethods]
-impl StreamNextChunk {
-    /// Creates a new StreamNextChunk instance from a Python list.
-    ///
-    /// Args:
-    ///     a (list[int]): The reference sequence (like the original file content).
-    #[new] // This defines the Python constructor (__init__)
-    #[pyo3(text_signature = "(a)")]
-    fn py_new(a_py: Bound<'_, PyList>) -> PyResult<Self> {
-        let a: Vec<i32> = a_py.extract()?;
-
-                // 2. Perform calculations directly here
-                let window_size = if a.is_empty() { 0 } else { max(1, a.len() / 15) };
-                let min_window_threshold = 100; // Example value
-                let a_window_factor = 3;      // Example value
-
-                // 3. Construct the struct directly using the owned 'a' and return it
-                Ok(StreamNextChunk {
-                    a, // Use the owned Vec directly
-                    window_size,
-                    min_window_threshold,
-                    a_window_factor,
-                })
-    }
-
-    #[pyo3(name="next_chunk", text_signature = "(current_b, chunk_size)")]
-    pub fn next_chunk_py(&self, current_b_py: Bound<'_, PyList>, chunk_size: usize) -> PyResult<Vec<i32>> {
-        let current_b: Vec<i32> = current_b_py.extract()?;
-        let result = self.next_chunk(current_b.as_slice(), chunk_size);
-        Ok(result.to_vec())
+/// Turns the suffix automaton position after `b` into an [`Anchor`].
+fn sam_anchor<T: Eq + Hash + Copy>(sam: &SuffixAutomaton<T>, cursor: SamCursor) -> Anchor {
+    match sam.end_of_match(cursor) {
+        Some(pos) => Anchor::At { pos, match_len: cursor.match_len },
+        // The last token of `b` doesn't occur in `a`
+        None => Anchor::Miss,
+    }
+}
+
+/// Turns the `(pos, match_len)` of the longest suffix of `b` found in `a`
+/// by an index into an [`Anchor`].
+fn suffix_match_anchor(found: Option<(usize, usize)>) -> Anchor {
+    match found {
+        Some((pos, match_len)) => Anchor::At { pos, match_len },
+        // The end of `b` doesn't occur in `a`
+        None => Anchor::Miss,
+    }
+}
+
+/// Diffs `b_tokens` against the window of the interned `a` one segment of
+/// `segment_len` tokens at a time, see [`NextChunkOptions::diff_segment_len`].
+/// Segments overlap by the length of `b` (up to half a segment) so a match
+/// of all of `b` falls inside one; the longest anchoring match wins, the
+/// earliest on ties.
+fn diff_segments(
+    context: &DiffContext<'_>,
+    a_tokens: &[Token],
+    b_tokens: &[Token],
+    window: Window,
+    segment_len: usize,
+    call: &mut CallStats,
+) -> (Anchor, usize) {
+    let step = segment_len - min(b_tokens.len(), segment_len / 2);
+    let (mut best, mut missed, mut total) = (None, false, 0);
+    for a_start in (window.a_start..window.a_end).step_by(step) {
+        if call.segments > 0 && context.scope.stopped(call) {
+            missed = true;
+            break;
+        }
+        let segment = Window { a_start, a_end: min(window.a_end, a_start + segment_len), ..window };
+        let (anchor, matches) = anchor_from_diff(context, &a_tokens[segment.a_start..segment.a_end], b_tokens, segment);
+        call.segments += 1;
+        total += matches;
+        match anchor {
+            Anchor::At { match_len, .. } if best.is_none_or(|(best_len, _)| match_len > best_len) => {
+                best = Some((match_len, anchor));
+            }
+            Anchor::Miss => missed = true,
+            _ => {}
+        }
+        if segment.a_end == window.a_end {
+            break;
+        }
+    }
+    trace_event!(debug, segments = call.segments, anchor = ?best, "diffed a segment at a time");
+    let unmatched = if missed { Anchor::Miss } else { Anchor::NoMatch };
+    (best.map_or(unmatched, |(_, anchor)| anchor), total)
+}
+
+/// Diffs the (interned) window slices and turns the matches into an [`Anchor`].
+/// Also returns the number of matching blocks found, whose lengths go to the
+/// context's recorder.
+fn anchor_from_diff(context: &DiffContext<'_>, a_tokens: &[Token], b_tokens: &[Token], window: Window) -> (Anchor, usize) {
+    if context.strategy == AnchorStrategy::LongestSuffixMatch {
+        return longest_suffix_anchor(context, a_tokens, b_tokens, window);
+    }
+    let DiffContext { algorithm, num_tokens, max_mismatches, lengths, .. } = *context;
+    let a_len = a_tokens.len() as u32; // Length of the slice being diffed
+    let b_len = b_tokens.len() as u32; // Length of the slice being diffed
+
+    // Pass the lengths of the *slices* being diffed to the collector
+    let mut matches = context.matches.take();
+    let sink = MatchCollector::with_buffer(a_len, b_len, std::mem::take(&mut *matches));
+    *matches = diff_with_tokens(algorithm.into(), a_tokens, b_tokens, num_tokens, sink);
+    lengths.record(matches.iter().map(|(a_range, _)| a_range.len()));
+
+    // --- Process matches ---
+    // Get the match to anchor on within the diffed slices
+    let Some((index, projected)) = anchoring_match(context, a_tokens, &matches, b_tokens.len(), window) else {
+        // No matches found *within the diffed slices*.
+        if window.applied {
+            // If windowing was active and found no match, it's hard to predict.
+            // Maybe the match lies outside the window.
+            return (Anchor::Miss, 0);
+        }
+        // Not windowing, and no matches found at all
+        return (Anchor::NoMatch, 0);
+    };
+
+    // Tokens of b_slice after the match; up to `max_mismatches` of them (any
+    // number for the strategies projecting past the match) are taken as
+    // substitutions of the same number of tokens in a_slice
+    let (last_match_a_range, last_match_b_range) = &matches[index];
+    let trailing = (b_len - last_match_b_range.end) as usize;
+    let unmatched_offset_in_a_slice = last_match_a_range.end as usize + trailing;
+
+    if (trailing > max_mismatches && !projected) || unmatched_offset_in_a_slice > a_tokens.len() {
+        // b_slice (or current_b if not windowing) ends mid-change or after the last match.
+        // Cannot confidently predict.
+        return (Anchor::Miss, matches.len());
+    }
+    // A run of only newlines, commas, indents... matches almost anywhere
+    let anchoring = &a_tokens[last_match_a_range.start as usize..last_match_a_range.end as usize];
+    if context.junk.is_some() && anchoring.iter().all(|&token| context.is_junk(token)) {
+        trace_event!(debug, match_len = anchoring.len(), "the anchoring match is all junk tokens");
+        return (Anchor::Miss, matches.len());
+    }
+
+    // Fold earlier matches separated by equal-length substitutions into the
+    // anchoring match while the mismatch budget lasts
+    let mut mismatches = if projected { 0 } else { trailing };
+    let mut match_len = last_match_a_range.len();
+    for pair in matches[..=index].windows(2).rev() {
+        let ((prev_a, prev_b), (cur_a, cur_b)) = (&pair[0], &pair[1]);
+        let gap = (cur_a.start - prev_a.end) as usize;
+        if gap != (cur_b.start - prev_b.end) as usize || mismatches + gap > max_mismatches {
+            break;
+        }
+        mismatches += gap;
+        match_len += prev_a.len();
     }
+
+    // --- Crucial: Convert offset back to the original self.a coordinate system ---
+    let anchor = Anchor::At {
+        pos: window.a_start + unmatched_offset_in_a_slice,
+        match_len,
+    };
+    (anchor, matches.len())
 }
 
+/// Index of the match [`NextChunkOptions::anchor_strategy`] anchors on, and
+/// whether the prediction is projected past it (`b` needn't end with it).
+/// `None` without matches.
+fn anchoring_match(
+    context: &DiffContext<'_>,
+    a_tokens: &[Token],
+    matches: &[(Range<u32>, Range<u32>)],
+    b_len: usize,
+    window: Window,
+) -> Option<(usize, bool)> {
+    let last = matches.len().checked_sub(1)?;
+    // Offset in `a` the prediction after each match would start from
+    let projected_pos = |(a_range, b_range): &(Range<u32>, Range<u32>)| {
+        window.a_start + a_range.end as usize + (b_len - b_range.end as usize)
+    };
+    match context.strategy {
+        AnchorStrategy::LastMatch | AnchorStrategy::LongestSuffixMatch => Some((last, false)),
+        AnchorStrategy::LongestMatch => {
+            let longest = (0..matches.len()).max_by_key(|&i| matches[i].0.len())?;
+            Some((longest, true))
+        }
+        AnchorStrategy::NearestToPreviousAnchor => {
+            // The last anchor is in terms of all of `b`
+            let full_b_len = window.b_start + b_len;
+            let previous = context.scope.last_anchor.filter(|&(anchor_b_len, _)| anchor_b_len <= full_b_len);
+            let Some((anchor_b_len, pos)) = previous else {
+                return Some((last, false));
+            };
+            let expected = pos + full_b_len - anchor_b_len;
+            // The later match on ties
+            let nearest = (0..matches.len()).rev().min_by_key(|&i| projected_pos(&matches[i]).abs_diff(expected))?;
+            Some((nearest, true))
+        }
+        AnchorStrategy::RecencyWeighted => {
+            let (counts, a_len) = context.counts;
+            let score = |(a_range, b_range): &(Range<u32>, Range<u32>)| {
+                let tokens = &a_tokens[a_range.start as usize..a_range.end as usize];
+                let inverse_frequency = |token: &Token| {
+                    let count = counts.get(token.0 as usize).copied().unwrap_or(1).max(1);
+                    (a_len as f64 / count as f64).ln()
+                };
+                let rarity = tokens.iter().map(inverse_frequency).sum::<f64>() / tokens.len().max(1) as f64;
+                context.weights.score(tokens.len(), b_range.end, b_len as u32, rarity)
+            };
+            // The later match on ties
+            let (best, _) = matches.iter().map(score).enumerate().max_by(|(_, x), (_, y)| x.total_cmp(y))?;
+            Some((best, true))
+        }
+    }
+}
+
+/// The longest run of `a_tokens` equal to the end of `b_tokens` for
+/// [`AnchorStrategy::LongestSuffixMatch`], the earliest on ties, found by
+/// scanning the window instead of diffing. Also returns the number of
+/// matches found (0 or 1).
+fn longest_suffix_anchor(context: &DiffContext<'_>, a_tokens: &[Token], b_tokens: &[Token], window: Window) -> (Anchor, usize) {
+    let suffix_len = |end: usize| a_tokens[..end].iter().rev().zip(b_tokens.iter().rev()).take_while(|(x, y)| x == y).count();
+    let longest = (1..=a_tokens.len())
+        .map(|end| (end, suffix_len(end)))
+        .filter(|&(_, match_len)| match_len > 0)
+        .max_by_key(|&(end, match_len)| (match_len, std::cmp::Reverse(end)));
+    let Some((end, match_len)) = longest else {
+        return (if window.applied { Anchor::Miss } else { Anchor::NoMatch }, 0);
+    };
+    context.lengths.record([match_len]);
+    if context.junk.is_some() && a_tokens[end - match_len..end].iter().all(|&token| context.is_junk(token)) {
+        trace_event!(debug, match_len, "the longest suffix match is all junk tokens");
+        return (Anchor::Miss, 1);
+    }
+    (Anchor::At { pos: window.a_start + end, match_len }, 1)
+}
+
+
 
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::time::Duration;
+    use std::sync::Mutex;
+    use crate::{EscalationPolicy, FallbackPolicy};
 
     #[test]
     fn test_stream_next_chunk() {
@@ -308,107 +2013,1108 @@ mod test {
 
 
     #[test]
-    fn test_real_case1_simulation() {
-        // --- Placeholder Data ---
-        // Replace these with your actual _long_testcase data
-        // let input_tokens: Vec<i32> = _long_testcase[0].to_vec(); // Example if loaded elsewhere
-        // let output_tokens: Vec<i32> = _long_testcase[1].to_vec();
-        let input_tokens: Vec<i32> = (1..=1000).collect(); // Mock data 1
-        let mut output_tokens: Vec<i32> = (1..=500).collect(); // Mock data 2 (shorter)
-        // Simulate some differences for a more realistic test
-        output_tokens[10] = 999;
-        output_tokens[100] = 888;
-        output_tokens.extend(501..=1000); // Add the rest matching input
-        output_tokens[600] = 777; // Another difference
-        // --- End Placeholder Data ---
+    fn test_next_chunk_into() {
+        let a: Vec<u32> = (10..20).collect();
+        let streamer = StreamNextChunk::new(&a);
+        let mut out = [0; 4];
+        assert_eq!(streamer.next_chunk_into(&a[..3], &mut out), 4);
+        assert_eq!(out, [13, 14, 15, 16]);
+        assert_eq!(streamer.next_chunk_into(&a[..8], &mut out), 2);
+        assert_eq!(out, [18, 19, 15, 16]);
+        assert_eq!(streamer.next_chunk_into(&a, &mut out), 0);
+        assert_eq!(streamer.next_chunk_into(&a[..3], &mut []), 0);
+    }
 
-        let mut current_idx: usize = 0;
-        let spec_num_tokens: usize = 80; // chunk_size for prediction
-        let mut iter: u32 = 0;
-        let mut total_accepted: usize = 0;
-        let mut first_token_duration: Option<Duration> = None; // Use Option for clarity
+    #[test]
+    fn test_next_chunk_depths() {
+        let a: Vec<u32> = (0..200).collect();
+        let streamer = StreamNextChunk::new(&a);
+        let b: Vec<u32> = [999].into_iter().chain(20..40).collect();
+        let chunks = streamer.next_chunk_depths(&b, &[32, 8, 128]);
+        assert_eq!(chunks, [&a[40..72], &a[40..48], &a[40..168]]);
+        assert_eq!(streamer.stats().diff_calls, 1);
+        // Past the end of `a`
+        assert_eq!(streamer.next_chunk_depths(&a[..150], &[8, 128]), [&a[150..158], &a[150..]]);
+        let calls = streamer.stats().calls;
+        assert!(streamer.next_chunk_depths(&b, &[]).is_empty());
+        // Nothing asked for, nothing matched or recorded
+        assert_eq!(streamer.stats().calls, calls);
+    }
 
-        // Create the streamer instance
-        let streamer = StreamNextChunk::new(&input_tokens);
+    #[test]
+    fn test_generic_token_types() {
+        // u32 ids above i32::MAX must round-trip without truncation
+        let big = u32::MAX - 10;
+        let a_u32: Vec<u32> = (0..10).map(|i| big + i).collect();
+        let streamer = StreamNextChunk::new(&a_u32);
+        let next = streamer.next_chunk(&a_u32[..4], 3);
+        assert_eq!(next, &[big + 4, big + 5, big + 6]);
+
+        let a_i64: Vec<i64> = (0..10).map(|i| (i64::MAX - 100) + i).collect();
+        let streamer = StreamNextChunk::from_vec(a_i64.clone());
+        let next = streamer.next_chunk(&a_i64[..8], 5);
+        assert_eq!(next, &a_i64[8..]);
+    }
 
-        println!("Input token count: {}", input_tokens.len());
-        println!("Output token count: {}", output_tokens.len());
-        println!("Chunk size: {}", spec_num_tokens);
 
-        let start_time = Instant::now(); // Start timing
 
-        while current_idx < output_tokens.len() {
-            iter += 1;
-            // println!("Iteration {}: Current output index: {}", iter, current_idx); // Optional detailed log
+    #[test]
+    fn test_stateful_append_predict() {
+        let original_a: Vec<i32> = (1..=300).collect();
+        let mut output: Vec<i32> = (1..=300).collect();
+        output[40] = 999; // divergence forces a re-diff
+        output.insert(150, 888);
+
+        let stateless = StreamNextChunk::new(&original_a);
+        let mut streamer = StreamNextChunk::new(&original_a);
+        assert_eq!(streamer.predict(4), &[1, 2, 3, 4]);
+
+        for (i, chunk) in output.chunks(7).enumerate() {
+            streamer.append(chunk);
+            let b_len = min((i + 1) * 7, output.len());
+            let expected = stateless.next_chunk(&output[..b_len], 5).to_vec();
+            assert_eq!(streamer.predict(5), expected.as_slice(), "after {} tokens", b_len);
+        }
+        assert_eq!(streamer.appended(), output.as_slice());
+
+        streamer.reset();
+        assert!(streamer.appended().is_empty());
+        streamer.append(&[1, 2, 5000]);
+        assert_eq!(streamer.predict(3), &[] as &[i32]);
+        streamer.append(&[4, 5]);
+        assert_eq!(streamer.predict(3), &[6, 7, 8]);
+    }
 
-            // Get the portion of output_tokens processed so far
-            let current_b_slice = &output_tokens[0..current_idx];
 
-            // Predict the next chunk based on current_b
-            let predict_chunk = streamer.next_chunk(current_b_slice, spec_num_tokens);
 
-            // Record time for the first prediction
-            if first_token_duration.is_none() {
-                first_token_duration = Some(start_time.elapsed());
-            }
+    #[test]
+    fn test_algorithm_selection() {
+        let original_a: Vec<i32> = (0..50).collect();
+        let mut current_b: Vec<i32> = (0..20).collect();
+        current_b[7] = -1;
+
+        let options = NextChunkOptions { algorithm: DiffAlgorithm::Myers, ..Default::default() };
+        let mut streamer = StreamNextChunk::with_options(original_a, options);
+        assert_eq!(streamer.options().algorithm, DiffAlgorithm::Myers);
+        for algorithm in [DiffAlgorithm::Histogram, DiffAlgorithm::Myers, DiffAlgorithm::MyersMinimal] {
+            assert_eq!(streamer.next_chunk_with_algorithm(&current_b, 3, algorithm), &[20, 21, 22]);
+        }
+        streamer.set_algorithm(DiffAlgorithm::MyersMinimal);
+        assert_eq!(streamer.next_chunk(&current_b, 3), &[20, 21, 22]);
+    }
+
+
+
+    #[test]
+    fn test_next_chunk_with_info() {
+        let original_a: Vec<i32> = (0..50).collect();
+        let streamer = StreamNextChunk::new(&original_a);
 
-            if predict_chunk.is_empty() && current_idx < output_tokens.len() {
-                // If prediction is empty but we aren't finished, it means there was likely
-                // a mismatch preventing prediction. We must advance by at least 1
-                // to potentially find a new match point later.
-                println!("Iteration {}: Prediction empty, advancing by 1.", iter);
-                current_idx += 1;
-                continue; // Skip comparison for this iteration
-            } else if predict_chunk.is_empty() {
-                // Prediction is empty and we are likely at the end or cannot predict further
-                println!("Iteration {}: Prediction empty, loop should terminate.", iter);
-                break; // Exit loop
+        let info = streamer.next_chunk_with_info(&[7, 30, 31, 32], 3);
+        assert_eq!(info.tokens, &[33, 34, 35]);
+        assert_eq!(info.start, Some(33));
+        assert_eq!(info.match_len, 3);
+        assert!(!info.windowed);
+
+        let info = streamer.next_chunk_with_info(&[], 2);
+        assert_eq!((info.tokens, info.start, info.match_len), (&[0, 1][..], Some(0), 0));
+
+        let info = streamer.next_chunk_with_info(&[1, 2, -5], 2);
+        assert!(info.tokens.is_empty());
+        assert_eq!(info.start, None);
+
+        // windowing kicks in once a is long enough for window_size >= 100
+        let long_a: Vec<i32> = (0..3000).collect();
+        let streamer = StreamNextChunk::new(&long_a);
+        let mut b = long_a[..1000].to_vec();
+        b[0] = -1;
+        let info = streamer.next_chunk_with_info(&b, 4);
+        assert!(info.windowed);
+        assert_eq!(info.tokens, &[1000, 1001, 1002, 1003]);
+        assert_eq!(info.start, Some(1000));
+        // an exact prefix of a skips the diff (and windowing) entirely
+        let info = streamer.next_chunk_with_info(&long_a[..1000], 4);
+        assert_eq!((info.start, info.match_len, info.windowed), (Some(1000), 1000, false));
+
+        let mut streamer = StreamNextChunk::new(&original_a);
+        streamer.append(&[10, 11, 12]);
+        let info = streamer.predict_with_info(2);
+        assert_eq!((info.tokens, info.start, info.match_len), (&[13, 14][..], Some(13), 3));
+        streamer.append(&[13]);
+        assert_eq!(streamer.predict_with_info(2).match_len, 4);
+    }
+
+
+
+    #[test]
+    fn test_suffix_automaton_backend() {
+        let original_a: Vec<i32> = (0..50).chain(100..150).collect();
+        let options = NextChunkOptions { matcher: MatcherBackend::SuffixAutomaton, ..Default::default() };
+        let mut streamer = StreamNextChunk::with_options(original_a.clone(), options);
+
+        assert_eq!(streamer.next_chunk(&[], 3), &[0, 1, 2]);
+        let info = streamer.next_chunk_with_info(&[7, 8, -1, 120, 121], 3);
+        assert_eq!(info.tokens, &[122, 123, 124]);
+        assert_eq!((info.start, info.match_len), (Some(72), 2));
+        assert_eq!(streamer.next_chunk(&[1, 2, -1], 3), &[] as &[i32]);
+
+        // stateful mode agrees with the diff backend on a simple divergence
+        let diff_streamer = StreamNextChunk::new(&original_a);
+        let b = [0, 1, 2, -1, 110, 111, 112];
+        streamer.append(&b[..4]);
+        assert_eq!(streamer.predict(2), &[] as &[i32]);
+        streamer.append(&b[4..]);
+        assert_eq!(streamer.predict(2), diff_streamer.next_chunk(&b, 2));
+        streamer.reset();
+        streamer.append(&[140]);
+        assert_eq!(streamer.predict(2), &[141, 142]);
+    }
+
+    #[test]
+    fn test_suffix_array_backend() {
+        let a: Vec<i32> = (0..50).chain(100..150).chain(10..20).collect();
+        let sam = StreamNextChunk::with_options(a.clone(), NextChunkOptions { matcher: MatcherBackend::SuffixAutomaton, ..Default::default() });
+        let mut sa = StreamNextChunk::with_options(a.clone(), NextChunkOptions { matcher: MatcherBackend::SuffixArray, ..Default::default() });
+        let bs: [&[i32]; 5] = [&[], &[7, 8, -1, 120, 121], &[1, 2, -1], &[12, 13, 14], &[-1, 149, 10]];
+        for b in bs {
+            assert_eq!(sa.next_chunk_with_info(b, 3), sam.next_chunk_with_info(b, 3), "b = {:?}", b);
+        }
+
+        sa.append(&[0, 1, 2, -1]);
+        assert_eq!(sa.predict(2), &[] as &[i32]);
+        sa.append(&[110, 111]);
+        assert_eq!(sa.predict(2), &[112, 113]);
+        sa.extend_reference(&[-1, 7]);
+        sa.reset();
+        sa.append(&[19, -1]);
+        assert_eq!(sa.predict(2), &[7]);
+        assert!(sa.memory_usage().used > 0);
+    }
+
+    #[test]
+    fn test_hash_chain_backend() {
+        let a: Vec<i32> = (0..50).chain(100..150).collect();
+        let options = NextChunkOptions { matcher: MatcherBackend::HashChain, ..Default::default() };
+        let mut streamer = StreamNextChunk::with_options(a.clone(), options);
+
+        assert_eq!(streamer.next_chunk(&[], 3), &[0, 1, 2]);
+        let info = streamer.next_chunk_with_info(&[7, 8, -1, 120, 121, 122, 123], 3);
+        assert_eq!((info.tokens, info.start, info.match_len), (&[124, 125, 126][..], Some(74), 4));
+        // Matches shorter than a hashed run aren't found
+        assert_eq!(streamer.next_chunk(&[1, 2, -1, 120, 121], 3), &[] as &[i32]);
+
+        streamer.append(&[0, 1, 2, -1]);
+        assert_eq!(streamer.predict(2), &[] as &[i32]);
+        streamer.append(&[110, 111, 112, 113]);
+        assert_eq!(streamer.predict(2), &[114, 115]);
+        streamer.extend_reference(&[-1, 7, 8, 9]);
+        streamer.reset();
+        streamer.append(&[148, 149, -1, 7]);
+        assert_eq!(streamer.predict(2), &[8, 9]);
+
+        let sam = StreamNextChunk::with_options(a, NextChunkOptions { matcher: MatcherBackend::SuffixAutomaton, ..Default::default() });
+        assert!(streamer.memory_usage().used < sam.memory_usage().used);
+    }
+
+
+
+    #[test]
+    fn test_rolling_hash_window() {
+        // `b` skipped a large block of `a`, so equal offsets put the 'a' window
+        // in the wrong place; the rolling hash pre-pass finds the right one
+        let a: Vec<i32> = (0..6000).collect();
+        let b: Vec<i32> = (0..1000).chain(4000..5000).collect();
+        let located = StreamNextChunk::new(&a);
+        let info = located.next_chunk_with_info(&b, 3);
+        assert!(info.windowed);
+        assert_eq!(info.tokens, &[5000, 5001, 5002]);
+
+        let options = NextChunkOptions { anchor_hash_len: 0, multi_window_search: false, ..Default::default() };
+        let length_based = StreamNextChunk::with_options(a, options);
+        assert_eq!(length_based.next_chunk(&b, 3), &[] as &[i32]);
+    }
+
+
+
+    #[test]
+    fn test_last_anchor_window() {
+        // The model wrote 1500 new tokens, then went on with `a` so heavily
+        // edited that no k-gram of b's window occurs in `a`: the window is
+        // placed from the caller's last confirmed anchor instead of equal offsets
+        let a: Vec<i32> = (0..6000).collect();
+        let inserted = (0..1500).map(|i| -1 - i);
+        let b: Vec<i32> = a[..1000].iter().copied().chain(inserted).chain(1000..1100).collect();
+        let noisy = (1100..1600).map(|t| if t % 4 == 1 { -t } else { t });
+        let b2: Vec<i32> = b.iter().copied().chain(noisy).collect();
+
+        let options = NextChunkOptions { multi_window_search: false, ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a.clone(), options.clone());
+        let (result, cursor) = streamer.next_chunk_from(&b, 3, None);
+        assert_eq!(result.tokens, [1100, 1101, 1102]);
+        let (info, _) = streamer.next_chunk_from(&b2, 3, Some(&cursor));
+        assert!(info.windowed);
+        assert_eq!(info.tokens, [1600, 1601, 1602]);
+        // Without its cursor, the anchor of another caller's call isn't used
+        assert!(streamer.next_chunk(&b2, 3).is_empty());
+
+        let mut stateful = StreamNextChunk::with_options(a, options);
+        stateful.append(&b);
+        assert_eq!(stateful.predict(3), [1100, 1101, 1102]);
+        stateful.append(&b2[b.len()..]);
+        assert_eq!(stateful.predict(3), [1600, 1601, 1602]);
+    }
+
+    #[test]
+    fn test_last_call_info() {
+        let a: Vec<i32> = (0..6000).collect();
+        let b: Vec<i32> = (0..1000).chain(4000..5000).collect();
+        let streamer = StreamNextChunk::new(&a);
+        assert_eq!(streamer.last_call_info(), None);
+        streamer.next_chunk(&b, 3);
+        let info = streamer.last_call_info().unwrap();
+        assert!(info.windowed && info.a_window.is_some_and(|(start, end)| start < 4600 && 5000 <= end));
+        assert_eq!((info.b_window_start, info.anchor), (1600, CallAnchor::At { pos: 5000, match_len: 400 }));
+
+        let options = NextChunkOptions { anchor_hash_len: 0, multi_window_search: false, ..Default::default() };
+        let length_based = StreamNextChunk::with_options(a.clone(), options);
+        assert!(length_based.next_chunk(&b, 3).is_empty());
+        let info = length_based.last_call_info().unwrap();
+        assert_eq!((info.windowed, info.b_window_start, info.anchor), (true, 1600, CallAnchor::Miss));
+        length_based.next_chunk(&b, 0);
+        assert_eq!(length_based.last_call_info().unwrap().anchor, CallAnchor::Skipped);
+
+        // Fast paths don't diff
+        streamer.next_chunk(&a[..10], 3);
+        let info = streamer.last_call_info().unwrap();
+        assert_eq!((info.a_window, info.anchor), (None, CallAnchor::At { pos: 10, match_len: 10 }));
+
+        let mut stateful = StreamNextChunk::with_options((0..50).collect(), NextChunkOptions::default());
+        stateful.append(&[100, 101]);
+        assert_eq!(stateful.predict(2), [0, 1]);
+        let info = stateful.last_call_info().unwrap();
+        assert_eq!((info.a_window, info.anchor), (Some((0, 50)), CallAnchor::NoMatch));
+    }
+
+    #[test]
+    fn test_prediction_hook() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut streamer = StreamNextChunk::new(&(0..50).collect::<Vec<i32>>());
+        let log = seen.clone();
+        streamer.set_hook(Some(Arc::new(move |result: &PredictionResult<'_, i32>, call: &CallStats| {
+            log.lock().unwrap().push((result.tokens.to_vec(), result.start, call.anchor));
+        })));
+        streamer.next_chunk(&[5, -1, 7, 8], 2);
+        streamer.append(&[0, 1]);
+        streamer.predict(3);
+        assert_eq!(*seen.lock().unwrap(), [
+            (vec![9, 10], Some(9), CallAnchor::At { pos: 9, match_len: 2 }),
+            (vec![2, 3, 4], Some(2), CallAnchor::At { pos: 2, match_len: 2 }),
+        ]);
+        streamer.set_hook(None);
+        streamer.next_chunk(&[1], 2);
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_fallback_policy() {
+        let a: Vec<i32> = (0..50).collect();
+        let streamer = |fallback| StreamNextChunk::with_options(a.clone(), NextChunkOptions { fallback, ..Default::default() });
+        // Nothing matched at all, then `b` ending mid-change
+        let (unmatched, mid_change) = ([100, 101], [20, 21, 22, 1000]);
+        let legacy = streamer(None);
+        assert_eq!(legacy.next_chunk(&unmatched, 2), [0, 1]);
+        assert!(legacy.next_chunk(&mid_change, 2).is_empty());
+
+        let empty = streamer(Some(FallbackPolicy::Empty));
+        assert!(empty.next_chunk(&unmatched, 2).is_empty());
+        let start = streamer(Some(FallbackPolicy::StartOfA));
+        assert_eq!(start.next_chunk(&mid_change, 2), [0, 1]);
+        let offset = streamer(Some(FallbackPolicy::Offset(10)));
+        let info = offset.next_chunk_with_info(&mid_change, 2);
+        assert_eq!((info.tokens, info.start, info.match_len), (&[10, 11][..], Some(10), 0));
+        assert_eq!(offset.next_chunk(&[20, 21], 2), [22, 23]);
+
+        // Goes on from the last anchor, past the one token `b` grew by
+        let mut last = streamer(Some(FallbackPolicy::LastPosition));
+        assert!(last.next_chunk(&mid_change, 2).is_empty());
+        assert_eq!(last.next_chunk(&mid_change[..3], 2), [23, 24]);
+        assert_eq!(last.next_chunk(&mid_change, 2), [24, 25]);
+        last.append(&mid_change[..3]);
+        assert_eq!(last.predict(2), [23, 24]);
+        last.append(&[1000, 1001]);
+        assert_eq!(last.predict(2), [25, 26]);
+        last.set_fallback(None);
+        assert!(last.predict(2).is_empty());
+    }
+
+    #[test]
+    fn test_window_escalation() {
+        // The length-based window and its 2x/4x widenings miss `b`'s skip
+        // over a large block of `a`; the full diff finds it
+        let a: Vec<i32> = (0..6000).collect();
+        let b: Vec<i32> = (0..1000).chain(4000..5000).collect();
+        // Generous enough for unoptimized builds
+        let policy = EscalationPolicy { budget: Duration::from_secs(60), ..Default::default() };
+        let options = |escalation| NextChunkOptions {
+            anchor_hash_len: 0,
+            multi_window_search: false,
+            escalation,
+            ..Default::default()
+        };
+        let streamer = StreamNextChunk::with_options(a.clone(), options(Some(policy.clone())));
+        let info = streamer.next_chunk_with_info(&b, 3);
+        assert_eq!((info.tokens, info.windowed), (&[5000, 5001, 5002][..], false));
+        assert_eq!(streamer.stats().last.unwrap().escalations, 3);
+
+        let mut stateful = StreamNextChunk::with_options(a.clone(), options(Some(policy.clone())));
+        stateful.append(&b);
+        assert_eq!(stateful.predict(3), [5000, 5001, 5002]);
+
+        let windows_only = EscalationPolicy { full_diff: false, ..policy.clone() };
+        let streamer = StreamNextChunk::with_options(a.clone(), options(Some(windows_only)));
+        assert!(streamer.next_chunk(&b, 3).is_empty());
+        assert_eq!(streamer.stats().last.unwrap().escalations, 2);
+
+        let no_budget = EscalationPolicy { budget: Duration::ZERO, ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a, options(Some(no_budget)));
+        assert!(streamer.next_chunk(&b, 3).is_empty());
+        assert_eq!(streamer.stats().last.unwrap().escalations, 0);
+    }
+
+    #[test]
+    fn test_global_reanchor() {
+        // The length-based window misses `b`'s jump to a later block of `a`;
+        // after two empty windowed predictions the third call diffs all of `a`
+        let a: Vec<i32> = (0..6000).collect();
+        let b: Vec<i32> = (0..1000).chain(4000..5000).collect();
+        let options = NextChunkOptions {
+            anchor_hash_len: 0,
+            multi_window_search: false,
+            global_reanchor_after: Some(2),
+            ..Default::default()
+        };
+        let streamer = StreamNextChunk::with_options(a.clone(), options.clone());
+        assert!(streamer.next_chunk(&b, 3).is_empty());
+        assert!(streamer.next_chunk(&b, 3).is_empty());
+        assert_eq!(streamer.next_chunk(&b, 3), [5000, 5001, 5002]);
+        let stats = streamer.stats();
+        assert_eq!((stats.global_reanchors, stats.empty_predictions), (1, 2));
+        assert!(stats.last.unwrap().global_reanchor);
+
+        let mut stateful = StreamNextChunk::with_options(a.clone(), options);
+        stateful.append(&b);
+        assert!(stateful.predict(3).is_empty());
+        assert!(stateful.predict(3).is_empty());
+        assert_eq!(stateful.predict(3), [5000, 5001, 5002]);
+        assert_eq!(stateful.stats().global_reanchors, 1);
+
+        let never = NextChunkOptions { anchor_hash_len: 0, multi_window_search: false, ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a, never);
+        for _ in 0..4 {
+            assert!(streamer.next_chunk(&b, 3).is_empty());
+        }
+        assert_eq!(streamer.stats().global_reanchors, 0);
+    }
+
+    #[test]
+    fn test_junk_tokens() {
+        // 0 is EOS, separating the documents of `a`
+        let a = vec![1, 2, 3, 0, 4, 5, 6, 0, 7, 8];
+        let mut streamer = StreamNextChunk::from_vec(a);
+        assert_eq!(streamer.next_chunk(&[9, 0], 2), [4, 5]);
+        assert_eq!(streamer.next_chunk(&[1, 2], 3), [3, 0, 4]);
+
+        streamer.set_junk_tokens([0]);
+        assert!(streamer.junk_tokens().contains(&0));
+        assert!(streamer.next_chunk(&[9, 0], 2).is_empty());
+        assert_eq!(streamer.next_chunk(&[1, 2], 3), [3]);
+        assert!(streamer.next_chunk(&[1, 2, 3], 3).is_empty());
+        assert_eq!(streamer.next_chunk(&[3, 0], 2), [4, 5]);
+        assert_eq!(streamer.next_chunk_candidates(&[5, 6], 2, 3).len(), 0);
+
+        streamer.set_fallback(Some(FallbackPolicy::Offset(4)));
+        assert_eq!(streamer.next_chunk(&[9, 0], 2), [4, 5]);
+        streamer.set_junk_tokens([]);
+        streamer.set_fallback(None);
+        assert_eq!(streamer.next_chunk(&[1, 2], 3), [3, 0, 4]);
+    }
+
+    #[test]
+    fn test_coarse_block_diff() {
+        let a: Vec<i32> = (0..120_000).map(|i| (i * 7919) % 100_003).collect();
+        // Short enough not to be windowed, so `b` is diffed against all of `a`
+        let b: Vec<i32> = a[50_000..51_000].iter().copied().chain([-1]).chain(a[51_000..52_000].iter().copied()).collect();
+        let options = NextChunkOptions { coarse_block_len: Some(64), ..Default::default() };
+        let mut streamer = StreamNextChunk::with_options(a.clone(), options.clone());
+        assert_eq!(streamer.next_chunk(&b, 4), &a[52_000..52_004]);
+        let call = streamer.last_call_info().unwrap();
+        assert!(call.coarse && !call.windowed);
+        let (a_start, a_end) = call.a_window.unwrap();
+        assert!(a_start >= 51_000 && a_end < 53_000, "{a_start}..{a_end}");
+
+        streamer.append(&b);
+        assert_eq!(streamer.predict(4), &a[52_000..52_004]);
+        assert!(streamer.last_call_info().unwrap().coarse);
+
+        let plain = StreamNextChunk::from_vec(a.clone());
+        assert_eq!(plain.next_chunk(&b, 4), &a[52_000..52_004]);
+        assert!(!plain.last_call_info().unwrap().coarse);
+        let short = StreamNextChunk::with_options(a[..50_000].to_vec(), options);
+        assert_eq!(short.next_chunk(&a[20_000..21_000], 4), &a[21_000..21_004]);
+        assert!(!short.last_call_info().unwrap().coarse);
+    }
+
+    #[test]
+    fn test_diff_segments() {
+        let a: Vec<i32> = (0..3000).collect();
+        let options = NextChunkOptions { diff_segment_len: Some(500), ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a.clone(), options);
+        // Shorter than the window, so `b` is diffed against all of `a`
+        let b: Vec<i32> = a[2000..2050].iter().copied().chain([-1]).chain(a[2050..2100].iter().copied()).collect();
+        assert_eq!(streamer.next_chunk(&b, 4), &a[2100..2104]);
+        let call = streamer.last_call_info().unwrap();
+        assert_eq!(call.segments, 8);
+        assert_eq!(call.anchor, CallAnchor::At { pos: 2100, match_len: 50 });
+
+        // No segment matched at all, as the full diff would tell
+        assert_eq!(streamer.next_chunk(&[-1, -2], 4), &a[..4]);
+        assert_eq!(streamer.last_call_info().unwrap().anchor, CallAnchor::NoMatch);
+        assert_eq!(StreamNextChunk::from_vec(a.clone()).next_chunk(&b, 4), &a[2100..2104]);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let a: Vec<i32> = (0..3000).collect();
+        let usage = StreamNextChunk::from_vec(a.clone()).memory_usage();
+        assert!(usage.used > usage.required && !usage.degraded && !usage.windowed_only);
+
+        // Room for the interned `a`, but not the rolling hash index or a suffix
+        // automaton: the window is placed by length, and the search finds the right one
+        let options =
+            NextChunkOptions { memory_budget: Some(usage.required + 1000), multi_window_search: true, ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a.clone(), options);
+        let usage = streamer.memory_usage();
+        assert!(usage.degraded && !usage.windowed_only && usage.used == usage.required);
+        assert_eq!(streamer.next_chunk(&a[1000..1500], 4), &a[1500..1504]);
+        assert_eq!(streamer.longest_match_at(&a[..10], 5), None);
+
+        // Not even room for the interned `a`: a short `b` is diffed against a window too
+        let options = NextChunkOptions { memory_budget: Some(1000), ..Default::default() };
+        let mut streamer = StreamNextChunk::with_options(a.clone(), options);
+        assert!(streamer.memory_usage().windowed_only);
+        let b: Vec<i32> = [-1].into_iter().chain(a[10..30].iter().copied()).collect();
+        assert_eq!(streamer.next_chunk(&b, 4), &a[30..34]);
+        let call = streamer.last_call_info().unwrap();
+        assert!(call.windowed && call.a_window.is_some_and(|(start, end)| end - start < a.len()));
+        streamer.extend_reference(&[-5; 100]);
+        assert!(streamer.memory_usage().windowed_only);
+    }
+
+    #[test]
+    fn test_anchor_strategy() {
+        let with_strategy = |a: &[i32], anchor_strategy| {
+            StreamNextChunk::with_options(a.to_vec(), NextChunkOptions { anchor_strategy, ..Default::default() })
+        };
+
+        // `b` diverged from `a` after a long match, then matched again briefly
+        let a: Vec<i32> = (0..100).collect();
+        let b: Vec<i32> = (10..30).chain([500, 501]).chain(60..63).collect();
+        assert_eq!(with_strategy(&a, AnchorStrategy::LastMatch).next_chunk(&b, 3), &[63, 64, 65]);
+        assert_eq!(with_strategy(&a, AnchorStrategy::LongestMatch).next_chunk(&b, 3), &[35, 36, 37]);
+
+        // The end of `b` is also found earlier in `a` than where the diff aligned it
+        let a: Vec<i32> = (0..50).chain([7, 8, 9]).chain(50..60).collect();
+        let b: Vec<i32> = (0..21).chain([7, 8, 9]).collect();
+        assert_eq!(with_strategy(&a, AnchorStrategy::LastMatch).next_chunk(&b, 3), &[50, 51, 52]);
+        assert_eq!(with_strategy(&a, AnchorStrategy::LongestSuffixMatch).next_chunk(&b, 3), &[10, 11, 12]);
+
+        // After a stray token `b` jumped ahead, but the previous anchor says it
+        // should still be around where it was
+        let a: Vec<i32> = (0..40).chain(200..210).chain(0..40).collect();
+        let b: Vec<i32> = (205..210).chain(0..5).collect();
+        let jumped: Vec<i32> = b.iter().copied().chain([5, 6, 7, 999, 30, 31]).collect();
+        for (strategy, expected) in [(AnchorStrategy::LastMatch, [32, 33, 34]), (AnchorStrategy::NearestToPreviousAnchor, [11, 12, 13])] {
+            let streamer = with_strategy(&a, strategy);
+            assert_eq!(streamer.next_chunk(&b, 3), &[5, 6, 7]);
+            assert_eq!(streamer.next_chunk(&jumped, 3), &expected);
+        }
+
+        // The model appended a novel token after a long match
+        let a: Vec<i32> = (0..100).collect();
+        let b: Vec<i32> = (10..40).chain([999]).collect();
+        assert_eq!(with_strategy(&a, AnchorStrategy::LastMatch).next_chunk(&b, 3), &[] as &[i32]);
+        assert_eq!(with_strategy(&a, AnchorStrategy::RecencyWeighted).next_chunk(&b, 3), &[41, 42, 43]);
+    }
+
+    #[test]
+    fn test_deadline() {
+        let a: Vec<i32> = (0..3000).collect();
+        let b: Vec<i32> = [-1].into_iter().chain(a[10..30].iter().copied()).collect();
+        let options = NextChunkOptions { deadline: Some(Duration::ZERO), ..Default::default() };
+        let mut streamer = StreamNextChunk::with_options(a.clone(), options);
+        assert_eq!(streamer.next_chunk(&b, 4), &[] as &[i32]);
+        let call = streamer.last_call_info().unwrap();
+        assert!(call.timed_out && call.matches == 0 && call.anchor == CallAnchor::Miss);
+        // No diff needed, no time needed
+        assert_eq!(streamer.next_chunk(&a[..20], 4), &a[20..24]);
+        assert!(!streamer.last_call_info().unwrap().timed_out);
+        assert_eq!(streamer.stats().timeouts, 1);
+
+        streamer.set_fallback(Some(FallbackPolicy::Offset(7)));
+        assert_eq!(streamer.next_chunk(&b, 4), &a[7..11]);
+
+        let options = NextChunkOptions { deadline: Some(Duration::from_secs(60)), ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a.clone(), options.clone());
+        assert_eq!(streamer.next_chunk(&b, 4), &a[30..34]);
+        assert!(!streamer.last_call_info().unwrap().timed_out);
+
+        // A long `a` is diffed a segment at a time, so no single diff outlasts the deadline
+        let a: Vec<i32> = (0..10_000).collect();
+        let streamer = StreamNextChunk::with_options(a.clone(), options);
+        let b: Vec<i32> = [-1].into_iter().chain(a[9000..9020].iter().copied()).collect();
+        assert_eq!(streamer.next_chunk(&b, 4), &a[9020..9024]);
+        assert_eq!(streamer.last_call_info().unwrap().segments, 3);
+    }
+
+    #[test]
+    fn test_autojunk() {
+        // Half of `a` is the newline token 0, which alone anchors anywhere
+        let tail = [2000, 2001, 2002];
+        let a: Vec<i32> = (1000..1150).flat_map(|line| [line, 0]).chain(tail).collect();
+        let b = [5000, 6000, 0];
+        let plain = StreamNextChunk::from_vec(a.clone());
+        assert_eq!(plain.next_chunk(&b, 2), [1001, 0]);
+        assert_eq!(plain.last_call_info().unwrap().anchor, CallAnchor::At { pos: 2, match_len: 1 });
+
+        let options = NextChunkOptions { autojunk: Some(0.1), ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a.clone(), options.clone());
+        assert!(streamer.next_chunk(&b, 2).is_empty());
+        assert_eq!(streamer.last_call_info().unwrap().anchor, CallAnchor::Miss);
+        // A match with a non-junk token still anchors
+        assert_eq!(streamer.next_chunk(&[5000, 1005, 0], 2), [1006, 0]);
+
+        // Too short for autojunk to apply
+        let short: Vec<i32> = a[..100].iter().copied().chain(tail).collect();
+        let streamer = StreamNextChunk::with_options(short, options);
+        assert_eq!(streamer.next_chunk(&b, 2), [1001, 0]);
+    }
+
+    #[test]
+    fn test_boundary_equivalence() {
+        // The model emitted 1010, a merged form of a's 10, right at the boundary
+        let a: Vec<i32> = (0..100).collect();
+        let b: Vec<i32> = (0..10).chain([1010]).collect();
+        let mut streamer = StreamNextChunk::from_vec(a.clone());
+        assert!(streamer.next_chunk(&b, 3).is_empty());
+        streamer.set_boundary_equivalence(Some(BoundaryEquivalence::new([(1010, 10)])));
+        let info = streamer.next_chunk_with_info(&b, 3);
+        assert_eq!((info.tokens, info.start), (&[11, 12, 13][..], Some(11)));
+        assert!(streamer.last_call_info().unwrap().boundary_skip);
+        // Only the token right after the anchor can be skipped
+        assert!(streamer.next_chunk(&[0, 1, 2, 1010], 3).is_empty());
+
+        streamer.append(&b);
+        assert_eq!(streamer.predict(3), [11, 12, 13]);
+        streamer.append(&[11, 12]);
+        assert_eq!(streamer.predict(2), [13, 14]);
+    }
+
+    #[test]
+    fn test_landmarks() {
+        let a: Vec<i32> = (0..100).collect();
+        let mut streamer = StreamNextChunk::from_vec(a);
+        streamer.set_landmarks([40..44, 70..73]);
+        assert_eq!(streamer.landmarks(), [40..44, 70..73]);
+
+        streamer.append(&[0, 1, 2, -1]);
+        streamer.append(&[-2, 40, 41, 42, 43, 44]);
+        assert_eq!(streamer.predict_with_info(2).match_len, 5);
+        assert_eq!(streamer.predict(2), [45, 46]);
+        // Re-anchored when the landmark was reached, no diff
+        assert!(streamer.last_call_info().unwrap().fast_path);
+
+        // Not followed by `a` after the landmark: re-diffed as usual
+        streamer.append(&[-1, 70, 71, 72, -3, 80, 81]);
+        assert_eq!(streamer.predict(2), [82, 83]);
+        assert!(!streamer.last_call_info().unwrap().fast_path);
+
+        streamer.set_reference(&[1, 2, 3]);
+        assert!(streamer.landmarks().is_empty());
+    }
+
+    #[test]
+    fn test_match_lengths() {
+        let a: Vec<i32> = (0..100).collect();
+        let streamer = StreamNextChunk::from_vec(a);
+        let b: Vec<i32> = (0..10).chain([-1]).chain(20..25).collect();
+        assert_eq!(streamer.next_chunk(&b, 2), [25, 26]);
+        let last = streamer.last_match_lengths();
+        assert_eq!(last.counts().iter().map(|(&len, &count)| (len, count)).collect::<Vec<_>>(), [(5, 1), (10, 1)]);
+        assert_eq!((last.total(), last.quantile(0.5), last.quantile(1.0)), (2, Some(5), Some(10)));
+
+        let b: Vec<i32> = (0..3).chain([-1]).chain(50..55).collect();
+        streamer.next_chunk(&b, 2);
+        assert_eq!(streamer.last_match_lengths().total(), 2);
+        let session = streamer.match_lengths();
+        assert_eq!((session.total(), session.counts()[&5]), (4, 2));
+        streamer.reset_stats();
+        assert!(streamer.match_lengths().is_empty());
+    }
+
+    #[test]
+    fn test_longest_match_at() {
+        let a = [1, 2, 3, 4, 5, 2, 3, 4, 9];
+        for matcher in [MatcherBackend::Diff, MatcherBackend::SuffixAutomaton, MatcherBackend::SuffixArray] {
+            let mut streamer = StreamNextChunk::with_options(a.to_vec(), NextChunkOptions { matcher, ..Default::default() });
+            assert_eq!(streamer.longest_match_at(&[7, 7, 2, 3, 4], 10), Some((4, 3)));
+            assert_eq!(streamer.longest_match_at(&[7, 7, 2, 3, 4], 2), Some((4, 2)));
+            assert_eq!(streamer.longest_match_at(&[1, 2, 3, 4, 9], 10), Some((9, 4)));
+            assert_eq!(streamer.longest_match_at(&[1, 7], 10), None);
+            assert_eq!(streamer.stats().calls, 0);
+            streamer.extend_reference(&[7]);
+            assert_eq!(streamer.longest_match_at(&[4, 9, 7], 10), Some((10, 3)));
+            streamer.set_reference(&[7, 1]);
+            assert_eq!(streamer.longest_match_at(&[7, 1], 10), Some((2, 2)));
+        }
+    }
+
+    #[test]
+    fn test_multi_window_search() {
+        // Without the rolling-hash pre-pass the length-based window misses;
+        // searching the other windows recovers the continuation
+        let a: Vec<i32> = (0..6000).collect();
+        let b: Vec<i32> = (0..1000).chain(4000..5000).collect();
+        let options = NextChunkOptions { anchor_hash_len: 0, multi_window_search: true, ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a.clone(), options);
+        let info = streamer.next_chunk_with_info(&b, 3);
+        assert!(info.windowed);
+        assert_eq!(info.tokens, &[5000, 5001, 5002]);
+
+        let options = NextChunkOptions { anchor_hash_len: 0, ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a, options);
+        assert_eq!(streamer.next_chunk(&b, 3), &[] as &[i32]);
+    }
+
+
+
+    #[test]
+    fn test_min_match_len() {
+        let a: Vec<i32> = (0..50).collect();
+        let b = [100, 101, 7, 8, -1, 30, 31];
+        let streamer = StreamNextChunk::new(&a);
+        assert_eq!(streamer.next_chunk(&b, 2), &[32, 33]);
+
+        let options = NextChunkOptions { min_match_len: 3, ..Default::default() };
+        let mut streamer = StreamNextChunk::with_options(a, options);
+        assert_eq!(streamer.next_chunk(&b, 2), &[] as &[i32]);
+        assert_eq!(streamer.next_chunk(&[-1, 30, 31, 32], 2), &[33, 34]);
+        assert_eq!(streamer.next_chunk(&[], 2), &[0, 1]);
+
+        streamer.append(&b);
+        assert_eq!(streamer.predict(2), &[] as &[i32]);
+        streamer.append(&[32]);
+        assert_eq!(streamer.predict(2), &[33, 34]);
+    }
+
+
+
+    #[test]
+    fn test_fuzzy_anchor() {
+        let a: Vec<i32> = (0..50).collect();
+        // renamed token at the very end of b, and a second one inside the match
+        let b = [20, 21, 22, 23, 1000];
+        let b2 = [20, 21, 1001, 23, 1000];
+        let exact = StreamNextChunk::new(&a);
+        assert_eq!(exact.next_chunk(&b, 2), &[] as &[i32]);
+
+        let options = NextChunkOptions { max_mismatches: 1, ..Default::default() };
+        let fuzzy = StreamNextChunk::with_options(a.clone(), options);
+        let info = fuzzy.next_chunk_with_info(&b, 2);
+        assert_eq!((info.tokens, info.match_len), (&[25, 26][..], 4));
+        // the budget is spent on the trailing token, so only `23` anchors
+        assert_eq!(fuzzy.next_chunk_with_info(&b2, 2).match_len, 1);
+        assert_eq!(fuzzy.next_chunk(&[20, 21, 22, 23, 1000, 1001], 2), &[] as &[i32]);
+
+        let options = NextChunkOptions { max_mismatches: 2, ..Default::default() };
+        let mut fuzzy = StreamNextChunk::with_options(a, options);
+        let info = fuzzy.next_chunk_with_info(&b2, 2);
+        assert_eq!((info.tokens, info.match_len), (&[25, 26][..], 3));
+        fuzzy.append(&b2);
+        assert_eq!(fuzzy.predict(2), &[25, 26]);
+    }
+
+
+
+    #[test]
+    fn test_stats() {
+        let a: Vec<i32> = (0..6000).collect();
+        let options = NextChunkOptions { anchor_hash_len: 0, multi_window_search: true, ..Default::default() };
+        let mut streamer = StreamNextChunk::with_options(a, options);
+        streamer.next_chunk(&[0, 1, 2], 3);
+        let stats = streamer.stats();
+        assert_eq!((stats.calls, stats.diff_calls), (1, 0));
+        assert!(stats.last.unwrap().fast_path);
+
+        let b: Vec<i32> = (0..1000).chain(4000..5000).collect();
+        streamer.next_chunk(&b, 3);
+        let stats = streamer.stats();
+        let last = stats.last.unwrap();
+        assert_eq!((stats.calls, stats.diff_calls, stats.windowed_calls), (2, 1, 1));
+        assert!(!last.fast_path && last.windowed && last.windows_searched > 0 && last.matches > 0);
+        assert!(last.wall >= last.diff && stats.max_wall >= last.wall);
+        assert_eq!((stats.window_misses, stats.empty_predictions, last.chunk_len), (1, 0, 3));
+
+        // At the end of `a` nothing is left to predict
+        streamer.next_chunk(&[5998, 5999], 3);
+        assert_eq!(streamer.stats().empty_predictions, 1);
+
+        streamer.append(&[5, 6, 7]);
+        streamer.predict(3);
+        assert_eq!(streamer.stats().diff_calls, 3);
+        streamer.reset_stats();
+        assert_eq!(streamer.stats(), NextChunkStats::default());
+    }
+
+
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let a: Vec<i32> = (0..50).collect();
+        let streamer = StreamNextChunk::new(&a);
+        let info = streamer.next_chunk_with_info(&[5, -1, 7, 8], 2);
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(json, r#"{"tokens":[9,10],"start":9,"match_len":2,"windowed":false}"#);
+        let replayed: OwnedPredictionResult<i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(replayed, info.into_owned());
+
+        let opcodes = crate::opcodes(&[1, 2, 3], &[1, 3], DiffAlgorithm::Histogram);
+        let json = serde_json::to_string(&opcodes).unwrap();
+        assert!(json.starts_with(r#"[{"tag":"equal","a":{"start":0,"end":1},"b":{"start":0,"end":1}}"#));
+        assert_eq!(serde_json::from_str::<Vec<crate::Opcode>>(&json).unwrap(), opcodes);
+
+        let options: NextChunkOptions = serde_json::from_str(r#"{"algorithm":"myers_minimal","matcher":"suffix_automaton"}"#).unwrap();
+        assert_eq!((options.algorithm, options.matcher), (DiffAlgorithm::MyersMinimal, MatcherBackend::SuffixAutomaton));
+        assert_eq!(options.min_window_threshold, NextChunkOptions::default().min_window_threshold);
+    }
+
+
+
+    #[test]
+    fn test_shared_across_threads() {
+        fn assert_send_sync<S: Send + Sync>() {}
+        assert_send_sync::<StreamNextChunk<i32>>();
+        assert_send_sync::<StreamNextChunk<u32>>();
+        assert_send_sync::<StreamNextChunk<i64>>();
+
+        let a: Vec<i32> = (0..5000).collect();
+        let streamer = StreamNextChunk::new(&a);
+        std::thread::scope(|scope| {
+            for t in 0..8 {
+                let streamer = &streamer;
+                scope.spawn(move || {
+                    for i in 0..20 {
+                        let end = 100 * t + i + 10;
+                        let b: Vec<i32> = (0..end).filter(|&x| x != 5).collect();
+                        assert_eq!(streamer.next_chunk(&b, 2), &[end, end + 1]);
+                    }
+                });
             }
+        });
+        assert_eq!(streamer.stats().calls, 160);
+    }
 
 
-            // Determine the actual next chunk from the target output
-            let actual_chunk_end = min(current_idx + predict_chunk.len(), output_tokens.len());
-            let actual_chunk = &output_tokens[current_idx..actual_chunk_end];
 
-            // Compare predicted chunk with actual chunk to find accepted tokens
-            let accepted = predict_chunk
-                .iter()
-                .zip(actual_chunk.iter()) // Pair up tokens
-                .take_while(|(predicted, actual)| predicted == actual) // Count matching prefix
-                .count();
+    #[test]
+    fn test_normalizer() {
+        // 1 and 2 are whitespace of different widths, reformatted between a and b
+        let a = vec![10, 1, 11, 1, 12, 2, 13, 14, 15];
+        let b = [10, 2, 11, 2, 12, 1];
+        assert!(StreamNextChunk::new(&a).next_chunk(&b, 2).is_empty());
+
+        let mut streamer = StreamNextChunk::with_normalizer(a, NextChunkOptions::default(), Normalizer::collapse([1, 2]));
+        let result = streamer.next_chunk_with_info(&b, 2);
+        assert_eq!((result.tokens, result.match_len), (&[13, 14][..], 6));
+        assert_eq!(streamer.next_chunk(&[99, 12, 1], 5), [13, 14, 15]);
+
+        streamer.append(&b[..4]);
+        streamer.append(&b[4..]);
+        assert_eq!(streamer.predict(3), [13, 14, 15]);
+        assert_eq!(streamer.appended(), b);
+
+        // Tokens 100+i stand for i, e.g. ids merged differently by the tokenizer
+        let streamer = StreamNextChunk::with_normalizer(
+            (0..20).collect(),
+            NextChunkOptions::default(),
+            Normalizer::from_fn(|t| if t >= 100 { t - 100 } else { t }),
+        );
+        assert_eq!(streamer.next_chunk(&[105, 6, 107], 3), [8, 9, 10]);
+    }
 
-            println!(
-                "Iteration {}: Predicted len {}, Accepted: {}",
-                iter,
-                predict_chunk.len(),
-                accepted
-            );
+    #[test]
+    fn test_set_reference() {
+        let mut streamer = StreamNextChunk::new(&[1, 2, 3, 4, 5]);
+        streamer.append(&[1, 2]);
+        assert_eq!(streamer.next_chunk(&[2, 3], 2), [4, 5]);
+
+        let long: Vec<i32> = (100..3100).collect();
+        streamer.set_reference(&long);
+        assert_eq!(streamer.reference(), long);
+        assert!(streamer.appended().is_empty());
+        assert_eq!(streamer.predict(2), [100, 101]);
+        let mut b = long[..2500].to_vec();
+        b[10] = -1;
+        let result = streamer.next_chunk_with_info(&b, 2);
+        assert_eq!((result.tokens, result.windowed), (&[2600, 2601][..], true));
+        assert_eq!(streamer.stats().calls, 3);
+
+        let mut sam = StreamNextChunk::with_options(vec![1, 2, 3], NextChunkOptions { matcher: MatcherBackend::SuffixAutomaton, ..Default::default() });
+        assert_eq!(sam.replace_reference(vec![7, 8, 9, 7, 6]), [1, 2, 3]);
+        assert_eq!(sam.next_chunk(&[9, 7], 1), [6]);
+
+        let mut normalized = StreamNextChunk::with_normalizer(vec![1], NextChunkOptions::default(), Normalizer::collapse([0, 1]));
+        normalized.set_reference(&[5, 0, 6, 7]);
+        assert_eq!(normalized.next_chunk(&[5, 1], 2), [6, 7]);
+    }
 
-            // Advance current_idx by the number of accepted tokens, or at least 1
-            let advance_by = max(accepted, 1);
-            current_idx += advance_by;
+    #[test]
+    fn test_shared_slice() {
+        let a: Arc<[i32]> = (0..500).collect();
+        let mut streamers: Vec<_> = (0..3).map(|_| StreamNextChunk::with_shared_slice(a.clone(), NextChunkOptions::default())).collect();
+        assert_eq!(Arc::strong_count(&a), 4);
+        assert!(streamers.iter().all(|s| std::ptr::eq(s.reference(), &a[..])));
+        assert_eq!(streamers[0].next_chunk(&[10, 11], 2), [12, 13]);
+
+        // Modifying copies the reference, leaving the other sessions alone
+        streamers[1].extend_reference(&[-1]);
+        assert_eq!(streamers[1].reference().len(), 501);
+        assert_eq!(streamers[2].replace_reference(vec![1, 2]), &a[..]);
+        assert_eq!(Arc::strong_count(&a), 2);
+    }
 
-            // Accumulate total accepted tokens (only add the truly matched ones)
-            total_accepted += accepted;
+    #[test]
+    fn test_extend_reference() {
+        let full: Vec<i32> = (0..3000).map(|i| i % 1000 + (i / 1000) * 5000).collect();
+        let mut b = full[..2600].to_vec();
+        b[2000] = -1;
+        let expected = StreamNextChunk::new(&full).next_chunk_with_info(&b, 3).into_owned();
+        assert!(expected.windowed);
+
+        // grows past the windowing threshold on the way
+        let mut streamer = StreamNextChunk::new(&full[..500]);
+        for part in full[500..].chunks(700) {
+            streamer.extend_reference(part);
+        }
+        assert_eq!(streamer.reference(), full);
+        assert_eq!(streamer.next_chunk_with_info(&b, 3).into_owned(), expected);
+
+        // the stateful stream keeps going, with `b` re-interned for the new tokens
+        let mut streamer = StreamNextChunk::new(&[1, 2, 3]);
+        streamer.append(&[1, 2, 3, 7]);
+        assert!(streamer.predict(2).is_empty());
+        streamer.extend_reference(&[7, 8, 9]);
+        assert_eq!(streamer.predict(2), [8, 9]);
+
+        let options = NextChunkOptions { matcher: MatcherBackend::SuffixAutomaton, ..Default::default() };
+        let mut sam = StreamNextChunk::with_options(vec![1, 2, 3], options);
+        sam.append(&[3, 4]);
+        sam.extend_reference(&[4, 5]);
+        assert_eq!(sam.predict_with_info(1).match_len, 2);
+        assert_eq!(sam.predict(1), [5]);
+    }
+
+    #[test]
+    fn test_shared_reference() {
+        let mut streamer = StreamNextChunk::new(&[1, 2, 3, 4]);
+        assert!(streamer.shared_reference().is_none());
+        streamer.share_reference();
+        let shared = streamer.shared_reference().unwrap();
+        assert_eq!(streamer.next_chunk(&[2], 2), [3, 4]);
+        // Modifying the reference leaves the shared tokens alone
+        streamer.extend_reference(&[5]);
+        streamer.set_reference(&[7, 8]);
+        assert_eq!((*shared).as_ref(), [1, 2, 3, 4]);
+        assert!(streamer.shared_reference().is_none());
+    }
 
-            // Optional: Log progress
-            // println!("  Advanced by {}, New index: {}", advance_by, current_idx);
+    #[test]
+    fn test_next_chunk_candidates() {
+        let streamer = StreamNextChunk::new(&[1, 2, 3, 9, 5, 0, 2, 3, 9, 5, 7, 3, 8]);
+        let candidates = streamer.next_chunk_candidates(&[0, 1, 2, 3], 2, 5);
+        let ranked: Vec<_> = candidates.iter().map(|c| (c.tokens, c.start, c.match_len)).collect();
+        // [9, 5] is predicted again from offset 8, with a shorter match
+        assert_eq!(ranked, [(&[9, 5][..], Some(3), 3), (&[8], Some(12), 1)]);
+        assert_eq!(streamer.next_chunk_candidates(&[0, 1, 2, 3], 2, 1)[0], streamer.next_chunk_with_info(&[0, 1, 2, 3], 2));
+        assert!(streamer.next_chunk_candidates(&[4], 2, 3).is_empty());
+        assert_eq!(streamer.next_chunk_candidates(&[], 2, 3)[0].tokens, [1, 2]);
+
+        let options = NextChunkOptions { min_match_len: 2, ..Default::default() };
+        let streamer = StreamNextChunk::with_options(streamer.reference().to_vec(), options);
+        assert_eq!(streamer.next_chunk_candidates(&[0, 1, 2, 3], 2, 5).len(), 1);
+
+        for matcher in [MatcherBackend::SuffixAutomaton, MatcherBackend::HashChain] {
+            let options = NextChunkOptions { matcher, ..Default::default() };
+            let streamer = StreamNextChunk::with_options(streamer.reference().to_vec(), options);
+            let candidates = streamer.next_chunk_candidates(&[0, 1, 2, 3], 2, 5);
+            assert_eq!(candidates.iter().map(|c| (c.tokens, c.start)).collect::<Vec<_>>(), [(&[9, 5][..], Some(3)), (&[8], Some(12))]);
         }
+        // A repetitive reference: every position matches, the earliest full match wins
+        // and only chunks cut short by the end of `a` differ
+        let streamer = StreamNextChunk::new(&[7; 5000]);
+        let candidates = streamer.next_chunk_candidates(&[7; 3000], 3, 5);
+        let ranked: Vec<_> = candidates.iter().map(|c| (c.tokens, c.start, c.match_len)).collect();
+        assert_eq!(ranked, [(&[7, 7, 7][..], Some(3000), 3000), (&[7, 7], Some(4998), 3000), (&[7], Some(4999), 3000)]);
+    }
+
+    #[test]
+    fn test_next_chunk_tree() {
+        let streamer = StreamNextChunk::new(&[1, 2, 3, 4, 5, 0, 2, 3, 4, 6, 9, 3, 7]);
+        let tree = streamer.next_chunk_tree(&[1, 2, 3], 2, 3);
+        assert_eq!(tree.tokens, [4, 7, 5, 6]);
+        assert_eq!(tree.parents, [-1, -1, 0, 0]);
+        assert!(streamer.next_chunk_tree(&[8], 3, 3).is_empty());
+    }
 
-        let total_duration = start_time.elapsed();
+    #[test]
+    fn test_verify_and_advance() {
+        let mut streamer = StreamNextChunk::new(&[1, 2, 3, 4, 5, 6]);
+        let predicted = streamer.predict(3).to_vec();
+        assert_eq!(streamer.verify_and_advance(&predicted, &[1, 2, 9]), 2);
+        assert_eq!(streamer.appended(), [1, 2]);
+        assert_eq!(streamer.verify_and_advance(&[3, 4], &[9, 4]), 0);
+        assert_eq!(streamer.appended(), [1, 2, 9]);
+        assert_eq!(streamer.verify_and_advance(&[4], &[]), 0);
+        let stats = streamer.stats();
+        assert_eq!((stats.verifications, stats.predicted_tokens, stats.accepted_tokens), (3, 6, 2));
+    }
 
-        println!("\n--- Simulation Summary ---");
-        println!("Total Iterations: {}", iter);
-        println!("Total Accepted Tokens: {} (out of {})", total_accepted, output_tokens.len());
-        if let Some(ftd) = first_token_duration {
-            println!("First Prediction Time: {:.3} ms", ftd.as_secs_f64() * 1000.0);
-        } else {
-            println!("First Prediction Time: N/A (no predictions made)");
+    #[test]
+    fn test_continuation_check() {
+        let a: Vec<i32> = (0..3000).collect();
+        let mut b = a.clone();
+        b[500] = -1;
+        let options = NextChunkOptions { continuation_check_len: 8, ..Default::default() };
+        let streamer = StreamNextChunk::with_options(a.clone(), options);
+        let plain = StreamNextChunk::new(&a);
+        assert_eq!(streamer.next_chunk(&b[..1000], 4), [1000, 1001, 1002, 1003]);
+
+        // The whole chunk accepted, then also the verifier's next token
+        for len in [1004, 1009] {
+            let info = streamer.next_chunk_with_info(&b[..len], 4);
+            assert_eq!((info.tokens, info.start, info.match_len), (&a[len..len + 4], Some(len), len - 501));
+            assert_eq!(info.tokens, plain.next_chunk(&b[..len], 4));
+            let last = streamer.last_call_info().unwrap();
+            assert!(last.continuation_hit && last.fast_path);
+        }
+        assert_eq!(streamer.stats().diff_calls, 1);
+
+        // Diverged from the prediction: diffed again
+        let diverged: Vec<i32> = b[..1013].iter().copied().chain([-2, 20, 21]).collect();
+        assert_eq!(streamer.next_chunk(&diverged, 2), plain.next_chunk(&diverged, 2));
+        assert!(!streamer.last_call_info().unwrap().continuation_hit);
+        assert_eq!(streamer.stats().diff_calls, 2);
+    }
+
+    #[test]
+    fn test_prediction_cursor() {
+        let a: Vec<i32> = (0..90).collect();
+        let mut b = a[..40].to_vec();
+        b[20] = -1;
+        let streamer = StreamNextChunk::new(&a);
+        let (result, cursor) = streamer.next_chunk_from(&b, 4, None);
+        assert_eq!(result.tokens, [40, 41, 42, 43]);
+        assert_eq!((cursor.b_len, cursor.pos, cursor.match_len, cursor.calls, cursor.diff_calls), (40, Some(40), 19, 1, 1));
+
+        // Two branches resuming from the same cursor
+        let accepted: Vec<i32> = b.iter().copied().chain([40, 41, 42, 43]).collect();
+        let retried: Vec<i32> = b.iter().copied().chain([40, 41, -5, 60, 61]).collect();
+        let (result, accepted_cursor) = streamer.next_chunk_from(&accepted, 4, Some(&cursor));
+        assert_eq!(result.tokens, [44, 45, 46, 47]);
+        let last = streamer.last_call_info().unwrap();
+        assert!(last.cursor_hit && !last.memo_hit);
+        assert_eq!((accepted_cursor.pos, accepted_cursor.match_len), (Some(44), 23));
+        let (result, retried_cursor) = streamer.next_chunk_from(&retried, 4, Some(&cursor));
+        assert_eq!(result.tokens, [62, 63, 64, 65]);
+        assert_eq!((retried_cursor.calls, retried_cursor.diff_calls, retried_cursor.pos), (2, 2, Some(62)));
+
+        let longer: Vec<i32> = accepted.iter().copied().chain([44, 45]).collect();
+        let (result, cursor) = streamer.next_chunk_from(&longer, 4, Some(&accepted_cursor));
+        assert_eq!(result.tokens, [46, 47, 48, 49]);
+        assert_eq!((cursor.calls, cursor.diff_calls, cursor.predicted_tokens), (3, 1, 12));
+
+        // A cursor past the end of `b` is ignored
+        let (result, cursor) = streamer.next_chunk_from(&b[..30], 4, Some(&cursor));
+        assert_eq!((result.tokens, cursor.b_len), (&[30, 31, 32, 33][..], 30));
+
+        // So is one of another branch as long as this `b`'s prefix
+        let other: Vec<i32> = (50..80).chain([80, 81]).collect();
+        let (result, _) = streamer.next_chunk_from(&other, 4, Some(&cursor));
+        assert_eq!(result.tokens, [82, 83, 84, 85]);
+        assert!(!streamer.last_call_info().unwrap().cursor_hit);
+    }
+
+    #[test]
+    fn test_memoized_anchors() {
+        let a: Vec<i32> = (0..3000).collect();
+        let mut b = a[..2000].to_vec();
+        b[1000] = -1;
+        let no_memo = StreamNextChunk::new(&a);
+        let streamer = StreamNextChunk::with_options(a.clone(), NextChunkOptions { memoize_anchors: true, ..Default::default() });
+        for len in [1500, 1520, 1600, 2000] {
+            let chunk = streamer.next_chunk_with_info(&b[..len], 4);
+            assert_eq!((chunk.tokens, chunk.start), (&a[len..len + 4], Some(len)));
+            assert_eq!(chunk.tokens, no_memo.next_chunk(&b[..len], 4));
+        }
+        let stats = streamer.stats();
+        assert_eq!((stats.diff_calls, stats.memo_hits), (1, 3));
+        assert!(stats.last.unwrap().memo_hit);
+
+        // new tokens off the remembered anchor are diffed again
+        b[1700] = -2;
+        assert_eq!(streamer.next_chunk(&b, 4), [2000, 2001, 2002, 2003]);
+        assert_eq!(streamer.stats().diff_calls, 2);
+        assert_eq!(no_memo.stats().memo_hits, 0);
+    }
+
+    #[test]
+    fn test_memoized_latest_call() {
+        let a: Vec<i32> = (0..3000).collect();
+        let mut b = a[..2000].to_vec();
+        b[1000] = -1;
+        let streamer = StreamNextChunk::with_options(a.clone(), NextChunkOptions { memoize_anchors: true, ..Default::default() });
+        for len in [1500, 1600, 1700] {
+            streamer.next_chunk(&b[..len], 4);
         }
-        println!("Total Simulation Time: {:.3} ms", total_duration.as_secs_f64() * 1000.0);
+        // Resumed from the 1700-token call, itself a memo hit: the windowed
+        // diff matched 200 tokens, and every call since added its tokens
+        assert_eq!(streamer.next_chunk(&b, 4), [2000, 2001, 2002, 2003]);
+        assert_eq!(streamer.last_call_info().unwrap().anchor, CallAnchor::At { pos: 2000, match_len: 700 });
+        assert_eq!((streamer.stats().diff_calls, streamer.stats().memo_hits), (1, 3));
+
+        // Off the latest prediction: diffed again
+        b.extend([-2, 2100, 2101, 2102, 2103, 2104, 2105, 2106, 2107]);
+        assert_eq!(streamer.next_chunk(&b, 4), [2108, 2109, 2110, 2111]);
+        assert_eq!(streamer.stats().diff_calls, 2);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let streamer = StreamNextChunkBytes::new("fn main() {\n    println!(\"héllo\");\n}\n".as_bytes());
+        assert_eq!(streamer.next_chunk(b"fn main() {\n    print", 7), b"ln!(\"h\xc3");
+        assert_eq!(streamer.next_chunk("    println!(\"hé".as_bytes(), 5), b"llo\")");
+    }
+
+    #[test]
+    fn test_real_case1_simulation() {
+        let input_tokens: Vec<i32> = (1..=1000).collect();
+        let mut output_tokens: Vec<i32> = (1..=1000).collect();
+        output_tokens[10] = 999;
+        output_tokens[100] = 888;
+        output_tokens[600] = 777;
 
-        // Optional: Add assertions for testing purposes if you have expected outcomes
-        // assert!(total_accepted > 0, "Should have accepted some tokens");
-        // assert_eq!(current_idx, output_tokens.len(), "Should have processed all output tokens");
+        let report = crate::simulate(&input_tokens, &output_tokens, 80);
+        // Only the steps around the three edited tokens miss
+        assert!(report.total_accepted() > output_tokens.len() * 95 / 100);
     }
 }
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::nextchunk::PredictionResult;
+
+
+/// Prompt-lookup decoding over a reference sequence.
+///
+/// Indexes every n-gram of `a` up to `max_ngram` tokens and predicts by
+/// looking up the last n tokens of `b` (longest first), returning what
+/// followed the first occurrence in `a`. No diffing involved, so it is much
+/// cheaper than [`crate::StreamNextChunk`] but only looks at the tail of `b`.
+pub struct NgramNextChunk<T> {
+    a: Vec<T>,
+    max_ngram: usize,
+    /// n-gram -> end position (exclusive) of its first occurrence in `a`.
+    index: HashMap<Vec<T>, usize>,
+}
+
+impl<T: Eq + Hash + Copy> NgramNextChunk<T> {
+    /// Builds the n-gram index over `a` for n in `1..=max_ngram`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_ngram` is 0.
+    pub fn new(a: Vec<T>, max_ngram: usize) -> Self {
+        assert!(max_ngram > 0, "max_ngram must be at least 1");
+        let mut index = HashMap::new();
+        for n in 1..=max_ngram {
+            for (start, ngram) in a.windows(n).enumerate() {
+                index.entry(ngram.to_vec()).or_insert(start + n);
+            }
+        }
+        NgramNextChunk { a, max_ngram, index }
+    }
+
+    /// The reference sequence.
+    pub fn reference(&self) -> &[T] {
+        &self.a
+    }
+
+    /// Appends `tokens` to the reference, indexing only the n-grams that end in them.
+    pub fn extend_reference(&mut self, tokens: &[T]) {
+        let old_len = self.a.len();
+        self.a.extend_from_slice(tokens);
+        for n in 1..=self.max_ngram {
+            let from = old_len.saturating_sub(n - 1);
+            for (start, ngram) in self.a[from..].windows(n).enumerate() {
+                self.index.entry(ngram.to_vec()).or_insert(from + start + n);
+            }
+        }
+    }
+
+    /// Longest n-gram size looked up.
+    pub fn max_ngram(&self) -> usize {
+        self.max_ngram
+    }
+
+    /// Predicts the next chunk of `a` following `current_b`.
+    pub fn next_chunk(&self, current_b: &[T], chunk_size: usize) -> &[T] {
+        self.next_chunk_with_info(current_b, chunk_size).tokens
+    }
+
+    /// Like [`Self::next_chunk`], also reporting where in `a` the chunk starts.
+    /// `match_len` is the size of the n-gram that matched.
+    pub fn next_chunk_with_info(&self, current_b: &[T], chunk_size: usize) -> PredictionResult<'_, T> {
+        if self.a.is_empty() || chunk_size == 0 {
+            return PredictionResult::empty();
+        }
+        if current_b.is_empty() {
+            return self.result(0, 0, chunk_size);
+        }
+
+        for n in (1..=self.max_ngram.min(current_b.len())).rev() {
+            if let Some(&end) = self.index.get(&current_b[current_b.len() - n..]) {
+                return self.result(end, n, chunk_size);
+            }
+        }
+        PredictionResult::empty()
+    }
+
+    fn result(&self, start: usize, match_len: usize, chunk_size: usize) -> PredictionResult<'_, T> {
+        let end = (start + chunk_size).min(self.a.len());
+        PredictionResult { tokens: &self.a[start..end], start: Some(start), match_len, windowed: false }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ngram_lookup() {
+        let a = vec![1, 2, 3, 4, 9, 2, 3, 5, 6];
+        let ngram = NgramNextChunk::new(a, 3);
+
+        assert_eq!(ngram.next_chunk(&[], 2), &[1, 2]);
+        // longest n-gram wins over the earlier shorter match
+        let info = ngram.next_chunk_with_info(&[7, 9, 2, 3], 2);
+        assert_eq!(info.tokens, &[5, 6]);
+        assert_eq!((info.start, info.match_len), (Some(7), 3));
+        // falls back to shorter n-grams, first occurrence
+        let info = ngram.next_chunk_with_info(&[8, 8, 2, 3], 3);
+        assert_eq!(info.tokens, &[4, 9, 2]);
+        assert_eq!((info.start, info.match_len), (Some(3), 2));
+        assert_eq!(ngram.next_chunk(&[8, 6], 2), &[] as &[i32]);
+        assert_eq!(ngram.next_chunk(&[42], 2), &[] as &[i32]);
+    }
+
+    #[test]
+    fn test_extend_reference() {
+        let mut ngram = NgramNextChunk::new(vec![1, 2, 3], 2);
+        assert_eq!(ngram.next_chunk(&[2, 3], 2), &[] as &[i32]);
+        ngram.extend_reference(&[4, 5, 2, 6]);
+        assert_eq!(ngram.reference(), [1, 2, 3, 4, 5, 2, 6]);
+        assert_eq!(ngram.next_chunk(&[2, 3], 2), [4, 5]);
+        assert_eq!(ngram.next_chunk(&[5, 2], 1), [6]);
+        // first occurrence still wins for n-grams seen before
+        assert_eq!(ngram.next_chunk(&[9, 2], 1), [3]);
+        assert_eq!(NgramNextChunk::new(vec![1, 2], 3).index, {
+            let mut grown = NgramNextChunk::new(vec![1], 3);
+            grown.extend_reference(&[2]);
+            grown.index
+        });
+    }
+}
@@ -0,0 +1,91 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+
+
+/// Canonicalizes tokens before they are matched, so tokens that differ only
+/// in ways the caller doesn't care about (e.g. the width of an indentation
+/// token after reformatting) still anchor the prediction.
+///
+/// Only matching sees the canonical tokens: predictions are sliced out of the
+/// original reference.
+#[derive(Clone)]
+pub enum Normalizer<T> {
+    /// Tokens found in the map are replaced by their value, others are kept.
+    Map(HashMap<T, T>),
+    /// Each token is replaced by what the function returns for it.
+    Fn(Arc<dyn Fn(T) -> T + Send + Sync>),
+}
+
+impl<T: fmt::Debug> fmt::Debug for Normalizer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Normalizer::Map(map) => f.debug_tuple("Map").field(map).finish(),
+            Normalizer::Fn(_) => f.write_str("Fn(..)"),
+        }
+    }
+}
+
+impl<T: Eq + Hash + Copy> Normalizer<T> {
+    /// Makes all of `ids` match each other, e.g. the whitespace token ids of
+    /// a tokenizer. They're all replaced by the first one.
+    pub fn collapse(ids: impl IntoIterator<Item = T>) -> Self {
+        let mut ids = ids.into_iter();
+        let map = match ids.next() {
+            Some(canonical) => ids.map(|id| (id, canonical)).collect(),
+            None => HashMap::new(),
+        };
+        Normalizer::Map(map)
+    }
+
+    /// Normalizes with `f`, e.g. to map token ids a tokenizer merge made
+    /// synonymous to one of them. Called for every token of `a` at
+    /// construction and of `b` on each call, so it should be cheap and pure.
+    pub fn from_fn(f: impl Fn(T) -> T + Send + Sync + 'static) -> Self {
+        Normalizer::Fn(Arc::new(f))
+    }
+
+    /// The canonical form of `token`.
+    pub fn normalize(&self, token: T) -> T {
+        match self {
+            Normalizer::Map(map) => map.get(&token).copied().unwrap_or(token),
+            Normalizer::Fn(f) => f(token),
+        }
+    }
+
+    /// `tokens` in canonical form.
+    pub fn normalize_all(&self, tokens: &[T]) -> Vec<T> {
+        tokens.iter().map(|&t| self.normalize(t)).collect()
+    }
+}
+
+/// `tokens` in canonical form, borrowed as-is without a normalizer.
+pub(crate) fn normalized<'a, T: Eq + Hash + Copy>(normalizer: Option<&Normalizer<T>>, tokens: &'a [T]) -> Cow<'a, [T]> {
+    match normalizer {
+        Some(normalizer) => Cow::Owned(normalizer.normalize_all(tokens)),
+        None => Cow::Borrowed(tokens),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_collapse() {
+        let normalizer = Normalizer::collapse([220, 256, 262]);
+        assert_eq!(normalizer.normalize_all(&[1, 256, 2, 262, 220]), [1, 220, 2, 220, 220]);
+        assert_eq!(Normalizer::collapse(Vec::<i32>::new()).normalize(7), 7);
+        assert!(matches!(normalized(None, &[1, 2]), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_from_fn() {
+        let normalizer = Normalizer::from_fn(|t: u32| t & !1);
+        assert_eq!(normalizer.normalize_all(&[4, 5, 6, 7]), [4, 4, 6, 6]);
+        assert_eq!(format!("{normalizer:?}"), "Fn(..)");
+    }
+}
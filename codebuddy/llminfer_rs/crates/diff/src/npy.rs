@@ -0,0 +1,184 @@
+use std::hash::Hash;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::nextchunk::StreamNextChunk;
+use super::options::NextChunkOptions;
+
+
+/// Error from [`read_npy`] and [`StreamNextChunk::from_npy`].
+#[derive(Debug, thiserror::Error)]
+pub enum NpyError {
+    #[error("failed to read {path:?}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("not a valid .npy file: {0}")]
+    Format(String),
+    #[error("unsupported .npy dtype {0:?}, expected int32 or int64")]
+    Dtype(String),
+    #[error("unsupported .npy shape {0:?}, expected a single row of tokens")]
+    Shape(String),
+    #[error("token {0} is out of range of the token type")]
+    Overflow(i64),
+}
+
+/// The tokens of a `.npy` file, in the integer type they were saved as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NpyArray {
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+}
+
+impl NpyArray {
+    /// Converts the tokens to `T`, failing on the first one out of its range.
+    pub fn into_tokens<T: TryFrom<i64>>(self) -> Result<Vec<T>, NpyError> {
+        let convert = |t: i64| T::try_from(t).map_err(|_| NpyError::Overflow(t));
+        match self {
+            NpyArray::I32(tokens) => tokens.into_iter().map(|t| convert(t.into())).collect(),
+            NpyArray::I64(tokens) => tokens.into_iter().map(convert).collect(),
+        }
+    }
+}
+
+/// Value of `key` in the header dict, up to the next top-level comma.
+fn header_value<'h>(header: &'h str, key: &str) -> Result<&'h str, NpyError> {
+    let missing = || NpyError::Format(format!("header has no {key:?}"));
+    let (_, rest) = header.split_once(&format!("'{key}':")).ok_or_else(missing)?;
+    let rest = rest.trim_start();
+    let end = match rest.strip_prefix('(') {
+        Some(tuple) => tuple.find(')').map(|i| i + 2),
+        None => rest.find([',', '}']),
+    };
+    Ok(rest[..end.ok_or_else(missing)?].trim())
+}
+
+/// Parses a `.npy` file (format versions 1 to 3) of little- or big-endian
+/// int32/int64 values. Any shape with a single row of data is accepted, e.g.
+/// `(n,)` or `(1, n)`.
+pub fn parse_npy(bytes: &[u8]) -> Result<NpyArray, NpyError> {
+    let truncated = || NpyError::Format("truncated file".to_owned());
+    let rest = bytes.strip_prefix(b"\x93NUMPY").ok_or_else(|| NpyError::Format("bad magic".to_owned()))?;
+    let (&[major, _minor], rest) = rest.split_first_chunk::<2>().ok_or_else(truncated)?;
+    let (header_len, rest) = match major {
+        1 => rest.split_first_chunk::<2>().map(|(len, rest)| (u16::from_le_bytes(*len) as usize, rest)),
+        2 | 3 => rest.split_first_chunk::<4>().map(|(len, rest)| (u32::from_le_bytes(*len) as usize, rest)),
+        _ => return Err(NpyError::Format(format!("unsupported version {major}"))),
+    }
+    .ok_or_else(truncated)?;
+    let header = rest.get(..header_len).ok_or_else(truncated)?;
+    let header = std::str::from_utf8(header).map_err(|_| NpyError::Format("header is not text".to_owned()))?;
+    let data = &rest[header_len..];
+
+    let shape = header_value(header, "shape")?;
+    let dims = shape
+        .trim_matches(['(', ')'])
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse::<usize>().map_err(|_| NpyError::Shape(shape.to_owned())))
+        .collect::<Result<Vec<_>, _>>()?;
+    // With at most one dimension above 1, C and Fortran order lay the data out alike
+    if dims.is_empty() || dims.iter().filter(|&&dim| dim != 1).count() > 1 {
+        return Err(NpyError::Shape(shape.to_owned()));
+    }
+    let len: usize = dims.iter().product();
+
+    let descr = header_value(header, "descr")?.trim_matches(['\'', '"']);
+    let little = match descr.as_bytes().first() {
+        Some(b'<' | b'|') => true,
+        Some(b'>') => false,
+        // Native order, as written on the host
+        Some(b'=') => cfg!(target_endian = "little"),
+        _ => return Err(NpyError::Dtype(descr.to_owned())),
+    };
+    macro_rules! read {
+        ($ty:ty) => {{
+            const SIZE: usize = size_of::<$ty>();
+            let data = data.get(..len * SIZE).ok_or_else(truncated)?;
+            data.chunks_exact(SIZE)
+                .map(|b| {
+                    let b = b.try_into().unwrap();
+                    if little { <$ty>::from_le_bytes(b) } else { <$ty>::from_be_bytes(b) }
+                })
+                .collect()
+        }};
+    }
+    match &descr[1..] {
+        "i4" => Ok(NpyArray::I32(read!(i32))),
+        "i8" => Ok(NpyArray::I64(read!(i64))),
+        _ => Err(NpyError::Dtype(descr.to_owned())),
+    }
+}
+
+/// Reads the `.npy` file at `path`, see [`parse_npy`].
+pub fn read_npy(path: impl AsRef<Path>) -> Result<NpyArray, NpyError> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|source| NpyError::Io { path: path.to_owned(), source })?;
+    parse_npy(&bytes)
+}
+
+impl<T: Eq + Hash + Copy + TryFrom<i64>> StreamNextChunk<T> {
+    /// Predicts from the int32 or int64 tokens of the `.npy` file at `path`,
+    /// e.g. a token dump recorded with `numpy.save`.
+    pub fn from_npy(path: impl AsRef<Path>) -> Result<Self, NpyError> {
+        Self::from_npy_with_options(path, NextChunkOptions::default())
+    }
+
+    /// Like [`StreamNextChunk::from_npy`] with non-default tunables.
+    pub fn from_npy_with_options(path: impl AsRef<Path>, options: NextChunkOptions) -> Result<Self, NpyError> {
+        Ok(Self::with_options(read_npy(path)?.into_tokens()?, options))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A version 1 `.npy` file as `numpy.save` writes it.
+    fn npy(descr: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+        let mut header = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+        while (header.len() + 11) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_npy() {
+        let data: Vec<u8> = [1i32, -2, 3].iter().flat_map(|t| t.to_le_bytes()).collect();
+        assert_eq!(parse_npy(&npy("<i4", "(3,)", &data)).unwrap(), NpyArray::I32(vec![1, -2, 3]));
+        assert_eq!(parse_npy(&npy("<i4", "(1, 3)", &data)).unwrap(), NpyArray::I32(vec![1, -2, 3]));
+        let data: Vec<u8> = [1i64 << 40, 5].iter().flat_map(|t| t.to_be_bytes()).collect();
+        assert_eq!(parse_npy(&npy(">i8", "(2,)", &data)).unwrap(), NpyArray::I64(vec![1 << 40, 5]));
+        assert_eq!(parse_npy(&npy("<i8", "(0,)", &[])).unwrap(), NpyArray::I64(vec![]));
+
+        assert!(matches!(parse_npy(&npy("<f4", "(0,)", &[])), Err(NpyError::Dtype(_))));
+        assert!(matches!(parse_npy(&npy("<i4", "(2, 3)", &[])), Err(NpyError::Shape(_))));
+        assert!(matches!(parse_npy(&npy("<i4", "(3,)", &[0; 8])), Err(NpyError::Format(_))));
+        assert!(matches!(parse_npy(b"PK\x03\x04"), Err(NpyError::Format(_))));
+    }
+
+    #[test]
+    fn test_into_tokens() {
+        assert_eq!(NpyArray::I32(vec![1, 2]).into_tokens::<i64>().unwrap(), [1, 2]);
+        assert_eq!(NpyArray::I64(vec![1, 2]).into_tokens::<u32>().unwrap(), [1, 2]);
+        assert!(matches!(NpyArray::I64(vec![1, 1 << 40]).into_tokens::<i32>(), Err(NpyError::Overflow(_))));
+        assert!(matches!(NpyArray::I32(vec![-1]).into_tokens::<u32>(), Err(NpyError::Overflow(-1))));
+    }
+
+    #[test]
+    fn test_from_npy() {
+        let a: Vec<i64> = (0..200).map(|i| i % 31).collect();
+        let path = std::env::temp_dir().join(format!("llminfer-npy-{}.npy", std::process::id()));
+        std::fs::write(&path, npy("<i8", "(200,)", &a.iter().flat_map(|t| t.to_le_bytes()).collect::<Vec<u8>>())).unwrap();
+        let streamer = StreamNextChunk::<i32>::from_npy(&path).unwrap();
+        assert_eq!(streamer.next_chunk(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9], 3), [10, 11, 12]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(StreamNextChunk::<i32>::from_npy(&path), Err(NpyError::Io { .. })));
+    }
+}
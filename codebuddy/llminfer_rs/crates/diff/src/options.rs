@@ -0,0 +1,430 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use imara_diff::Algorithm;
+
+use super::sink::MatchWeights;
+
+
+/// Error returned when parsing an option value from its string name.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown {kind} {value:?}, expected one of: {expected}")]
+pub struct ParseOptionError {
+    kind: &'static str,
+    value: String,
+    expected: &'static str,
+}
+
+impl ParseOptionError {
+    pub(crate) fn new(kind: &'static str, value: &str, expected: &'static str) -> Self {
+        ParseOptionError { kind, value: value.to_owned(), expected }
+    }
+}
+
+
+/// Diff algorithm used to match `b` against `a`.
+///
+/// Histogram gives the best anchors on code; Myers is faster on very short
+/// windows, MyersMinimal produces the smallest edit script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DiffAlgorithm {
+    #[default]
+    Histogram,
+    Myers,
+    MyersMinimal,
+}
+
+impl DiffAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiffAlgorithm::Histogram => "histogram",
+            DiffAlgorithm::Myers => "myers",
+            DiffAlgorithm::MyersMinimal => "myers_minimal",
+        }
+    }
+}
+
+impl From<DiffAlgorithm> for Algorithm {
+    fn from(algorithm: DiffAlgorithm) -> Self {
+        match algorithm {
+            DiffAlgorithm::Histogram => Algorithm::Histogram,
+            DiffAlgorithm::Myers => Algorithm::Myers,
+            DiffAlgorithm::MyersMinimal => Algorithm::MyersMinimal,
+        }
+    }
+}
+
+impl FromStr for DiffAlgorithm {
+    type Err = ParseOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "histogram" => Ok(DiffAlgorithm::Histogram),
+            "myers" => Ok(DiffAlgorithm::Myers),
+            "myers_minimal" | "myersminimal" => Ok(DiffAlgorithm::MyersMinimal),
+            _ => Err(ParseOptionError::new("diff algorithm", s, "histogram, myers, myers_minimal")),
+        }
+    }
+}
+
+impl fmt::Display for DiffAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+/// How [`crate::StreamNextChunk`] finds where `b` lines up with `a`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MatcherBackend {
+    /// Diff `b` (or a window of it) against `a` and anchor on the last match.
+    #[default]
+    Diff,
+    /// Longest suffix of `b` occurring in `a`, via a suffix automaton built
+    /// over `a` at construction. Much cheaper than diffing very long references.
+    SuffixAutomaton,
+    /// Longest suffix of `b` occurring in `a`, via a suffix array built over
+    /// `a` at construction: O(m log n) per call for a match of m tokens, in
+    /// a fraction of the suffix automaton's memory. For references too large
+    /// to diff over and over that don't grow: [`StreamNextChunk::extend_reference`](crate::StreamNextChunk::extend_reference)
+    /// rebuilds the whole array in O(n log² n), so prefer `SuffixAutomaton`
+    /// or `HashChain` for references extended chunk by chunk.
+    SuffixArray,
+    /// Longest suffix of `b` found through LZ77-style hash chains over the
+    /// runs of 4 tokens of `a`. The lightest index, O(1) to extend as `a`
+    /// grows, but misses matches shorter than 4 tokens.
+    HashChain,
+}
+
+impl MatcherBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatcherBackend::Diff => "diff",
+            MatcherBackend::SuffixAutomaton => "suffix_automaton",
+            MatcherBackend::SuffixArray => "suffix_array",
+            MatcherBackend::HashChain => "hash_chain",
+        }
+    }
+}
+
+impl FromStr for MatcherBackend {
+    type Err = ParseOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "diff" => Ok(MatcherBackend::Diff),
+            "suffix_automaton" | "sam" => Ok(MatcherBackend::SuffixAutomaton),
+            "suffix_array" | "sa" => Ok(MatcherBackend::SuffixArray),
+            "hash_chain" | "lz77" => Ok(MatcherBackend::HashChain),
+            _ => Err(ParseOptionError::new("matcher backend", s, "diff, suffix_automaton, suffix_array, hash_chain")),
+        }
+    }
+}
+
+impl fmt::Display for MatcherBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+/// What [`crate::StreamNextChunk`] predicts when `b` can't be anchored in `a`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FallbackPolicy {
+    /// Predict nothing.
+    Empty,
+    /// Predict the start of `a`.
+    StartOfA,
+    /// Go on from the last confirmed anchor, moved past the tokens `b` grew
+    /// by since; nothing without one.
+    LastPosition,
+    /// Predict from this offset of `a`.
+    Offset(usize),
+}
+
+impl FromStr for FallbackPolicy {
+    type Err = ParseOptionError;
+
+    /// Parses a policy name, or an offset of `a` for [`FallbackPolicy::Offset`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "empty" => Ok(FallbackPolicy::Empty),
+            "start_of_a" => Ok(FallbackPolicy::StartOfA),
+            "last_position" => Ok(FallbackPolicy::LastPosition),
+            offset => offset
+                .parse()
+                .map(FallbackPolicy::Offset)
+                .map_err(|_| ParseOptionError::new("fallback policy", s, "empty, start_of_a, last_position, an offset")),
+        }
+    }
+}
+
+impl fmt::Display for FallbackPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FallbackPolicy::Empty => f.write_str("empty"),
+            FallbackPolicy::StartOfA => f.write_str("start_of_a"),
+            FallbackPolicy::LastPosition => f.write_str("last_position"),
+            FallbackPolicy::Offset(offset) => write!(f, "{offset}"),
+        }
+    }
+}
+
+
+/// Which match the prediction of [`crate::StreamNextChunk`] goes on from.
+///
+/// Except for [`AnchorStrategy::LastMatch`], `b` may have diverged from `a`
+/// after the chosen match: the prediction starts as many tokens past its end
+/// as `b` goes on after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AnchorStrategy {
+    /// The last matching block of the diff, which `b` has to end with (up to
+    /// `max_mismatches` tokens). Suits completion, where `b` copies `a`.
+    #[default]
+    LastMatch,
+    /// The longest matching block of the diff, the later one on ties.
+    LongestMatch,
+    /// The longest run of `a` equal to the end of `b`, wherever the diff
+    /// aligned `b`; the earlier one on ties. Ignores `max_mismatches`.
+    LongestSuffixMatch,
+    /// The matching block predicting closest to where the last confirmed
+    /// anchor, moved past the tokens `b` grew by since, says `b` ends; the
+    /// last matching block without one. Suits rewriting a document in order.
+    NearestToPreviousAnchor,
+    /// The matching block scoring highest by `anchor_weights`: longer, closer
+    /// to the end of `b` and of rarer tokens is better; the later one on
+    /// ties. Still predicts after a long match followed by a novel token.
+    RecencyWeighted,
+}
+
+impl AnchorStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnchorStrategy::LastMatch => "last",
+            AnchorStrategy::LongestMatch => "longest",
+            AnchorStrategy::LongestSuffixMatch => "longest_suffix",
+            AnchorStrategy::NearestToPreviousAnchor => "nearest",
+            AnchorStrategy::RecencyWeighted => "weighted",
+        }
+    }
+}
+
+impl FromStr for AnchorStrategy {
+    type Err = ParseOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "last" | "last_match" => Ok(AnchorStrategy::LastMatch),
+            "longest" | "longest_match" => Ok(AnchorStrategy::LongestMatch),
+            "longest_suffix" | "longest_suffix_match" => Ok(AnchorStrategy::LongestSuffixMatch),
+            "nearest" | "nearest_to_previous_anchor" => Ok(AnchorStrategy::NearestToPreviousAnchor),
+            "weighted" | "recency_weighted" => Ok(AnchorStrategy::RecencyWeighted),
+            _ => Err(ParseOptionError::new("anchor strategy", s, "last, longest, longest_suffix, nearest, weighted")),
+        }
+    }
+}
+
+impl fmt::Display for AnchorStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+/// Retries after the windowed diff found no match, widening the window
+/// around the same spot of `a` before giving up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct EscalationPolicy {
+    /// Window sizes tried in order, as multiples of the normal window size.
+    pub factors: Vec<usize>,
+    /// Diff all of `a` and `b` once the widest window missed too.
+    pub full_diff: bool,
+    /// No retry starts once the call has run this long.
+    pub budget: Duration,
+}
+
+impl Default for EscalationPolicy {
+    fn default() -> Self {
+        EscalationPolicy { factors: vec![2, 4], full_diff: true, budget: Duration::from_millis(20) }
+    }
+}
+
+
+/// Tunables for [`crate::StreamNextChunk`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NextChunkOptions {
+    /// Diff algorithm used unless a call overrides it.
+    pub algorithm: DiffAlgorithm,
+    /// How `b` is matched against `a`.
+    pub matcher: MatcherBackend,
+    /// Minimum window size to activate windowing.
+    pub min_window_threshold: usize,
+    /// How much larger the 'a' window should be than the 'b' window (e.g., 3x).
+    pub a_window_factor: usize,
+    /// Length of the k-grams hashed to locate the 'a' window from the tail of
+    /// 'b' before diffing; 0 keeps the purely length-based window.
+    pub anchor_hash_len: usize,
+    /// When the windowed diff finds no match, retry with wider windows (and
+    /// then the full diff) first. `None` goes straight to `multi_window_search`.
+    pub escalation: Option<EscalationPolicy>,
+    /// When the windowed diff finds no match, diff `b`'s window against every
+    /// other window of 'a' (in parallel with the `parallel` feature) before
+    /// giving up. Off by default: it costs a diff per window of 'a'.
+    pub multi_window_search: bool,
+    /// Anchoring matches shorter than this many tokens are treated as
+    /// accidental, as if there was no match (see `fallback`).
+    pub min_match_len: usize,
+    /// Mismatching tokens tolerated inside the anchoring match (e.g. a renamed
+    /// variable), each standing in for one token of 'a'. 0 requires an exact match.
+    /// Only used by the diff matcher.
+    pub max_mismatches: usize,
+    /// What to predict when `b` can't be anchored, or only with a match
+    /// shorter than `min_match_len`. `None` keeps the historical behavior:
+    /// the start of `a` when the full diff found no match at all, nothing
+    /// when a window missed or `b` ends mid-change.
+    pub fallback: Option<FallbackPolicy>,
+    /// Remember where recent `next_chunk` calls anchored, so a call whose `b`
+    /// extends an earlier one only checks the new tokens instead of re-diffing.
+    /// The memory is the instance's, keyed by the length and tail of `b`, so
+    /// a call may resume from another caller's: only enable it when one
+    /// stream at a time uses the instance, and pass each stream's
+    /// [`crate::PredictionCursor`] to `next_chunk_from` otherwise. Off by default.
+    pub memoize_anchors: bool,
+    /// After this many consecutive windowed calls predicted nothing, the next
+    /// windowed miss runs one diff over all of `a` to find a new anchor, in
+    /// case the model jumped to another part of it. `None` never does.
+    pub global_reanchor_after: Option<usize>,
+    /// Tokens making up more than this fraction of 'a' (newlines, commas,
+    /// indents...) are junk: an anchoring match of only junk tokens is treated
+    /// as no match. Like difflib's autojunk, only applies when 'a' has at
+    /// least 200 tokens. `None` disables it. Only used by the diff matcher.
+    pub autojunk: Option<f64>,
+    /// Above 100k tokens of 'a', diffs over all of 'a' first diff the hashes
+    /// of blocks of this many tokens to find the region 'b' ends in, then
+    /// diff tokens only there. Much faster on huge references, at the cost
+    /// of anchors a token diff might have found elsewhere. `None` always diffs
+    /// token by token. Only used by the diff matcher.
+    pub coarse_block_len: Option<usize>,
+    /// Caps the tokens of 'a' a single diff runs over, bounding its peak
+    /// memory: longer spans of 'a' are diffed one overlapping segment of this
+    /// many tokens at a time, keeping the longest anchoring match of any
+    /// segment. Matches straddling two segments may be cut short. `None`
+    /// diffs any span at once. Only used by the diff matcher.
+    pub diff_segment_len: Option<usize>,
+    /// Caps the bytes of the interning tables and indexes built over 'a'
+    /// (see [`crate::MemoryUsage`]). Optional indexes that don't fit are left
+    /// out; when the interned 'a' alone doesn't fit, every diff is restricted
+    /// to a window of 'a' instead of all of it. `None` is unbounded.
+    pub memory_budget: Option<usize>,
+    /// Time budget of a single `next_chunk` call. Once spent, no further
+    /// diff (escalation, re-anchoring, window search or segment) starts and
+    /// the call is a miss, predicting what `fallback` says. A diff already
+    /// running isn't interrupted, so without `diff_segment_len` diffs are
+    /// segmented at 4096 tokens of 'a' to bound each. `None` is unbounded.
+    /// Only used by the diff matcher.
+    pub deadline: Option<Duration>,
+    /// Which match the prediction goes on from. Only used by the diff matcher.
+    pub anchor_strategy: AnchorStrategy,
+    /// How [`AnchorStrategy::RecencyWeighted`] scores the matching blocks;
+    /// rarity is measured over all of `a`.
+    pub anchor_weights: MatchWeights,
+    /// Before diffing, a `next_chunk` call whose `b` ends with this many
+    /// tokens `a` has right before a position the previous call predicted
+    /// (its end when the whole chunk was accepted) anchors there without a
+    /// diff. Most calls of a generation copying from `a` take this path, but
+    /// it may settle on a repeat the diff would have passed over. As with
+    /// `memoize_anchors`, the previous call is the instance's, whichever
+    /// stream made it. 0 disables it. Only used by the diff matcher.
+    pub continuation_check_len: usize,
+}
+
+impl Default for NextChunkOptions {
+    fn default() -> Self {
+        NextChunkOptions {
+            algorithm: DiffAlgorithm::Histogram,
+            matcher: MatcherBackend::Diff,
+            min_window_threshold: 100,
+            a_window_factor: 3,
+            anchor_hash_len: 8,
+            escalation: None,
+            multi_window_search: false,
+            min_match_len: 1,
+            max_mismatches: 0,
+            fallback: None,
+            memoize_anchors: false,
+            global_reanchor_after: None,
+            autojunk: None,
+            coarse_block_len: None,
+            diff_segment_len: None,
+            memory_budget: None,
+            deadline: None,
+            anchor_strategy: AnchorStrategy::LastMatch,
+            anchor_weights: MatchWeights::default(),
+            continuation_check_len: 0,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_algorithm() {
+        assert_eq!("Myers".parse::<DiffAlgorithm>(), Ok(DiffAlgorithm::Myers));
+        assert_eq!("myers_minimal".parse::<DiffAlgorithm>(), Ok(DiffAlgorithm::MyersMinimal));
+        for algorithm in [DiffAlgorithm::Histogram, DiffAlgorithm::Myers, DiffAlgorithm::MyersMinimal] {
+            assert_eq!(algorithm.as_str().parse::<DiffAlgorithm>(), Ok(algorithm));
+        }
+        let err = "patience".parse::<DiffAlgorithm>().unwrap_err();
+        assert!(err.to_string().contains("patience"));
+    }
+
+    #[test]
+    fn test_parse_matcher() {
+        assert_eq!("sam".parse::<MatcherBackend>(), Ok(MatcherBackend::SuffixAutomaton));
+        for matcher in [MatcherBackend::Diff, MatcherBackend::SuffixAutomaton, MatcherBackend::SuffixArray, MatcherBackend::HashChain] {
+            assert_eq!(matcher.as_str().parse::<MatcherBackend>(), Ok(matcher));
+        }
+        assert!("regex".parse::<MatcherBackend>().is_err());
+    }
+
+    #[test]
+    fn test_parse_anchor_strategy() {
+        assert_eq!("longest_suffix_match".parse::<AnchorStrategy>(), Ok(AnchorStrategy::LongestSuffixMatch));
+        for strategy in [
+            AnchorStrategy::LastMatch,
+            AnchorStrategy::LongestMatch,
+            AnchorStrategy::LongestSuffixMatch,
+            AnchorStrategy::NearestToPreviousAnchor,
+            AnchorStrategy::RecencyWeighted,
+        ] {
+            assert_eq!(strategy.as_str().parse::<AnchorStrategy>(), Ok(strategy));
+        }
+        assert!("first".parse::<AnchorStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_parse_fallback() {
+        assert_eq!("start_of_a".parse::<FallbackPolicy>(), Ok(FallbackPolicy::StartOfA));
+        assert_eq!("42".parse::<FallbackPolicy>(), Ok(FallbackPolicy::Offset(42)));
+        for policy in [FallbackPolicy::Empty, FallbackPolicy::LastPosition, FallbackPolicy::Offset(7)] {
+            assert_eq!(policy.to_string().parse::<FallbackPolicy>(), Ok(policy));
+        }
+        assert!("-1".parse::<FallbackPolicy>().is_err());
+    }
+}
@@ -0,0 +1,38 @@
+/// Tokens compared at once by [`common_prefix_len`].
+const CHUNK: usize = 16;
+
+/// Length of the common prefix of `a` and `b`.
+///
+/// Compares fixed-size chunks first: slice equality on primitive token types
+/// lowers to `memcmp`/vector compares, so long matching runs are checked many
+/// tokens at a time. Only the mismatching chunk is scanned token by token.
+pub(crate) fn common_prefix_len<T: Eq>(a: &[T], b: &[T]) -> usize {
+    let len = a.len().min(b.len());
+    let (a, b) = (&a[..len], &b[..len]);
+    let matched = a
+        .chunks_exact(CHUNK)
+        .zip(b.chunks_exact(CHUNK))
+        .take_while(|(x, y)| x == y)
+        .count()
+        * CHUNK;
+    matched + a[matched..].iter().zip(&b[matched..]).take_while(|(x, y)| x == y).count()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_common_prefix_len() {
+        let a: Vec<i32> = (0..100).collect();
+        for mismatch in [0, 5, 15, 16, 17, 63, 99] {
+            let mut b = a.clone();
+            b[mismatch] = -1;
+            assert_eq!(common_prefix_len(&a, &b), mismatch);
+        }
+        assert_eq!(common_prefix_len(&a, &a[..40]), 40);
+        assert_eq!(common_prefix_len(&a[..3], &a), 3);
+        assert_eq!(common_prefix_len::<i32>(&[], &a), 0);
+    }
+}
@@ -0,0 +1,59 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+
+/// Read-only token storage that can back a reference without being copied,
+/// e.g. a memory-mapped file shared by many instances.
+pub type SharedTokens<T> = Arc<dyn AsRef<[T]> + Send + Sync>;
+
+/// The reference of a [`StreamNextChunk`](crate::StreamNextChunk): owned, or
+/// shared until it's first modified.
+pub(crate) enum Reference<T> {
+    Owned(Vec<T>),
+    Slice(Arc<[T]>),
+    Shared(SharedTokens<T>),
+}
+
+impl<T: Copy> Reference<T> {
+    /// The owned tokens, copying shared ones first.
+    pub(crate) fn to_mut(&mut self) -> &mut Vec<T> {
+        if !matches!(self, Reference::Owned(_)) {
+            *self = Reference::Owned(self.to_vec());
+        }
+        match self {
+            Reference::Owned(tokens) => tokens,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Replaces the tokens with `new`, reusing the owned buffer if any.
+    pub(crate) fn assign(&mut self, new: &[T]) {
+        match self {
+            Reference::Owned(tokens) => {
+                tokens.clear();
+                tokens.extend_from_slice(new);
+            }
+            _ => *self = Reference::Owned(new.to_vec()),
+        }
+    }
+
+    /// The tokens as a `Vec`, copying shared ones.
+    pub(crate) fn into_vec(self) -> Vec<T> {
+        match self {
+            Reference::Owned(tokens) => tokens,
+            shared => shared.to_vec(),
+        }
+    }
+}
+
+impl<T> Deref for Reference<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Reference::Owned(tokens) => tokens,
+            Reference::Slice(tokens) => tokens,
+            Reference::Shared(tokens) => (**tokens).as_ref(),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use super::budget::map_bytes;
+
+
+const BASE: u64 = 0x100_0000_01b3;
+
+/// Rabin–Karp index of every `k`-gram of a reference sequence, used to find
+/// where the tail of `b` lines up with `a` before the (windowed) diff runs.
+pub(crate) struct RollingHashIndex {
+    k: usize,
+    /// `BASE^(k-1)`, to remove the outgoing token from the rolling hash.
+    high: u64,
+    /// Rolling hash -> start positions of the `k`-grams of `a` with that hash.
+    positions: HashMap<u64, Vec<usize>>,
+    /// Number of `k`-grams indexed.
+    kgrams: usize,
+}
+
+fn token_hash<T: Hash>(token: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    token.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Polynomial hash of `tokens`, as rolled over the `k`-grams of the index.
+pub(crate) fn slice_hash<T: Hash>(tokens: &[T]) -> u64 {
+    tokens.iter().fold(0u64, |h, t| h.wrapping_mul(BASE).wrapping_add(token_hash(t)))
+}
+
+/// `BASE^(k-1)`, the weight of the first token of a `k`-gram's hash.
+pub(crate) fn leading_power(k: usize) -> u64 {
+    (1..k).fold(1u64, |h, _| h.wrapping_mul(BASE))
+}
+
+/// Calls `f(start, hash)` for every `k`-gram of `tokens`.
+pub(crate) fn for_each_kgram<T: Hash>(tokens: &[T], k: usize, high: u64, mut f: impl FnMut(usize, u64)) {
+    if tokens.len() < k {
+        return;
+    }
+    let hashes: Vec<u64> = tokens.iter().map(token_hash).collect();
+    let mut hash = hashes[..k].iter().fold(0u64, |h, &t| h.wrapping_mul(BASE).wrapping_add(t));
+    f(0, hash);
+    for start in 1..=tokens.len() - k {
+        hash = hash
+            .wrapping_sub(hashes[start - 1].wrapping_mul(high))
+            .wrapping_mul(BASE)
+            .wrapping_add(hashes[start + k - 1]);
+        f(start, hash);
+    }
+}
+
+impl RollingHashIndex {
+    pub(crate) fn new<T: Hash>(a: &[T], k: usize) -> Self {
+        assert!(k > 0);
+        let high = leading_power(k);
+        let mut positions: HashMap<u64, Vec<usize>> = HashMap::new();
+        for_each_kgram(a, k, high, |start, hash| positions.entry(hash).or_default().push(start));
+        RollingHashIndex { k, high, positions, kgrams: (a.len() + 1).saturating_sub(k) }
+    }
+
+    /// Indexes the `k`-grams that end past `old_len` after `a` grew from `old_len` tokens.
+    pub(crate) fn extend<T: Hash>(&mut self, a: &[T], old_len: usize) {
+        let from = old_len.saturating_sub(self.k - 1);
+        let positions = &mut self.positions;
+        for_each_kgram(&a[from..], self.k, self.high, |start, hash| positions.entry(hash).or_default().push(from + start));
+        self.kgrams = (a.len() + 1).saturating_sub(self.k);
+    }
+
+    /// Approximate heap bytes held by the index.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        map_bytes::<(u64, Vec<usize>)>(self.positions.len()) + self.kgrams * 8
+    }
+
+    /// Upper bound of [`RollingHashIndex::heap_bytes`] over `a_len` tokens,
+    /// before building the index.
+    pub(crate) fn estimate_bytes(a_len: usize) -> usize {
+        map_bytes::<(u64, Vec<usize>)>(a_len) + a_len * 8
+    }
+
+    /// Finds the last `k`-gram of `b[b_from..]` that also occurs in `a`.
+    ///
+    /// Returns `(b_end, a_end)`: the exclusive end of that `k`-gram in `b` and
+    /// in `a`. Among several occurrences in `a` the one whose offset is closest
+    /// to `b_end` wins, matching the length-based heuristic when it is right.
+    pub(crate) fn locate<T: Hash + Eq>(&self, a: &[T], b: &[T], b_from: usize) -> Option<(usize, usize)> {
+        let tail = &b[b_from.min(b.len())..];
+        let mut found = None;
+        for_each_kgram(tail, self.k, self.high, |start, hash| {
+            let Some(candidates) = self.positions.get(&hash) else { return };
+            let b_end = b_from + start + self.k;
+            let kgram = &tail[start..start + self.k];
+            let best = candidates
+                .iter()
+                .filter(|&&a_start| a[a_start..a_start + self.k] == *kgram)
+                .map(|&a_start| a_start + self.k)
+                .min_by_key(|&a_end| a_end.abs_diff(b_end));
+            if let Some(a_end) = best {
+                found = Some((b_end, a_end));
+            }
+        });
+        found
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let a: Vec<i32> = (0..100).chain(0..100).collect();
+        let index = RollingHashIndex::new(&a, 4);
+
+        // tail matches twice in `a`; the occurrence nearest to b's own offset wins
+        let b: Vec<i32> = (-120..0).chain(0..10).collect();
+        assert_eq!(index.locate(&a, &b, 0), Some((130, 110)));
+        let b: Vec<i32> = (0..10).collect();
+        assert_eq!(index.locate(&a, &b, 0), Some((10, 10)));
+        // last matching k-gram, even if the very end of `b` diverged
+        let b = [20, 21, 22, 23, 24, -1, -2];
+        assert_eq!(index.locate(&a, &b, 0), Some((5, 25)));
+        assert_eq!(index.locate(&a, &b, 3), None);
+        assert_eq!(index.locate(&a, &[1, 2], 0), None);
+    }
+}
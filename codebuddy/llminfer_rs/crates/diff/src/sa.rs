@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem::size_of;
+
+use super::budget::map_bytes;
+
+
+/// Suffix array with LCP over a reference sequence.
+///
+/// Answers "what is the longest suffix of `b` occurring in `a`, and where
+/// does it first end?" in O(m log n) for a match of m tokens, without the
+/// per-token hash maps of [`crate::SuffixAutomaton`]: about 24 bytes per
+/// token of `a`, plus its vocabulary. Built over `a` reversed, so a suffix
+/// of `b` is a prefix of `b` reversed, found by narrowing the range of
+/// sorted suffixes one token at a time. Built in O(n log² n); extending `a`
+/// rebuilds it.
+pub struct SuffixArray<T> {
+    ids: HashMap<T, u32>,
+    /// Ids of `a` reversed.
+    text: Vec<u32>,
+    /// Starts in `text` of its suffixes, sorted.
+    suffixes: Vec<u32>,
+    /// Largest suffix start of each range of `suffixes`, as a bottom-up
+    /// segment tree: the last suffix of `text` is the first match in `a`.
+    max_start: Vec<u32>,
+    /// Smallest LCP of neighbouring suffixes over each range, as a segment
+    /// tree of the LCP array (`lcp[i]` between suffixes `i - 1` and `i`):
+    /// when every suffix of a range shares the next token, no search is needed.
+    min_lcp: Vec<u32>,
+}
+
+impl<T: Eq + Hash + Copy> SuffixArray<T> {
+    pub fn new(a: &[T]) -> Self {
+        let mut ids = HashMap::new();
+        let text: Vec<u32> = a
+            .iter()
+            .rev()
+            .map(|token| {
+                let next = ids.len() as u32;
+                *ids.entry(*token).or_insert(next)
+            })
+            .collect();
+        let suffixes = sort_suffixes(&text);
+        let lcp = lcp_array(&text, &suffixes);
+        SuffixArray { ids, max_start: segment_tree(&suffixes, u32::max), min_lcp: segment_tree(&lcp, u32::min), text, suffixes }
+    }
+
+    /// Approximate heap bytes held by the index.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        map_bytes::<(T, u32)>(self.ids.len())
+            + (self.text.len() + self.suffixes.len() + self.max_start.len() + self.min_lcp.len()) * size_of::<u32>()
+    }
+
+    /// Number of tokens indexed.
+    pub fn len(&self) -> usize {
+        self.text.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// The longest suffix of `b` occurring in `a`, as `(pos, match_len)`
+    /// with `pos` the offset in `a` right after its first occurrence; `None`
+    /// when the last token of `b` doesn't occur in `a`.
+    pub fn longest_suffix_match(&self, b: &[T]) -> Option<(usize, usize)> {
+        // Suffixes of `text` starting with the `depth` last tokens of `b` reversed
+        let (mut lo, mut hi, mut depth) = (0, self.suffixes.len(), 0);
+        for token in b.iter().rev() {
+            let Some(&id) = self.ids.get(token) else { break };
+            if hi - lo > 1 && fold(&self.min_lcp, lo + 1, hi, u32::MAX, u32::min) <= depth as u32 {
+                let range = &self.suffixes[lo..hi];
+                let start = lo + range.partition_point(|&suffix| self.token_at(suffix, depth) < Some(id));
+                let end = start + self.suffixes[start..hi].partition_point(|&suffix| self.token_at(suffix, depth) == Some(id));
+                if start == end {
+                    break;
+                }
+                (lo, hi) = (start, end);
+            } else if self.token_at(self.suffixes[lo], depth) != Some(id) {
+                break;
+            }
+            depth += 1;
+        }
+        if depth == 0 {
+            return None;
+        }
+        let start = fold(&self.max_start, lo, hi, 0, u32::max) as usize;
+        Some((self.text.len() - start, depth))
+    }
+
+    fn token_at(&self, suffix: u32, depth: usize) -> Option<u32> {
+        self.text.get(suffix as usize + depth).copied()
+    }
+}
+
+/// Starts of the suffixes of `text` in sorted order, by prefix doubling.
+fn sort_suffixes(text: &[u32]) -> Vec<u32> {
+    let n = text.len();
+    let mut suffixes: Vec<u32> = (0..n as u32).collect();
+    let mut rank: Vec<u32> = text.to_vec();
+    let mut next_rank = vec![0; n];
+    let mut k = 1;
+    // Until every suffix has its own rank
+    let mut distinct = n <= 1;
+    while !distinct {
+        // Ranks of the suffixes by their first 2k tokens; ranks start at 1 so
+        // a suffix shorter than k sorts first
+        let key = |i: u32| (rank[i as usize], rank.get(i as usize + k).map_or(0, |&r| r + 1));
+        suffixes.sort_unstable_by_key(|&i| key(i));
+        next_rank[suffixes[0] as usize] = 0;
+        for w in 1..n {
+            let (prev, cur) = (suffixes[w - 1], suffixes[w]);
+            next_rank[cur as usize] = next_rank[prev as usize] + u32::from(key(prev) != key(cur));
+        }
+        std::mem::swap(&mut rank, &mut next_rank);
+        distinct = rank[suffixes[n - 1] as usize] as usize == n - 1;
+        k *= 2;
+    }
+    suffixes
+}
+
+/// LCP of each suffix with the one before it in sorted order (Kasai), 0 for the first.
+fn lcp_array(text: &[u32], suffixes: &[u32]) -> Vec<u32> {
+    let n = text.len();
+    let mut rank = vec![0; n];
+    for (i, &suffix) in suffixes.iter().enumerate() {
+        rank[suffix as usize] = i;
+    }
+    let mut lcp = vec![0; n];
+    let mut h = 0;
+    for suffix in 0..n {
+        if rank[suffix] == 0 {
+            h = 0;
+            continue;
+        }
+        let prev = suffixes[rank[suffix] - 1] as usize;
+        while suffix + h < n && prev + h < n && text[suffix + h] == text[prev + h] {
+            h += 1;
+        }
+        lcp[rank[suffix]] = h as u32;
+        h = h.saturating_sub(1);
+    }
+    lcp
+}
+
+/// Bottom-up segment tree over `values`: leaves at `n..2n`, each inner node
+/// the `op` of its two children.
+fn segment_tree(values: &[u32], op: fn(u32, u32) -> u32) -> Vec<u32> {
+    let n = values.len();
+    let mut tree = vec![0; 2 * n];
+    tree[n..].copy_from_slice(values);
+    for node in (1..n).rev() {
+        tree[node] = op(tree[2 * node], tree[2 * node + 1]);
+    }
+    tree
+}
+
+/// `op` of the values `lo..hi` of a [`segment_tree`], `identity` when empty.
+fn fold(tree: &[u32], lo: usize, hi: usize, identity: u32, op: fn(u32, u32) -> u32) -> u32 {
+    let n = tree.len() / 2;
+    let (mut lo, mut hi, mut acc) = (lo + n, hi + n, identity);
+    while lo < hi {
+        if lo % 2 == 1 {
+            acc = op(acc, tree[lo]);
+            lo += 1;
+        }
+        if hi % 2 == 1 {
+            hi -= 1;
+            acc = op(acc, tree[hi]);
+        }
+        (lo, hi) = (lo / 2, hi / 2);
+    }
+    acc
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Longest suffix of `b` occurring in `a` and the end of its first occurrence, by brute force.
+    fn naive(a: &[i32], b: &[i32]) -> Option<(usize, usize)> {
+        (1..=b.len()).rev().find_map(|len| {
+            let suffix = &b[b.len() - len..];
+            a.windows(len).position(|w| w == suffix).map(|start| (start + len, len))
+        })
+    }
+
+    #[test]
+    fn test_matches_naive() {
+        let a = vec![1, 2, 1, 2, 3, 1, 2, 3, 4, 2, 2, 5, 1, 2];
+        let sa = SuffixArray::new(&a);
+        assert_eq!(sa.len(), a.len());
+        let bs: Vec<Vec<i32>> = vec![
+            vec![],
+            vec![9],
+            vec![1, 2, 3],
+            vec![7, 3, 1, 2],
+            vec![2, 2, 5, 1, 2, 3],
+            vec![4, 2, 2],
+            vec![1, 2, 9, 1, 2, 3, 4],
+            vec![2],
+            a.clone(),
+        ];
+        for b in bs {
+            assert_eq!(sa.longest_suffix_match(&b), naive(&a, &b), "b = {:?}", b);
+        }
+    }
+
+    #[test]
+    fn test_sorted_suffixes() {
+        let text = [3, 1, 3, 1, 3, 0, 3];
+        let suffixes = sort_suffixes(&text);
+        let sorted: Vec<&[u32]> = suffixes.iter().map(|&i| &text[i as usize..]).collect();
+        assert!(sorted.windows(2).all(|w| w[0] < w[1]));
+        let lcp = lcp_array(&text, &suffixes);
+        for i in 1..text.len() {
+            let common = sorted[i - 1].iter().zip(sorted[i]).take_while(|(x, y)| x == y).count();
+            assert_eq!(lcp[i] as usize, common);
+        }
+        assert!(SuffixArray::<i32>::new(&[]).longest_suffix_match(&[1]).is_none());
+    }
+}
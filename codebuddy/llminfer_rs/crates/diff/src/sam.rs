@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem::size_of;
+
+use super::budget::map_bytes;
+
+
+/// Suffix automaton over a reference sequence.
+///
+/// Answers "what is the longest suffix of `b` occurring in `a`, and where does
+/// it end?" while `b` is fed one token at a time, in O(1) amortized per token.
+pub struct SuffixAutomaton<T> {
+    states: Vec<State<T>>,
+    /// State of the whole sequence added so far.
+    last: usize,
+    /// Number of tokens added so far.
+    len: usize,
+    /// Transitions over all states, to size the automaton.
+    transitions: usize,
+}
+
+struct State<T> {
+    /// Length of the longest string in this state.
+    len: usize,
+    /// Suffix link; `None` only for the root.
+    link: Option<usize>,
+    next: HashMap<T, usize>,
+    /// End position (inclusive) of the first occurrence of this state's strings.
+    first_end: usize,
+    /// States whose suffix link is this one.
+    children: Vec<usize>,
+    /// Split off another state, so `first_end` isn't an occurrence of its own.
+    cloned: bool,
+}
+
+/// Position in the automaton after matching some tokens of `b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SamCursor {
+    state: usize,
+    /// Length of the longest suffix of the fed tokens that occurs in `a`.
+    pub match_len: usize,
+}
+
+const ROOT: usize = 0;
+
+impl<T: Eq + Hash + Copy> SuffixAutomaton<T> {
+    /// Builds the automaton over `a` in O(|a|).
+    pub fn new(a: &[T]) -> Self {
+        let root = State { len: 0, link: None, next: HashMap::new(), first_end: 0, children: Vec::new(), cloned: false };
+        let mut sam = SuffixAutomaton {
+            states: Vec::with_capacity(2 * a.len() + 1),
+            last: ROOT,
+            len: 0,
+            transitions: 0,
+        };
+        sam.states.push(root);
+        sam.extend(a);
+        sam
+    }
+
+    /// Approximate heap bytes held by the automaton.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        // Every state but the root is the child of one other
+        self.states.len() * (size_of::<State<T>>() + size_of::<usize>()) + map_bytes::<(T, usize)>(self.transitions)
+    }
+
+    /// Rough size of the automaton over `a_len` tokens, before building it:
+    /// up to two states per token, with a few transitions each.
+    pub(crate) fn estimate_bytes(a_len: usize) -> usize {
+        2 * a_len * (size_of::<State<T>>() + size_of::<usize>() + map_bytes::<(T, usize)>(4))
+    }
+
+    /// Appends tokens to the indexed sequence.
+    pub fn extend(&mut self, tokens: &[T]) {
+        for &token in tokens {
+            self.push(token);
+        }
+    }
+
+    /// Number of tokens indexed.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn push(&mut self, token: T) {
+        let cur = self.states.len();
+        self.states.push(State {
+            len: self.states[self.last].len + 1,
+            link: None,
+            next: HashMap::new(),
+            first_end: self.len,
+            children: Vec::new(),
+            cloned: false,
+        });
+        self.len += 1;
+
+        let mut p = Some(self.last);
+        while let Some(pp) = p {
+            if self.states[pp].next.contains_key(&token) {
+                break;
+            }
+            self.states[pp].next.insert(token, cur);
+            self.transitions += 1;
+            p = self.states[pp].link;
+        }
+
+        let link = match p {
+            None => ROOT,
+            Some(pp) => {
+                let q = self.states[pp].next[&token];
+                if self.states[pp].len + 1 == self.states[q].len {
+                    q
+                } else {
+                    let clone = self.states.len();
+                    self.transitions += self.states[q].next.len();
+                    self.states.push(State {
+                        len: self.states[pp].len + 1,
+                        link: self.states[q].link,
+                        next: self.states[q].next.clone(),
+                        first_end: self.states[q].first_end,
+                        children: vec![q],
+                        cloned: true,
+                    });
+                    // The clone takes `q`'s place under its old link
+                    if let Some(parent) = self.states[q].link {
+                        let siblings = &mut self.states[parent].children;
+                        if let Some(slot) = siblings.iter_mut().find(|child| **child == q) {
+                            *slot = clone;
+                        }
+                    }
+                    let mut p = Some(pp);
+                    while let Some(pp) = p {
+                        if self.states[pp].next.get(&token) != Some(&q) {
+                            break;
+                        }
+                        self.states[pp].next.insert(token, clone);
+                        p = self.states[pp].link;
+                    }
+                    self.states[q].link = Some(clone);
+                    clone
+                }
+            }
+        };
+        self.states[cur].link = Some(link);
+        self.states[link].children.push(cur);
+        self.last = cur;
+    }
+
+    /// Advances `cursor` by one token of `b`.
+    pub fn step(&self, cursor: SamCursor, token: T) -> SamCursor {
+        let mut state = cursor.state;
+        let mut match_len = cursor.match_len;
+        loop {
+            if let Some(&next) = self.states[state].next.get(&token) {
+                return SamCursor { state: next, match_len: match_len + 1 };
+            }
+            match self.states[state].link {
+                Some(link) => {
+                    state = link;
+                    match_len = self.states[link].len;
+                }
+                None => return SamCursor::default(),
+            }
+        }
+    }
+
+    /// Feeds all of `b` from scratch.
+    pub fn match_suffix(&self, b: &[T]) -> SamCursor {
+        b.iter().fold(SamCursor::default(), |cursor, &token| self.step(cursor, token))
+    }
+
+    /// Offset in `a` right after the first occurrence of the matched suffix,
+    /// `None` when nothing matched.
+    pub fn end_of_match(&self, cursor: SamCursor) -> Option<usize> {
+        (cursor.match_len > 0).then(|| self.states[cursor.state].first_end + 1)
+    }
+
+    /// Every offset in `a` right after an occurrence of a suffix of the fed
+    /// tokens at least `min_len` long, as `(pos, match_len)` with the longest
+    /// suffix ending there; in no particular order. Walks the suffix links
+    /// from `cursor`: the occurrences of a shorter suffix not already found
+    /// for a longer one are in the subtree of its state, minus the subtree
+    /// just visited, so each state is visited once.
+    pub fn occurrences(&self, cursor: SamCursor, min_len: usize) -> Vec<(usize, usize)> {
+        let mut found = Vec::new();
+        let (mut state, mut match_len, mut visited) = (cursor.state, cursor.match_len, None);
+        while match_len >= min_len.max(1) {
+            let mut stack = vec![state];
+            while let Some(s) = stack.pop() {
+                if !self.states[s].cloned {
+                    found.push((self.states[s].first_end + 1, match_len));
+                }
+                stack.extend(self.states[s].children.iter().filter(|&&child| Some(child) != visited));
+            }
+            let Some(link) = self.states[state].link else { break };
+            (visited, state, match_len) = (Some(state), link, self.states[link].len);
+        }
+        found
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Longest suffix of `b` occurring in `a` and the end of its first occurrence, by brute force.
+    fn naive(a: &[i32], b: &[i32]) -> (usize, Option<usize>) {
+        for len in (1..=b.len()).rev() {
+            let suffix = &b[b.len() - len..];
+            if let Some(start) = a.windows(len).position(|w| w == suffix) {
+                return (len, Some(start + len));
+            }
+        }
+        (0, None)
+    }
+
+    #[test]
+    fn test_matches_naive() {
+        let a = vec![1, 2, 1, 2, 3, 1, 2, 3, 4, 2, 2, 5, 1, 2];
+        let sam = SuffixAutomaton::new(&a);
+        assert_eq!(sam.len(), a.len());
+        let bs: Vec<Vec<i32>> = vec![
+            vec![],
+            vec![9],
+            vec![1, 2, 3],
+            vec![7, 3, 1, 2],
+            vec![2, 2, 5, 1, 2, 3],
+            vec![4, 2, 2],
+            vec![1, 2, 9, 1, 2, 3, 4],
+        ];
+        for b in bs {
+            let cursor = sam.match_suffix(&b);
+            assert_eq!((cursor.match_len, sam.end_of_match(cursor)), naive(&a, &b), "b = {:?}", b);
+        }
+    }
+
+    #[test]
+    fn test_occurrences() {
+        let a = vec![1, 2, 1, 2, 3, 1, 2, 3, 4, 2, 2, 5, 1, 2];
+        let mut sam = SuffixAutomaton::new(&a[..6]);
+        sam.extend(&a[6..]);
+        for b in [vec![9], vec![1, 2, 3], vec![4, 2, 2], vec![5, 1, 2], vec![9, 2]] {
+            let mut found = sam.occurrences(sam.match_suffix(&b), 2);
+            found.sort();
+            let naive: Vec<_> = (1..=a.len())
+                .map(|pos| (pos, a[..pos].iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count()))
+                .filter(|&(_, match_len)| match_len >= 2)
+                .collect();
+            assert_eq!(found, naive, "b = {:?}", b);
+        }
+        let found = sam.occurrences(sam.match_suffix(&[3, 2]), 1);
+        assert_eq!(found.len(), 6);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut sam = SuffixAutomaton::new(&[1, 2, 3]);
+        sam.extend(&[4, 1, 2, 5]);
+        let cursor = sam.match_suffix(&[9, 1, 2, 5]);
+        assert_eq!(cursor.match_len, 3);
+        assert_eq!(sam.end_of_match(cursor), Some(7));
+    }
+}
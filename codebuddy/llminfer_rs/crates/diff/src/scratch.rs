@@ -0,0 +1,96 @@
+use std::ops::{Deref, DerefMut, Range};
+use std::sync::{Mutex, PoisonError};
+
+use imara_diff::intern::Token;
+
+
+/// Idle buffers a [`ScratchPool`] keeps; more concurrent calls than this
+/// just allocate.
+const MAX_IDLE: usize = 8;
+
+/// Buffers reused across calls instead of being reallocated by each one.
+///
+/// Stateless calls may run concurrently on a shared streamer, so each call
+/// takes a buffer of its own, which goes back to the pool cleared but with
+/// its allocation kept when the [`ScratchBuf`] is dropped.
+#[derive(Debug)]
+pub(crate) struct ScratchPool<E>(Mutex<Vec<Vec<E>>>);
+
+impl<E> Default for ScratchPool<E> {
+    fn default() -> Self {
+        ScratchPool(Mutex::new(Vec::new()))
+    }
+}
+
+impl<E> ScratchPool<E> {
+    /// An empty buffer, allocated by an earlier call if one is idle.
+    pub(crate) fn take(&self) -> ScratchBuf<'_, E> {
+        let buf = self.0.lock().unwrap_or_else(PoisonError::into_inner).pop().unwrap_or_default();
+        ScratchBuf { pool: self, buf }
+    }
+}
+
+/// A buffer taken from a [`ScratchPool`], given back on drop.
+pub(crate) struct ScratchBuf<'a, E> {
+    pool: &'a ScratchPool<E>,
+    buf: Vec<E>,
+}
+
+impl<E> Deref for ScratchBuf<'_, E> {
+    type Target = Vec<E>;
+
+    fn deref(&self) -> &Vec<E> {
+        &self.buf
+    }
+}
+
+impl<E> DerefMut for ScratchBuf<'_, E> {
+    fn deref_mut(&mut self) -> &mut Vec<E> {
+        &mut self.buf
+    }
+}
+
+impl<E> Drop for ScratchBuf<'_, E> {
+    fn drop(&mut self) {
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        let mut idle = self.pool.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < MAX_IDLE && buf.capacity() > 0 {
+            idle.push(buf);
+        }
+    }
+}
+
+/// The per-call buffers of a streamer: the interned `b` and the matching
+/// blocks of each diff.
+#[derive(Debug, Default)]
+pub(crate) struct Scratch {
+    pub(crate) tokens: ScratchPool<Token>,
+    pub(crate) matches: ScratchPool<(Range<u32>, Range<u32>)>,
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reuse() {
+        let pool = ScratchPool::<u32>::default();
+        let mut buf = pool.take();
+        buf.extend(0..100);
+        let ptr = buf.as_ptr();
+        drop(buf);
+
+        let buf = pool.take();
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 100);
+        assert_eq!(buf.as_ptr(), ptr);
+
+        // A concurrent caller gets a buffer of its own
+        let other = pool.take();
+        assert_eq!(other.capacity(), 0);
+        drop((buf, other));
+        assert_eq!(pool.0.lock().unwrap().len(), 1);
+    }
+}
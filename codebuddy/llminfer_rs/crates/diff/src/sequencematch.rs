@@ -0,0 +1,127 @@
+use std::hash::Hash;
+use std::ops::Range;
+
+use imara_diff::intern::InternedInput;
+
+use super::options::DiffAlgorithm;
+use super::sink::{MatchCollector, Opcode, OpcodeCollector};
+use super::source::TokenSlice;
+
+
+/// The equal runs of the diff of `a` and `b` as `(range_in_a, range_in_b)`
+/// pairs of the same length, in order, as collected by [`MatchCollector`].
+pub fn find_matches<T: Eq + Hash + Copy>(a: &[T], b: &[T], algorithm: DiffAlgorithm) -> Vec<(Range<u32>, Range<u32>)> {
+    let input = InternedInput::new(TokenSlice(a), TokenSlice(b));
+    imara_diff::diff(algorithm.into(), &input, MatchCollector::new(a.len() as u32, b.len() as u32))
+}
+
+/// Similarity of `a` and `b` as `2 * M / (len(a) + len(b))`, where `M` is the
+/// number of matched tokens; the same measure as difflib's
+/// `SequenceMatcher.ratio()`. `M` comes from the diff rather than difflib's
+/// matching heuristic, so values can differ slightly on junk-heavy input.
+///
+/// Two empty sequences are identical (1.0).
+pub fn similarity<T: Eq + Hash + Copy>(a: &[T], b: &[T], algorithm: DiffAlgorithm) -> f64 {
+    let total = a.len() + b.len();
+    if total == 0 {
+        return 1.0;
+    }
+    let matched: usize = find_matches(a, b, algorithm).iter().map(|(range_a, _)| range_a.len()).sum();
+    2.0 * matched as f64 / total as f64
+}
+
+/// Matching blocks as `(i, j, n)` triples meaning `a[i..i + n] == b[j..j + n]`,
+/// in increasing order and ending with the `(a.len(), b.len(), 0)` sentinel,
+/// like difflib's `SequenceMatcher.get_matching_blocks()`.
+pub fn matching_blocks<T: Eq + Hash + Copy>(a: &[T], b: &[T], algorithm: DiffAlgorithm) -> Vec<(usize, usize, usize)> {
+    let mut blocks: Vec<_> = find_matches(a, b, algorithm)
+        .into_iter()
+        .map(|(range_a, range_b)| (range_a.start as usize, range_b.start as usize, range_a.len()))
+        .collect();
+    blocks.push((a.len(), b.len(), 0));
+    blocks
+}
+
+/// The edit script turning `a` into `b`, equal runs included, like difflib's
+/// `SequenceMatcher.get_opcodes()`.
+pub fn opcodes<T: Eq + Hash + Copy>(a: &[T], b: &[T], algorithm: DiffAlgorithm) -> Vec<Opcode> {
+    let input = InternedInput::new(TokenSlice(a), TokenSlice(b));
+    imara_diff::diff(algorithm.into(), &input, OpcodeCollector::new(a.len() as u32, b.len() as u32))
+}
+
+/// A common subsequence of two sequences with the positions it was taken from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lcs<T> {
+    pub tokens: Vec<T>,
+    /// `(i, j)` for each token: `tokens[k] == a[i] == b[j]`.
+    pub pairs: Vec<(usize, usize)>,
+}
+
+/// Common token subsequence of `a` and `b`, built from the diff's match ranges.
+///
+/// Exact longest for [`DiffAlgorithm::MyersMinimal`]; the other algorithms
+/// trade a possibly slightly shorter subsequence for speed.
+pub fn lcs<T: Eq + Hash + Copy>(a: &[T], b: &[T], algorithm: DiffAlgorithm) -> Lcs<T> {
+    let mut tokens = Vec::new();
+    let mut pairs = Vec::new();
+    for (range_a, range_b) in find_matches(a, b, algorithm) {
+        tokens.extend_from_slice(&a[range_a.start as usize..range_a.end as usize]);
+        pairs.extend((range_a.start as usize..range_a.end as usize).zip(range_b.start as usize..));
+    }
+    Lcs { tokens, pairs }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_similarity() {
+        let algorithm = DiffAlgorithm::default();
+        assert_eq!(similarity(&[1, 2, 3, 4], &[1, 2, 3, 4], algorithm), 1.0);
+        assert_eq!(similarity(&[1, 2, 3, 4], &[1, 9, 3, 4, 5, 6], algorithm), 0.6);
+        assert_eq!(similarity(&[1, 2], &[3], algorithm), 0.0);
+        assert_eq!(similarity::<i32>(&[], &[], algorithm), 1.0);
+    }
+
+    #[test]
+    fn test_find_matches() {
+        let algorithm = DiffAlgorithm::default();
+        assert_eq!(find_matches(&[1, 2, 3, 4, 5], &[0, 1, 2, 9, 4, 5], algorithm), vec![(0..2, 1..3), (3..5, 4..6)]);
+        assert!(find_matches(&[1, 2], &[3], algorithm).is_empty());
+    }
+
+    #[test]
+    fn test_matching_blocks() {
+        let algorithm = DiffAlgorithm::default();
+        assert_eq!(
+            matching_blocks(&[1, 2, 3, 4, 5], &[0, 1, 2, 9, 4, 5], algorithm),
+            vec![(0, 1, 2), (3, 4, 2), (5, 6, 0)]
+        );
+        assert_eq!(matching_blocks::<i32>(&[], &[], algorithm), vec![(0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_opcodes() {
+        use crate::sink::OpTag::*;
+
+        let ops = opcodes(&[1, 2, 3, 4, 5], &[0, 1, 2, 9, 4, 5, 6], DiffAlgorithm::default());
+        let ops: Vec<_> = ops.into_iter().map(|op| (op.tag, op.a, op.b)).collect();
+        assert_eq!(
+            ops,
+            vec![(Insert, 0..0, 0..1), (Equal, 0..2, 1..3), (Replace, 2..3, 3..4), (Equal, 3..5, 4..6), (Insert, 5..5, 6..7)]
+        );
+        assert_eq!(opcodes(&[1], &[], DiffAlgorithm::default()), vec![Opcode { tag: Delete, a: 0..1, b: 0..0 }]);
+        assert!(opcodes::<i32>(&[], &[], DiffAlgorithm::default()).is_empty());
+    }
+
+    #[test]
+    fn test_lcs() {
+        let result = lcs(&[1, 2, 3, 4, 5], &[0, 1, 2, 9, 4, 5, 6], DiffAlgorithm::MyersMinimal);
+        assert_eq!(result.tokens, [1, 2, 4, 5]);
+        assert_eq!(result.pairs, [(0, 1), (1, 2), (3, 4), (4, 5)]);
+        assert!(lcs(&[1, 2], &[3], DiffAlgorithm::default()).tokens.is_empty());
+    }
+}
@@ -0,0 +1,235 @@
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use lru::LruCache;
+
+use super::clock::Instant;
+
+
+/// Bounds on the sessions a [`SessionPool`] keeps open; `None` is unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionLimits {
+    pub max_sessions: Option<usize>,
+    /// Total tokens over all sessions, as reported to the pool, a proxy for
+    /// their memory.
+    pub max_tokens: Option<usize>,
+    /// Idle time after which a session is closed.
+    pub ttl: Option<Duration>,
+}
+
+/// Why a [`SessionPool`] closed a session itself rather than on request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// Idle for longer than [`SessionLimits::ttl`].
+    Expired,
+    /// Least recently used while over [`SessionLimits::max_sessions`] or
+    /// [`SessionLimits::max_tokens`].
+    Capacity,
+}
+
+impl Eviction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Eviction::Expired => "expired",
+            Eviction::Capacity => "capacity",
+        }
+    }
+}
+
+/// Sessions a [`SessionPool`] closed itself, with the reason.
+pub type Evicted<K> = Vec<(K, Eviction)>;
+
+struct Entry<S> {
+    session: Arc<Mutex<S>>,
+    last_used: Instant,
+    /// Tokens held by the session, as last reported.
+    tokens: usize,
+}
+
+/// The open sessions, least recently used last, and their total tokens.
+struct Sessions<K, S> {
+    map: LruCache<K, Entry<S>>,
+    tokens: usize,
+}
+
+impl<K: Eq + Hash, S> Sessions<K, S> {
+    fn remove(&mut self, id: &K) -> Option<Entry<S>> {
+        let entry = self.map.pop(id)?;
+        self.tokens -= entry.tokens;
+        Some(entry)
+    }
+}
+
+/// Sessions by id, e.g. one streamer per request of an inference server,
+/// closing the idle and least recently used ones so that leaked sessions
+/// can't grow a long-running process without bound.
+///
+/// The map lock is only held to look a session up; callers lock just the
+/// session they use, so different sessions run concurrently. Limits are
+/// enforced on [`SessionPool::insert`], [`SessionPool::get`],
+/// [`SessionPool::set_tokens`] and [`SessionPool::evict`], which return the
+/// sessions they closed; the session being inserted or updated is never
+/// evicted for capacity. Sessions are kept in order of use with their
+/// total tokens, so that costs time in the sessions closed only.
+pub struct SessionPool<K, S> {
+    limits: SessionLimits,
+    sessions: Mutex<Sessions<K, S>>,
+}
+
+impl<K: Eq + Hash + Clone, S> SessionPool<K, S> {
+    pub fn new(limits: SessionLimits) -> Self {
+        SessionPool { limits, sessions: Mutex::new(Sessions { map: LruCache::unbounded(), tokens: 0 }) }
+    }
+
+    pub fn limits(&self) -> SessionLimits {
+        self.limits
+    }
+
+    /// Opens session `id` holding `tokens` tokens. Gives `session` back when
+    /// `id` is already open.
+    pub fn insert(&self, id: K, session: S, tokens: usize) -> Result<Evicted<K>, S> {
+        let mut sessions = self.sessions();
+        if sessions.map.contains(&id) {
+            return Err(session);
+        }
+        let entry = Entry { session: Arc::new(Mutex::new(session)), last_used: Instant::now(), tokens };
+        sessions.map.push(id.clone(), entry);
+        sessions.tokens += tokens;
+        Ok(self.enforce(&mut sessions, Some(&id)))
+    }
+
+    /// Session `id`, marked as used now, after closing the sessions over the
+    /// limits; `None` if it isn't open or was just closed for expiring.
+    pub fn get(&self, id: &K) -> (Option<Arc<Mutex<S>>>, Evicted<K>) {
+        let mut sessions = self.sessions();
+        let evicted = self.enforce(&mut sessions, None);
+        let session = sessions.map.get_mut(id).map(|entry| {
+            entry.last_used = Instant::now();
+            entry.session.clone()
+        });
+        (session, evicted)
+    }
+
+    /// Session `id` as is, without marking it used or enforcing the limits,
+    /// e.g. to inspect it.
+    pub fn peek(&self, id: &K) -> Option<Arc<Mutex<S>>> {
+        self.sessions().map.peek(id).map(|entry| entry.session.clone())
+    }
+
+    /// Records that session `id` now holds `tokens` tokens, closing others
+    /// if that goes over [`SessionLimits::max_tokens`].
+    pub fn set_tokens(&self, id: &K, tokens: usize) -> Evicted<K> {
+        let mut sessions = self.sessions();
+        let Some(entry) = sessions.map.peek_mut(id) else {
+            return Vec::new();
+        };
+        if entry.tokens == tokens {
+            return Vec::new();
+        }
+        let old = std::mem::replace(&mut entry.tokens, tokens);
+        sessions.tokens = sessions.tokens - old + tokens;
+        self.enforce(&mut sessions, Some(id))
+    }
+
+    /// Closes session `id`, returning it if it was open.
+    pub fn remove(&self, id: &K) -> Option<Arc<Mutex<S>>> {
+        self.sessions().remove(id).map(|entry| entry.session)
+    }
+
+    /// Applies the limits now, e.g. periodically to close expired sessions
+    /// without waiting for the next call.
+    pub fn evict(&self) -> Evicted<K> {
+        self.enforce(&mut self.sessions(), None)
+    }
+
+    /// Ids of the open sessions, most recently used first.
+    pub fn ids(&self) -> Vec<K> {
+        self.sessions().map.iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    pub fn contains(&self, id: &K) -> bool {
+        self.sessions().map.contains(id)
+    }
+
+    /// Number of open sessions.
+    pub fn len(&self) -> usize {
+        self.sessions().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn sessions(&self) -> MutexGuard<'_, Sessions<K, S>> {
+        self.sessions.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Closes the sessions idle for longer than the TTL, then the least
+    /// recently used ones while over capacity, sparing `keep` for capacity.
+    ///
+    /// Both start from the least recently used session: the first one left
+    /// open ends the sweep, as every session used since expires later.
+    fn enforce(&self, sessions: &mut Sessions<K, S>, keep: Option<&K>) -> Evicted<K> {
+        let mut evicted = Vec::new();
+        let now = Instant::now();
+        loop {
+            let over_capacity = self.limits.max_sessions.is_some_and(|max| sessions.map.len() > max)
+                || self.limits.max_tokens.is_some_and(|max| sessions.tokens > max);
+            let Some((id, entry)) = sessions.map.iter().rev().find(|(id, _)| Some(*id) != keep) else { break };
+            let reason = if self.limits.ttl.is_some_and(|ttl| now.duration_since(entry.last_used) > ttl) {
+                Eviction::Expired
+            } else if over_capacity {
+                Eviction::Capacity
+            } else {
+                break;
+            };
+            let id = id.clone();
+            sessions.remove(&id);
+            evicted.push((id, reason));
+        }
+        evicted
+    }
+}
+
+impl<K: Eq + Hash + Clone, S> Default for SessionPool<K, S> {
+    fn default() -> Self {
+        Self::new(SessionLimits::default())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_capacity() {
+        let pool = SessionPool::new(SessionLimits { max_sessions: Some(2), max_tokens: Some(10), ..Default::default() });
+        assert_eq!(pool.insert(1, "a", 3), Ok(vec![]));
+        assert_eq!(pool.insert(2, "b", 3), Ok(vec![]));
+        assert_eq!(pool.insert(2, "c", 3), Err("c"));
+        // 1 was used since 2, so 2 goes
+        assert!(pool.peek(&2).is_some());
+        assert!(pool.get(&1).0.is_some());
+        assert_eq!(pool.insert(3, "d", 3), Ok(vec![(2, Eviction::Capacity)]));
+        assert_eq!(pool.set_tokens(&3, 8), vec![(1, Eviction::Capacity)]);
+        assert_eq!(pool.ids(), [3]);
+        // The updated session itself stays, even alone over the limit
+        assert_eq!(pool.set_tokens(&3, 20), vec![]);
+        assert!(pool.remove(&3).is_some() && pool.is_empty());
+    }
+
+    #[test]
+    fn test_ttl() {
+        let pool = SessionPool::new(SessionLimits { ttl: Some(Duration::from_millis(50)), ..Default::default() });
+        pool.insert("a", (), 0).unwrap();
+        assert_eq!(pool.evict(), vec![]);
+        std::thread::sleep(Duration::from_millis(80));
+        assert_eq!(pool.insert("b", (), 0), Ok(vec![("a", Eviction::Expired)]));
+        std::thread::sleep(Duration::from_millis(80));
+        let (session, evicted) = pool.get(&"b");
+        assert!(session.is_none() && !pool.contains(&"b"));
+        assert_eq!(evicted, vec![("b", Eviction::Expired)]);
+    }
+}
@@ -0,0 +1,124 @@
+use std::cmp::{max, min};
+use std::hash::Hash;
+use std::time::Duration;
+
+use super::clock::Instant;
+use super::nextchunk::StreamNextChunk;
+use super::options::NextChunkOptions;
+use super::prefix::common_prefix_len;
+
+
+/// One prediction of a [`simulate`] run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimulationStep {
+    /// Length of the predicted chunk.
+    pub predicted: usize,
+    /// Leading predicted tokens that matched the target.
+    pub accepted: usize,
+    /// Wall time of the prediction call.
+    pub latency: Duration,
+}
+
+/// Outcome of replaying a target sequence through speculative prediction.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimulationReport {
+    /// One step per prediction, i.e. per simulated verification round.
+    pub steps: Vec<SimulationStep>,
+    /// Time until the first prediction was available.
+    pub first_prediction: Duration,
+    /// Wall time of the whole run, building the streamer included.
+    pub total: Duration,
+}
+
+impl SimulationReport {
+    pub fn iterations(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn total_predicted(&self) -> usize {
+        self.steps.iter().map(|step| step.predicted).sum()
+    }
+
+    pub fn total_accepted(&self) -> usize {
+        self.steps.iter().map(|step| step.accepted).sum()
+    }
+
+    /// Fraction of the predicted tokens that were accepted, 0 when nothing was predicted.
+    pub fn acceptance_rate(&self) -> f64 {
+        match self.total_predicted() {
+            0 => 0.0,
+            predicted => self.total_accepted() as f64 / predicted as f64,
+        }
+    }
+}
+
+/// Replays generating `target` with speculative chunks predicted from
+/// `reference`, e.g. to evaluate a heuristic change on a recorded trace.
+///
+/// Each step predicts `chunk_size` tokens from the target generated so far,
+/// accepts their prefix matching the target and advances by that many
+/// tokens, or by one when nothing was accepted (the token the model would
+/// have generated itself).
+pub fn simulate<T: Eq + Hash + Copy>(reference: &[T], target: &[T], chunk_size: usize) -> SimulationReport {
+    simulate_with_options(reference, target, chunk_size, NextChunkOptions::default())
+}
+
+/// [`simulate`] with a streamer built from `options`.
+pub fn simulate_with_options<T: Eq + Hash + Copy>(
+    reference: &[T],
+    target: &[T],
+    chunk_size: usize,
+    options: NextChunkOptions,
+) -> SimulationReport {
+    let started = Instant::now();
+    let streamer = StreamNextChunk::with_options(reference.to_vec(), options);
+    let mut report = SimulationReport::default();
+    let mut current_idx = 0;
+    while current_idx < target.len() {
+        let calling = Instant::now();
+        let predicted = streamer.next_chunk(&target[..current_idx], chunk_size);
+        let latency = calling.elapsed();
+        if report.steps.is_empty() {
+            report.first_prediction = started.elapsed();
+        }
+        let actual = &target[current_idx..min(current_idx + predicted.len(), target.len())];
+        let accepted = common_prefix_len(predicted, actual);
+        report.steps.push(SimulationStep { predicted: predicted.len(), accepted, latency });
+        current_idx += max(accepted, 1);
+    }
+    report.total = started.elapsed();
+    trace_event!(debug, iterations = report.iterations(), accepted = report.total_accepted(), "simulated target");
+    report
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simulate() {
+        let reference: Vec<i32> = (0..100).collect();
+        let report = simulate(&reference, &reference, 10);
+        assert_eq!(report.iterations(), 10);
+        assert_eq!((report.total_predicted(), report.total_accepted()), (100, 100));
+        assert_eq!(report.acceptance_rate(), 1.0);
+        assert!(report.total >= report.first_prediction);
+        assert!(report.steps[0].latency <= report.first_prediction);
+
+        let mut target = reference.clone();
+        target[25] = -1;
+        let report = simulate(&reference, &target, 10);
+        let outcome = |step: &SimulationStep| (step.predicted, step.accepted);
+        assert_eq!(outcome(&report.steps[2]), (10, 5));
+        assert_eq!(outcome(&report.steps[3]), (10, 0));
+        assert!(report.total_accepted() > 90);
+
+        let report = simulate(&[1, 2, 3], &[7, 8], 4);
+        assert_eq!((report.iterations(), report.total_accepted()), (2, 0));
+        assert_eq!(outcome(&report.steps[0]), (3, 0));
+        assert_eq!(simulate::<i32>(&[], &[], 4).acceptance_rate(), 0.0);
+    }
+}
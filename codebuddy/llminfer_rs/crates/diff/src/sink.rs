@@ -1,5 +1,9 @@
 
+use imara_diff::intern::{InternedInput, Token};
 use imara_diff::sink::Sink;
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+use std::hash::Hash;
 use std::ops::Range;
 
 #[derive(Debug, Default)]
@@ -15,6 +19,7 @@ impl Sink for ChangeRangeCollector {
     }
 
     fn finish(self) -> Self::Out {
+        trace_event!(trace, changes = self.changes.len(), "ChangeRangeCollector finished");
         self.changes
     }
 }
@@ -38,6 +43,16 @@ impl MatchCollector {
             ..Default::default()
         }
     }
+
+    /// Like [`MatchCollector::new`], collecting into `matches` (cleared
+    /// first) to reuse its allocation.
+    pub fn with_buffer(total_a_len: u32, total_b_len: u32, mut matches: Vec<(Range<u32>, Range<u32>)>) -> Self {
+        matches.clear();
+        Self {
+            matches,
+            ..Self::new(total_a_len, total_b_len)
+        }
+    }
 }
 
 impl Sink for MatchCollector {
@@ -72,6 +87,308 @@ impl Sink for MatchCollector {
             self.matches.push((final_match_range_a, final_match_range_b));
         }
 
+        trace_event!(trace, matches = self.matches.len(), "MatchCollector finished");
         self.matches
     }
 }
+
+
+
+/// How [`ScoredMatchCollector`] weighs the parts of a match's score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchWeights {
+    /// Per matched token.
+    pub length: f64,
+    /// Times how close the match ends to the end of `b`, from 0 (at its
+    /// start) to 1 (ending `b`, where a continuation would be anchored).
+    pub recency: f64,
+    /// Times the mean inverse frequency `ln(a_len / count)` of the matched
+    /// tokens in `a`, so matches of common tokens (whitespace, brackets) rank lower.
+    pub rarity: f64,
+}
+
+impl Default for MatchWeights {
+    fn default() -> Self {
+        MatchWeights { length: 1.0, recency: 8.0, rarity: 1.0 }
+    }
+}
+
+impl MatchWeights {
+    /// Score of a match of `len` tokens ending at `b_end` of the `b_len`
+    /// tokens of `b`, whose tokens have the mean inverse frequency `rarity`.
+    pub(crate) fn score(&self, len: usize, b_end: u32, b_len: u32, rarity: f64) -> f64 {
+        let recency = b_end as f64 / b_len.max(1) as f64;
+        self.length * len as f64 + self.recency * recency + self.rarity * rarity
+    }
+}
+
+/// A matching block with its [`ScoredMatchCollector`] score.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScoredMatch {
+    pub a: Range<u32>,
+    pub b: Range<u32>,
+    pub score: f64,
+}
+
+impl ScoredMatch {
+    /// The highest scoring match, the latest one on ties.
+    pub fn best(matches: &[ScoredMatch]) -> Option<&ScoredMatch> {
+        matches.iter().max_by(|x, y| x.score.total_cmp(&y.score))
+    }
+}
+
+/// Like [`MatchCollector`], but scores each matching block by its length,
+/// recency in `b` and the rarity of its tokens in `a`, so an anchor can be
+/// picked on more than being the last match.
+#[derive(Debug)]
+pub struct ScoredMatchCollector<'a> {
+    before: &'a [Token],
+    /// Occurrences of each token in `before`.
+    counts: HashMap<Token, u32>,
+    weights: MatchWeights,
+    matches: MatchCollector,
+    total_b_len: u32,
+}
+
+impl<'a> ScoredMatchCollector<'a> {
+    pub fn new(before: &'a [Token], total_b_len: u32, weights: MatchWeights) -> Self {
+        let mut counts = HashMap::new();
+        for &token in before {
+            *counts.entry(token).or_insert(0) += 1;
+        }
+        let matches = MatchCollector::new(before.len() as u32, total_b_len);
+        ScoredMatchCollector { before, counts, weights, matches, total_b_len }
+    }
+
+    fn score(&self, a: &Range<u32>, b: &Range<u32>) -> f64 {
+        let len = a.len() as f64;
+        let a_len = self.before.len() as f64;
+        let tokens = &self.before[a.start as usize..a.end as usize];
+        let rarity = tokens.iter().map(|token| (a_len / self.counts[token] as f64).ln()).sum::<f64>() / len.max(1.0);
+        self.weights.score(a.len(), b.end, self.total_b_len, rarity)
+    }
+}
+
+impl Sink for ScoredMatchCollector<'_> {
+    type Out = Vec<ScoredMatch>;
+
+    fn process_change(&mut self, before: Range<u32>, after: Range<u32>) {
+        self.matches.process_change(before, after);
+    }
+
+    fn finish(mut self) -> Self::Out {
+        let matches = std::mem::take(&mut self.matches).finish();
+        trace_event!(trace, matches = matches.len(), "ScoredMatchCollector finished");
+        matches.into_iter().map(|(a, b)| ScoredMatch { score: self.score(&a, &b), a, b }).collect()
+    }
+}
+
+
+
+/// Kind of an [`Opcode`], named like difflib's opcode tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum OpTag {
+    Equal,
+    Insert,
+    Delete,
+    Replace,
+}
+
+impl OpTag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpTag::Equal => "equal",
+            OpTag::Insert => "insert",
+            OpTag::Delete => "delete",
+            OpTag::Replace => "replace",
+        }
+    }
+}
+
+/// One step of an edit script: `a[a]` becomes `b[b]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Opcode {
+    pub tag: OpTag,
+    pub a: Range<u32>,
+    pub b: Range<u32>,
+}
+
+/// Collects the full edit script, equal runs included, like difflib's
+/// `SequenceMatcher.get_opcodes()`.
+#[derive(Debug, Default)]
+pub struct OpcodeCollector {
+    opcodes: Vec<Opcode>,
+    last_a: u32,
+    last_b: u32,
+    total_a_len: u32,
+    total_b_len: u32,
+}
+
+impl OpcodeCollector {
+    pub fn new(total_a_len: u32, total_b_len: u32) -> Self {
+        Self {
+            total_a_len,
+            total_b_len,
+            ..Default::default()
+        }
+    }
+
+    fn push_equal(&mut self, a_end: u32, b_end: u32) {
+        if a_end > self.last_a {
+            self.opcodes.push(Opcode { tag: OpTag::Equal, a: self.last_a..a_end, b: self.last_b..b_end });
+        }
+    }
+}
+
+impl Sink for OpcodeCollector {
+    type Out = Vec<Opcode>;
+
+    fn process_change(&mut self, before: Range<u32>, after: Range<u32>) {
+        self.push_equal(before.start, after.start);
+        let tag = match (before.is_empty(), after.is_empty()) {
+            (true, _) => OpTag::Insert,
+            (_, true) => OpTag::Delete,
+            _ => OpTag::Replace,
+        };
+        self.last_a = before.end;
+        self.last_b = after.end;
+        self.opcodes.push(Opcode { tag, a: before, b: after });
+    }
+
+    fn finish(mut self) -> Self::Out {
+        self.push_equal(self.total_a_len, self.total_b_len);
+        trace_event!(trace, opcodes = self.opcodes.len(), "OpcodeCollector finished");
+        self.opcodes
+    }
+}
+
+
+/// Renders changes as unified diff hunks (`@@ -a,n +b,m @@`) with
+/// `context_len` unchanged lines around each change.
+///
+/// Tokens are printed with `Display` followed by a newline, so it's meant for
+/// line-tokenized input.
+pub struct UnifiedDiffSink<'a, T: Display + Eq + Hash> {
+    input: &'a InternedInput<T>,
+    context_len: u32,
+    /// Position in `before` up to which lines were written to the hunk.
+    pos: u32,
+    before_hunk_start: u32,
+    after_hunk_start: u32,
+    before_hunk_len: u32,
+    after_hunk_len: u32,
+    hunk: String,
+    dst: String,
+}
+
+impl<'a, T: Display + Eq + Hash> UnifiedDiffSink<'a, T> {
+    pub fn new(input: &'a InternedInput<T>, context_len: u32) -> Self {
+        Self {
+            input,
+            context_len,
+            pos: 0,
+            before_hunk_start: 0,
+            after_hunk_start: 0,
+            before_hunk_len: 0,
+            after_hunk_len: 0,
+            hunk: String::new(),
+            dst: String::new(),
+        }
+    }
+
+    fn write_lines(&mut self, prefix: char, tokens: &[Token]) {
+        for &token in tokens {
+            // Writing to a String can't fail
+            let _ = writeln!(self.hunk, "{prefix}{}", self.input.interner[token]);
+        }
+    }
+
+    /// Writes the context before `before.start` into the current hunk.
+    fn write_context(&mut self, end: u32) {
+        let context = &self.input.before[self.pos as usize..end as usize];
+        self.write_lines(' ', context);
+        self.before_hunk_len += end - self.pos;
+        self.after_hunk_len += end - self.pos;
+        self.pos = end;
+    }
+
+    fn flush(&mut self) {
+        if self.before_hunk_len == 0 && self.after_hunk_len == 0 {
+            return;
+        }
+        let end = (self.pos + self.context_len).min(self.input.before.len() as u32);
+        self.write_context(end);
+        // Ranges are 1-based, except that an empty range names the line before it
+        let start = |start: u32, len: u32| if len == 0 { start } else { start + 1 };
+        let _ = writeln!(
+            self.dst,
+            "@@ -{},{} +{},{} @@",
+            start(self.before_hunk_start, self.before_hunk_len),
+            self.before_hunk_len,
+            start(self.after_hunk_start, self.after_hunk_len),
+            self.after_hunk_len,
+        );
+        self.dst.push_str(&self.hunk);
+        self.hunk.clear();
+        self.before_hunk_len = 0;
+        self.after_hunk_len = 0;
+    }
+}
+
+impl<T: Display + Eq + Hash> Sink for UnifiedDiffSink<'_, T> {
+    type Out = String;
+
+    fn process_change(&mut self, before: Range<u32>, after: Range<u32>) {
+        // Start a new hunk unless this change's leading context overlaps the current one
+        if before.start - self.pos > 2 * self.context_len {
+            self.flush();
+        }
+        if self.before_hunk_len == 0 && self.after_hunk_len == 0 {
+            self.pos = before.start.saturating_sub(self.context_len);
+            self.before_hunk_start = self.pos;
+            self.after_hunk_start = after.start - (before.start - self.pos);
+        }
+        self.write_context(before.start);
+
+        let input = self.input;
+        self.write_lines('-', &input.before[before.start as usize..before.end as usize]);
+        self.write_lines('+', &input.after[after.start as usize..after.end as usize]);
+        self.before_hunk_len += before.len() as u32;
+        self.after_hunk_len += after.len() as u32;
+        self.pos = before.end;
+    }
+
+    fn finish(mut self) -> Self::Out {
+        self.flush();
+        trace_event!(trace, bytes = self.dst.len(), "UnifiedDiffSink finished");
+        self.dst
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use imara_diff::{diff_with_tokens, Algorithm};
+
+    #[test]
+    fn test_scored_match_collector() {
+        // b matches a long run of common tokens early and a short rare run at its end
+        let a: Vec<Token> = [0, 0, 0, 0, 0, 0, 1, 2, 3].map(Token).to_vec();
+        let b: Vec<Token> = [0, 0, 0, 0, 0, 0, 4, 2, 3].map(Token).to_vec();
+        let sink = ScoredMatchCollector::new(&a, b.len() as u32, MatchWeights::default());
+        let matches = diff_with_tokens(Algorithm::Histogram, &a, &b, 5, sink);
+        assert_eq!(matches.iter().map(|m| (m.a.clone(), m.b.clone())).collect::<Vec<_>>(), [(0..6, 0..6), (7..9, 7..9)]);
+        assert_eq!(ScoredMatch::best(&matches).unwrap().a, 7..9);
+
+        let by_length = MatchWeights { length: 1.0, recency: 0.0, rarity: 0.0 };
+        let sink = ScoredMatchCollector::new(&a, b.len() as u32, by_length);
+        let matches = diff_with_tokens(Algorithm::Histogram, &a, &b, 5, sink);
+        assert_eq!(ScoredMatch::best(&matches).unwrap().a, 0..6);
+    }
+}
@@ -1,17 +1,27 @@
-
 use std::{
+    hash::Hash,
     slice::Iter, // Need Iter for the Tokenizer type
     iter::Copied, // Helper to turn Iter<&T> into Iter<T> for Copy types
 };
 
 use imara_diff::intern::TokenSource;
+use regex::Regex;
 
+/// A borrowed token sequence usable as an imara-diff `TokenSource`.
+///
+/// Any `Copy` token type that can be hashed works (i32, u32, i64, ...).
 #[derive(Debug, Clone, Copy)] // Add derives for convenience
-pub struct I32Slice<'a>(pub &'a [i32]);
+pub struct TokenSlice<'a, T>(pub &'a [T]);
+
+/// Kept for callers that still name the i32 source explicitly.
+pub type I32Slice<'a> = TokenSlice<'a, i32>;
 
-impl<'a> TokenSource for I32Slice<'a>{
-    type Token = i32;
-    type Tokenizer = Copied<Iter<'a, i32>>;
+/// Raw bytes, e.g. UTF-8 text for models with byte-level vocabularies.
+pub type U8Slice<'a> = TokenSlice<'a, u8>;
+
+impl<'a, T: Eq + Hash + Copy> TokenSource for TokenSlice<'a, T> {
+    type Token = T;
+    type Tokenizer = Copied<Iter<'a, T>>;
 
     fn tokenize(&self) -> Self::Tokenizer {
         self.0.iter().copied()
@@ -21,3 +31,108 @@ impl<'a> TokenSource for I32Slice<'a>{
         self.0.len() as u32
     }
 }
+
+
+/// How [`WordSource`] splits text into words.
+#[derive(Debug, Clone, Default)]
+pub enum WordSplit {
+    /// Runs of whitespace separate words.
+    #[default]
+    Whitespace,
+    /// Matches of the regex separate words.
+    Regex(Regex),
+}
+
+impl WordSplit {
+    /// Splits `text` into words and the separators between them, in order:
+    /// concatenating the pieces gives back `text`.
+    pub fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut pieces = Vec::new();
+        let mut pos = 0;
+        let mut push_separator = |start: usize, end: usize| {
+            if start > pos {
+                pieces.push(&text[pos..start]);
+            }
+            if end > start {
+                pieces.push(&text[start..end]);
+            }
+            pos = end;
+        };
+        match self {
+            WordSplit::Whitespace => {
+                let mut run: Option<usize> = None;
+                for (i, c) in text.char_indices() {
+                    match (c.is_whitespace(), run) {
+                        (true, None) => run = Some(i),
+                        (false, Some(start)) => {
+                            push_separator(start, i);
+                            run = None;
+                        }
+                        _ => {}
+                    }
+                }
+                if let Some(start) = run {
+                    push_separator(start, text.len());
+                }
+            }
+            WordSplit::Regex(regex) => {
+                for m in regex.find_iter(text) {
+                    push_separator(m.start(), m.end());
+                }
+            }
+        }
+        if pos < text.len() {
+            pieces.push(&text[pos..]);
+        }
+        pieces
+    }
+}
+
+/// Text split into words (and the separators between them) as an imara-diff
+/// `TokenSource`, for word-level diffs of plain text.
+#[derive(Debug, Clone)]
+pub struct WordSource<'a> {
+    words: Vec<&'a str>,
+}
+
+impl<'a> WordSource<'a> {
+    pub fn new(text: &'a str, split: &WordSplit) -> Self {
+        WordSource { words: split.split(text) }
+    }
+
+    pub fn words(&self) -> &[&'a str] {
+        &self.words
+    }
+}
+
+impl<'a> TokenSource for WordSource<'a> {
+    type Token = &'a str;
+    type Tokenizer = std::vec::IntoIter<&'a str>;
+
+    fn tokenize(&self) -> Self::Tokenizer {
+        self.words.clone().into_iter()
+    }
+
+    fn estimate_tokens(&self) -> u32 {
+        self.words.len() as u32
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_word_split() {
+        let text = "  let x  = 1;\n";
+        assert_eq!(WordSplit::Whitespace.split(text), ["  ", "let", " ", "x", "  ", "=", " ", "1;", "\n"]);
+        let split = WordSplit::Regex(Regex::new(r"[\s;]+").unwrap());
+        assert_eq!(split.split(text), ["  ", "let", " ", "x", "  ", "=", " ", "1", ";\n"]);
+        assert_eq!(split.split("").len(), 0);
+
+        let source = WordSource::new(text, &WordSplit::Whitespace);
+        assert_eq!(source.tokenize().collect::<String>(), text);
+        assert_eq!(source.estimate_tokens(), 9);
+    }
+}
@@ -0,0 +1,420 @@
+use std::hash::Hash;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::nextchunk::StreamNextChunk;
+use super::normalize::Normalizer;
+use super::options::{EscalationPolicy, NextChunkOptions};
+use super::sink::MatchWeights;
+use super::stats::NextChunkStats;
+
+
+const MAGIC: &[u8; 8] = b"LLMSTATE";
+const VERSION: u8 = 1;
+
+/// Error from [`StreamNextChunk::load`] and [`StreamNextChunk::from_state_bytes`].
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    #[error("failed to access {path:?}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("not a valid streamer state: {0}")]
+    Format(String),
+    #[error("unsupported streamer state version {0}, expected {VERSION}")]
+    Version(u8),
+    #[error("token {0} is out of range of the token type")]
+    Overflow(i64),
+}
+
+/// Everything [`StreamNextChunk::save`] persists. Derived indexes are rebuilt
+/// on load rather than stored.
+pub(crate) struct SavedState<T> {
+    pub(crate) options: NextChunkOptions,
+    pub(crate) a: Vec<T>,
+    /// Tokens fed through `append`.
+    pub(crate) b: Vec<T>,
+    pub(crate) anchor: Option<usize>,
+    pub(crate) match_len: usize,
+    pub(crate) confirmed: Option<(usize, usize)>,
+    pub(crate) last_anchor: Option<(usize, usize)>,
+    pub(crate) stats: NextChunkStats,
+}
+
+/// LEB128 varints throughout; tokens are zigzag encoded, so small ids of
+/// any width take one or two bytes.
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u64(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.0.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.0.push(v as u8);
+    }
+
+    fn usize(&mut self, v: usize) {
+        self.u64(v as u64)
+    }
+
+    fn bool(&mut self, v: bool) {
+        self.0.push(u8::from(v))
+    }
+
+    fn str(&mut self, v: &str) {
+        self.usize(v.len());
+        self.0.extend_from_slice(v.as_bytes());
+    }
+
+    fn duration(&mut self, v: Duration) {
+        self.u64(v.as_secs());
+        self.u64(v.subsec_nanos().into());
+    }
+
+    fn pair(&mut self, v: Option<(usize, usize)>) {
+        self.bool(v.is_some());
+        if let Some((x, y)) = v {
+            self.usize(x);
+            self.usize(y);
+        }
+    }
+
+    fn tokens<T: Copy + Into<i64>>(&mut self, tokens: &[T]) {
+        self.usize(tokens.len());
+        for &t in tokens {
+            let t: i64 = t.into();
+            self.u64(((t << 1) ^ (t >> 63)) as u64);
+        }
+    }
+}
+
+struct Reader<'b>(&'b [u8]);
+
+fn truncated() -> StateError {
+    StateError::Format("truncated".to_owned())
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, StateError> {
+        let (&byte, rest) = self.0.split_first().ok_or_else(truncated)?;
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            v |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(StateError::Format("varint too long".to_owned()))
+    }
+
+    fn usize(&mut self) -> Result<usize, StateError> {
+        usize::try_from(self.u64()?).map_err(|_| StateError::Format("length too large".to_owned()))
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(StateError::Format(format!("invalid bool {other}"))),
+        }
+    }
+
+    fn str(&mut self) -> Result<&str, StateError> {
+        let len = self.usize()?;
+        let bytes = self.0.get(..len).ok_or_else(truncated)?;
+        self.0 = &self.0[len..];
+        std::str::from_utf8(bytes).map_err(|_| StateError::Format("invalid string".to_owned()))
+    }
+
+    fn parse<V: FromStr>(&mut self) -> Result<V, StateError> {
+        let s = self.str()?;
+        s.parse().map_err(|_| StateError::Format(format!("invalid option value {s:?}")))
+    }
+
+    fn duration(&mut self) -> Result<Duration, StateError> {
+        let secs = self.u64()?;
+        let nanos = u32::try_from(self.u64()?).map_err(|_| StateError::Format("invalid duration".to_owned()))?;
+        Duration::from_secs(secs).checked_add(Duration::from_nanos(nanos.into())).ok_or_else(|| StateError::Format("invalid duration".to_owned()))
+    }
+
+    /// A value preceded by its presence flag; the value is stored either way.
+    fn optional<V>(&mut self, read: impl FnOnce(&mut Self) -> Result<V, StateError>) -> Result<Option<V>, StateError> {
+        let present = self.bool()?;
+        let v = read(self)?;
+        Ok(Some(v).filter(|_| present))
+    }
+
+    fn pair(&mut self) -> Result<Option<(usize, usize)>, StateError> {
+        Ok(if self.bool()? { Some((self.usize()?, self.usize()?)) } else { None })
+    }
+
+    fn tokens<T: TryFrom<i64>>(&mut self) -> Result<Vec<T>, StateError> {
+        let len = self.usize()?;
+        // Every token takes at least a byte, so a corrupt length can't over-allocate
+        if len > self.0.len() {
+            return Err(truncated());
+        }
+        (0..len)
+            .map(|_| {
+                let v = self.u64()?;
+                let t = ((v >> 1) as i64) ^ -((v & 1) as i64);
+                T::try_from(t).map_err(|_| StateError::Overflow(t))
+            })
+            .collect()
+    }
+}
+
+impl<T: Copy + Into<i64> + TryFrom<i64>> SavedState<T> {
+    fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.0.extend_from_slice(MAGIC);
+        w.0.push(VERSION);
+
+        let o = &self.options;
+        w.str(o.algorithm.as_str());
+        w.str(o.matcher.as_str());
+        w.usize(o.min_window_threshold);
+        w.usize(o.a_window_factor);
+        w.usize(o.anchor_hash_len);
+        w.bool(o.escalation.is_some());
+        if let Some(escalation) = &o.escalation {
+            w.usize(escalation.factors.len());
+            escalation.factors.iter().for_each(|&factor| w.usize(factor));
+            w.bool(escalation.full_diff);
+            w.duration(escalation.budget);
+        }
+        w.bool(o.multi_window_search);
+        w.usize(o.min_match_len);
+        w.usize(o.max_mismatches);
+        w.bool(o.fallback.is_some());
+        if let Some(fallback) = o.fallback {
+            w.str(&fallback.to_string());
+        }
+        w.bool(o.memoize_anchors);
+        w.bool(o.global_reanchor_after.is_some());
+        w.usize(o.global_reanchor_after.unwrap_or_default());
+        w.bool(o.autojunk.is_some());
+        w.u64(o.autojunk.unwrap_or_default().to_bits());
+        w.bool(o.coarse_block_len.is_some());
+        w.usize(o.coarse_block_len.unwrap_or_default());
+        w.bool(o.diff_segment_len.is_some());
+        w.usize(o.diff_segment_len.unwrap_or_default());
+        w.bool(o.memory_budget.is_some());
+        w.usize(o.memory_budget.unwrap_or_default());
+        w.bool(o.deadline.is_some());
+        w.duration(o.deadline.unwrap_or_default());
+        w.str(o.anchor_strategy.as_str());
+        for weight in [o.anchor_weights.length, o.anchor_weights.recency, o.anchor_weights.rarity] {
+            w.u64(weight.to_bits());
+        }
+        w.usize(o.continuation_check_len);
+
+        w.tokens(&self.a);
+        w.tokens(&self.b);
+        w.bool(self.anchor.is_some());
+        w.usize(self.anchor.unwrap_or_default());
+        w.usize(self.match_len);
+        w.pair(self.confirmed);
+        w.pair(self.last_anchor);
+
+        let s = &self.stats;
+        for count in [
+            s.calls,
+            s.diff_calls,
+            s.memo_hits,
+            s.windowed_calls,
+            s.verifications,
+            s.predicted_tokens,
+            s.accepted_tokens,
+            s.window_misses,
+            s.empty_predictions,
+            s.global_reanchors,
+            s.timeouts,
+        ] {
+            w.u64(count);
+        }
+        for time in [s.wall, s.intern, s.diff, s.max_wall] {
+            w.duration(time);
+        }
+        w.0
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, StateError> {
+        let rest = bytes.strip_prefix(MAGIC).ok_or_else(|| StateError::Format("bad magic".to_owned()))?;
+        let mut r = Reader(rest);
+        let version = r.byte()?;
+        if version != VERSION {
+            return Err(StateError::Version(version));
+        }
+
+        let options = NextChunkOptions {
+            algorithm: r.parse()?,
+            matcher: r.parse()?,
+            min_window_threshold: r.usize()?,
+            a_window_factor: r.usize()?,
+            anchor_hash_len: r.usize()?,
+            escalation: if r.bool()? {
+                let factors = (0..r.usize()?).map(|_| r.usize()).collect::<Result<_, _>>()?;
+                Some(EscalationPolicy { factors, full_diff: r.bool()?, budget: r.duration()? })
+            } else {
+                None
+            },
+            multi_window_search: r.bool()?,
+            min_match_len: r.usize()?,
+            max_mismatches: r.usize()?,
+            fallback: if r.bool()? { Some(r.parse()?) } else { None },
+            memoize_anchors: r.bool()?,
+            global_reanchor_after: r.optional(Reader::usize)?,
+            autojunk: r.optional(|r| Ok(f64::from_bits(r.u64()?)))?,
+            coarse_block_len: r.optional(Reader::usize)?,
+            diff_segment_len: r.optional(Reader::usize)?,
+            memory_budget: r.optional(Reader::usize)?,
+            deadline: r.optional(Reader::duration)?,
+            anchor_strategy: r.parse()?,
+            anchor_weights: MatchWeights {
+                length: f64::from_bits(r.u64()?),
+                recency: f64::from_bits(r.u64()?),
+                rarity: f64::from_bits(r.u64()?),
+            },
+            continuation_check_len: r.usize()?,
+        };
+
+        let a = r.tokens()?;
+        let b = r.tokens()?;
+        let anchor = r.optional(Reader::usize)?;
+        let match_len = r.usize()?;
+        let confirmed = r.pair()?;
+        let last_anchor = r.pair()?;
+
+        let mut stats = NextChunkStats::default();
+        for count in [
+            &mut stats.calls,
+            &mut stats.diff_calls,
+            &mut stats.memo_hits,
+            &mut stats.windowed_calls,
+            &mut stats.verifications,
+            &mut stats.predicted_tokens,
+            &mut stats.accepted_tokens,
+            &mut stats.window_misses,
+            &mut stats.empty_predictions,
+            &mut stats.global_reanchors,
+            &mut stats.timeouts,
+        ] {
+            *count = r.u64()?;
+        }
+        for time in [&mut stats.wall, &mut stats.intern, &mut stats.diff, &mut stats.max_wall] {
+            *time = r.duration()?;
+        }
+        if !r.0.is_empty() {
+            return Err(StateError::Format("trailing bytes".to_owned()));
+        }
+        Ok(SavedState { options, a, b, anchor, match_len, confirmed, last_anchor, stats })
+    }
+}
+
+impl<T: Eq + Hash + Copy + Into<i64> + TryFrom<i64>> StreamNextChunk<T> {
+    /// Serializes the reference, options, stateful stream (appended tokens and
+    /// anchor), last stateless anchor and statistics to a compact binary
+    /// format, e.g. so a session survives a worker restart.
+    ///
+    /// The normalizer and hook are code and aren't saved: pass the normalizer
+    /// again to [`StreamNextChunk::load_with_normalizer`]. Neither is the
+    /// most recent call of the statistics.
+    pub fn to_state_bytes(&self) -> Vec<u8> {
+        self.saved_state().encode()
+    }
+
+    /// Restores an instance from [`StreamNextChunk::to_state_bytes`].
+    pub fn from_state_bytes(bytes: &[u8]) -> Result<Self, StateError> {
+        Ok(Self::restore(SavedState::decode(bytes)?, None))
+    }
+
+    /// Writes [`StreamNextChunk::to_state_bytes`] to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_state_bytes()).map_err(|source| StateError::Io { path: path.to_owned(), source })
+    }
+
+    /// Restores an instance saved to `path` by [`StreamNextChunk::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        Self::load_impl(path.as_ref(), None)
+    }
+
+    /// Like [`StreamNextChunk::load`] for an instance created with
+    /// [`StreamNextChunk::with_normalizer`], matching through `normalizer` again.
+    pub fn load_with_normalizer(path: impl AsRef<Path>, normalizer: Normalizer<T>) -> Result<Self, StateError> {
+        Self::load_impl(path.as_ref(), Some(normalizer))
+    }
+
+    fn load_impl(path: &Path, normalizer: Option<Normalizer<T>>) -> Result<Self, StateError> {
+        let bytes = std::fs::read(path).map_err(|source| StateError::Io { path: path.to_owned(), source })?;
+        Ok(Self::restore(SavedState::decode(&bytes)?, normalizer))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::options::{AnchorStrategy, FallbackPolicy};
+
+    #[test]
+    fn test_state_round_trip() {
+        let a: Vec<i64> = (0..500).map(|i| (i * 7919) % 1000 - 300).collect();
+        let options = NextChunkOptions {
+            escalation: Some(EscalationPolicy::default()),
+            fallback: Some(FallbackPolicy::Offset(3)),
+            min_match_len: 2,
+            global_reanchor_after: Some(4),
+            autojunk: Some(0.01),
+            coarse_block_len: Some(64),
+            diff_segment_len: Some(1000),
+            memory_budget: Some(1 << 20),
+            deadline: Some(Duration::from_millis(2)),
+            anchor_strategy: AnchorStrategy::RecencyWeighted,
+            anchor_weights: MatchWeights { length: 2.0, recency: 4.0, rarity: 0.5 },
+            continuation_check_len: 8,
+            ..Default::default()
+        };
+        let mut streamer = StreamNextChunk::with_options(a.clone(), options.clone());
+        streamer.next_chunk(&a[..150], 4);
+        streamer.append(&a[..200]);
+        streamer.predict(4);
+        streamer.append(&[1 << 40]);
+
+        let mut restored = StreamNextChunk::<i64>::from_state_bytes(&streamer.to_state_bytes()).unwrap();
+        assert_eq!(restored.reference(), a);
+        assert_eq!(restored.appended(), streamer.appended());
+        assert_eq!(format!("{:?}", restored.options()), format!("{options:?}"));
+        assert_eq!(restored.stats(), NextChunkStats { last: None, ..streamer.stats() });
+        assert_eq!(restored.predict_with_info(4).start, streamer.predict_with_info(4).start);
+        restored.append(&a[200..230]);
+        streamer.append(&a[200..230]);
+        assert_eq!(restored.predict(4), streamer.predict(4));
+    }
+
+    #[test]
+    fn test_state_errors() {
+        let streamer = StreamNextChunk::new(&[1i64 << 40, 2, 3]);
+        let bytes = streamer.to_state_bytes();
+        assert!(matches!(StreamNextChunk::<i32>::from_state_bytes(&bytes), Err(StateError::Overflow(_))));
+        assert!(matches!(StreamNextChunk::<i64>::from_state_bytes(&bytes[..bytes.len() - 1]), Err(StateError::Format(_))));
+        let newer = [&MAGIC[..], &[VERSION + 1]].concat();
+        assert!(matches!(StreamNextChunk::<i64>::from_state_bytes(&newer), Err(StateError::Version(v)) if v == VERSION + 1));
+        assert!(matches!(StreamNextChunk::<i64>::from_state_bytes(b"garbage"), Err(StateError::Format(_))));
+
+        let path = std::env::temp_dir().join(format!("llminfer-state-{}", std::process::id()));
+        streamer.save(&path).unwrap();
+        assert_eq!(StreamNextChunk::<i64>::load(&path).unwrap().reference(), streamer.reference());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(StreamNextChunk::<i64>::load(&path), Err(StateError::Io { .. })));
+    }
+}
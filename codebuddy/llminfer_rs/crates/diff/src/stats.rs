@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+
+/// Where matching left `b` in a prediction call, before any
+/// [`crate::FallbackPolicy`] applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CallAnchor {
+    /// Nothing was matched: empty `a` or a `chunk_size` of 0.
+    #[default]
+    Skipped,
+    /// `b` ends at offset `pos` of `a`, via a match of `match_len` tokens.
+    At { pos: usize, match_len: usize },
+    /// `b` is empty, so the prediction starts at the beginning of `a`.
+    StartOfA,
+    /// The full diff found no match at all.
+    NoMatch,
+    /// The window (and any retries) missed, or `b` ends mid-change.
+    Miss,
+}
+
+impl CallAnchor {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CallAnchor::Skipped => "skipped",
+            CallAnchor::At { .. } => "at",
+            CallAnchor::StartOfA => "start_of_a",
+            CallAnchor::NoMatch => "no_match",
+            CallAnchor::Miss => "miss",
+        }
+    }
+}
+
+/// Timings and decisions of a single prediction call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallStats {
+    /// Wall time of the whole call.
+    pub wall: Duration,
+    /// Time spent interning `b` against the interned reference.
+    pub intern: Duration,
+    /// Time spent diffing, including the multi-window search.
+    pub diff: Duration,
+    /// Whether the diff was restricted to a window of `a`/`b`.
+    pub windowed: bool,
+    /// Wider windows (or the full diff) tried by the escalation policy after
+    /// the primary window missed.
+    pub escalations: usize,
+    /// Extra windows of `a` diffed after the primary window missed.
+    pub windows_searched: usize,
+    /// Matching blocks found by all diffs of the call.
+    pub matches: usize,
+    /// Whether the call was answered without diffing (empty `b`, exact
+    /// prefix of `a`, held or remembered anchor, or suffix automaton).
+    pub fast_path: bool,
+    /// Whether the anchor came from a remembered earlier call whose `b` this one extends.
+    pub memo_hit: bool,
+    /// Whether `b` continued the previous call's prediction, see
+    /// [`crate::NextChunkOptions::continuation_check_len`].
+    pub continuation_hit: bool,
+    /// Whether `b` extended the one of the cursor passed to
+    /// [`crate::StreamNextChunk::next_chunk_from`].
+    pub cursor_hit: bool,
+    /// Bounds of the `a` window the anchor was looked for in (all of `a`
+    /// without windowing); `None` when the call didn't diff.
+    pub a_window: Option<(usize, usize)>,
+    /// Offset in `b` of the first diffed token, 0 without windowing.
+    pub b_window_start: usize,
+    /// The anchor matching ended on.
+    pub anchor: CallAnchor,
+    /// Tokens predicted.
+    pub chunk_len: usize,
+    /// Whether a run of empty windowed predictions made the call re-anchor
+    /// with a full diff, see [`crate::NextChunkOptions::global_reanchor_after`].
+    pub global_reanchor: bool,
+    /// Whether the last token of `b` was matched through the boundary
+    /// equivalence table, see [`crate::BoundaryEquivalence`].
+    pub boundary_skip: bool,
+    /// Whether a diff over all of `a` was narrowed by diffing block hashes
+    /// first, see [`crate::NextChunkOptions::coarse_block_len`].
+    pub coarse: bool,
+    /// Segments of `a` diffed one at a time to bound memory, see
+    /// [`crate::NextChunkOptions::diff_segment_len`]; 0 when no diff was split.
+    pub segments: usize,
+    /// Whether the call ran out of time and skipped diffs, see
+    /// [`crate::NextChunkOptions::deadline`].
+    pub timed_out: bool,
+}
+
+impl CallStats {
+    /// Whether the call was windowed and the primary window didn't hold the
+    /// anchor, so it was retried wider, searched elsewhere or missed.
+    pub fn window_missed(&self) -> bool {
+        self.windowed && (self.escalations > 0 || self.windows_searched > 0 || self.anchor == CallAnchor::Miss)
+    }
+}
+
+/// Aggregated [`CallStats`] over all prediction calls of a streamer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NextChunkStats {
+    /// Number of prediction calls.
+    pub calls: u64,
+    /// Calls that ran at least one diff.
+    pub diff_calls: u64,
+    /// Calls anchored by extending a remembered earlier call.
+    pub memo_hits: u64,
+    /// Calls whose diff was windowed.
+    pub windowed_calls: u64,
+    /// Windowed calls whose primary window missed, see [`CallStats::window_missed`].
+    pub window_misses: u64,
+    /// Calls that predicted no tokens.
+    pub empty_predictions: u64,
+    /// Calls that re-anchored with a full diff after a run of empty windowed predictions.
+    pub global_reanchors: u64,
+    /// Calls that ran out of time, see [`CallStats::timed_out`].
+    pub timeouts: u64,
+    /// Summed wall time of all calls.
+    pub wall: Duration,
+    /// Summed interning time of all calls.
+    pub intern: Duration,
+    /// Summed diff time of all calls.
+    pub diff: Duration,
+    /// Slowest call, to spot latency spikes.
+    pub max_wall: Duration,
+    /// The most recent call.
+    pub last: Option<CallStats>,
+    /// Predicted chunks checked with `verify_and_advance`.
+    pub verifications: u64,
+    /// Tokens of the verified chunks.
+    pub predicted_tokens: u64,
+    /// Leading tokens of the verified chunks that matched what was generated.
+    pub accepted_tokens: u64,
+}
+
+impl NextChunkStats {
+    fn record(&mut self, call: CallStats) {
+        self.calls += 1;
+        self.diff_calls += u64::from(!call.fast_path);
+        self.memo_hits += u64::from(call.memo_hit);
+        self.windowed_calls += u64::from(call.windowed);
+        self.window_misses += u64::from(call.window_missed());
+        self.empty_predictions += u64::from(call.chunk_len == 0);
+        self.global_reanchors += u64::from(call.global_reanchor);
+        self.timeouts += u64::from(call.timed_out);
+        self.wall += call.wall;
+        self.intern += call.intern;
+        self.diff += call.diff;
+        self.max_wall = self.max_wall.max(call.wall);
+        self.last = Some(call);
+    }
+
+    fn record_verification(&mut self, predicted: usize, accepted: usize) {
+        self.verifications += 1;
+        self.predicted_tokens += predicted as u64;
+        self.accepted_tokens += accepted as u64;
+    }
+}
+
+/// [`NextChunkStats`] shared by `&self` prediction calls, possibly running
+/// on several threads at once.
+#[derive(Debug, Default)]
+pub(crate) struct StatsRecorder(Mutex<NextChunkStats>);
+
+impl StatsRecorder {
+    pub(crate) fn record(&self, call: CallStats) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).record(call);
+    }
+
+    pub(crate) fn record_verification(&self, predicted: usize, accepted: usize) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).record_verification(predicted, accepted);
+    }
+
+    pub(crate) fn last(&self) -> Option<CallStats> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).last
+    }
+
+    pub(crate) fn snapshot(&self) -> NextChunkStats {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn restore(&self, stats: NextChunkStats) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = stats;
+    }
+
+    pub(crate) fn reset(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = NextChunkStats::default();
+    }
+}
+
+/// How many matching blocks of each length diffs found, e.g. to tune
+/// `min_match_len` and the window sizes for a language.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchLengthHistogram {
+    counts: BTreeMap<usize, u64>,
+}
+
+impl MatchLengthHistogram {
+    pub fn record(&mut self, len: usize) {
+        *self.counts.entry(len).or_insert(0) += 1;
+    }
+
+    /// Matching blocks per length, by increasing length.
+    pub fn counts(&self) -> &BTreeMap<usize, u64> {
+        &self.counts
+    }
+
+    /// Number of matching blocks recorded.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Smallest length at least a `q` fraction (0 to 1) of the blocks don't
+    /// exceed; `None` when empty.
+    pub fn quantile(&self, q: f64) -> Option<usize> {
+        let target = (q.clamp(0.0, 1.0) * self.total() as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        self.counts.iter().find_map(|(&len, &count)| {
+            seen += count;
+            (seen >= target).then_some(len)
+        })
+    }
+
+    /// Adds the blocks of `other`.
+    pub fn merge(&mut self, other: &MatchLengthHistogram) {
+        for (&len, &count) in &other.counts {
+            *self.counts.entry(len).or_insert(0) += count;
+        }
+    }
+}
+
+/// Match lengths of the last diff and of all diffs since the last reset.
+#[derive(Debug, Default)]
+pub(crate) struct MatchLengthRecorder(Mutex<(MatchLengthHistogram, MatchLengthHistogram)>);
+
+impl MatchLengthRecorder {
+    /// Records the blocks of one diff.
+    pub(crate) fn record(&self, lengths: impl IntoIterator<Item = usize>) {
+        let mut last = MatchLengthHistogram::default();
+        lengths.into_iter().for_each(|len| last.record(len));
+        let mut histograms = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        histograms.1.merge(&last);
+        histograms.0 = last;
+    }
+
+    pub(crate) fn last(&self) -> MatchLengthHistogram {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).0.clone()
+    }
+
+    pub(crate) fn session(&self) -> MatchLengthHistogram {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).1.clone()
+    }
+
+    pub(crate) fn reset(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Default::default();
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use super::nextchunk::StreamNextChunk;
+use super::options::NextChunkOptions;
+
+
+/// Many candidate reference documents (e.g. the source files of a
+/// repository), indexed to find the ones the output is copying from.
+///
+/// Every n-gram of `ngram_len` tokens maps to the documents containing it;
+/// [`ReferenceStore::retrieve`] ranks documents by how many n-grams of the
+/// tail of `b` they contain, and [`ReferenceStore::stream_next_chunk`] builds
+/// a streamer over the best one. Documents are shared with the streamers
+/// built over them, not copied.
+pub struct ReferenceStore<T> {
+    ngram_len: usize,
+    options: NextChunkOptions,
+    documents: Vec<Arc<[T]>>,
+    /// n-gram -> ids of the documents containing it, increasing.
+    index: HashMap<Vec<T>, Vec<usize>>,
+}
+
+impl<T: Eq + Hash + Copy> ReferenceStore<T> {
+    /// An empty store whose streamers use `options`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ngram_len` is 0.
+    pub fn new(ngram_len: usize, options: NextChunkOptions) -> Self {
+        assert!(ngram_len > 0, "ngram_len must be at least 1");
+        ReferenceStore { ngram_len, options, documents: Vec::new(), index: HashMap::new() }
+    }
+
+    /// Indexes `document` and returns its id, the number of documents added before it.
+    pub fn add(&mut self, document: impl Into<Arc<[T]>>) -> usize {
+        let id = self.documents.len();
+        let document = document.into();
+        for ngram in document.windows(self.ngram_len) {
+            let ids = self.index.entry(ngram.to_vec()).or_default();
+            if ids.last() != Some(&id) {
+                ids.push(id);
+            }
+        }
+        self.documents.push(document);
+        id
+    }
+
+    /// Length of the indexed n-grams.
+    pub fn ngram_len(&self) -> usize {
+        self.ngram_len
+    }
+
+    /// Number of documents.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// The document with id `id`, if any.
+    pub fn document(&self, id: usize) -> Option<&[T]> {
+        self.documents.get(id).map(|document| &**document)
+    }
+
+    /// Up to `k` `(id, hits)` pairs of the documents sharing the most
+    /// n-grams with `tail`, the end of `b` (e.g. its last 64 tokens), most
+    /// hits first and earlier documents first on ties. `hits` counts the
+    /// n-grams of `tail` found in the document; documents with none are left out.
+    pub fn retrieve(&self, tail: &[T], k: usize) -> Vec<(usize, usize)> {
+        let mut hits: HashMap<usize, usize> = HashMap::new();
+        for ngram in tail.windows(self.ngram_len) {
+            for &id in self.index.get(ngram).into_iter().flatten() {
+                *hits.entry(id).or_insert(0) += 1;
+            }
+        }
+        let mut ranked: Vec<(usize, usize)> = hits.into_iter().collect();
+        ranked.sort_unstable_by_key(|&(id, hits)| (std::cmp::Reverse(hits), id));
+        ranked.truncate(k);
+        ranked
+    }
+
+    /// A streamer over the document [`ReferenceStore::retrieve`] ranks best
+    /// for `tail`, and its id; `None` when no document shares an n-gram with it.
+    /// Building the streamer interns the document, so keep it for the
+    /// following calls of the same generation rather than retrieving again.
+    pub fn stream_next_chunk(&self, tail: &[T]) -> Option<(usize, StreamNextChunk<T>)> {
+        let (id, _) = *self.retrieve(tail, 1).first()?;
+        Some((id, StreamNextChunk::with_shared_slice(Arc::clone(&self.documents[id]), self.options.clone())))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_retrieve() {
+        let mut store = ReferenceStore::new(3, NextChunkOptions::default());
+        assert_eq!(store.add(vec![1, 2, 3, 4, 5, 6, 7]), 0);
+        assert_eq!(store.add(vec![9, 2, 3, 4, 8, 8, 2, 3, 4]), 1);
+        assert_eq!(store.add(vec![5, 6, 7, 10, 11, 12]), 2);
+        assert_eq!(store.len(), 3);
+
+        assert_eq!(store.retrieve(&[0, 2, 3, 4, 5, 6], 5), [(0, 3), (1, 1)]);
+        assert_eq!(store.retrieve(&[5, 6, 7, 10], 5), [(2, 2), (0, 1)]);
+        assert_eq!(store.retrieve(&[5, 6, 7, 10], 1), [(2, 2)]);
+        assert!(store.retrieve(&[2, 3], 5).is_empty());
+
+        let (id, streamer) = store.stream_next_chunk(&[0, 6, 7, 10]).unwrap();
+        assert_eq!(id, 2);
+        assert_eq!(streamer.reference(), store.document(2).unwrap());
+        assert_eq!(streamer.next_chunk(&[0, 6, 7, 10], 2), &[11, 12]);
+        assert!(store.stream_next_chunk(&[42, 43, 44]).is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use std::ops::Range;
+
+use imara_diff::intern::InternedInput;
+
+use super::options::DiffAlgorithm;
+use super::sink::{ChangeRangeCollector, MatchCollector, UnifiedDiffSink};
+
+
+/// Line-based diff of two texts.
+///
+/// Lines are interned by imara-diff, so the diff itself runs on integer
+/// tokens just like the token-level API. All ranges are in lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextDiff {
+    before_lines: u32,
+    after_lines: u32,
+    changes: Vec<(Range<u32>, Range<u32>)>,
+    matches: Vec<(Range<u32>, Range<u32>)>,
+}
+
+impl TextDiff {
+    /// Diffs `before` against `after` line by line.
+    pub fn new(before: &str, after: &str, algorithm: DiffAlgorithm) -> Self {
+        let input = InternedInput::new(before, after);
+        let before_lines = input.before.len() as u32;
+        let after_lines = input.after.len() as u32;
+        let changes = imara_diff::diff(algorithm.into(), &input, ChangeRangeCollector::default());
+        let matches = imara_diff::diff(algorithm.into(), &input, MatchCollector::new(before_lines, after_lines));
+        TextDiff { before_lines, after_lines, changes, matches }
+    }
+
+    /// Number of lines in the `before` text.
+    pub fn before_lines(&self) -> u32 {
+        self.before_lines
+    }
+
+    /// Number of lines in the `after` text.
+    pub fn after_lines(&self) -> u32 {
+        self.after_lines
+    }
+
+    /// Changed line ranges as `(range_in_before, range_in_after)`.
+    pub fn changes(&self) -> &[(Range<u32>, Range<u32>)] {
+        &self.changes
+    }
+
+    /// Unchanged line ranges as `(range_in_before, range_in_after)`.
+    pub fn matches(&self) -> &[(Range<u32>, Range<u32>)] {
+        &self.matches
+    }
+
+    /// Whether both texts have the same lines.
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Line-based unified diff of `before` against `after`, with `context_len`
+/// unchanged lines around each hunk. Returns only the hunks (no `---`/`+++`
+/// header); an empty string when the texts have the same lines.
+pub fn unified_diff(before: &str, after: &str, context_len: u32, algorithm: DiffAlgorithm) -> String {
+    let input = InternedInput::new(before, after);
+    imara_diff::diff(algorithm.into(), &input, UnifiedDiffSink::new(&input, context_len))
+}
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_text_diff() {
+        let before = "fn main() {\n    foo();\n}\n";
+        let after = "fn main() {\n    bar();\n    baz();\n}\n";
+        let diff = TextDiff::new(before, after, DiffAlgorithm::default());
+        assert_eq!((diff.before_lines(), diff.after_lines()), (3, 4));
+        assert_eq!(diff.changes(), &[(1..2, 1..3)]);
+        assert_eq!(diff.matches(), &[(0..1, 0..1), (2..3, 3..4)]);
+        assert!(!diff.is_unchanged());
+        assert!(TextDiff::new(before, before, DiffAlgorithm::Myers).is_unchanged());
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let before: String = (1..=12).map(|i| format!("line {i}\n")).collect();
+        let after = before.replace("line 2\n", "line two\n").replace("line 11\n", "");
+        let expected = "\
+@@ -1,4 +1,4 @@
+ line 1
+-line 2
++line two
+ line 3
+ line 4
+@@ -9,4 +9,3 @@
+ line 9
+ line 10
+-line 11
+ line 12
+";
+        assert_eq!(unified_diff(&before, &after, 2, DiffAlgorithm::default()), expected);
+        // with more context both changes share one hunk
+        assert!(unified_diff(&before, &after, 4, DiffAlgorithm::default()).starts_with("@@ -1,12 +1,11 @@\n"));
+        assert_eq!(unified_diff(&before, &before, 3, DiffAlgorithm::default()), "");
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokenizers::Tokenizer;
+
+use super::nextchunk::StreamNextChunk;
+use super::options::NextChunkOptions;
+
+
+/// Error from the text entry points of [`StreamNextChunk`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TokenizerError {
+    #[error("failed to load tokenizer from {path:?}: {message}")]
+    Load { path: PathBuf, message: String },
+    #[error("failed to tokenize text: {0}")]
+    Encode(String),
+    /// `next_chunk_text` on an instance not created with [`StreamNextChunk::from_text`].
+    #[error("this StreamNextChunk has no tokenizer, create it with from_text")]
+    NoTokenizer,
+}
+
+/// Token ids of `text`, without special tokens: both the reference and `b`
+/// are plain content.
+fn encode(tokenizer: &Tokenizer, text: &str) -> Result<Vec<u32>, TokenizerError> {
+    let encoding = tokenizer.encode(text, false).map_err(|e| TokenizerError::Encode(e.to_string()))?;
+    Ok(encoding.get_ids().to_vec())
+}
+
+impl StreamNextChunk<u32> {
+    /// Tokenizes `text` with the HuggingFace `tokenizer.json` at `tokenizer_path`
+    /// and uses the ids as the reference.
+    pub fn from_text(text: &str, tokenizer_path: impl AsRef<Path>) -> Result<Self, TokenizerError> {
+        Self::from_text_with_options(text, tokenizer_path, NextChunkOptions::default())
+    }
+
+    /// Like [`StreamNextChunk::from_text`] with non-default tunables.
+    pub fn from_text_with_options(
+        text: &str,
+        tokenizer_path: impl AsRef<Path>,
+        options: NextChunkOptions,
+    ) -> Result<Self, TokenizerError> {
+        let path = tokenizer_path.as_ref();
+        let tokenizer = Tokenizer::from_file(path)
+            .map_err(|e| TokenizerError::Load { path: path.to_owned(), message: e.to_string() })?;
+        Self::from_text_with_tokenizer(text, Arc::new(tokenizer), options)
+    }
+
+    /// Like [`StreamNextChunk::from_text`] with an already loaded tokenizer,
+    /// which can be shared between instances.
+    pub fn from_text_with_tokenizer(
+        text: &str,
+        tokenizer: Arc<Tokenizer>,
+        options: NextChunkOptions,
+    ) -> Result<Self, TokenizerError> {
+        let a = encode(&tokenizer, text)?;
+        let mut streamer = Self::with_options(a, options);
+        streamer.tokenizer = Some(tokenizer);
+        Ok(streamer)
+    }
+
+    /// The tokenizer the reference was tokenized with, if created from text.
+    pub fn tokenizer(&self) -> Option<&Arc<Tokenizer>> {
+        self.tokenizer.as_ref()
+    }
+
+    /// Like [`StreamNextChunk::next_chunk`], tokenizing the text generated so far first.
+    pub fn next_chunk_text(&self, current_b: &str, chunk_size: usize) -> Result<&[u32], TokenizerError> {
+        let tokenizer = self.tokenizer.as_ref().ok_or(TokenizerError::NoTokenizer)?;
+        let current_b = encode(tokenizer, current_b)?;
+        Ok(self.next_chunk(&current_b, chunk_size))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::pre_tokenizers::whitespace::Whitespace;
+
+    fn word_tokenizer() -> Tokenizer {
+        let words = ["[UNK]", "fn", "main", "(", ")", "{", "}", "let", "x", "=", "1", ";"];
+        let vocab = words.iter().enumerate().map(|(id, w)| (w.to_string(), id as u32)).collect();
+        let model = WordLevel::builder().vocab(vocab).unk_token("[UNK]".into()).build().unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+        tokenizer
+    }
+
+    #[test]
+    fn test_from_text() {
+        let path = std::env::temp_dir().join(format!("llminfer_tokenizer_{}.json", std::process::id()));
+        word_tokenizer().save(&path, false).unwrap();
+        let streamer = StreamNextChunk::from_text("fn main ( ) { let x = 1 ; }", &path);
+        std::fs::remove_file(&path).unwrap();
+        let streamer = streamer.unwrap();
+
+        assert_eq!(streamer.reference(), [1, 2, 3, 4, 5, 7, 8, 9, 10, 11, 6]);
+        assert_eq!(streamer.next_chunk_text("fn main ( ) {", 3).unwrap(), [7, 8, 9]);
+
+        let missing = StreamNextChunk::from_text("fn", std::env::temp_dir().join("missing_tokenizer.json"));
+        assert!(matches!(missing, Err(TokenizerError::Load { .. })));
+    }
+
+    #[test]
+    fn test_no_tokenizer() {
+        let streamer = StreamNextChunk::new(&[1u32, 2, 3]);
+        assert!(streamer.tokenizer().is_none());
+        assert_eq!(streamer.next_chunk_text("fn", 1), Err(TokenizerError::NoTokenizer));
+
+        let shared = Arc::new(word_tokenizer());
+        let streamer = StreamNextChunk::from_text_with_tokenizer("let x = 1", shared.clone(), NextChunkOptions::default()).unwrap();
+        assert!(Arc::ptr_eq(streamer.tokenizer().unwrap(), &shared));
+        assert_eq!(streamer.next_chunk_text("let", 2).unwrap(), [8, 9]);
+    }
+}
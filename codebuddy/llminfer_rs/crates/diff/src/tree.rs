@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+
+/// Candidate continuations merged into a prefix tree, so tree attention can
+/// verify all of them in one forward pass.
+///
+/// Nodes are stored breadth-first, every node after its parent, and described
+/// by parallel arrays: the flattened `tokens` are fed to the model as-is and
+/// `parents` gives the topology in the "parent index, -1 for a root" form tree
+/// verification kernels take. Siblings keep the order of the candidates they
+/// first appear in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TokenTree<T> {
+    /// Token of each node.
+    pub tokens: Vec<T>,
+    /// Index of each node's parent, -1 for the roots, which directly follow `b`.
+    pub parents: Vec<i64>,
+    /// Depth of each node, 0 for the roots: its position offset past `b`.
+    pub depths: Vec<usize>,
+}
+
+impl<T: Eq + Hash + Copy> TokenTree<T> {
+    /// Merges `candidates` on their common prefixes.
+    pub fn from_candidates<'a>(candidates: impl IntoIterator<Item = &'a [T]>) -> Self
+    where
+        T: 'a,
+    {
+        let candidates: Vec<&[T]> = candidates.into_iter().collect();
+        Self::merge(&candidates).0
+    }
+
+    /// The tree of `candidates` and the nodes each of them runs through.
+    fn merge(candidates: &[&[T]]) -> (Self, Vec<Vec<usize>>) {
+        let mut tree = TokenTree { tokens: Vec::new(), parents: Vec::new(), depths: Vec::new() };
+        let mut paths: Vec<Vec<usize>> = candidates.iter().map(|c| Vec::with_capacity(c.len())).collect();
+        // Node each candidate's prefix ends at; built a level at a time for the breadth-first order
+        let mut at = vec![-1; candidates.len()];
+        let max_depth = candidates.iter().map(|c| c.len()).max().unwrap_or(0);
+        for depth in 0..max_depth {
+            let mut level = HashMap::new();
+            for ((candidate, node), path) in candidates.iter().zip(&mut at).zip(&mut paths) {
+                let Some(&token) = candidate.get(depth) else { continue };
+                let parent = *node;
+                *node = *level.entry((parent, token)).or_insert_with(|| {
+                    tree.tokens.push(token);
+                    tree.parents.push(parent);
+                    tree.depths.push(depth);
+                    tree.tokens.len() as i64 - 1
+                });
+                path.push(*node as usize);
+            }
+        }
+        (tree, paths)
+    }
+}
+
+impl<T> TokenTree<T> {
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Indices of the children of `node`.
+    pub fn children(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        (node + 1..self.len()).filter(move |&child| self.parents[child] == node as i64)
+    }
+
+    /// Node indices from a root to each leaf, one path per leaf in node order:
+    /// the candidates the tree verifies, e.g. to pick the accepted one.
+    pub fn paths(&self) -> Vec<Vec<usize>> {
+        let mut is_leaf = vec![true; self.len()];
+        for &parent in self.parents.iter().filter(|&&parent| parent >= 0) {
+            is_leaf[parent as usize] = false;
+        }
+        (0..self.len())
+            .filter(|&node| is_leaf[node])
+            .map(|leaf| {
+                let mut path = vec![leaf];
+                while let Ok(parent) = usize::try_from(self.parents[*path.last().unwrap()]) {
+                    path.push(parent);
+                }
+                path.reverse();
+                path
+            })
+            .collect()
+    }
+
+    /// Walks the tree along `accepted`, the tokens the verifier generated
+    /// after `b`, and works out what is left of the speculation, see
+    /// [`TreeVerification`].
+    pub fn verify(&self, accepted: &[T]) -> TreeVerification<T>
+    where
+        T: PartialEq + Copy,
+    {
+        let mut accepted_path = Vec::new();
+        let mut parent = -1;
+        for token in accepted {
+            // Children come after their parent
+            let first_child = (parent + 1) as usize;
+            let Some(node) = (first_child..self.len()).find(|&node| self.parents[node] == parent && self.tokens[node] == *token) else {
+                break;
+            };
+            accepted_path.push(node);
+            parent = node as i64;
+        }
+
+        // Only the descendants of the last accepted node still speculate on
+        // what follows, and only when the verifier didn't go past the tree
+        let mut surviving = TokenTree { tokens: Vec::new(), parents: Vec::new(), depths: Vec::new() };
+        let mut surviving_nodes = Vec::new();
+        let mut new_index = vec![None; self.len()];
+        if accepted_path.len() == accepted.len() {
+            for node in (parent + 1) as usize..self.len() {
+                let new_parent = match self.parents[node] {
+                    p if p == parent => -1,
+                    p => match usize::try_from(p).ok().and_then(|p| new_index[p]) {
+                        Some(new_parent) => new_parent as i64,
+                        None => continue,
+                    },
+                };
+                new_index[node] = Some(surviving.len());
+                surviving.tokens.push(self.tokens[node]);
+                surviving.parents.push(new_parent);
+                surviving.depths.push(self.depths[node] - accepted.len());
+                surviving_nodes.push(node);
+            }
+        }
+        let retired = self
+            .paths()
+            .iter()
+            .enumerate()
+            .filter(|(_, path)| path.last().is_some_and(|&leaf| new_index[leaf].is_none()))
+            .map(|(candidate, _)| candidate)
+            .collect();
+        TreeVerification { accepted_path, surviving, surviving_nodes, retired }
+    }
+
+    /// `mask[i][j]` is whether node `i` attends to node `j`, i.e. `j` is `i`
+    /// or one of its ancestors.
+    pub fn attention_mask(&self) -> Vec<Vec<bool>> {
+        let mut mask: Vec<Vec<bool>> = Vec::with_capacity(self.len());
+        for (node, &parent) in self.parents.iter().enumerate() {
+            let mut row = match usize::try_from(parent) {
+                Ok(parent) => mask[parent].clone(),
+                Err(_) => vec![false; self.len()],
+            };
+            row[node] = true;
+            mask.push(row);
+        }
+        mask
+    }
+}
+
+/// What is left of a [`TokenTree`] once verified, see [`TokenTree::verify`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TreeVerification<T> {
+    /// Nodes of the longest root-to-node path matching the accepted tokens,
+    /// root first: how many draft tokens were accepted, and whose KV cache
+    /// entries to keep.
+    pub accepted_path: Vec<usize>,
+    /// The descendants of the last accepted node re-rooted past it (the
+    /// whole tree when nothing was accepted), still to verify next step;
+    /// empty when the verifier generated a token the tree doesn't have.
+    pub surviving: TokenTree<T>,
+    /// Node of the original tree each surviving node was.
+    pub surviving_nodes: Vec<usize>,
+    /// Candidates, by index in [`TokenTree::paths`], with nothing left to
+    /// verify: they diverged from the accepted tokens or were accepted whole.
+    pub retired: Vec<usize>,
+}
+
+/// A [`TokenTree`] merged from the drafts of several sources (e.g. this
+/// matcher and a draft model), labeling each node with the sources that
+/// proposed it, so verification can tell which drafter its accepted path
+/// came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LabeledTokenTree<T, L> {
+    pub tree: TokenTree<T>,
+    /// Labels of the candidates running through each node, without
+    /// duplicates, in the order of the candidates.
+    pub sources: Vec<Vec<L>>,
+}
+
+impl<T: Eq + Hash + Copy, L: PartialEq + Clone> LabeledTokenTree<T, L> {
+    /// Merges `(label, candidate)` pairs on their common prefixes; several
+    /// candidates may share a label.
+    pub fn from_candidates<'a>(candidates: impl IntoIterator<Item = (L, &'a [T])>) -> Self
+    where
+        T: 'a,
+    {
+        let (labels, candidates): (Vec<L>, Vec<&[T]>) = candidates.into_iter().unzip();
+        let (tree, paths) = TokenTree::merge(&candidates);
+        let mut sources = vec![Vec::new(); tree.len()];
+        for (label, path) in labels.iter().zip(&paths) {
+            for &node in path {
+                if !sources[node].contains(label) {
+                    sources[node].push(label.clone());
+                }
+            }
+        }
+        LabeledTokenTree { tree, sources }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_candidates() {
+        let tree = TokenTree::from_candidates([&[1, 2, 3][..], &[1, 2, 4], &[5], &[1, 6]]);
+        assert_eq!(tree.tokens, [1, 5, 2, 6, 3, 4]);
+        assert_eq!(tree.parents, [-1, -1, 0, 0, 2, 2]);
+        assert_eq!(tree.depths, [0, 0, 1, 1, 2, 2]);
+        assert_eq!(tree.children(0).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(tree.paths(), [vec![1], vec![0, 3], vec![0, 2, 4], vec![0, 2, 5]]);
+        assert_eq!(tree.attention_mask()[4], [true, false, true, false, true, false]);
+        assert_eq!(tree.attention_mask()[1], [false, true, false, false, false, false]);
+        assert!(TokenTree::<i32>::from_candidates([]).is_empty());
+    }
+
+    #[test]
+    fn test_verify() {
+        let tree = TokenTree::from_candidates([&[1, 2, 3][..], &[1, 2, 4, 5], &[5], &[1, 6]]);
+        assert_eq!(tree.paths(), [vec![1], vec![0, 3], vec![0, 2, 4], vec![0, 2, 5, 6]]);
+
+        let verified = tree.verify(&[1, 2]);
+        assert_eq!(verified.accepted_path, [0, 2]);
+        assert_eq!(verified.surviving, TokenTree::from_candidates([&[3][..], &[4, 5]]));
+        assert_eq!(verified.surviving_nodes, [4, 5, 6]);
+        assert_eq!(verified.retired, [0, 1]);
+
+        // The verifier went past the tree: nothing survives
+        let verified = tree.verify(&[1, 2, 4, 5, 9]);
+        assert_eq!(verified.accepted_path, [0, 2, 5, 6]);
+        assert!(verified.surviving.is_empty() && verified.surviving_nodes.is_empty());
+        assert_eq!(verified.retired, [0, 1, 2, 3]);
+
+        let verified = tree.verify(&[]);
+        assert_eq!((verified.surviving, verified.retired), (tree.clone(), vec![]));
+        assert_eq!(tree.verify(&[7]).accepted_path, [] as [usize; 0]);
+    }
+
+    #[test]
+    fn test_labeled_candidates() {
+        let drafts: [(&str, &[i32]); 4] = [("matcher", &[1, 2, 3]), ("model", &[1, 2, 4]), ("model", &[1, 2, 3, 5]), ("matcher", &[7])];
+        let LabeledTokenTree { tree, sources } = LabeledTokenTree::from_candidates(drafts);
+        assert_eq!(tree, TokenTree::from_candidates(drafts.iter().map(|&(_, draft)| draft)));
+        assert_eq!(tree.tokens, [1, 7, 2, 3, 4, 5]);
+        assert_eq!(sources, [vec!["matcher", "model"], vec!["matcher"], vec!["matcher", "model"], vec!["matcher", "model"], vec!["model"], vec!["model"]]);
+    }
+}
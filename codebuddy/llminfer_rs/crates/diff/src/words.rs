@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::nextchunk::StreamNextChunk;
+use super::options::NextChunkOptions;
+use super::source::{WordSource, WordSplit};
+
+
+/// Word id given to words of `b` that never occur in the reference.
+const UNKNOWN_WORD: u32 = u32::MAX;
+
+/// Word-level next-chunk prediction over plain text.
+///
+/// The reference is split with a [`WordSplit`] and each distinct word mapped
+/// to an id, so matching runs on [`StreamNextChunk`] over those ids. The
+/// separators are words too, so joining a predicted chunk gives its text back.
+/// `current_b` should end on a word boundary: a partially generated last word
+/// doesn't match its reference counterpart.
+pub struct WordNextChunk {
+    text: String,
+    /// Byte range of each word of `text`.
+    spans: Vec<Range<usize>>,
+    ids: HashMap<String, u32>,
+    split: WordSplit,
+    streamer: StreamNextChunk<u32>,
+}
+
+impl WordNextChunk {
+    pub fn new(text: impl Into<String>, split: WordSplit) -> Self {
+        Self::with_options(text, split, NextChunkOptions::default())
+    }
+
+    pub fn with_options(text: impl Into<String>, split: WordSplit, options: NextChunkOptions) -> Self {
+        let text = text.into();
+        let mut ids = HashMap::new();
+        let mut spans = Vec::new();
+        let mut a = Vec::new();
+        for word in WordSource::new(&text, &split).words() {
+            let start = word.as_ptr() as usize - text.as_ptr() as usize;
+            spans.push(start..start + word.len());
+            let next = ids.len() as u32;
+            a.push(*ids.entry(word.to_string()).or_insert(next));
+        }
+        let streamer = StreamNextChunk::with_options(a, options);
+        WordNextChunk { text, spans, ids, split, streamer }
+    }
+
+    /// The reference text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn split(&self) -> &WordSplit {
+        &self.split
+    }
+
+    /// The reference's words, separators included.
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.spans.iter().map(|span| &self.text[span.clone()])
+    }
+
+    /// Predicts the next `chunk_size` words of the reference following `current_b`.
+    pub fn next_chunk(&self, current_b: &str, chunk_size: usize) -> Vec<&str> {
+        let b: Vec<u32> = self
+            .split
+            .split(current_b)
+            .into_iter()
+            .map(|word| self.ids.get(word).copied().unwrap_or(UNKNOWN_WORD))
+            .collect();
+        let result = self.streamer.next_chunk_with_info(&b, chunk_size);
+        let start = result.start.unwrap_or(0);
+        self.spans[start..start + result.tokens.len()].iter().map(|span| &self.text[span.clone()]).collect()
+    }
+
+    /// Like [`WordNextChunk::next_chunk`], joined back into text.
+    pub fn next_chunk_text(&self, current_b: &str, chunk_size: usize) -> &str {
+        match self.next_chunk(current_b, chunk_size).as_slice() {
+            [] => "",
+            [first, .., last] => {
+                let start = first.as_ptr() as usize - self.text.as_ptr() as usize;
+                let end = last.as_ptr() as usize - self.text.as_ptr() as usize + last.len();
+                &self.text[start..end]
+            }
+            [word] => word,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn test_word_next_chunk() {
+        let words = WordNextChunk::new("the quick brown fox jumps over the lazy dog", WordSplit::Whitespace);
+        assert_eq!(words.words().count(), 17);
+        assert_eq!(words.next_chunk("a quick brown ", 3), ["fox", " ", "jumps"]);
+        assert_eq!(words.next_chunk_text("the quick brown fox jumps ", 5), "over the lazy");
+        assert_eq!(words.next_chunk_text("the quick brown fox jumps over the lazy dog", 5), "");
+    }
+
+    #[test]
+    fn test_regex_split() {
+        let split = WordSplit::Regex(Regex::new(r"\b").unwrap());
+        let words = WordNextChunk::new("let total = price * qty;\nreturn total;", split);
+        assert_eq!(words.next_chunk_text("let total = price * qty;\n", 4), "return total;");
+        assert!(words.next_chunk("unrelated", 4).iter().all(|w| !w.is_empty()));
+    }
+}
@@ -0,0 +1,65 @@
+#ifndef LLMINFER_FFI_H
+#define LLMINFER_FFI_H
+
+/* Generated by cbindgen from crates/ffi/src/lib.rs; do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Opaque handle returned by `snc_new`.
+ */
+typedef struct SncStreamNextChunk SncStreamNextChunk;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a streamer over the reference `a` (`a_len` tokens, copied).
+ *
+ * Returns null on failure. Free the result with `snc_free`.
+ *
+ * # Safety
+ *
+ * Unless null, `a` must point to `a_len` readable `int32_t`s.
+ */
+struct SncStreamNextChunk *snc_new(const int32_t *a, size_t a_len);
+
+/**
+ * Predicts the next chunk of `a` following `b` (`b_len` tokens) and writes
+ * up to `chunk_size` tokens to `out`.
+ *
+ * Returns the number of tokens written; 0 when nothing can be predicted or
+ * on invalid arguments.
+ *
+ * # Safety
+ *
+ * `snc` must come from `snc_new` and not be freed yet. Unless null, `b` must
+ * point to `b_len` readable `int32_t`s, and `out` to room for `chunk_size`
+ * writable `int32_t`s. `snc` may be shared by several threads calling
+ * `snc_next_chunk` concurrently.
+ */
+size_t snc_next_chunk(const struct SncStreamNextChunk *snc,
+                      const int32_t *b,
+                      size_t b_len,
+                      size_t chunk_size,
+                      int32_t *out);
+
+/**
+ * Frees a streamer created by `snc_new`; null is ignored.
+ *
+ * # Safety
+ *
+ * `snc` must come from `snc_new` and not be used afterwards.
+ */
+void snc_free(struct SncStreamNextChunk *snc);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LLMINFER_FFI_H */
@@ -0,0 +1,115 @@
+//! C ABI for [`diff::StreamNextChunk`] over `int32_t` tokens.
+//!
+//! Every function tolerates null pointers and never unwinds into C: on
+//! invalid arguments or an internal panic it returns null / 0.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use diff::StreamNextChunk;
+
+
+/// Opaque handle returned by `snc_new`.
+pub struct SncStreamNextChunk(StreamNextChunk<i32>);
+
+/// Views `len` tokens at `ptr`, treating a null pointer as an empty sequence.
+///
+/// # Safety
+///
+/// Unless null, `ptr` must point to `len` readable, initialized `i32`s.
+unsafe fn tokens<'a>(ptr: *const i32, len: usize) -> &'a [i32] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        // SAFETY: upheld by the caller
+        unsafe { slice::from_raw_parts(ptr, len) }
+    }
+}
+
+/// Creates a streamer over the reference `a` (`a_len` tokens, copied).
+///
+/// Returns null on failure. Free the result with `snc_free`.
+///
+/// # Safety
+///
+/// Unless null, `a` must point to `a_len` readable `int32_t`s.
+#[no_mangle]
+pub unsafe extern "C" fn snc_new(a: *const i32, a_len: usize) -> *mut SncStreamNextChunk {
+    // SAFETY: upheld by the caller
+    let a = unsafe { tokens(a, a_len) };
+    catch_unwind(|| Box::into_raw(Box::new(SncStreamNextChunk(StreamNextChunk::new(a))))).unwrap_or(ptr::null_mut())
+}
+
+/// Predicts the next chunk of `a` following `b` (`b_len` tokens) and writes
+/// up to `chunk_size` tokens to `out`.
+///
+/// Returns the number of tokens written; 0 when nothing can be predicted or
+/// on invalid arguments.
+///
+/// # Safety
+///
+/// `snc` must come from `snc_new` and not be freed yet. Unless null, `b` must
+/// point to `b_len` readable `int32_t`s, and `out` to room for `chunk_size`
+/// writable `int32_t`s. `snc` may be shared by several threads calling
+/// `snc_next_chunk` concurrently.
+#[no_mangle]
+pub unsafe extern "C" fn snc_next_chunk(
+    snc: *const SncStreamNextChunk,
+    b: *const i32,
+    b_len: usize,
+    chunk_size: usize,
+    out: *mut i32,
+) -> usize {
+    if snc.is_null() || out.is_null() {
+        return 0;
+    }
+    // SAFETY: upheld by the caller
+    let (snc, b) = unsafe { (&(*snc).0, tokens(b, b_len)) };
+    let chunk = match catch_unwind(AssertUnwindSafe(|| snc.next_chunk(b, chunk_size))) {
+        Ok(chunk) => chunk,
+        Err(_) => return 0,
+    };
+    // SAFETY: `chunk.len() <= chunk_size` and `out` has room for `chunk_size` tokens
+    unsafe { ptr::copy_nonoverlapping(chunk.as_ptr(), out, chunk.len()) };
+    chunk.len()
+}
+
+/// Frees a streamer created by `snc_new`; null is ignored.
+///
+/// # Safety
+///
+/// `snc` must come from `snc_new` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn snc_free(snc: *mut SncStreamNextChunk) {
+    if !snc.is_null() {
+        // SAFETY: upheld by the caller
+        drop(unsafe { Box::from_raw(snc) });
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let a: Vec<i32> = (0..100).collect();
+        let b = [5, -1, 7, 8];
+        let mut out = [0; 4];
+        unsafe {
+            let snc = snc_new(a.as_ptr(), a.len());
+            assert!(!snc.is_null());
+            assert_eq!(snc_next_chunk(snc, b.as_ptr(), b.len(), 3, out.as_mut_ptr()), 3);
+            assert_eq!(&out[..3], &[9, 10, 11]);
+            // empty b predicts the start of a
+            assert_eq!(snc_next_chunk(snc, ptr::null(), 0, 2, out.as_mut_ptr()), 2);
+            assert_eq!(&out[..2], &[0, 1]);
+            assert_eq!(snc_next_chunk(snc, b.as_ptr(), b.len(), 3, ptr::null_mut()), 0);
+            snc_free(snc);
+            assert_eq!(snc_next_chunk(ptr::null(), b.as_ptr(), b.len(), 3, out.as_mut_ptr()), 0);
+            snc_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use pyo3::prelude::*;
+
+use diff::AcceptanceEstimator;
+
+
+/// Estimates how many tokens of a predicted chunk will be accepted.
+///
+/// Feed verification outcomes through `record`, then pass the estimator to
+/// `StreamNextChunk.next_chunk_with_estimate`.
+#[pyclass(name = "AcceptanceEstimator", module = "stream_chunk_py")]
+pub struct PyAcceptanceEstimator {
+    pub(crate) inner: AcceptanceEstimator,
+}
+
+#[pymethods]
+impl PyAcceptanceEstimator {
+    /// Creates a new estimator.
+    ///
+    /// Args:
+    ///     smoothing (float): Weight (0..1) of each recorded outcome in the acceptance history.
+    ///     horizon (int): Number of trailing `current_b` tokens inspected for the local edit density.
+    #[new]
+    #[pyo3(signature = (smoothing = 0.2, horizon = 32), text_signature = "(smoothing=0.2, horizon=32)")]
+    fn py_new(smoothing: f64, horizon: usize) -> Self {
+        PyAcceptanceEstimator { inner: AcceptanceEstimator::new(smoothing, horizon) }
+    }
+
+    /// Records that `accepted` of `predicted` speculative tokens were accepted.
+    #[pyo3(text_signature = "(predicted, accepted)")]
+    fn record(&mut self, predicted: usize, accepted: usize) {
+        self.inner.record(predicted, accepted)
+    }
+
+    /// Moving average of accepted / predicted over the recorded outcomes.
+    #[getter]
+    fn history(&self) -> f64 {
+        self.inner.history()
+    }
+}
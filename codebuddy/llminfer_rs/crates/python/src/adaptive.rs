@@ -0,0 +1,137 @@
+use pyo3::prelude::*;
+
+use diff::{AdaptiveChunker, NextChunkOptions, StreamNextChunk};
+
+use crate::tokens::{parse_algorithm, tokens_to_py, with_tokens, DType, Output, PyToken};
+
+
+by_dtype! {
+    /// Concrete instantiations of the generic chunker selected by `dtype`.
+    enum Inner => AdaptiveChunker
+}
+
+fn new_inner<T: PyToken>(
+    a_py: &Bound<'_, PyAny>,
+    options: NextChunkOptions,
+    min_chunk: usize,
+    max_chunk: usize,
+) -> PyResult<AdaptiveChunker<T>> {
+    let streamer = with_tokens(a_py, |a| StreamNextChunk::with_options(a.to_vec(), options))?;
+    Ok(AdaptiveChunker::new(streamer, min_chunk, max_chunk))
+}
+
+fn next_chunk_impl<T: PyToken>(
+    py: Python<'_>,
+    chunker: &AdaptiveChunker<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    output: Output,
+) -> PyResult<PyObject> {
+    let chunk = with_tokens(current_b_py, |current_b| py.allow_threads(|| chunker.next_chunk(current_b)))?;
+    tokens_to_py(py, chunk, output)
+}
+
+fn append_impl<T: PyToken>(chunker: &mut AdaptiveChunker<T>, new_tokens_py: &Bound<'_, PyAny>) -> PyResult<()> {
+    with_tokens(new_tokens_py, |new_tokens| chunker.append(new_tokens))
+}
+
+fn verify_and_advance_impl<T: PyToken>(
+    chunker: &mut AdaptiveChunker<T>,
+    predicted_py: &Bound<'_, PyAny>,
+    actual_py: &Bound<'_, PyAny>,
+) -> PyResult<usize> {
+    with_tokens(predicted_py, |predicted| with_tokens(actual_py, |actual| chunker.verify_and_advance(predicted, actual)))?
+}
+
+fn predict_impl<T: PyToken>(py: Python<'_>, chunker: &mut AdaptiveChunker<T>, output: Output) -> PyResult<PyObject> {
+    let chunk = py.allow_threads(|| chunker.predict());
+    tokens_to_py(py, chunk, output)
+}
+
+
+/// StreamNextChunk that picks its own chunk size.
+///
+/// Report how many predicted tokens were accepted through `record`; the
+/// chunk size doubles after a fully accepted chunk and halves when less than
+/// half of a chunk was accepted, staying within `[min_chunk, max_chunk]`.
+#[pyclass(name = "AdaptiveChunker", module = "stream_chunk_py")]
+pub struct PyAdaptiveChunker {
+    inner: Inner,
+}
+
+#[pymethods]
+impl PyAdaptiveChunker {
+    /// Args:
+    ///     a (list[int] | numpy.ndarray): The reference sequence.
+    ///     min_chunk (int): Smallest (and initial) chunk size.
+    ///     max_chunk (int): Largest chunk size.
+    ///     dtype (str): Token id type, one of "int32" (default), "uint32" or "int64".
+    ///     algorithm (str): Diff algorithm, one of "histogram" (default), "myers" or "myers_minimal".
+    #[new]
+    #[pyo3(
+        signature = (a, min_chunk = 1, max_chunk = 64, dtype = "int32", algorithm = "histogram"),
+        text_signature = "(a, min_chunk=1, max_chunk=64, dtype='int32', algorithm='histogram')"
+    )]
+    fn py_new(a: &Bound<'_, PyAny>, min_chunk: usize, max_chunk: usize, dtype: &str, algorithm: &str) -> PyResult<Self> {
+        let options = NextChunkOptions { algorithm: parse_algorithm(algorithm)?, ..Default::default() };
+        let inner = new_by_dtype!(Inner, DType::parse(dtype)?, T => new_inner::<T>(a, options, min_chunk, max_chunk)?);
+        Ok(PyAdaptiveChunker { inner })
+    }
+
+    /// The token id type this instance was created with.
+    #[getter]
+    fn dtype(&self) -> &'static str {
+        self.inner.dtype().as_str()
+    }
+
+    /// The chunk size the next prediction will use.
+    #[getter]
+    fn chunk_size(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.chunk_size())
+    }
+
+    /// `(predicted, accepted)` token totals over all recorded calls.
+    #[getter]
+    fn totals(&self) -> (usize, usize) {
+        dispatch!(Inner, &self.inner, s => s.totals())
+    }
+
+    /// Predicts the next chunk of `a` following `current_b` with the current chunk size.
+    #[pyo3(signature = (current_b, output = "list"), text_signature = "(current_b, output='list')")]
+    fn next_chunk(&self, py: Python<'_>, current_b: &Bound<'_, PyAny>, output: &str) -> PyResult<PyObject> {
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &self.inner, s => next_chunk_impl(py, s, current_b, output))
+    }
+
+    /// Appends newly generated tokens to the internally tracked sequence.
+    #[pyo3(text_signature = "(new_tokens)")]
+    fn append(&mut self, new_tokens: &Bound<'_, PyAny>) -> PyResult<()> {
+        dispatch!(Inner, &mut self.inner, s => append_impl(s, new_tokens))
+    }
+
+    /// Predicts the next chunk for the tokens fed through `append`.
+    #[pyo3(signature = (output = "list"), text_signature = "(output='list')")]
+    fn predict(&mut self, py: Python<'_>, output: &str) -> PyResult<PyObject> {
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &mut self.inner, s => predict_impl(py, s, output))
+    }
+
+    /// Records that `accepted` of `predicted` tokens were accepted and
+    /// adjusts the chunk size.
+    #[pyo3(text_signature = "(predicted, accepted)")]
+    fn record(&mut self, predicted: usize, accepted: usize) {
+        dispatch!(Inner, &mut self.inner, s => s.record(predicted, accepted))
+    }
+
+    /// Compares `predicted` with the tokens actually generated, appends the
+    /// accepted prefix (at least one token), records the outcome like
+    /// `record` and returns the number of accepted tokens.
+    #[pyo3(text_signature = "(predicted, actual)")]
+    fn verify_and_advance(&mut self, predicted: &Bound<'_, PyAny>, actual: &Bound<'_, PyAny>) -> PyResult<usize> {
+        dispatch!(Inner, &mut self.inner, s => verify_and_advance_impl(s, predicted, actual))
+    }
+
+    /// Forgets appended tokens, totals and the learned chunk size.
+    fn reset(&mut self) {
+        dispatch!(Inner, &mut self.inner, s => s.reset())
+    }
+}
@@ -0,0 +1,84 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
+
+use diff::{apply_edits, edit_script, DiffAlgorithm, Edit};
+
+use crate::tokens::{parse_algorithm, tokens_to_py, with_tokens, DType, Output, PyToken};
+
+
+/// An edit as handed to/from Python: `((a_start, a_end), (b_start, b_end), replacement)`.
+type PyEdit<'py> = ((u32, u32), (u32, u32), Bound<'py, PyAny>);
+
+fn edit_script_impl<T: PyToken>(
+    py: Python<'_>,
+    a: &Bound<'_, PyAny>,
+    b: &Bound<'_, PyAny>,
+    algorithm: DiffAlgorithm,
+    output: Output,
+) -> PyResult<Vec<PyObject>> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    let edits = with_tokens(b, |b| py.allow_threads(|| edit_script(&a, b, algorithm)))?;
+    edits
+        .into_iter()
+        .map(|edit| {
+            let replacement = tokens_to_py(py, &edit.replacement, output)?;
+            ((edit.before.start, edit.before.end), (edit.after.start, edit.after.end), replacement).into_py_any(py)
+        })
+        .collect()
+}
+
+fn apply_impl<T: PyToken>(py: Python<'_>, a: &Bound<'_, PyAny>, edits: Vec<PyEdit<'_>>, output: Output) -> PyResult<PyObject> {
+    let edits = edits
+        .into_iter()
+        .map(|(before, after, replacement)| {
+            let replacement = with_tokens(&replacement, <[T]>::to_vec)?;
+            Ok(Edit { before: before.0..before.1, after: after.0..after.1, replacement })
+        })
+        .collect::<PyResult<Vec<Edit<T>>>>()?;
+    let b = with_tokens(a, |a| py.allow_threads(|| apply_edits(a, &edits)))?
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    tokens_to_py(py, &b, output)
+}
+
+/// Builds the edit script turning `a` into `b`.
+///
+/// Returns:
+///     list[tuple[tuple[int, int], tuple[int, int], list[int]]]: One
+///     `((a_start, a_end), (b_start, b_end), replacement)` per change; feed it to `apply_edits`.
+#[pyfunction(name = "edit_script")]
+#[pyo3(
+    signature = (a, b, dtype = "int32", algorithm = "histogram", output = "list"),
+    text_signature = "(a, b, dtype='int32', algorithm='histogram', output='list')"
+)]
+pub fn py_edit_script(
+    py: Python<'_>,
+    a: &Bound<'_, PyAny>,
+    b: &Bound<'_, PyAny>,
+    dtype: &str,
+    algorithm: &str,
+    output: &str,
+) -> PyResult<Vec<PyObject>> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let output = Output::parse(output)?;
+    match DType::parse(dtype)? {
+        DType::I32 => edit_script_impl::<i32>(py, a, b, algorithm, output),
+        DType::U32 => edit_script_impl::<u32>(py, a, b, algorithm, output),
+        DType::I64 => edit_script_impl::<i64>(py, a, b, algorithm, output),
+    }
+}
+
+/// Applies an edit script to `a`, reconstructing `b`.
+///
+/// Raises `ValueError` describing the offending edit when the edits are
+/// unordered, out of bounds or inconsistent with their `b` ranges.
+#[pyfunction(name = "apply_edits")]
+#[pyo3(signature = (a, edits, dtype = "int32", output = "list"), text_signature = "(a, edits, dtype='int32', output='list')")]
+pub fn py_apply_edits(py: Python<'_>, a: &Bound<'_, PyAny>, edits: Vec<PyEdit<'_>>, dtype: &str, output: &str) -> PyResult<PyObject> {
+    let output = Output::parse(output)?;
+    match DType::parse(dtype)? {
+        DType::I32 => apply_impl::<i32>(py, a, edits, output),
+        DType::U32 => apply_impl::<u32>(py, a, edits, output),
+        DType::I64 => apply_impl::<i64>(py, a, edits, output),
+    }
+}
@@ -0,0 +1,97 @@
+//! Zero-copy reads of Arrow arrays through the Arrow C data interface, as
+//! exported by `pyarrow.Array.__arrow_c_array__` (and polars, nanoarrow, ...).
+
+use std::any::TypeId;
+use std::ffi::{c_char, c_void, CStr};
+use std::slice;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyCapsule, PyTuple};
+
+
+/// `struct ArrowSchema` of the C data interface.
+#[repr(C)]
+struct ArrowSchema {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut ArrowSchema,
+    dictionary: *mut ArrowSchema,
+    release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    private_data: *mut c_void,
+}
+
+/// `struct ArrowArray` of the C data interface.
+#[repr(C)]
+struct ArrowArray {
+    length: i64,
+    null_count: i64,
+    offset: i64,
+    n_buffers: i64,
+    n_children: i64,
+    buffers: *mut *const c_void,
+    children: *mut *mut ArrowArray,
+    dictionary: *mut ArrowArray,
+    release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    private_data: *mut c_void,
+}
+
+/// Arrow format string of the token type `T`.
+fn format_of<T: 'static>() -> Option<&'static str> {
+    [(TypeId::of::<i32>(), "i"), (TypeId::of::<u32>(), "I"), (TypeId::of::<i64>(), "l")]
+        .into_iter()
+        .find_map(|(id, format)| (id == TypeId::of::<T>()).then_some(format))
+}
+
+/// Whether `obj` exports itself through the Arrow PyCapsule interface.
+pub fn is_arrow_array(obj: &Bound<'_, PyAny>) -> bool {
+    obj.hasattr("__arrow_c_array__").unwrap_or(false)
+}
+
+/// The capsule of `capsules` named `name`, checked to hold a pointer.
+fn capsule<'py>(capsules: &Bound<'py, PyTuple>, index: usize, name: &CStr) -> PyResult<Bound<'py, PyCapsule>> {
+    let capsule = capsules.get_item(index)?.downcast_into::<PyCapsule>()?;
+    if capsule.name()? != Some(name) || capsule.pointer().is_null() {
+        return Err(PyValueError::new_err(format!("__arrow_c_array__ returned no {name:?} capsule")));
+    }
+    Ok(capsule)
+}
+
+/// Calls `f` with the values of a null-free primitive Arrow array of `T`,
+/// read in place from its data buffer.
+pub fn with_arrow_tokens<T: 'static, R>(obj: &Bound<'_, PyAny>, f: impl FnOnce(&[T]) -> R) -> PyResult<R> {
+    // The capsules own the exported structs and release them when dropped,
+    // so the buffers stay valid for as long as `capsules` is alive
+    let capsules = obj.call_method0("__arrow_c_array__")?.downcast_into::<PyTuple>()?;
+    let schema = capsule(&capsules, 0, c"arrow_schema")?;
+    let array = capsule(&capsules, 1, c"arrow_array")?;
+    // SAFETY: the capsule names guarantee the pointed-to structs per the
+    // Arrow PyCapsule interface, and they're alive until the capsules drop
+    let (schema, array) = unsafe { (&*schema.pointer().cast::<ArrowSchema>(), &*array.pointer().cast::<ArrowArray>()) };
+    // SAFETY: `format` is a required, NUL-terminated field of the schema
+    let format = unsafe { CStr::from_ptr(schema.format) }.to_string_lossy();
+    if Some(format.as_ref()) != format_of::<T>() {
+        return Err(PyValueError::new_err(format!(
+            "arrow array of format {format:?} doesn't match the dtype, expected {:?}",
+            format_of::<T>().unwrap_or("?")
+        )));
+    }
+    if array.n_buffers != 2 || array.length < 0 || array.offset < 0 {
+        return Err(PyValueError::new_err("malformed arrow array"));
+    }
+    // SAFETY: primitive arrays have a validity and a data buffer
+    let (validity, data) = unsafe { (*array.buffers, *array.buffers.add(1)) };
+    if array.null_count != 0 && !validity.is_null() {
+        return Err(PyValueError::new_err("arrow array contains nulls"));
+    }
+    let tokens = match array.length {
+        0 => &[][..],
+        // SAFETY: the data buffer holds `offset + length` values of the
+        // format's type, which is `T`
+        len => unsafe { slice::from_raw_parts(data.cast::<T>().add(array.offset as usize), len as usize) },
+    };
+    Ok(f(tokens))
+}
@@ -0,0 +1,57 @@
+use std::sync::OnceLock;
+
+use pyo3::prelude::*;
+
+
+/// Threads running the `async_*` methods. Separate from rayon's global pool,
+/// which the diffs themselves use, so workers waiting on the GIL don't hold
+/// up a parallel multi-window search.
+fn pool() -> &'static rayon::ThreadPool {
+    static POOL: OnceLock<rayon::ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        rayon::ThreadPoolBuilder::new()
+            .thread_name(|i| format!("llminfer-async-{i}"))
+            .build()
+            .expect("failed to start the async worker pool")
+    })
+}
+
+/// Completes `future` with `result` or `error`, unless it was cancelled meanwhile.
+#[pyfunction]
+#[pyo3(signature = (future, result, error = None))]
+fn resolve(future: &Bound<'_, PyAny>, result: PyObject, error: Option<PyObject>) -> PyResult<()> {
+    if future.call_method0("done")?.is_truthy()? {
+        return Ok(());
+    }
+    match error {
+        Some(error) => future.call_method1("set_exception", (error,))?,
+        None => future.call_method1("set_result", (result,))?,
+    };
+    Ok(())
+}
+
+/// Runs `f` on the worker pool and returns an asyncio future of its result,
+/// bound to the running event loop. `f` gets the GIL and should release it
+/// for the heavy part.
+pub fn spawn<F>(py: Python<'_>, f: F) -> PyResult<PyObject>
+where
+    F: FnOnce(Python<'_>) -> PyResult<PyObject> + Send + 'static,
+{
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    let future = event_loop.call_method0("create_future")?;
+    let (event_loop, future_ref) = (event_loop.unbind(), future.clone().unbind());
+    pool().spawn(move || {
+        Python::with_gil(|py| {
+            let (result, error) = match f(py) {
+                Ok(result) => (result, None),
+                Err(err) => (py.None(), Some(err.into_value(py).into_any())),
+            };
+            // Fails when the loop was closed before the call completed, so
+            // nobody awaits the future anymore
+            let _ = wrap_pyfunction!(resolve, py).and_then(|resolve| {
+                event_loop.call_method1(py, "call_soon_threadsafe", (resolve, future_ref, result, error))
+            });
+        })
+    });
+    Ok(future.unbind())
+}
@@ -0,0 +1,160 @@
+use pyo3::exceptions::asyncio::CancelledError;
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+
+use diff::{BatchNextChunk, CancellationToken, NextChunkOptions, StreamNextChunk};
+
+use crate::cancel::PyCancellationToken;
+use crate::tokens::{parse_algorithm, tokens_to_py, with_tokens, DType, Output, PyToken};
+
+
+by_dtype! {
+    /// Concrete instantiations of the generic batch selected by `dtype`.
+    enum Inner => BatchNextChunk
+}
+
+fn new_inner<T: PyToken>(references: &Bound<'_, PyAny>, options: NextChunkOptions) -> PyResult<BatchNextChunk<T>> {
+    let references = references
+        .try_iter()?
+        .map(|reference| with_tokens(&reference?, <[T]>::to_vec))
+        .collect::<PyResult<Vec<Vec<T>>>>()?;
+    Ok(BatchNextChunk::new(references, options))
+}
+
+fn add_impl<T: PyToken>(batch: &mut BatchNextChunk<T>, reference: &Bound<'_, PyAny>) -> PyResult<usize> {
+    let options = batch.get(0).map(|s| s.options().clone()).unwrap_or_default();
+    let streamer = with_tokens(reference, |a| StreamNextChunk::with_options(a.to_vec(), options))?;
+    Ok(batch.push(streamer))
+}
+
+fn next_chunk_batch_impl<'py, T: PyToken>(
+    py: Python<'py>,
+    batch: &BatchNextChunk<T>,
+    bs: &Bound<'py, PyAny>,
+    chunk_size: usize,
+    indices: Option<Vec<usize>>,
+    output: Output,
+    cancel: Option<CancellationToken>,
+) -> PyResult<Bound<'py, PyList>> {
+    let bs = bs
+        .try_iter()?
+        .map(|b| with_tokens(&b?, <[T]>::to_vec))
+        .collect::<PyResult<Vec<Vec<T>>>>()?;
+    let b_slices: Vec<&[T]> = bs.iter().map(Vec::as_slice).collect();
+
+    let chunks = match indices {
+        Some(indices) => {
+            if indices.len() != b_slices.len() {
+                return Err(PyValueError::new_err(format!(
+                    "got {} sequences for {} indices",
+                    b_slices.len(),
+                    indices.len()
+                )));
+            }
+            if let Some(&index) = indices.iter().find(|&&index| index >= batch.len()) {
+                return Err(PyIndexError::new_err(format!("stream index {index} out of range")));
+            }
+            let requests: Vec<(usize, &[T])> = indices.into_iter().zip(b_slices).collect();
+            py.allow_threads(|| match &cancel {
+                Some(cancel) => batch.next_chunk_batch_at_cancellable(&requests, chunk_size, cancel),
+                None => Ok(batch.next_chunk_batch_at(&requests, chunk_size)),
+            })
+        }
+        None => {
+            if b_slices.len() != batch.len() {
+                return Err(PyValueError::new_err(format!(
+                    "expected one sequence per stream ({}), got {}",
+                    batch.len(),
+                    b_slices.len()
+                )));
+            }
+            py.allow_threads(|| match &cancel {
+                Some(cancel) => batch.next_chunk_batch_cancellable(&b_slices, chunk_size, cancel),
+                None => Ok(batch.next_chunk_batch(&b_slices, chunk_size)),
+            })
+        }
+    };
+    let chunks = chunks.map_err(|cancelled| CancelledError::new_err(cancelled.to_string()))?;
+
+    let chunks = chunks
+        .into_iter()
+        .map(|chunk| tokens_to_py(py, chunk, output))
+        .collect::<PyResult<Vec<PyObject>>>()?;
+    PyList::new(py, chunks)
+}
+
+
+/// Predicts next chunks for many streams in one call.
+///
+/// Holds one reference per stream; `next_chunk_batch` runs the per-stream
+/// diffs in parallel on a Rust thread pool with the GIL released.
+#[pyclass(name = "BatchStreamNextChunk", module = "stream_chunk_py")]
+pub struct PyBatchStreamNextChunk {
+    inner: Inner,
+}
+
+#[pymethods]
+impl PyBatchStreamNextChunk {
+    /// Args:
+    ///     references (Iterable[list[int] | numpy.ndarray]): One reference sequence per stream.
+    ///     dtype (str): Token id type, one of "int32" (default), "uint32" or "int64".
+    ///     algorithm (str): Diff algorithm, one of "histogram" (default), "myers" or "myers_minimal".
+    #[new]
+    #[pyo3(signature = (references, dtype = "int32", algorithm = "histogram"), text_signature = "(references, dtype='int32', algorithm='histogram')")]
+    fn py_new(references: &Bound<'_, PyAny>, dtype: &str, algorithm: &str) -> PyResult<Self> {
+        let options = NextChunkOptions { algorithm: parse_algorithm(algorithm)?, ..Default::default() };
+        let inner = new_by_dtype!(Inner, DType::parse(dtype)?, T => new_inner::<T>(references, options)?);
+        Ok(PyBatchStreamNextChunk { inner })
+    }
+
+    fn __len__(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.len())
+    }
+
+    /// The token id type this batch was created with.
+    #[getter]
+    fn dtype(&self) -> &'static str {
+        self.inner.dtype().as_str()
+    }
+
+    /// Adds a stream with the given reference and returns its index.
+    #[pyo3(text_signature = "(reference)")]
+    fn add(&mut self, reference: &Bound<'_, PyAny>) -> PyResult<usize> {
+        dispatch!(Inner, &mut self.inner, s => add_impl(s, reference))
+    }
+
+    /// Predicts the next chunk for several streams at once.
+    ///
+    /// Args:
+    ///     list_of_b: Sequence received so far on each stream, in stream order.
+    ///     chunk_size (int): Maximum size of each returned chunk.
+    ///     indices (list[int] | None): Stream index of each entry in `list_of_b`,
+    ///         to predict for a subset of streams. Defaults to all streams in order.
+    ///     output (str): "list" (default) or "numpy".
+    ///     cancel (CancellationToken | None): Aborts the batch once cancelled, e.g.
+    ///         from another thread when the client disconnected.
+    ///
+    /// Returns:
+    ///     list: One predicted chunk per entry in `list_of_b`.
+    ///
+    /// Raises:
+    ///     asyncio.CancelledError: `cancel` was cancelled before the batch finished.
+    #[pyo3(
+        signature = (list_of_b, chunk_size, indices = None, output = "list", cancel = None),
+        text_signature = "(list_of_b, chunk_size, indices=None, output='list', cancel=None)"
+    )]
+    fn next_chunk_batch<'py>(
+        &self,
+        py: Python<'py>,
+        list_of_b: &Bound<'py, PyAny>,
+        chunk_size: usize,
+        indices: Option<Vec<usize>>,
+        output: &str,
+        cancel: Option<&Bound<'py, PyCancellationToken>>,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let output = Output::parse(output)?;
+        let cancel = cancel.map(|cancel| cancel.get().inner.clone());
+        dispatch!(Inner, &self.inner, s => next_chunk_batch_impl(py, s, list_of_b, chunk_size, indices, output, cancel))
+    }
+}
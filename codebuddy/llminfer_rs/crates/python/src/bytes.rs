@@ -0,0 +1,79 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString};
+
+use diff::{NextChunkOptions, StreamNextChunkBytes};
+
+use crate::tokens::{parse_algorithm, parse_matcher};
+
+
+/// Calls `f` with the bytes of `obj`: `bytes` are read in place, `str` as its
+/// UTF-8 encoding, anything else (`bytearray`, `memoryview`, ...) is copied first.
+pub fn with_bytes<R>(obj: &Bound<'_, PyAny>, f: impl FnOnce(&[u8]) -> R) -> PyResult<R> {
+    if let Ok(bytes) = obj.downcast::<PyBytes>() {
+        return Ok(f(bytes.as_bytes()));
+    }
+    if let Ok(text) = obj.downcast::<PyString>() {
+        return Ok(f(text.to_str()?.as_bytes()));
+    }
+    let bytes: Vec<u8> = obj.extract()?;
+    Ok(f(&bytes))
+}
+
+
+/// `StreamNextChunk` over raw bytes, for models with byte-level vocabularies.
+///
+/// Takes `bytes` (or `str`, matched as UTF-8) and returns `bytes`; a predicted
+/// chunk may end inside a multi-byte character. Same threading rules as
+/// `StreamNextChunk`.
+#[pyclass(name = "StreamNextChunkBytes", module = "stream_chunk_py")]
+pub struct PyStreamNextChunkBytes {
+    inner: StreamNextChunkBytes,
+}
+
+#[pymethods]
+impl PyStreamNextChunkBytes {
+    /// Args:
+    ///     a (bytes | str): The reference, e.g. the original file content.
+    ///     algorithm, matcher, min_match_len, max_mismatches: As for `StreamNextChunk`.
+    #[new]
+    #[pyo3(
+        signature = (a, algorithm = "histogram", matcher = "diff", min_match_len = 1, max_mismatches = 0),
+        text_signature = "(a, algorithm='histogram', matcher='diff', min_match_len=1, max_mismatches=0)"
+    )]
+    fn py_new(a: &Bound<'_, PyAny>, algorithm: &str, matcher: &str, min_match_len: usize, max_mismatches: usize) -> PyResult<Self> {
+        let options = NextChunkOptions {
+            algorithm: parse_algorithm(algorithm)?,
+            matcher: parse_matcher(matcher)?,
+            min_match_len,
+            max_mismatches,
+            ..Default::default()
+        };
+        let inner = with_bytes(a, |a| StreamNextChunkBytes::with_options(a.to_vec(), options))?;
+        Ok(PyStreamNextChunkBytes { inner })
+    }
+
+    /// Predicts the next `chunk_size` bytes of `a` following `current_b`.
+    #[pyo3(text_signature = "(current_b, chunk_size)")]
+    fn next_chunk<'py>(&self, py: Python<'py>, current_b: &Bound<'py, PyAny>, chunk_size: usize) -> PyResult<Bound<'py, PyBytes>> {
+        let chunk = with_bytes(current_b, |current_b| py.allow_threads(|| self.inner.next_chunk(current_b, chunk_size)))?;
+        Ok(PyBytes::new(py, chunk))
+    }
+
+    /// Feeds newly generated bytes to the stateful API.
+    #[pyo3(text_signature = "(new_bytes)")]
+    fn append(&mut self, new_bytes: &Bound<'_, PyAny>) -> PyResult<()> {
+        with_bytes(new_bytes, |new_bytes| self.inner.append(new_bytes))
+    }
+
+    /// Predicts the next chunk from everything passed to `append` so far.
+    #[pyo3(text_signature = "(chunk_size)")]
+    fn predict<'py>(&mut self, py: Python<'py>, chunk_size: usize) -> Bound<'py, PyBytes> {
+        let chunk = py.allow_threads(|| self.inner.predict(chunk_size));
+        PyBytes::new(py, chunk)
+    }
+
+    /// Forgets the appended bytes, keeping the reference.
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
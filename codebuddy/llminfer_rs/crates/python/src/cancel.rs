@@ -0,0 +1,35 @@
+use pyo3::prelude::*;
+
+use diff::CancellationToken;
+
+
+/// Handle to abort predictions in flight, e.g. when the client disconnected.
+///
+/// Pass it as `cancel` to `BatchStreamNextChunk.next_chunk_batch` and call
+/// `cancel()` from another thread (or the event loop, for a batch run in an
+/// executor): the batch starts no further diff and raises
+/// `asyncio.CancelledError`. A token stays cancelled once cancelled.
+#[pyclass(name = "CancellationToken", module = "stream_chunk_py", frozen)]
+#[derive(Default)]
+pub struct PyCancellationToken {
+    pub(crate) inner: CancellationToken,
+}
+
+#[pymethods]
+impl PyCancellationToken {
+    #[new]
+    fn py_new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the predictions using this token to stop.
+    fn cancel(&self) {
+        self.inner.cancel()
+    }
+
+    /// Whether `cancel()` was called.
+    #[getter]
+    fn cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+}
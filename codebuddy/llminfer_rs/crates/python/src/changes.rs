@@ -0,0 +1,68 @@
+use std::ops::Range;
+
+use pyo3::prelude::*;
+
+use diff::{diff_changes, find_matches, DiffAlgorithm};
+
+use crate::tokens::{parse_algorithm, with_tokens, DType, PyToken};
+
+
+/// A `(before, after)` pair of half-open ranges as handed to Python.
+pub(crate) type Change = ((u32, u32), (u32, u32));
+
+pub(crate) fn to_py_changes<'a>(ranges: impl IntoIterator<Item = &'a (Range<u32>, Range<u32>)>) -> Vec<Change> {
+    ranges.into_iter().map(|(before, after)| ((before.start, before.end), (after.start, after.end))).collect()
+}
+
+fn diff_impl<T: PyToken>(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, algorithm: DiffAlgorithm) -> PyResult<Vec<Change>> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    let changes = with_tokens(b, |b| py.allow_threads(|| diff_changes(&a, b, algorithm)))?;
+    Ok(to_py_changes(&changes))
+}
+
+/// Diffs two token sequences and returns the changes.
+///
+/// Args:
+///     a (list[int] | numpy.ndarray): The "before" sequence.
+///     b (list[int] | numpy.ndarray): The "after" sequence.
+///     dtype (str): Token id type, one of "int32" (default), "uint32" or "int64".
+///     algorithm (str): Diff algorithm, one of "histogram" (default), "myers" or "myers_minimal".
+///
+/// Returns:
+///     list[tuple[tuple[int, int], tuple[int, int]]]: `((a_start, a_end), (b_start, b_end))`
+///     half-open ranges for every change, in order.
+#[pyfunction(name = "diff")]
+#[pyo3(signature = (a, b, dtype = "int32", algorithm = "histogram"), text_signature = "(a, b, dtype='int32', algorithm='histogram')")]
+pub fn py_diff(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, dtype: &str, algorithm: &str) -> PyResult<Vec<Change>> {
+    let algorithm = parse_algorithm(algorithm)?;
+    match DType::parse(dtype)? {
+        DType::I32 => diff_impl::<i32>(py, a, b, algorithm),
+        DType::U32 => diff_impl::<u32>(py, a, b, algorithm),
+        DType::I64 => diff_impl::<i64>(py, a, b, algorithm),
+    }
+}
+
+fn find_matches_impl<T: PyToken>(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, algorithm: DiffAlgorithm) -> PyResult<Vec<Change>> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    let matches = with_tokens(b, |b| py.allow_threads(|| find_matches(&a, b, algorithm)))?;
+    Ok(to_py_changes(&matches))
+}
+
+/// Diffs two token sequences and returns the runs they have in common.
+///
+/// Args:
+///     a, b, dtype, algorithm: As for `diff`.
+///
+/// Returns:
+///     list[tuple[tuple[int, int], tuple[int, int]]]: `((a_start, a_end), (b_start, b_end))`
+///     half-open ranges of equal length with `a[a_start:a_end] == b[b_start:b_end]`, in order.
+#[pyfunction(name = "find_matches")]
+#[pyo3(signature = (a, b, dtype = "int32", algorithm = "histogram"), text_signature = "(a, b, dtype='int32', algorithm='histogram')")]
+pub fn py_find_matches(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, dtype: &str, algorithm: &str) -> PyResult<Vec<Change>> {
+    let algorithm = parse_algorithm(algorithm)?;
+    match DType::parse(dtype)? {
+        DType::I32 => find_matches_impl::<i32>(py, a, b, algorithm),
+        DType::U32 => find_matches_impl::<u32>(py, a, b, algorithm),
+        DType::I64 => find_matches_impl::<i64>(py, a, b, algorithm),
+    }
+}
@@ -0,0 +1,93 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use diff::{DetokenizedNextChunk, NextChunkOptions, Vocab};
+
+use crate::bytes::with_bytes;
+use crate::tokens::{parse_algorithm, parse_matcher, with_tokens};
+
+
+/// Reads a `{id: bytes | str}` vocabulary.
+fn vocab_from_py(vocab: &Bound<'_, PyDict>) -> PyResult<Vocab<u32>> {
+    let entries = vocab
+        .iter()
+        .map(|(id, bytes)| Ok((id.extract::<u32>()?, with_bytes(&bytes, <[u8]>::to_vec)?)))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(Vocab::new(entries))
+}
+
+
+/// Next-chunk prediction matching on decoded bytes rather than token ids, so
+/// a reference tokenized with another tokenizer version (or given as text)
+/// still anchors.
+///
+/// `current_b` is decoded with `vocab` and predictions are tokenized back
+/// with it, greedily taking the longest token at each position, which may
+/// differ from the tokenizer's own encoding on some text.
+#[pyclass(name = "DetokenizedNextChunk", module = "stream_chunk_py")]
+pub struct PyDetokenizedNextChunk {
+    inner: DetokenizedNextChunk<u32>,
+}
+
+#[pymethods]
+impl PyDetokenizedNextChunk {
+    /// Args:
+    ///     a (bytes | str | list[int]): The reference: bytes or text, or token ids
+    ///         decoded with `a_vocab`.
+    ///     vocab (dict[int, bytes | str]): The bytes each token id of the current
+    ///         tokenizer decodes to.
+    ///     a_vocab (dict[int, bytes | str] | None): The vocabulary `a` was tokenized
+    ///         with, e.g. a previous tokenizer version. Without it `a` is read as bytes.
+    ///     algorithm, matcher, min_match_len, max_mismatches: As for `StreamNextChunk`.
+    #[new]
+    #[pyo3(
+        signature = (a, vocab, a_vocab = None, algorithm = "histogram", matcher = "diff", min_match_len = 1, max_mismatches = 0),
+        text_signature = "(a, vocab, a_vocab=None, algorithm='histogram', matcher='diff', min_match_len=1, max_mismatches=0)"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        a: &Bound<'_, PyAny>,
+        vocab: &Bound<'_, PyDict>,
+        a_vocab: Option<&Bound<'_, PyDict>>,
+        algorithm: &str,
+        matcher: &str,
+        min_match_len: usize,
+        max_mismatches: usize,
+    ) -> PyResult<Self> {
+        let options = NextChunkOptions {
+            algorithm: parse_algorithm(algorithm)?,
+            matcher: parse_matcher(matcher)?,
+            min_match_len,
+            max_mismatches,
+            ..Default::default()
+        };
+        let vocab = vocab_from_py(vocab)?;
+        let inner = match a_vocab {
+            Some(a_vocab) => {
+                let a_vocab = vocab_from_py(a_vocab)?;
+                with_tokens(a, |a: &[u32]| DetokenizedNextChunk::from_tokens(a, &a_vocab, vocab, options))?
+            }
+            None => with_bytes(a, |a| DetokenizedNextChunk::with_options(a.to_vec(), vocab, options))?,
+        };
+        Ok(PyDetokenizedNextChunk { inner })
+    }
+
+    /// The reference as bytes.
+    #[getter]
+    fn reference<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.inner.reference())
+    }
+
+    /// Predicts the next `chunk_size` tokens (current vocabulary) following `current_b`.
+    #[pyo3(text_signature = "(current_b, chunk_size)")]
+    fn next_chunk(&self, py: Python<'_>, current_b: &Bound<'_, PyAny>, chunk_size: usize) -> PyResult<Vec<u32>> {
+        with_tokens(current_b, |current_b: &[u32]| py.allow_threads(|| self.inner.next_chunk(current_b, chunk_size)))
+    }
+
+    /// Predicts up to `max_bytes` bytes of the reference following `current_b`.
+    #[pyo3(text_signature = "(current_b, max_bytes)")]
+    fn next_chunk_bytes<'py>(&self, py: Python<'py>, current_b: &Bound<'py, PyAny>, max_bytes: usize) -> PyResult<Bound<'py, PyBytes>> {
+        let chunk = with_tokens(current_b, |current_b: &[u32]| py.allow_threads(|| self.inner.next_chunk_bytes(current_b, max_bytes)))?;
+        Ok(PyBytes::new(py, chunk))
+    }
+}
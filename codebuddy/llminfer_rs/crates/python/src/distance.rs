@@ -0,0 +1,44 @@
+use pyo3::prelude::*;
+
+use diff::{edit_distance, edit_distance_within, DiffAlgorithm};
+
+use crate::tokens::{parse_algorithm, with_tokens, DType, PyToken};
+
+
+fn edit_distance_impl<T: PyToken>(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, algorithm: DiffAlgorithm) -> PyResult<usize> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    with_tokens(b, |b| py.allow_threads(|| edit_distance(&a, b, algorithm)))
+}
+
+fn edit_distance_within_impl<T: PyToken>(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, k: usize) -> PyResult<Option<usize>> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    with_tokens(b, |b| py.allow_threads(|| edit_distance_within(&a, b, k)))
+}
+
+/// Token-level edit distance (deleted plus inserted tokens) computed from the diff.
+///
+/// Exactly minimal with `algorithm="myers_minimal"`.
+#[pyfunction(name = "edit_distance")]
+#[pyo3(signature = (a, b, dtype = "int32", algorithm = "histogram"), text_signature = "(a, b, dtype='int32', algorithm='histogram')")]
+pub fn py_edit_distance(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, dtype: &str, algorithm: &str) -> PyResult<usize> {
+    let algorithm = parse_algorithm(algorithm)?;
+    match DType::parse(dtype)? {
+        DType::I32 => edit_distance_impl::<i32>(py, a, b, algorithm),
+        DType::U32 => edit_distance_impl::<u32>(py, a, b, algorithm),
+        DType::I64 => edit_distance_impl::<i64>(py, a, b, algorithm),
+    }
+}
+
+/// The minimal edit distance between `a` and `b` if it is at most `k`, else None.
+///
+/// Stops as soon as the distance is known to exceed `k`, so it costs
+/// O(len * k) instead of a full diff.
+#[pyfunction(name = "edit_distance_within")]
+#[pyo3(signature = (a, b, k, dtype = "int32"), text_signature = "(a, b, k, dtype='int32')")]
+pub fn py_edit_distance_within(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, k: usize, dtype: &str) -> PyResult<Option<usize>> {
+    match DType::parse(dtype)? {
+        DType::I32 => edit_distance_within_impl::<i32>(py, a, b, k),
+        DType::U32 => edit_distance_within_impl::<u32>(py, a, b, k),
+        DType::I64 => edit_distance_within_impl::<i64>(py, a, b, k),
+    }
+}
@@ -0,0 +1,117 @@
+//! Zero-copy reads of CPU tensors through DLPack, as exported by
+//! `torch.Tensor.__dlpack__` (and jax, cupy on the host, ...).
+
+use std::any::TypeId;
+use std::ffi::c_void;
+use std::slice;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::PyCapsule;
+
+
+/// `kDLCPU` device type.
+const DL_CPU: i32 = 1;
+/// `kDLInt` type code.
+const DL_INT: u8 = 0;
+/// `kDLUInt` type code.
+const DL_UINT: u8 = 1;
+
+#[repr(C)]
+struct DLDevice {
+    device_type: i32,
+    device_id: i32,
+}
+
+#[repr(C)]
+struct DLDataType {
+    code: u8,
+    bits: u8,
+    lanes: u16,
+}
+
+#[repr(C)]
+struct DLTensor {
+    data: *mut c_void,
+    device: DLDevice,
+    ndim: i32,
+    dtype: DLDataType,
+    shape: *mut i64,
+    strides: *mut i64,
+    byte_offset: u64,
+}
+
+#[repr(C)]
+struct DLManagedTensor {
+    dl_tensor: DLTensor,
+    manager_ctx: *mut c_void,
+    deleter: Option<unsafe extern "C" fn(*mut DLManagedTensor)>,
+}
+
+/// Hands the tensor back to its producer when dropped.
+struct Consumed(*mut DLManagedTensor);
+
+impl Drop for Consumed {
+    fn drop(&mut self) {
+        // SAFETY: the tensor was taken out of its capsule, so it's released once, here
+        unsafe {
+            if let Some(deleter) = (*self.0).deleter {
+                deleter(self.0)
+            }
+        }
+    }
+}
+
+/// DLPack type code and width of the token type `T`.
+fn dtype_of<T: 'static>() -> Option<(u8, u8)> {
+    [(TypeId::of::<i32>(), (DL_INT, 32)), (TypeId::of::<u32>(), (DL_UINT, 32)), (TypeId::of::<i64>(), (DL_INT, 64))]
+        .into_iter()
+        .find_map(|(id, dtype)| (id == TypeId::of::<T>()).then_some(dtype))
+}
+
+/// Whether `obj` exports itself through DLPack, e.g. a torch tensor.
+pub fn is_dlpack_tensor(obj: &Bound<'_, PyAny>) -> bool {
+    obj.hasattr("__dlpack__").unwrap_or(false)
+}
+
+/// Calls `f` with the values of a contiguous 1-d CPU tensor of `T`, read in
+/// place. Hands `f` back when the tensor has another dtype or is strided, so
+/// the caller can copy it instead.
+pub fn with_dlpack_tokens<T: 'static, R, F: FnOnce(&[T]) -> R>(obj: &Bound<'_, PyAny>, f: F) -> PyResult<Result<R, F>> {
+    let (device_type, _): (i32, i32) = obj.call_method0("__dlpack_device__")?.extract()?;
+    if device_type != DL_CPU {
+        return Err(PyValueError::new_err("only CPU tensors can be read, move the tensor with .cpu() first"));
+    }
+    let capsule = obj.call_method0("__dlpack__")?.downcast_into::<PyCapsule>()?;
+    if capsule.name()? != Some(c"dltensor") || capsule.pointer().is_null() {
+        return Err(PyValueError::new_err("__dlpack__ returned no unconsumed \"dltensor\" capsule"));
+    }
+    let managed = capsule.pointer().cast::<DLManagedTensor>();
+    // Take ownership as the protocol asks, so the capsule doesn't release it too
+    // SAFETY: `capsule` is a live capsule and the name is a static string
+    if unsafe { ffi::PyCapsule_SetName(capsule.as_ptr(), c"used_dltensor".as_ptr()) } != 0 {
+        return Err(PyErr::fetch(obj.py()));
+    }
+    let _consumed = Consumed(managed);
+    // SAFETY: a "dltensor" capsule holds a valid DLManagedTensor, alive until released
+    let tensor = unsafe { &(*managed).dl_tensor };
+    if tensor.ndim != 1 {
+        return Err(PyValueError::new_err(format!("expected a 1-d tensor, got {} dimensions", tensor.ndim)));
+    }
+    // SAFETY: `shape` (and `strides` unless null) hold `ndim` values
+    let (len, stride) = unsafe { (*tensor.shape, if tensor.strides.is_null() { 1 } else { *tensor.strides }) };
+    let dtype = (tensor.dtype.code, tensor.dtype.bits);
+    if Some(dtype) != dtype_of::<T>() || tensor.dtype.lanes != 1 || (stride != 1 && len > 1) {
+        return Ok(Err(f));
+    }
+    let tokens = match len {
+        0 => &[][..],
+        // SAFETY: the contiguous tensor holds `len` values of `T` from `data + byte_offset`
+        len => unsafe {
+            let data = tensor.data.cast::<u8>().add(tensor.byte_offset as usize).cast::<T>();
+            slice::from_raw_parts(data, len as usize)
+        },
+    };
+    Ok(Ok(f(tokens)))
+}
@@ -1,21 +1,98 @@
+use pyo3::prelude::*;
 
-use pyo3::{prelude::*, wrap_pymodule};
+#[macro_use]
+mod tokens;
+mod acceptance;
+mod adaptive;
+mod apply;
+mod arrow;
+mod asyncio;
+mod batch;
+mod bytes;
+mod cancel;
+mod changes;
+mod detok;
+mod distance;
+mod dlpack;
+mod logging;
+mod lookahead;
+mod merge;
+mod multiref;
+mod nextchunk;
+mod ngram;
+mod sequencematch;
+mod sessions;
+mod simulate;
+mod store;
+mod text;
+mod tree;
+mod words;
 
-use diff::StreamNextChunk;
+use acceptance::PyAcceptanceEstimator;
+use adaptive::PyAdaptiveChunker;
+use batch::PyBatchStreamNextChunk;
+use bytes::PyStreamNextChunkBytes;
+use cancel::PyCancellationToken;
+use detok::PyDetokenizedNextChunk;
+use lookahead::PyNgramPool;
+use multiref::PyMultiRefStreamNextChunk;
+use nextchunk::{PyPredictionCursor, PyPredictionResult, PyPredictionStream, PyStreamNextChunk, PyTokenTree};
+use ngram::PyNgramNextChunk;
+use sessions::PySessionManager;
+use store::PyReferenceStore;
+use text::PyTextDiff;
+use tree::PyTreeVerification;
+use words::PyWordNextChunk;
 
 
 #[pymodule(submodule)]
 fn _diff(_py: Python, m: &Bound<PyModule>) -> PyResult<()> {
-    m.add_class::<StreamNextChunk>()?;
+    m.add_class::<PyStreamNextChunk>()?;
+    m.add_class::<PyStreamNextChunkBytes>()?;
+    m.add_class::<PyDetokenizedNextChunk>()?;
+    m.add_class::<PyPredictionResult>()?;
+    m.add_class::<PyPredictionCursor>()?;
+    m.add_class::<PyTokenTree>()?;
+    m.add_class::<PyTreeVerification>()?;
+    m.add_class::<PyPredictionStream>()?;
+    m.add_class::<PyBatchStreamNextChunk>()?;
+    m.add_class::<PyCancellationToken>()?;
+    m.add_class::<PyMultiRefStreamNextChunk>()?;
+    m.add_class::<PyReferenceStore>()?;
+    m.add_class::<PyNgramNextChunk>()?;
+    m.add_class::<PyNgramPool>()?;
+    m.add_class::<PyWordNextChunk>()?;
+    m.add_class::<PyTextDiff>()?;
+    m.add_class::<PyAcceptanceEstimator>()?;
+    m.add_class::<PyAdaptiveChunker>()?;
+    m.add_class::<PySessionManager>()?;
+    m.add_function(wrap_pyfunction!(changes::py_diff, m)?)?;
+    m.add_function(wrap_pyfunction!(changes::py_find_matches, m)?)?;
+    m.add_function(wrap_pyfunction!(sequencematch::py_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(sequencematch::py_matching_blocks, m)?)?;
+    m.add_function(wrap_pyfunction!(sequencematch::py_opcodes, m)?)?;
+    m.add_function(wrap_pyfunction!(sequencematch::py_lcs, m)?)?;
+    m.add_function(wrap_pyfunction!(sequencematch::py_diff_json, m)?)?;
+    m.add_function(wrap_pyfunction!(distance::py_edit_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(distance::py_edit_distance_within, m)?)?;
+    m.add_function(wrap_pyfunction!(text::py_unified_diff, m)?)?;
+    m.add_function(wrap_pyfunction!(apply::py_edit_script, m)?)?;
+    m.add_function(wrap_pyfunction!(apply::py_apply_edits, m)?)?;
+    m.add_function(wrap_pyfunction!(merge::py_merge3, m)?)?;
+    m.add_function(wrap_pyfunction!(tree::py_merge_candidates, m)?)?;
+    m.add_function(wrap_pyfunction!(simulate::py_simulate, m)?)?;
+    m.add_function(wrap_pyfunction!(logging::py_install_logging, m)?)?;
     Ok(())
 }
 
 /// Python module definition
 // this name must be same as package name
-#[pymodule]
+// All classes are Send + Sync and guard their state, so the module can run
+// without the GIL on free-threaded builds
+#[pymodule(gil_used = false)]
 fn llminfer_rs(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
    // Create the submodule
-   let diff_mod = PyModule::new_bound(py, "diff")?;
+   let diff_mod = PyModule::new(py, "diff")?;
    _diff(py, &diff_mod)?;
    // Add submodule to the main module
    m.add_submodule(&diff_mod)?;
@@ -0,0 +1,97 @@
+use std::fmt::{self, Write};
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+
+
+/// Formats an event's or span's fields as `message k=v k=v`.
+#[derive(Default)]
+struct FieldFormatter(String);
+
+impl Visit for FieldFormatter {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let sep = if self.0.is_empty() { "" } else { " " };
+        let _ = if field.name() == "message" {
+            write!(self.0, "{sep}{value:?}")
+        } else {
+            write!(self.0, "{sep}{}={value:?}", field.name())
+        };
+    }
+}
+
+/// Fields of a span, formatted once when it is created.
+struct SpanFields(String);
+
+/// Forwards `tracing` events to a Python `logging.Logger`, prefixed with the
+/// enclosing spans (`next_chunk{b_len=.. chunk_size=..}: ...`).
+struct PyLoggingLayer {
+    logger: Py<PyAny>,
+}
+
+/// Python logging level of a `tracing` level.
+fn py_level(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 40,
+        Level::WARN => 30,
+        Level::INFO => 20,
+        Level::DEBUG => 10,
+        Level::TRACE => 5,
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for PyLoggingLayer {
+    fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, id: &tracing::span::Id, ctx: Context<'_, S>) {
+        let mut fields = FieldFormatter::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(fields.0));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut message = String::new();
+        for span in ctx.event_scope(event).into_iter().flat_map(|scope| scope.from_root()) {
+            let extensions = span.extensions();
+            let fields = extensions.get::<SpanFields>().map_or("", |fields| fields.0.as_str());
+            let _ = write!(message, "{}{{{fields}}}: ", span.name());
+        }
+        let mut fields = FieldFormatter::default();
+        event.record(&mut fields);
+        message.push_str(&fields.0);
+
+        let level = py_level(event.metadata().level());
+        // Events are mostly emitted with the GIL released (inside `allow_threads`
+        // or on rayon threads), so take it back just for the logging call
+        Python::with_gil(|py| {
+            if let Err(err) = self.logger.call_method1(py, "log", (level, message)) {
+                err.write_unraisable(py, None);
+            }
+        });
+    }
+}
+
+/// Forwards the library's `tracing` spans and events to Python `logging`.
+///
+/// Args:
+///     level (str): Most verbose level to forward: "error", "warn", "info",
+///         "debug" (default) or "trace". Rust's TRACE maps to Python level 5.
+///     logger (str): Name of the Python logger records are sent to.
+///
+/// Can only be called once per process; raises RuntimeError afterwards.
+#[pyfunction(name = "install_logging")]
+#[pyo3(signature = (level = "debug", logger = "llminfer_rs"), text_signature = "(level='debug', logger='llminfer_rs')")]
+pub fn py_install_logging(py: Python<'_>, level: &str, logger: &str) -> PyResult<()> {
+    let filter: LevelFilter = level
+        .parse()
+        .map_err(|_| PyValueError::new_err(format!("unsupported level {level:?}")))?;
+    let logger = py.import("logging")?.call_method1("getLogger", (logger,))?.unbind();
+    let subscriber = Registry::default().with(PyLoggingLayer { logger }.with_filter(filter));
+    tracing::subscriber::set_global_default(subscriber)
+        .map_err(|_| PyRuntimeError::new_err("a tracing subscriber is already installed"))
+}
@@ -0,0 +1,149 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use diff::NgramPool;
+
+use crate::nextchunk::PyTokenTree;
+use crate::tokens::{tokens_to_py, with_tokens, DType, Output, PyToken};
+
+
+by_dtype! {
+    /// Concrete instantiations of the generic n-gram pool selected by `dtype`.
+    enum Inner => NgramPool
+}
+
+fn new_inner<T: PyToken>(
+    reference: Option<&Bound<'_, PyAny>>,
+    key_len: usize,
+    continuation_len: usize,
+    max_per_key: usize,
+) -> PyResult<NgramPool<T>> {
+    let mut pool = NgramPool::new(key_len, continuation_len, max_per_key);
+    if let Some(reference) = reference {
+        with_tokens(reference, |reference| pool.insert(reference))?;
+    }
+    Ok(pool)
+}
+
+fn insert_impl<T: PyToken>(py: Python<'_>, pool: &mut NgramPool<T>, tokens_py: &Bound<'_, PyAny>) -> PyResult<()> {
+    with_tokens(tokens_py, |tokens| py.allow_threads(|| pool.insert(tokens)))
+}
+
+fn candidates_impl<T: PyToken>(
+    py: Python<'_>,
+    pool: &NgramPool<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    k: usize,
+    output: Output,
+) -> PyResult<Vec<PyObject>> {
+    with_tokens(current_b_py, |current_b| {
+        pool.candidates(current_b, k).into_iter().map(|candidate| tokens_to_py(py, candidate, output)).collect()
+    })?
+}
+
+fn candidate_tree_impl<T: PyToken>(
+    py: Python<'_>,
+    pool: &NgramPool<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    k: usize,
+    output: Output,
+) -> PyResult<PyTokenTree> {
+    let tree = with_tokens(current_b_py, |current_b| pool.candidate_tree(current_b, k))?;
+    PyTokenTree::new(py, tree, output)
+}
+
+
+/// Pool of n-grams for lookahead (Jacobi) decoding, harvested from the
+/// reference and from verified output.
+///
+/// Each run of `key_len` tokens maps to up to `max_per_key` distinct
+/// continuations of at most `continuation_len` tokens, most recently inserted
+/// first. Feed it each window of accepted tokens through `insert` and query
+/// `candidates` (or `candidate_tree`) with the sequence so far; it complements
+/// `StreamNextChunk` when the model repeats itself away from the reference.
+#[pyclass(name = "NgramPool", module = "stream_chunk_py")]
+pub struct PyNgramPool {
+    inner: Inner,
+}
+
+#[pymethods]
+impl PyNgramPool {
+    /// Args:
+    ///     reference (list[int] | numpy.ndarray | None): Tokens to seed the pool with.
+    ///     key_len (int): Tokens at the end of `current_b` looked up.
+    ///     continuation_len (int): Longest continuation kept per key.
+    ///     max_per_key (int): Most continuations kept per key; the least recent is dropped.
+    ///     dtype (str): Token id type, one of "int32" (default), "uint32" or "int64".
+    #[new]
+    #[pyo3(
+        signature = (reference = None, key_len = 1, continuation_len = 4, max_per_key = 8, dtype = "int32"),
+        text_signature = "(reference=None, key_len=1, continuation_len=4, max_per_key=8, dtype='int32')"
+    )]
+    fn py_new(
+        reference: Option<&Bound<'_, PyAny>>,
+        key_len: usize,
+        continuation_len: usize,
+        max_per_key: usize,
+        dtype: &str,
+    ) -> PyResult<Self> {
+        if key_len == 0 || continuation_len == 0 || max_per_key == 0 {
+            return Err(PyValueError::new_err("key_len, continuation_len and max_per_key must be at least 1"));
+        }
+        let inner = new_by_dtype!(Inner, DType::parse(dtype)?, T => new_inner::<T>(reference, key_len, continuation_len, max_per_key)?);
+        Ok(PyNgramPool { inner })
+    }
+
+    /// The token id type this instance was created with.
+    #[getter]
+    fn dtype(&self) -> &'static str {
+        self.inner.dtype().as_str()
+    }
+
+    #[getter]
+    fn key_len(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.key_len())
+    }
+
+    #[getter]
+    fn continuation_len(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.continuation_len())
+    }
+
+    #[getter]
+    fn max_per_key(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.max_per_key())
+    }
+
+    /// Number of continuations in the pool.
+    fn __len__(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.len())
+    }
+
+    /// Harvests the n-grams of `tokens`, e.g. a window of verified output.
+    /// N-grams spanning two calls are only seen when the windows overlap by
+    /// `key_len + continuation_len - 1` tokens.
+    #[pyo3(text_signature = "(tokens)")]
+    fn insert(&mut self, py: Python<'_>, tokens: &Bound<'_, PyAny>) -> PyResult<()> {
+        dispatch!(Inner, &mut self.inner, s => insert_impl(py, s, tokens))
+    }
+
+    /// Up to `k` continuations of the last `key_len` tokens of `current_b`,
+    /// most recently inserted first.
+    #[pyo3(signature = (current_b, k = 8, output = "list"), text_signature = "(current_b, k=8, output='list')")]
+    fn candidates(&self, py: Python<'_>, current_b: &Bound<'_, PyAny>, k: usize, output: &str) -> PyResult<Vec<PyObject>> {
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &self.inner, s => candidates_impl(py, s, current_b, k, output))
+    }
+
+    /// `candidates` merged into a `TokenTree` for tree-attention verification.
+    #[pyo3(signature = (current_b, k = 8, output = "list"), text_signature = "(current_b, k=8, output='list')")]
+    fn candidate_tree(&self, py: Python<'_>, current_b: &Bound<'_, PyAny>, k: usize, output: &str) -> PyResult<PyTokenTree> {
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &self.inner, s => candidate_tree_impl(py, s, current_b, k, output))
+    }
+
+    /// Forgets every n-gram.
+    fn clear(&mut self) {
+        dispatch!(Inner, &mut self.inner, s => s.clear())
+    }
+}
@@ -0,0 +1,61 @@
+use pyo3::prelude::*;
+
+use diff::{merge3, DiffAlgorithm};
+
+use crate::tokens::{parse_algorithm, tokens_to_py, with_tokens, DType, Output, PyToken};
+
+
+/// A conflict as handed to Python: `((base_start, base_end), (out_start, out_end), ours, theirs)`.
+type PyConflict = ((u32, u32), (u32, u32), PyObject, PyObject);
+
+fn merge3_impl<T: PyToken>(
+    py: Python<'_>,
+    base: &Bound<'_, PyAny>,
+    ours: &Bound<'_, PyAny>,
+    theirs: &Bound<'_, PyAny>,
+    algorithm: DiffAlgorithm,
+    output: Output,
+) -> PyResult<(PyObject, Vec<PyConflict>)> {
+    let base = with_tokens(base, <[T]>::to_vec)?;
+    let ours = with_tokens(ours, <[T]>::to_vec)?;
+    let merge = with_tokens(theirs, |theirs| py.allow_threads(|| merge3(&base, &ours, theirs, algorithm)))?;
+    let conflicts = merge
+        .conflicts
+        .iter()
+        .map(|c| {
+            let ours = tokens_to_py(py, &c.ours, output)?;
+            let theirs = tokens_to_py(py, &c.theirs, output)?;
+            Ok(((c.base.start, c.base.end), (c.output.start, c.output.end), ours, theirs))
+        })
+        .collect::<PyResult<_>>()?;
+    Ok((tokens_to_py(py, &merge.merged, output)?, conflicts))
+}
+
+/// Three-way merge of two token sequences derived from `base`.
+///
+/// Returns:
+///     tuple[list[int], list[tuple]]: The merged sequence and its conflicts, each
+///     `((base_start, base_end), (out_start, out_end), ours, theirs)`. Conflicting
+///     regions hold the `ours` side at `out_start:out_end` of the merged sequence.
+#[pyfunction(name = "merge3")]
+#[pyo3(
+    signature = (base, ours, theirs, dtype = "int32", algorithm = "histogram", output = "list"),
+    text_signature = "(base, ours, theirs, dtype='int32', algorithm='histogram', output='list')"
+)]
+pub fn py_merge3(
+    py: Python<'_>,
+    base: &Bound<'_, PyAny>,
+    ours: &Bound<'_, PyAny>,
+    theirs: &Bound<'_, PyAny>,
+    dtype: &str,
+    algorithm: &str,
+    output: &str,
+) -> PyResult<(PyObject, Vec<PyConflict>)> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let output = Output::parse(output)?;
+    match DType::parse(dtype)? {
+        DType::I32 => merge3_impl::<i32>(py, base, ours, theirs, algorithm, output),
+        DType::U32 => merge3_impl::<u32>(py, base, ours, theirs, algorithm, output),
+        DType::I64 => merge3_impl::<i64>(py, base, ours, theirs, algorithm, output),
+    }
+}
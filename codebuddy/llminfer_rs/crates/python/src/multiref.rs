@@ -0,0 +1,95 @@
+use pyo3::prelude::*;
+
+use diff::{MultiRefStreamNextChunk, NextChunkOptions};
+
+use crate::nextchunk::PyPredictionResult;
+use crate::tokens::{parse_algorithm, tokens_to_py, with_tokens, DType, Output, PyToken};
+
+
+by_dtype! {
+    /// Concrete instantiations of the generic multi-reference streamer selected by `dtype`.
+    enum Inner => MultiRefStreamNextChunk
+}
+
+fn new_inner<T: PyToken>(references: &Bound<'_, PyAny>, options: NextChunkOptions) -> PyResult<MultiRefStreamNextChunk<T>> {
+    let references = references
+        .try_iter()?
+        .map(|reference| with_tokens(&reference?, <[T]>::to_vec))
+        .collect::<PyResult<Vec<Vec<T>>>>()?;
+    Ok(MultiRefStreamNextChunk::new(references, options))
+}
+
+fn next_chunk_impl<T: PyToken>(
+    py: Python<'_>,
+    multi: &MultiRefStreamNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    chunk_size: usize,
+    output: Output,
+) -> PyResult<(PyPredictionResult, Option<usize>)> {
+    let prediction = with_tokens(current_b_py, |current_b| {
+        py.allow_threads(|| multi.next_chunk(current_b, chunk_size))
+    })?;
+    Ok((PyPredictionResult::new(py, prediction.result, output)?, prediction.reference))
+}
+
+
+/// Predicts from whichever of several candidate references best matches `b`.
+#[pyclass(name = "MultiRefStreamNextChunk", module = "stream_chunk_py", frozen)]
+pub struct PyMultiRefStreamNextChunk {
+    inner: Inner,
+}
+
+#[pymethods]
+impl PyMultiRefStreamNextChunk {
+    /// Args:
+    ///     references (Iterable[list[int] | numpy.ndarray]): Candidate reference sequences.
+    ///     dtype (str): Token id type, one of "int32" (default), "uint32" or "int64".
+    ///     algorithm (str): Diff algorithm, one of "histogram" (default), "myers" or "myers_minimal".
+    #[new]
+    #[pyo3(signature = (references, dtype = "int32", algorithm = "histogram"), text_signature = "(references, dtype='int32', algorithm='histogram')")]
+    fn py_new(references: &Bound<'_, PyAny>, dtype: &str, algorithm: &str) -> PyResult<Self> {
+        let options = NextChunkOptions { algorithm: parse_algorithm(algorithm)?, ..Default::default() };
+        let inner = new_by_dtype!(Inner, DType::parse(dtype)?, T => new_inner::<T>(references, options)?);
+        Ok(PyMultiRefStreamNextChunk { inner })
+    }
+
+    fn __len__(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.len())
+    }
+
+    /// Returns the reference at `index` as a list.
+    #[pyo3(text_signature = "(index)")]
+    fn reference(&self, py: Python<'_>, index: usize) -> PyResult<Option<PyObject>> {
+        dispatch!(Inner, &self.inner, s => s.reference(index).map(|a| tokens_to_py(py, a, Output::List)).transpose())
+    }
+
+    /// Predicts the next chunk from the best-matching reference.
+    ///
+    /// Returns:
+    ///     tuple[list[int], int | None]: The chunk and the index of the reference
+    ///     it came from (None when no reference could predict).
+    #[pyo3(signature = (current_b, chunk_size, output = "list"), text_signature = "(current_b, chunk_size, output='list')")]
+    fn next_chunk(
+        &self,
+        py: Python<'_>,
+        current_b: &Bound<'_, PyAny>,
+        chunk_size: usize,
+        output: &str,
+    ) -> PyResult<(PyObject, Option<usize>)> {
+        let (result, reference) = self.next_chunk_with_info(py, current_b, chunk_size, output)?;
+        Ok((result.tokens(py), reference))
+    }
+
+    /// Like `next_chunk`, but returns a `PredictionResult` instead of the bare chunk.
+    #[pyo3(signature = (current_b, chunk_size, output = "list"), text_signature = "(current_b, chunk_size, output='list')")]
+    fn next_chunk_with_info(
+        &self,
+        py: Python<'_>,
+        current_b: &Bound<'_, PyAny>,
+        chunk_size: usize,
+        output: &str,
+    ) -> PyResult<(PyPredictionResult, Option<usize>)> {
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &self.inner, s => next_chunk_impl(py, s, current_b, chunk_size, output))
+    }
+}
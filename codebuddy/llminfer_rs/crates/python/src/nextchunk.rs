@@ -0,0 +1,1406 @@
+use std::cmp::max;
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use numpy::PyReadwriteArray1;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyIterator, PyList, PySet};
+
+use diff::{AcceptanceEstimator, BoundaryEquivalence, CallAnchor, CallStats, DiffAlgorithm, EscalationPolicy, LabeledTokenTree, MatchWeights, MemoryUsage, NextChunkOptions, NextChunkStats, Normalizer, PredictionCursor, PredictionHook, PredictionResult, StreamNextChunk, TokenTree};
+
+use crate::acceptance::PyAcceptanceEstimator;
+use crate::asyncio;
+use crate::tokens::{fallback_to_py, parse_algorithm, parse_anchor_strategy, parse_fallback, parse_matcher, share_for_view, tokens_to_py, tokens_to_py_view, view_base, with_tokens, DType, Output, PyToken};
+use crate::tree::PyTreeVerification;
+
+
+by_dtype! {
+    /// Concrete instantiations of the generic streamer selected by `dtype`.
+    pub(crate) enum Inner => StreamNextChunk
+}
+
+fn new_inner<T: PyToken>(
+    a_py: &Bound<'_, PyAny>,
+    options: NextChunkOptions,
+    whitespace_ids: Option<&Bound<'_, PyAny>>,
+    normalize: Option<&Bound<'_, PyAny>>,
+    boundary_equivalents: Option<&Bound<'_, PyAny>>,
+    junk_tokens: Option<&Bound<'_, PyAny>>,
+) -> PyResult<StreamNextChunk<T>> {
+    let a = with_tokens(a_py, <[T]>::to_vec)?;
+    let collapse = whitespace_ids.map(|ids| with_tokens(ids, |ids| Normalizer::collapse(ids.iter().copied()))).transpose()?;
+    let normalizer = match normalize {
+        Some(normalize) => Some(callable_normalizer(normalize, &a, collapse)?),
+        None => collapse,
+    };
+    let boundary = boundary_equivalents.map(boundary_equivalence).transpose()?;
+    let mut streamer = match normalizer {
+        Some(normalizer) => StreamNextChunk::with_normalizer(a, options, normalizer),
+        None => StreamNextChunk::with_options(a, options),
+    };
+    streamer.set_boundary_equivalence(boundary);
+    if let Some(junk_tokens) = junk_tokens {
+        with_tokens(junk_tokens, |tokens| streamer.set_junk_tokens(tokens.iter().copied()))?;
+    }
+    Ok(streamer)
+}
+
+/// Reads a boundary equivalence table from a dict or an iterable of pairs.
+fn boundary_equivalence<T: PyToken>(pairs: &Bound<'_, PyAny>) -> PyResult<BoundaryEquivalence<T>> {
+    let pairs: Vec<(T, T)> = match pairs.downcast::<PyDict>() {
+        Ok(dict) => dict.items().extract()?,
+        Err(_) => pairs.try_iter()?.map(|pair| pair?.extract()).collect::<PyResult<_>>()?,
+    };
+    Ok(BoundaryEquivalence::new(pairs))
+}
+
+/// Wraps the `normalize` callable as a [`Normalizer`] applying `then` to its results.
+///
+/// Results are cached per token, so the GIL is only taken again for tokens of
+/// `b` not seen before. It is called for all of `a` here so that errors there
+/// raise; later errors can't propagate through the diff and are reported as
+/// unraisable, leaving the token as is.
+fn callable_normalizer<T: PyToken>(
+    normalize: &Bound<'_, PyAny>,
+    a: &[T],
+    then: Option<Normalizer<T>>,
+) -> PyResult<Normalizer<T>> {
+    let mut cache = HashMap::new();
+    for &token in a {
+        if let Entry::Vacant(entry) = cache.entry(token) {
+            entry.insert(normalize.call1((token,))?.extract::<T>()?);
+        }
+    }
+    let cache = Mutex::new(cache);
+    let normalize = normalize.clone().unbind();
+    Ok(Normalizer::from_fn(move |token| {
+        let cached = cache.lock().unwrap_or_else(|e| e.into_inner()).get(&token).copied();
+        let canonical = cached.unwrap_or_else(|| {
+            let canonical = Python::with_gil(|py| {
+                let normalize = normalize.bind(py);
+                normalize.call1((token,)).and_then(|v| v.extract::<T>()).unwrap_or_else(|err| {
+                    err.write_unraisable(py, Some(normalize));
+                    token
+                })
+            });
+            cache.lock().unwrap_or_else(|e| e.into_inner()).insert(token, canonical);
+            canonical
+        });
+        match &then {
+            Some(then) => then.normalize(canonical),
+            None => canonical,
+        }
+    }))
+}
+
+fn next_chunk_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &StreamNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    chunk_size: usize,
+    algorithm: Option<DiffAlgorithm>,
+    output: Output,
+) -> PyResult<PyObject> {
+    let algorithm = algorithm.unwrap_or(streamer.options().algorithm);
+    // The diff can take milliseconds on long references; let other Python
+    // threads run meanwhile. Only the extraction above needs the GIL.
+    let result = with_tokens(current_b_py, |current_b| {
+        py.allow_threads(|| streamer.next_chunk_with_algorithm(current_b, chunk_size, algorithm))
+    })?;
+    tokens_to_py_view(py, result, output, view_base(streamer, output))
+}
+
+fn next_chunk_depths_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &StreamNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    depths: &[usize],
+    output: Output,
+) -> PyResult<Vec<PyObject>> {
+    let chunks = with_tokens(current_b_py, |current_b| py.allow_threads(|| streamer.next_chunk_depths(current_b, depths)))?;
+    chunks
+        .into_iter()
+        .map(|chunk| tokens_to_py_view(py, chunk, output, view_base(streamer, output)))
+        .collect()
+}
+
+fn next_chunk_into_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &StreamNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    out_py: &Bound<'_, PyAny>,
+) -> PyResult<usize> {
+    let mut out = out_py.extract::<PyReadwriteArray1<'_, T>>().map_err(|_| {
+        pyo3::exceptions::PyTypeError::new_err("out must be a writable 1-d numpy array of the instance's dtype")
+    })?;
+    let out = out.as_slice_mut().map_err(|_| pyo3::exceptions::PyValueError::new_err("out must be contiguous"))?;
+    with_tokens(current_b_py, |current_b| py.allow_threads(|| streamer.next_chunk_into(current_b, out)))
+}
+
+fn set_reference_impl<T: PyToken>(streamer: &mut StreamNextChunk<T>, a_py: &Bound<'_, PyAny>) -> PyResult<()> {
+    with_tokens(a_py, |a| streamer.set_reference(a))
+}
+
+fn extend_reference_impl<T: PyToken>(streamer: &mut StreamNextChunk<T>, tokens_py: &Bound<'_, PyAny>) -> PyResult<()> {
+    with_tokens(tokens_py, |tokens| streamer.extend_reference(tokens))
+}
+
+fn append_impl<T: PyToken>(streamer: &mut StreamNextChunk<T>, new_tokens_py: &Bound<'_, PyAny>) -> PyResult<()> {
+    with_tokens(new_tokens_py, |new_tokens| streamer.append(new_tokens))
+}
+
+fn verify_and_advance_impl<T: PyToken>(
+    streamer: &mut StreamNextChunk<T>,
+    predicted_py: &Bound<'_, PyAny>,
+    actual_py: &Bound<'_, PyAny>,
+) -> PyResult<usize> {
+    with_tokens(predicted_py, |predicted| with_tokens(actual_py, |actual| streamer.verify_and_advance(predicted, actual)))?
+}
+
+fn predict_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &mut StreamNextChunk<T>,
+    chunk_size: usize,
+    output: Output,
+) -> PyResult<PyObject> {
+    share_for_view(streamer, output);
+    let base = view_base(streamer, output);
+    let result = py.allow_threads(|| streamer.predict(chunk_size));
+    tokens_to_py_view(py, result, output, base)
+}
+
+fn next_chunk_with_info_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &StreamNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    chunk_size: usize,
+    output: Output,
+) -> PyResult<PyPredictionResult> {
+    let result = with_tokens(current_b_py, |current_b| {
+        py.allow_threads(|| streamer.next_chunk_with_info(current_b, chunk_size))
+    })?;
+    let tokens = tokens_to_py_view(py, result.tokens, output, view_base(streamer, output))?;
+    Ok(PyPredictionResult::from_parts(tokens, &result))
+}
+
+fn next_chunk_from_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &StreamNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    chunk_size: usize,
+    cursor: Option<PredictionCursor>,
+    output: Output,
+) -> PyResult<(PyPredictionResult, PyPredictionCursor)> {
+    let (result, cursor) = with_tokens(current_b_py, |current_b| {
+        py.allow_threads(|| streamer.next_chunk_from(current_b, chunk_size, cursor.as_ref()))
+    })?;
+    let tokens = tokens_to_py_view(py, result.tokens, output, view_base(streamer, output))?;
+    Ok((PyPredictionResult::from_parts(tokens, &result), PyPredictionCursor { inner: cursor }))
+}
+
+fn next_chunk_candidates_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &StreamNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    chunk_size: usize,
+    k: usize,
+    output: Output,
+) -> PyResult<Vec<PyPredictionResult>> {
+    let candidates = with_tokens(current_b_py, |current_b| {
+        py.allow_threads(|| streamer.next_chunk_candidates(current_b, chunk_size, k))
+    })?;
+    candidates
+        .iter()
+        .map(|result| {
+            let tokens = tokens_to_py_view(py, result.tokens, output, view_base(streamer, output))?;
+            Ok(PyPredictionResult::from_parts(tokens, result))
+        })
+        .collect()
+}
+
+fn next_chunk_tree_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &StreamNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    chunk_size: usize,
+    k: usize,
+    output: Output,
+) -> PyResult<PyTokenTree> {
+    let tree = with_tokens(current_b_py, |current_b| {
+        py.allow_threads(|| streamer.next_chunk_tree(current_b, chunk_size, k))
+    })?;
+    PyTokenTree::new(py, tree, output)
+}
+
+fn next_chunk_with_estimate_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &StreamNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    chunk_size: usize,
+    estimator: &AcceptanceEstimator,
+    output: Output,
+) -> PyResult<(PyPredictionResult, usize)> {
+    let (result, estimate) = with_tokens(current_b_py, |current_b| {
+        py.allow_threads(|| {
+            let result = streamer.next_chunk_with_info(current_b, chunk_size);
+            let estimate = estimator.estimate(streamer.reference(), current_b, &result);
+            (result, estimate)
+        })
+    })?;
+    let tokens = tokens_to_py_view(py, result.tokens, output, view_base(streamer, output))?;
+    Ok((PyPredictionResult::from_parts(tokens, &result), estimate.expected_len))
+}
+
+fn predict_with_info_impl<T: PyToken>(
+    py: Python<'_>,
+    streamer: &mut StreamNextChunk<T>,
+    chunk_size: usize,
+    output: Output,
+) -> PyResult<PyPredictionResult> {
+    share_for_view(streamer, output);
+    let base = view_base(streamer, output);
+    let result = py.allow_threads(|| streamer.predict_with_info(chunk_size));
+    let tokens = tokens_to_py_view(py, result.tokens, output, base)?;
+    Ok(PyPredictionResult::from_parts(tokens, &result))
+}
+
+/// Unreadable files raise `OSError`, out of range tokens `OverflowError`,
+/// everything else `ValueError`.
+fn npy_err(err: diff::NpyError) -> PyErr {
+    match err {
+        diff::NpyError::Io { .. } => pyo3::exceptions::PyOSError::new_err(err.to_string()),
+        diff::NpyError::Overflow(_) => pyo3::exceptions::PyOverflowError::new_err(err.to_string()),
+        _ => pyo3::exceptions::PyValueError::new_err(err.to_string()),
+    }
+}
+
+/// Unreadable files raise `OSError`, out of range tokens `OverflowError`,
+/// everything else `ValueError`.
+fn state_err(err: diff::StateError) -> PyErr {
+    match err {
+        diff::StateError::Io { .. } => pyo3::exceptions::PyOSError::new_err(err.to_string()),
+        diff::StateError::Overflow(_) => pyo3::exceptions::PyOverflowError::new_err(err.to_string()),
+        _ => pyo3::exceptions::PyValueError::new_err(err.to_string()),
+    }
+}
+
+/// Tokenizer files that can't be read raise `OSError`, everything else `ValueError`.
+#[cfg(feature = "tokenizers")]
+fn tokenizer_err(err: diff::TokenizerError) -> PyErr {
+    match err {
+        diff::TokenizerError::Load { .. } => pyo3::exceptions::PyOSError::new_err(err.to_string()),
+        _ => pyo3::exceptions::PyValueError::new_err(err.to_string()),
+    }
+}
+
+fn call_stats_to_py<'py>(py: Python<'py>, call: &CallStats) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("wall_s", call.wall.as_secs_f64())?;
+    dict.set_item("intern_s", call.intern.as_secs_f64())?;
+    dict.set_item("diff_s", call.diff.as_secs_f64())?;
+    dict.set_item("windowed", call.windowed)?;
+    dict.set_item("escalations", call.escalations)?;
+    dict.set_item("windows_searched", call.windows_searched)?;
+    dict.set_item("matches", call.matches)?;
+    dict.set_item("fast_path", call.fast_path)?;
+    dict.set_item("memo_hit", call.memo_hit)?;
+    dict.set_item("continuation_hit", call.continuation_hit)?;
+    dict.set_item("cursor_hit", call.cursor_hit)?;
+    dict.set_item("a_window", call.a_window)?;
+    dict.set_item("b_window_start", call.b_window_start)?;
+    dict.set_item("anchor", call.anchor.as_str())?;
+    let (anchor_pos, anchor_match_len) = match call.anchor {
+        CallAnchor::At { pos, match_len } => (Some(pos), Some(match_len)),
+        _ => (None, None),
+    };
+    dict.set_item("anchor_pos", anchor_pos)?;
+    dict.set_item("anchor_match_len", anchor_match_len)?;
+    dict.set_item("chunk_len", call.chunk_len)?;
+    dict.set_item("global_reanchor", call.global_reanchor)?;
+    dict.set_item("boundary_skip", call.boundary_skip)?;
+    dict.set_item("coarse", call.coarse)?;
+    dict.set_item("segments", call.segments)?;
+    dict.set_item("timed_out", call.timed_out)?;
+    Ok(dict)
+}
+
+/// Hook calling `callback` with a `last_call_info`-style dict per prediction,
+/// plus the prediction's `start` and `match_len`.
+fn set_hook_impl<T: PyToken>(streamer: &mut StreamNextChunk<T>, callback: Option<PyObject>) {
+    let hook = callback.map(|callback| -> PredictionHook<T> {
+        Arc::new(move |result: &PredictionResult<'_, T>, call: &CallStats| {
+            // Predictions run without the GIL
+            Python::with_gil(|py| {
+                let called = call_stats_to_py(py, call).and_then(|info| {
+                    info.set_item("start", result.start)?;
+                    info.set_item("match_len", result.match_len)?;
+                    callback.call1(py, (info,))
+                });
+                // There's no caller to raise to
+                if let Err(err) = called {
+                    err.write_unraisable(py, Some(callback.bind(py)));
+                }
+            })
+        })
+    });
+    streamer.set_hook(hook);
+}
+
+fn stats_to_py<'py>(py: Python<'py>, stats: &NextChunkStats) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("calls", stats.calls)?;
+    dict.set_item("diff_calls", stats.diff_calls)?;
+    dict.set_item("memo_hits", stats.memo_hits)?;
+    dict.set_item("windowed_calls", stats.windowed_calls)?;
+    dict.set_item("window_misses", stats.window_misses)?;
+    dict.set_item("empty_predictions", stats.empty_predictions)?;
+    dict.set_item("global_reanchors", stats.global_reanchors)?;
+    dict.set_item("timeouts", stats.timeouts)?;
+    dict.set_item("wall_s", stats.wall.as_secs_f64())?;
+    dict.set_item("intern_s", stats.intern.as_secs_f64())?;
+    dict.set_item("diff_s", stats.diff.as_secs_f64())?;
+    dict.set_item("max_wall_s", stats.max_wall.as_secs_f64())?;
+    dict.set_item("verifications", stats.verifications)?;
+    dict.set_item("predicted_tokens", stats.predicted_tokens)?;
+    dict.set_item("accepted_tokens", stats.accepted_tokens)?;
+    let acceptance_rate = (stats.predicted_tokens > 0).then(|| stats.accepted_tokens as f64 / stats.predicted_tokens as f64);
+    dict.set_item("acceptance_rate", acceptance_rate)?;
+    dict.set_item("last", stats.last.as_ref().map(|call| call_stats_to_py(py, call)).transpose()?)?;
+    Ok(dict)
+}
+
+/// `StreamNextChunk.memory_usage` as a dict.
+pub(crate) fn memory_usage_to_py<'py>(py: Python<'py>, usage: &MemoryUsage) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("budget", usage.budget)?;
+    dict.set_item("used", usage.used)?;
+    dict.set_item("required", usage.required)?;
+    dict.set_item("degraded", usage.degraded)?;
+    dict.set_item("windowed_only", usage.windowed_only)?;
+    Ok(dict)
+}
+
+
+/// A predicted chunk together with where in `a` it came from.
+#[pyclass(name = "PredictionResult", module = "stream_chunk_py", frozen, get_all)]
+pub struct PyPredictionResult {
+    /// The predicted tokens (list or numpy array, depending on `output`).
+    tokens: PyObject,
+    /// Offset in `a` of the first predicted token, None when no anchor was found.
+    start: Option<usize>,
+    /// Length of the match the prediction continues from.
+    match_len: usize,
+    /// Whether the diff was restricted to a window of `a`/`b`.
+    windowed: bool,
+}
+
+impl PyPredictionResult {
+    pub(crate) fn new<T: PyToken>(py: Python<'_>, result: PredictionResult<'_, T>, output: Output) -> PyResult<Self> {
+        Ok(Self::from_parts(tokens_to_py(py, result.tokens, output)?, &result))
+    }
+
+    fn from_parts<T>(tokens: PyObject, result: &PredictionResult<'_, T>) -> Self {
+        PyPredictionResult {
+            tokens,
+            start: result.start,
+            match_len: result.match_len,
+            windowed: result.windowed,
+        }
+    }
+
+    pub(crate) fn tokens(&self, py: Python<'_>) -> PyObject {
+        self.tokens.clone_ref(py)
+    }
+}
+
+#[pymethods]
+impl PyPredictionResult {
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let start = self.start.map_or_else(|| "None".to_owned(), |start| start.to_string());
+        Ok(format!(
+            "PredictionResult(tokens={}, start={}, match_len={}, windowed={})",
+            self.tokens.bind(py).repr()?,
+            start,
+            self.match_len,
+            if self.windowed { "True" } else { "False" },
+        ))
+    }
+}
+
+
+/// Where a `next_chunk_from` call left its `current_b` in `a`, to pass back
+/// to the next call of the same branch. Cursors belong to the caller, so
+/// beam search or retries keep one per branch over one shared streamer.
+#[pyclass(name = "PredictionCursor", module = "stream_chunk_py", frozen)]
+#[derive(Clone)]
+pub struct PyPredictionCursor {
+    inner: PredictionCursor,
+}
+
+#[pymethods]
+impl PyPredictionCursor {
+    /// Length of the `current_b` of the call.
+    #[getter]
+    fn b_len(&self) -> usize {
+        self.inner.b_len
+    }
+
+    /// Offset in `a` aligned with the end of that `current_b`, None when it
+    /// couldn't be anchored.
+    #[getter]
+    fn pos(&self) -> Option<usize> {
+        self.inner.pos
+    }
+
+    /// Length of the match in `a` ending at `pos`.
+    #[getter]
+    fn match_len(&self) -> usize {
+        self.inner.match_len
+    }
+
+    /// Calls along this branch, the last one included.
+    #[getter]
+    fn calls(&self) -> u64 {
+        self.inner.calls
+    }
+
+    /// Of those, calls that had to diff.
+    #[getter]
+    fn diff_calls(&self) -> u64 {
+        self.inner.diff_calls
+    }
+
+    /// Tokens predicted along this branch.
+    #[getter]
+    fn predicted_tokens(&self) -> u64 {
+        self.inner.predicted_tokens
+    }
+
+    fn __repr__(&self) -> String {
+        let PredictionCursor { b_len, pos, match_len, calls, .. } = self.inner;
+        let pos = pos.map_or_else(|| "None".to_owned(), |pos| pos.to_string());
+        format!("PredictionCursor(b_len={b_len}, pos={pos}, match_len={match_len}, calls={calls})")
+    }
+}
+
+
+/// Candidate continuations merged into a prefix tree, nodes stored
+/// breadth-first, for verification with tree attention.
+#[pyclass(name = "TokenTree", module = "stream_chunk_py", frozen, get_all)]
+pub struct PyTokenTree {
+    /// Token of each node (list or numpy array, depending on `output`).
+    tokens: PyObject,
+    /// Index of each node's parent, -1 for the roots, which directly follow `b`.
+    parents: Vec<i64>,
+    /// Depth of each node, 0 for the roots.
+    depths: Vec<usize>,
+    /// Node indices from a root to each leaf, one path per candidate.
+    paths: Vec<Vec<usize>>,
+    /// `attention_mask[i][j]`: whether node `i` attends to node `j`, its ancestor or itself.
+    attention_mask: Vec<Vec<bool>>,
+    /// Labels of the drafts running through each node for trees built by
+    /// `merge_candidates`, None otherwise.
+    sources: Option<Vec<Vec<String>>>,
+}
+
+impl PyTokenTree {
+    pub(crate) fn new<T: PyToken>(py: Python<'_>, tree: TokenTree<T>, output: Output) -> PyResult<Self> {
+        Ok(PyTokenTree {
+            tokens: tokens_to_py(py, &tree.tokens, output)?,
+            paths: tree.paths(),
+            attention_mask: tree.attention_mask(),
+            parents: tree.parents,
+            depths: tree.depths,
+            sources: None,
+        })
+    }
+
+    pub(crate) fn labeled<T: PyToken>(py: Python<'_>, tree: LabeledTokenTree<T, String>, output: Output) -> PyResult<Self> {
+        Ok(PyTokenTree { sources: Some(tree.sources), ..Self::new(py, tree.tree, output)? })
+    }
+}
+
+#[pymethods]
+impl PyTokenTree {
+    fn __len__(&self) -> usize {
+        self.parents.len()
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("TokenTree(tokens={}, parents={:?})", self.tokens.bind(py).repr()?, self.parents))
+    }
+
+    /// Walks the tree along `accepted`, the tokens the verifier generated
+    /// after `b`, and returns a `TreeVerification`: the accepted path, the
+    /// subtree still to verify next step and the candidates to retire.
+    /// `output` is for the surviving tree's tokens.
+    #[pyo3(signature = (accepted, output = "list"), text_signature = "(accepted, output='list')")]
+    fn verify(&self, py: Python<'_>, accepted: &Bound<'_, PyAny>, output: &str) -> PyResult<PyTreeVerification> {
+        let output = Output::parse(output)?;
+        // Any dtype's ids fit in i64
+        let tree = TokenTree {
+            tokens: with_tokens(self.tokens.bind(py), <[i64]>::to_vec)?,
+            parents: self.parents.clone(),
+            depths: self.depths.clone(),
+        };
+        let verified = with_tokens(accepted, |accepted: &[i64]| py.allow_threads(|| tree.verify(accepted)))?;
+        let mut surviving = PyTokenTree::new(py, verified.surviving, output)?;
+        surviving.sources = self
+            .sources
+            .as_ref()
+            .map(|sources| verified.surviving_nodes.iter().map(|&node| sources[node].clone()).collect());
+        Ok(PyTreeVerification {
+            accepted_path: verified.accepted_path,
+            surviving: Py::new(py, surviving)?,
+            surviving_nodes: verified.surviving_nodes,
+            retired: verified.retired,
+        })
+    }
+}
+
+
+/// Iterator returned by `StreamNextChunk.stream`: each step predicts a chunk,
+/// checks it against the next target tokens and advances the stream.
+#[pyclass(name = "PredictionStream", module = "stream_chunk_py")]
+pub struct PyPredictionStream {
+    owner: Py<PyStreamNextChunk>,
+    /// Iterator over the tokens the model generates.
+    target: Py<PyIterator>,
+    chunk_size: usize,
+    output: Output,
+    /// Target tokens pulled to verify a prediction but not appended yet.
+    pending: VecDeque<PyObject>,
+}
+
+impl PyPredictionStream {
+    /// Pulls target tokens until `n` are pending or the target runs out.
+    fn fill(&mut self, py: Python<'_>, n: usize) -> PyResult<()> {
+        let mut target = self.target.bind(py).clone();
+        while self.pending.len() < n {
+            match target.next() {
+                Some(token) => self.pending.push_back(token?.unbind()),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl PyPredictionStream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// The next `(predicted_chunk, accepted_count)` pair.
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<(PyObject, usize)>> {
+        self.fill(py, 1)?;
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+        let owner = self.owner.bind(py);
+        let mut this = owner.borrow_mut();
+        let (chunk_size, output) = (self.chunk_size, self.output);
+        let predicted = dispatch!(Inner, &mut this.inner, s => predict_impl(py, s, chunk_size, output))?;
+        self.fill(py, predicted.bind(py).len()?)?;
+        let verified = max(predicted.bind(py).len()?, 1).min(self.pending.len());
+        let actual = PyList::new(py, self.pending.iter().take(verified))?;
+        let accepted = dispatch!(Inner, &mut this.inner, s => verify_and_advance_impl(s, predicted.bind(py), actual.as_any()))?;
+        // `verify_and_advance` appended the accepted tokens, or the first one
+        self.pending.drain(..max(accepted, 1));
+        Ok(Some((predicted, accepted)))
+    }
+}
+
+
+/// Python wrapper over the generic streamer.
+///
+/// Thread safety: `next_chunk`, `next_chunk_with_info` and the getters only
+/// read the instance, so any number of threads may call them on one shared
+/// instance at once; they release the GIL while diffing and also run in
+/// parallel on free-threaded builds. The stateful `append`/`predict`/`reset`
+/// need exclusive access: calling them while another thread is inside any
+/// method of the same instance raises `RuntimeError`, so give each thread its
+/// own instance (or lock around them) when using the stateful API.
+#[pyclass(name = "StreamNextChunk", module = "stream_chunk_py")]
+pub struct PyStreamNextChunk {
+    inner: Inner,
+}
+
+impl PyStreamNextChunk {
+    pub(crate) fn new(inner: Inner) -> Self {
+        PyStreamNextChunk { inner }
+    }
+
+    /// [`share_for_view`] ahead of a read-only call. Skipped while another
+    /// thread is inside this instance, whose views are then copies.
+    fn share_for_view(slf: &Bound<'_, Self>, output: Output) {
+        if output != Output::View {
+            return;
+        }
+        if let Ok(mut this) = slf.try_borrow_mut() {
+            dispatch!(Inner, &mut this.inner, s => share_for_view(s, output));
+        }
+    }
+}
+
+#[pymethods]
+impl PyStreamNextChunk {
+    /// Creates a new StreamNextChunk instance from a Python list, 1-d numpy array,
+    /// Arrow array or CPU tensor.
+    ///
+    /// Args:
+    ///     a (list[int] | numpy.ndarray | pyarrow.Array | torch.Tensor): The reference sequence
+    ///         (like the original file content).
+    ///     dtype (str): Token id type, one of "int32" (default), "uint32" or "int64", e.g.
+    ///         for packed (token, position) ids. Ids out of its range raise OverflowError
+    ///         instead of being truncated.
+    ///     algorithm (str): Diff algorithm, one of "histogram" (default), "myers" or "myers_minimal".
+    ///     matcher (str): How `current_b` is matched against `a`: "diff" (default),
+    ///         "suffix_automaton" (longest suffix of `current_b` found in `a`; fast on long references),
+    ///         "suffix_array" (the same match from a suffix array, smaller on very long references
+    ///         but rebuilt by every `extend_reference`)
+    ///         or "hash_chain" (the same match through hash chains of 4-token runs; lightest and
+    ///         cheapest to extend, but misses matches shorter than 4 tokens).
+    ///     min_match_len (int): Predict nothing when the match anchoring the
+    ///         prediction is shorter than this many tokens.
+    ///     max_mismatches (int): Mismatching tokens (e.g. a renamed variable) tolerated
+    ///         inside the anchoring match; 0 (default) requires an exact match.
+    ///     whitespace_ids (list[int] | None): Token ids that all match each other, e.g. the
+    ///         whitespace tokens of different widths, so reformatted code still anchors.
+    ///         Predictions keep the original tokens of `a`.
+    ///     normalize (Callable[[int], int] | None): Maps each token to the canonical id it
+    ///         is matched as (applied before `whitespace_ids`), e.g. to unify ids made
+    ///         synonymous by tokenizer merges. Should be pure: results are cached per id.
+    ///         Predictions keep the original tokens of `a`.
+    ///     escalation_budget_ms (float | None): When the windowed diff finds no match,
+    ///         retry with 2x and 4x wider windows and then the full diff, starting no
+    ///         retry after this many milliseconds. None (default) disables the retries.
+    ///     fallback (str | int | None): What to predict when `current_b` can't be anchored:
+    ///         "empty", "start_of_a", "last_position" (go on from the last confirmed
+    ///         anchor) or an offset of `a`. None (default) predicts the start of `a` when
+    ///         nothing matched at all and nothing otherwise.
+    ///     global_reanchor_after (int | None): After this many consecutive windowed
+    ///         predictions came out empty, diff `current_b` against all of `a` on the
+    ///         next windowed miss, in case the model jumped elsewhere in `a`. None
+    ///         (default) never does; `stats()["global_reanchors"]` counts them.
+    ///     boundary_equivalents (dict[int, int] | Iterable[tuple[int, int]] | None): Pairs of
+    ///         tokens the tokenizer may emit for one another at a chunk boundary (e.g. the
+    ///         ids of " def" and "def"). When the last token of `current_b` keeps it from
+    ///         anchoring but is paired with the next token of `a`, that token is skipped
+    ///         and the prediction starts after it.
+    ///     autojunk (float | None): Tokens making up more than this fraction of `a`
+    ///         (newlines, commas, indents...) are junk, and a match of only junk tokens
+    ///         doesn't anchor. Only applies to the "diff" matcher and when `a` has at
+    ///         least 200 tokens, as in difflib. None (default) disables it.
+    ///     junk_tokens (list[int] | None): Token ids (EOS, padding, special tokens) never
+    ///         to anchor on or predict across: a match of only these doesn't anchor and
+    ///         predictions stop before the next one in `a`. Assign `junk_tokens` to change them.
+    ///     coarse_block_len (int | None): When `a` has at least 100k tokens, diffs over all
+    ///         of `a` first diff hashes of blocks of this many tokens to find the region
+    ///         `current_b` ends in, then diff tokens only there. Much faster on huge
+    ///         references. None (default) always diffs token by token.
+    ///     diff_segment_len (int | None): Caps the tokens of `a` one diff runs over,
+    ///         bounding its peak memory: longer spans are diffed one overlapping segment
+    ///         of this many tokens at a time, keeping the longest match of any segment.
+    ///         None (default) diffs any span at once.
+    ///     memory_budget (int | None): Caps the bytes of the interning tables and indexes
+    ///         built over `a`. Optional indexes that don't fit are left out; when the
+    ///         interned `a` alone doesn't fit, every diff is restricted to a window of `a`.
+    ///         See `memory_usage()`. None (default) is unbounded.
+    ///     deadline_ms (float | None): Time budget of one `next_chunk` call: once spent,
+    ///         no further diff starts and the call predicts what `fallback` says, as for
+    ///         no match. A running diff isn't interrupted, so diffs are segmented at
+    ///         `diff_segment_len` tokens of `a` (4096 if None) to bound each.
+    ///         None (default) is unbounded; `stats()["timeouts"]` counts the calls over it.
+    ///     anchor_strategy (str): Which match of the diff the prediction goes on from:
+    ///         "last" (default; `current_b` has to end with it), "longest", "longest_suffix"
+    ///         (the longest run of `a` equal to the end of `current_b`) or "nearest" (closest
+    ///         to where the previous anchor says `current_b` ends, e.g. for rewriting a
+    ///         document in order) or "weighted" (the best by `anchor_weights`, which still
+    ///         predicts when the model appended a novel token after a long match). Except
+    ///         with "last", the prediction starts as many tokens past the match as
+    ///         `current_b` goes on after it.
+    ///     anchor_weights (tuple[float, float, float] | None): `(length, recency, rarity)`
+    ///         weights of the "weighted" strategy: per matched token, times how close the
+    ///         match ends to the end of `current_b` (0 to 1), and times the mean inverse
+    ///         frequency in `a` of the matched tokens. None (default) is `(1.0, 8.0, 1.0)`.
+    ///     continuation_check_len (int): When `current_b` ends with this many tokens that `a`
+    ///         has right before where the previous `next_chunk` prediction ended (or
+    ///         anywhere in it), anchor there without a diff. 0 (default) disables the check.
+    ///     multi_window_search (bool): When the windowed diff finds no match (after any
+    ///         `escalation_budget_ms` retries), diff the window of `current_b` against every
+    ///         other window of `a`, in parallel. False (default) gives up instead.
+    ///     memoize_anchors (bool): Remember where recent `next_chunk` calls anchored, so a
+    ///         call whose `current_b` extends an earlier one only checks the new tokens.
+    ///         Calls may then resume from another caller's, so only enable it when one
+    ///         stream at a time uses the instance; `next_chunk_from` keeps a cursor per
+    ///         stream instead. False (default) diffs every call.
+    #[new] // This defines the Python constructor (__init__)
+    #[pyo3(
+        signature = (a, dtype = "int32", algorithm = "histogram", matcher = "diff", min_match_len = 1, max_mismatches = 0, whitespace_ids = None, normalize = None, escalation_budget_ms = None, fallback = None, global_reanchor_after = None, boundary_equivalents = None, autojunk = None, junk_tokens = None, coarse_block_len = None, diff_segment_len = None, memory_budget = None, deadline_ms = None, anchor_strategy = "last", anchor_weights = None, continuation_check_len = 0, multi_window_search = false, memoize_anchors = false),
+        text_signature = "(a, dtype='int32', algorithm='histogram', matcher='diff', min_match_len=1, max_mismatches=0, whitespace_ids=None, normalize=None, escalation_budget_ms=None, fallback=None, global_reanchor_after=None, boundary_equivalents=None, autojunk=None, junk_tokens=None, coarse_block_len=None, diff_segment_len=None, memory_budget=None, deadline_ms=None, anchor_strategy='last', anchor_weights=None, continuation_check_len=0, multi_window_search=False, memoize_anchors=False)"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        a: &Bound<'_, PyAny>,
+        dtype: &str,
+        algorithm: &str,
+        matcher: &str,
+        min_match_len: usize,
+        max_mismatches: usize,
+        whitespace_ids: Option<&Bound<'_, PyAny>>,
+        normalize: Option<&Bound<'_, PyAny>>,
+        escalation_budget_ms: Option<f64>,
+        fallback: Option<&Bound<'_, PyAny>>,
+        global_reanchor_after: Option<usize>,
+        boundary_equivalents: Option<&Bound<'_, PyAny>>,
+        autojunk: Option<f64>,
+        junk_tokens: Option<&Bound<'_, PyAny>>,
+        coarse_block_len: Option<usize>,
+        diff_segment_len: Option<usize>,
+        memory_budget: Option<usize>,
+        deadline_ms: Option<f64>,
+        anchor_strategy: &str,
+        anchor_weights: Option<(f64, f64, f64)>,
+        continuation_check_len: usize,
+        multi_window_search: bool,
+        memoize_anchors: bool,
+    ) -> PyResult<Self> {
+        let deadline = deadline_ms
+            .map(|ms| {
+                Duration::try_from_secs_f64(ms / 1000.0)
+                    .map_err(|_| pyo3::exceptions::PyValueError::new_err(format!("invalid deadline_ms {ms}")))
+            })
+            .transpose()?;
+        let escalation = escalation_budget_ms
+            .map(|ms| {
+                let budget = Duration::try_from_secs_f64(ms / 1000.0)
+                    .map_err(|_| pyo3::exceptions::PyValueError::new_err(format!("invalid escalation_budget_ms {ms}")))?;
+                PyResult::Ok(EscalationPolicy { budget, ..Default::default() })
+            })
+            .transpose()?;
+        let options = NextChunkOptions {
+            algorithm: parse_algorithm(algorithm)?,
+            matcher: parse_matcher(matcher)?,
+            min_match_len,
+            max_mismatches,
+            escalation,
+            fallback: fallback.map(parse_fallback).transpose()?.flatten(),
+            global_reanchor_after,
+            autojunk,
+            coarse_block_len,
+            diff_segment_len,
+            memory_budget,
+            deadline,
+            anchor_strategy: parse_anchor_strategy(anchor_strategy)?,
+            anchor_weights: anchor_weights
+                .map(|(length, recency, rarity)| MatchWeights { length, recency, rarity })
+                .unwrap_or_default(),
+            continuation_check_len,
+            multi_window_search,
+            memoize_anchors,
+            ..Default::default()
+        };
+        let inner = new_by_dtype!(Inner, DType::parse(dtype)?, T => new_inner::<T>(a, options, whitespace_ids, normalize, boundary_equivalents, junk_tokens)?);
+        Ok(PyStreamNextChunk::new(inner))
+    }
+
+    /// Creates an instance from a `.npy` file of int32 or int64 tokens, e.g. a
+    /// token dump recorded with `numpy.save`, without needing numpy.
+    ///
+    /// Args:
+    ///     path (str | os.PathLike): The `.npy` file; its array must hold a single
+    ///         row of tokens, e.g. of shape `(n,)` or `(1, n)`.
+    ///     dtype (str | None): Token id type as for the constructor; None (default)
+    ///         keeps the type of the file. Ids out of its range raise OverflowError.
+    ///     algorithm, matcher, min_match_len, max_mismatches: As for the constructor.
+    #[staticmethod]
+    #[pyo3(
+        signature = (path, dtype = None, algorithm = "histogram", matcher = "diff", min_match_len = 1, max_mismatches = 0),
+        text_signature = "(path, dtype=None, algorithm='histogram', matcher='diff', min_match_len=1, max_mismatches=0)"
+    )]
+    fn from_npy(
+        py: Python<'_>,
+        path: std::path::PathBuf,
+        dtype: Option<&str>,
+        algorithm: &str,
+        matcher: &str,
+        min_match_len: usize,
+        max_mismatches: usize,
+    ) -> PyResult<Self> {
+        let options = NextChunkOptions {
+            algorithm: parse_algorithm(algorithm)?,
+            matcher: parse_matcher(matcher)?,
+            min_match_len,
+            max_mismatches,
+            ..Default::default()
+        };
+        let array = py.allow_threads(|| diff::read_npy(path)).map_err(npy_err)?;
+        let dtype = match (dtype, &array) {
+            (Some(dtype), _) => DType::parse(dtype)?,
+            (None, diff::NpyArray::I32(_)) => DType::I32,
+            (None, diff::NpyArray::I64(_)) => DType::I64,
+        };
+        let inner = new_by_dtype!(Inner, dtype, T => StreamNextChunk::with_options(array.into_tokens::<T>().map_err(npy_err)?, options));
+        Ok(PyStreamNextChunk::new(inner))
+    }
+
+    /// Creates a "uint32" instance from text, tokenized in Rust with a
+    /// HuggingFace tokenizer instead of round-tripping the ids through Python.
+    ///
+    /// Args:
+    ///     text (str): The reference text.
+    ///     tokenizer_path (str | os.PathLike): Path to a `tokenizer.json`.
+    ///     algorithm, matcher, min_match_len, max_mismatches: As for the constructor.
+    #[cfg(feature = "tokenizers")]
+    #[staticmethod]
+    #[pyo3(
+        signature = (text, tokenizer_path, algorithm = "histogram", matcher = "diff", min_match_len = 1, max_mismatches = 0),
+        text_signature = "(text, tokenizer_path, algorithm='histogram', matcher='diff', min_match_len=1, max_mismatches=0)"
+    )]
+    fn from_text(
+        py: Python<'_>,
+        text: &str,
+        tokenizer_path: std::path::PathBuf,
+        algorithm: &str,
+        matcher: &str,
+        min_match_len: usize,
+        max_mismatches: usize,
+    ) -> PyResult<Self> {
+        let options = NextChunkOptions {
+            algorithm: parse_algorithm(algorithm)?,
+            matcher: parse_matcher(matcher)?,
+            min_match_len,
+            max_mismatches,
+            ..Default::default()
+        };
+        let streamer = py
+            .allow_threads(|| StreamNextChunk::from_text_with_options(text, tokenizer_path, options))
+            .map_err(tokenizer_err)?;
+        Ok(PyStreamNextChunk::new(Inner::U32(streamer)))
+    }
+
+    /// Like `next_chunk`, but takes the text generated so far and tokenizes it
+    /// with the tokenizer passed to `from_text`.
+    ///
+    /// Raises:
+    ///     ValueError: When this instance wasn't created with `from_text`.
+    #[cfg(feature = "tokenizers")]
+    #[pyo3(signature = (current_b, chunk_size, output = "list"), text_signature = "(current_b, chunk_size, output='list')")]
+    fn next_chunk_text(slf: &Bound<'_, Self>, current_b: &str, chunk_size: usize, output: &str) -> PyResult<PyObject> {
+        let py = slf.py();
+        let output = Output::parse(output)?;
+        Self::share_for_view(slf, output);
+        let this = slf.borrow();
+        let Inner::U32(streamer) = &this.inner else {
+            return Err(tokenizer_err(diff::TokenizerError::NoTokenizer));
+        };
+        let result = py.allow_threads(|| streamer.next_chunk_text(current_b, chunk_size)).map_err(tokenizer_err)?;
+        tokens_to_py_view(py, result, output, view_base(streamer, output))
+    }
+
+    /// The token id type this instance was created with.
+    #[getter]
+    fn dtype(&self) -> &'static str {
+        self.inner.dtype().as_str()
+    }
+
+    /// The diff algorithm used when `next_chunk` doesn't override it.
+    #[getter]
+    fn algorithm(&self) -> &'static str {
+        dispatch!(Inner, &self.inner, s => s.options().algorithm.as_str())
+    }
+
+    /// The matcher backend this instance was created with.
+    #[getter]
+    fn matcher(&self) -> &'static str {
+        dispatch!(Inner, &self.inner, s => s.options().matcher.as_str())
+    }
+
+    /// Shortest anchoring match that still yields a prediction.
+    #[getter]
+    fn min_match_len(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.options().min_match_len)
+    }
+
+    /// Mismatching tokens tolerated inside the anchoring match.
+    #[getter]
+    fn max_mismatches(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.options().max_mismatches)
+    }
+
+    /// Empty windowed predictions in a row after which a miss re-anchors with a full diff.
+    #[getter]
+    fn global_reanchor_after(&self) -> Option<usize> {
+        dispatch!(Inner, &self.inner, s => s.options().global_reanchor_after)
+    }
+
+    /// Fraction of `a` above which a token is junk, None when disabled.
+    #[getter]
+    fn autojunk(&self) -> Option<f64> {
+        dispatch!(Inner, &self.inner, s => s.options().autojunk)
+    }
+
+    /// Block length of the coarse diff of huge references, None when disabled.
+    #[getter]
+    fn coarse_block_len(&self) -> Option<usize> {
+        dispatch!(Inner, &self.inner, s => s.options().coarse_block_len)
+    }
+
+    /// Most tokens of `a` one diff runs over, None when unbounded.
+    #[getter]
+    fn diff_segment_len(&self) -> Option<usize> {
+        dispatch!(Inner, &self.inner, s => s.options().diff_segment_len)
+    }
+
+    /// Tokens at the end of `current_b` checked against the previous prediction
+    /// before diffing, 0 when the check is off.
+    #[getter]
+    fn continuation_check_len(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.options().continuation_check_len)
+    }
+
+    /// Whether a windowed miss is retried against every other window of `a`.
+    #[getter]
+    fn multi_window_search(&self) -> bool {
+        dispatch!(Inner, &self.inner, s => s.options().multi_window_search)
+    }
+
+    /// Whether calls extending a recent one's `current_b` resume from its anchor.
+    #[getter]
+    fn memoize_anchors(&self) -> bool {
+        dispatch!(Inner, &self.inner, s => s.options().memoize_anchors)
+    }
+
+    /// Which match of the diff predictions go on from.
+    #[getter]
+    fn anchor_strategy(&self) -> &'static str {
+        dispatch!(Inner, &self.inner, s => s.options().anchor_strategy.as_str())
+    }
+
+    /// `(length, recency, rarity)` weights of the "weighted" anchor strategy.
+    #[getter]
+    fn anchor_weights(&self) -> (f64, f64, f64) {
+        let MatchWeights { length, recency, rarity } = dispatch!(Inner, &self.inner, s => s.options().anchor_weights);
+        (length, recency, rarity)
+    }
+
+    /// Time budget of one `next_chunk` call in milliseconds, None when unbounded.
+    #[getter]
+    fn deadline_ms(&self) -> Option<f64> {
+        dispatch!(Inner, &self.inner, s => s.options().deadline.map(|deadline| deadline.as_secs_f64() * 1000.0))
+    }
+
+    /// Token ids never anchored on or predicted across, as a set; assign any
+    /// sequence of ids to change them.
+    #[getter]
+    fn junk_tokens<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PySet>> {
+        dispatch!(Inner, &self.inner, s => PySet::new(py, s.junk_tokens()))
+    }
+
+    #[setter(junk_tokens)]
+    fn set_junk_tokens(&mut self, tokens: &Bound<'_, PyAny>) -> PyResult<()> {
+        dispatch!(Inner, &mut self.inner, s => with_tokens(tokens, |tokens| s.set_junk_tokens(tokens.iter().copied())))
+    }
+
+    /// Distinctive runs of `a` as `(start, end)` ranges: once the stream
+    /// appended through `append` diverged, reaching the end of one re-anchors
+    /// right after it without a diff. Assign a list of ranges to change them;
+    /// dropped when the reference is replaced.
+    #[getter]
+    fn landmarks(&self) -> Vec<(usize, usize)> {
+        dispatch!(Inner, &self.inner, s => s.landmarks().iter().map(|range| (range.start, range.end)).collect())
+    }
+
+    #[setter(landmarks)]
+    fn set_landmarks(&mut self, landmarks: Vec<(usize, usize)>) -> PyResult<()> {
+        let a_len = dispatch!(Inner, &self.inner, s => s.reference().len());
+        if let Some(&(start, end)) = landmarks.iter().find(|&&(start, end)| start > end || end > a_len) {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!("landmark ({start}, {end}) is out of the reference of {a_len} tokens")));
+        }
+        dispatch!(Inner, &mut self.inner, s => s.set_landmarks(landmarks.iter().map(|&(start, end)| start..end)));
+        Ok(())
+    }
+
+    /// What is predicted when `current_b` can't be anchored, as passed to the
+    /// constructor; assign to change it.
+    #[getter]
+    fn fallback(&self, py: Python<'_>) -> PyResult<PyObject> {
+        fallback_to_py(py, dispatch!(Inner, &self.inner, s => s.options().fallback))
+    }
+
+    #[setter(fallback)]
+    fn set_fallback(&mut self, fallback: &Bound<'_, PyAny>) -> PyResult<()> {
+        let fallback = parse_fallback(fallback)?;
+        dispatch!(Inner, &mut self.inner, s => s.set_fallback(fallback));
+        Ok(())
+    }
+
+    /// Predicts the next chunk of `a` following `current_b`.
+    ///
+    /// `current_b` may be a list, a 1-d numpy array, a null-free Arrow array such
+    /// as `pyarrow.Int32Array` or a 1-d CPU tensor passed via DLPack (e.g. torch);
+    /// arrays and tensors are read without copying when their dtype matches
+    /// (Arrow arrays must match). Pass `output="numpy"` to get a numpy array back,
+    /// `output="view"` for a read-only numpy array sharing memory with `a`
+    /// (no copy; it keeps this instance alive), and `algorithm` to diff this
+    /// call with another algorithm.
+    #[pyo3(
+        name="next_chunk",
+        signature = (current_b, chunk_size, output = "list", algorithm = None),
+        text_signature = "(current_b, chunk_size, output='list', algorithm=None)"
+    )]
+    pub fn next_chunk_py(
+        slf: &Bound<'_, Self>,
+        current_b: &Bound<'_, PyAny>,
+        chunk_size: usize,
+        output: &str,
+        algorithm: Option<&str>,
+    ) -> PyResult<PyObject> {
+        let algorithm = algorithm.map(parse_algorithm).transpose()?;
+        let output = Output::parse(output)?;
+        Self::share_for_view(slf, output);
+        let this = slf.borrow();
+        dispatch!(Inner, &this.inner, s => next_chunk_impl(slf.py(), s, current_b, chunk_size, algorithm, output))
+    }
+
+    /// Nested predictions of each of `depths` tokens (e.g. `[8, 32, 128]`)
+    /// from one match, in the order of `depths`: each is a prefix of the
+    /// deepest one, so pick how deep to speculate by batch pressure without
+    /// matching again. `output` is as for `next_chunk`.
+    #[pyo3(signature = (current_b, depths, output = "list"), text_signature = "(current_b, depths, output='list')")]
+    fn next_chunk_depths(slf: &Bound<'_, Self>, current_b: &Bound<'_, PyAny>, depths: Vec<usize>, output: &str) -> PyResult<Vec<PyObject>> {
+        let output = Output::parse(output)?;
+        Self::share_for_view(slf, output);
+        let this = slf.borrow();
+        dispatch!(Inner, &this.inner, s => next_chunk_depths_impl(slf.py(), s, current_b, &depths, output))
+    }
+
+    /// Like `next_chunk` with `len(out)` as the chunk size, but writes the
+    /// prediction into `out`, a writable contiguous 1-d numpy array of this
+    /// instance's dtype, and returns the number of tokens written. Reusing one
+    /// `out` every decoding step avoids building a list or array per call.
+    #[pyo3(text_signature = "(current_b, out)")]
+    fn next_chunk_into(&self, py: Python<'_>, current_b: &Bound<'_, PyAny>, out: &Bound<'_, PyAny>) -> PyResult<usize> {
+        dispatch!(Inner, &self.inner, s => next_chunk_into_impl(py, s, current_b, out))
+    }
+
+    /// Like `next_chunk`, but diffs on a Rust worker thread and returns an
+    /// awaitable, so an asyncio event loop keeps serving other requests
+    /// meanwhile. Must be called with a running event loop; don't modify
+    /// `current_b` until the result is in.
+    #[pyo3(
+        signature = (current_b, chunk_size, output = "list", algorithm = None),
+        text_signature = "(current_b, chunk_size, output='list', algorithm=None)"
+    )]
+    fn async_next_chunk(
+        slf: &Bound<'_, Self>,
+        current_b: &Bound<'_, PyAny>,
+        chunk_size: usize,
+        output: &str,
+        algorithm: Option<&str>,
+    ) -> PyResult<PyObject> {
+        let algorithm = algorithm.map(parse_algorithm).transpose()?;
+        let output = Output::parse(output)?;
+        let (owner, current_b) = (slf.clone().unbind(), current_b.clone().unbind());
+        asyncio::spawn(slf.py(), move |py| {
+            let (owner, current_b) = (owner.bind(py), current_b.bind(py));
+            Self::share_for_view(owner, output);
+            let this = owner.borrow();
+            dispatch!(Inner, &this.inner, s => next_chunk_impl(py, s, current_b, chunk_size, algorithm, output))
+        })
+    }
+
+    /// Like `next_chunk`, but returns a `PredictionResult` carrying the
+    /// predicted tokens plus the start offset in `a`, the anchoring match
+    /// length and whether windowing was applied.
+    #[pyo3(signature = (current_b, chunk_size, output = "list"), text_signature = "(current_b, chunk_size, output='list')")]
+    fn next_chunk_with_info(
+        slf: &Bound<'_, Self>,
+        current_b: &Bound<'_, PyAny>,
+        chunk_size: usize,
+        output: &str,
+    ) -> PyResult<PyPredictionResult> {
+        let output = Output::parse(output)?;
+        Self::share_for_view(slf, output);
+        let this = slf.borrow();
+        dispatch!(Inner, &this.inner, s => next_chunk_with_info_impl(slf.py(), s, current_b, chunk_size, output))
+    }
+
+    /// Like `next_chunk_with_info`, resuming from `cursor`, as returned by an
+    /// earlier call on a prefix of `current_b`: only the tokens appended since
+    /// are checked against `a`, and `current_b` is matched as usual when they
+    /// don't follow it. Keep one cursor per branch (beam search, retries).
+    ///
+    /// Returns:
+    ///     tuple[PredictionResult, PredictionCursor]: The prediction and the
+    ///     cursor to pass to the next call of the branch.
+    #[pyo3(
+        signature = (current_b, chunk_size, cursor = None, output = "list"),
+        text_signature = "(current_b, chunk_size, cursor=None, output='list')"
+    )]
+    fn next_chunk_from(
+        slf: &Bound<'_, Self>,
+        current_b: &Bound<'_, PyAny>,
+        chunk_size: usize,
+        cursor: Option<PyPredictionCursor>,
+        output: &str,
+    ) -> PyResult<(PyPredictionResult, PyPredictionCursor)> {
+        let output = Output::parse(output)?;
+        Self::share_for_view(slf, output);
+        let this = slf.borrow();
+        let cursor = cursor.map(|cursor| cursor.inner);
+        dispatch!(Inner, &this.inner, s => next_chunk_from_impl(slf.py(), s, current_b, chunk_size, cursor, output))
+    }
+
+    /// The longest suffix of the last `suffix_len` tokens of `current_b` found
+    /// in `a`, without predicting a chunk, e.g. to decide whether to speculate
+    /// at all this step.
+    ///
+    /// Args:
+    ///     suffix_len (int): How many trailing tokens of `current_b` to consider.
+    ///     current_b (list[int] | numpy.ndarray | None): The sequence so far;
+    ///         None (default) uses the tokens fed through `append`.
+    ///
+    /// Returns:
+    ///     tuple[int, int] | None: `(pos, match_len)` with `a[pos - match_len:pos]`
+    ///     the first occurrence of the match, or None when the last token isn't in `a`.
+    #[pyo3(signature = (suffix_len, current_b = None), text_signature = "(suffix_len, current_b=None)")]
+    fn longest_match_at(&self, py: Python<'_>, suffix_len: usize, current_b: Option<&Bound<'_, PyAny>>) -> PyResult<Option<(usize, usize)>> {
+        dispatch!(Inner, &self.inner, s => match current_b {
+            Some(current_b) => with_tokens(current_b, |b| py.allow_threads(|| s.longest_match_at(b, suffix_len))),
+            None => Ok(py.allow_threads(|| s.longest_match_at(s.appended(), suffix_len))),
+        })
+    }
+
+    /// Up to `k` `PredictionResult`s for the distinct continuations of every
+    /// position in `a` the tail of `current_b` matches, longest match first,
+    /// e.g. to verify several candidates at once with tree attention.
+    #[pyo3(signature = (current_b, chunk_size, k, output = "list"), text_signature = "(current_b, chunk_size, k, output='list')")]
+    fn next_chunk_candidates(
+        slf: &Bound<'_, Self>,
+        current_b: &Bound<'_, PyAny>,
+        chunk_size: usize,
+        k: usize,
+        output: &str,
+    ) -> PyResult<Vec<PyPredictionResult>> {
+        let output = Output::parse(output)?;
+        Self::share_for_view(slf, output);
+        let this = slf.borrow();
+        dispatch!(Inner, &this.inner, s => next_chunk_candidates_impl(slf.py(), s, current_b, chunk_size, k, output))
+    }
+
+    /// `next_chunk_candidates` merged into a `TokenTree`, whose flattened
+    /// tokens, parent indices and attention mask feed tree verification.
+    /// `output` may be "list" or "numpy" (the tree's tokens are copied).
+    #[pyo3(signature = (current_b, chunk_size, k, output = "list"), text_signature = "(current_b, chunk_size, k, output='list')")]
+    fn next_chunk_tree(&self, py: Python<'_>, current_b: &Bound<'_, PyAny>, chunk_size: usize, k: usize, output: &str) -> PyResult<PyTokenTree> {
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &self.inner, s => next_chunk_tree_impl(py, s, current_b, chunk_size, k, output))
+    }
+
+    /// Like `next_chunk_with_info`, but also returns the number of leading
+    /// predicted tokens `estimator` expects to be accepted, based on the
+    /// anchoring match length, the edit density around the anchor and the
+    /// acceptance history recorded in `estimator`.
+    #[pyo3(
+        signature = (current_b, chunk_size, estimator, output = "list"),
+        text_signature = "(current_b, chunk_size, estimator, output='list')"
+    )]
+    fn next_chunk_with_estimate(
+        slf: &Bound<'_, Self>,
+        current_b: &Bound<'_, PyAny>,
+        chunk_size: usize,
+        estimator: PyRef<'_, PyAcceptanceEstimator>,
+        output: &str,
+    ) -> PyResult<(PyPredictionResult, usize)> {
+        let output = Output::parse(output)?;
+        Self::share_for_view(slf, output);
+        let this = slf.borrow();
+        let estimator = &estimator.inner;
+        dispatch!(Inner, &this.inner, s => next_chunk_with_estimate_impl(slf.py(), s, current_b, chunk_size, estimator, output))
+    }
+
+    /// Replaces the reference with `a` (same dtype), keeping the options and
+    /// the allocations where possible, and restarts the stateful stream.
+    ///
+    /// Arrays returned with `output="view"` keep showing the reference they
+    /// were taken from, which lives as long as the last of them.
+    #[pyo3(text_signature = "(a)")]
+    fn set_reference(&mut self, a: &Bound<'_, PyAny>) -> PyResult<()> {
+        dispatch!(Inner, &mut self.inner, s => set_reference_impl(s, a))
+    }
+
+    /// Appends `tokens` to the reference, updating the matching indexes
+    /// instead of rebuilding them (except for `matcher="suffix_array"`,
+    /// rebuilt every call); tokens passed to `append` stay tracked.
+    ///
+    /// Once views were taken, the reference is copied rather than grown in
+    /// place, so they keep showing it as for `set_reference`.
+    #[pyo3(text_signature = "(tokens)")]
+    fn extend_reference(&mut self, tokens: &Bound<'_, PyAny>) -> PyResult<()> {
+        dispatch!(Inner, &mut self.inner, s => extend_reference_impl(s, tokens))
+    }
+
+    /// Appends newly generated tokens to the internally tracked sequence.
+    ///
+    /// Use together with `predict` instead of passing the whole sequence to
+    /// `next_chunk` every step.
+    #[pyo3(text_signature = "(new_tokens)")]
+    fn append(&mut self, new_tokens: &Bound<'_, PyAny>) -> PyResult<()> {
+        dispatch!(Inner, &mut self.inner, s => append_impl(s, new_tokens))
+    }
+
+    /// Compares `predicted` with `actual`, the tokens the model generated at
+    /// those positions, appends the accepted prefix (at least one `actual`
+    /// token) and returns the number of accepted tokens. Totals show up in
+    /// `stats()`.
+    #[pyo3(text_signature = "(predicted, actual)")]
+    fn verify_and_advance(&mut self, predicted: &Bound<'_, PyAny>, actual: &Bound<'_, PyAny>) -> PyResult<usize> {
+        dispatch!(Inner, &mut self.inner, s => verify_and_advance_impl(s, predicted, actual))
+    }
+
+    /// Runs the speculative decoding loop against `target`, an iterable of the
+    /// tokens the model generates after those appended so far.
+    ///
+    /// Returns an iterator of `(predicted_chunk, accepted_count)` pairs: each
+    /// step predicts `chunk_size` tokens, pulls as many target tokens to verify
+    /// them and appends the accepted prefix (or the one token the model would
+    /// have generated itself), as `verify_and_advance` does. Ends with `target`.
+    #[pyo3(signature = (target, chunk_size, output = "list"), text_signature = "(target, chunk_size, output='list')")]
+    fn stream(slf: &Bound<'_, Self>, target: &Bound<'_, PyAny>, chunk_size: usize, output: &str) -> PyResult<PyPredictionStream> {
+        Ok(PyPredictionStream {
+            owner: slf.clone().unbind(),
+            target: target.try_iter()?.unbind(),
+            chunk_size,
+            output: Output::parse(output)?,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Predicts the next chunk for the tokens fed through `append`.
+    #[pyo3(signature = (chunk_size, output = "list"), text_signature = "(chunk_size, output='list')")]
+    fn predict(slf: &Bound<'_, Self>, chunk_size: usize, output: &str) -> PyResult<PyObject> {
+        let mut this = slf.borrow_mut();
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &mut this.inner, s => predict_impl(slf.py(), s, chunk_size, output))
+    }
+
+    /// Like `predict`, but returns a `PredictionResult`.
+    #[pyo3(signature = (chunk_size, output = "list"), text_signature = "(chunk_size, output='list')")]
+    fn predict_with_info(slf: &Bound<'_, Self>, chunk_size: usize, output: &str) -> PyResult<PyPredictionResult> {
+        let mut this = slf.borrow_mut();
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &mut this.inner, s => predict_with_info_impl(slf.py(), s, chunk_size, output))
+    }
+
+    /// Timings and windowing decisions of the prediction calls so far.
+    ///
+    /// Returns a dict with the call counts (`calls`, `diff_calls`,
+    /// `windowed_calls`, `window_misses`: windowed calls whose window didn't
+    /// hold the anchor, `empty_predictions`, `global_reanchors`, `timeouts`), summed times in seconds
+    /// (`wall_s`, `intern_s`, `diff_s`), the slowest call's `max_wall_s`, the
+    /// `verify_and_advance` totals (`verifications`, `predicted_tokens`,
+    /// `accepted_tokens` and their `acceptance_rate`, None before any), and
+    /// `last`: the same timings for the most recent call plus its `windowed`,
+    /// `windows_searched`, `matches`, `fast_path`, `chunk_len`,
+    /// `global_reanchor`, `boundary_skip`, `coarse`, `segments` and `timed_out`
+    /// (None before any call).
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let stats = dispatch!(Inner, &self.inner, s => s.stats());
+        stats_to_py(py, &stats)
+    }
+
+    /// Approximate bytes held by the interning tables and indexes over `a`,
+    /// as a dict: the `budget` (None when unbounded), the bytes `used` now,
+    /// the `required` part matching can't do without, whether an optional
+    /// index was left out to fit the budget (`degraded`) and whether diffs are
+    /// restricted to windows of `a` because even the required part doesn't
+    /// (`windowed_only`).
+    fn memory_usage<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let usage = dispatch!(Inner, &self.inner, s => s.memory_usage());
+        memory_usage_to_py(py, &usage)
+    }
+
+    /// Why the most recent prediction came out as it did, or None before any call.
+    ///
+    /// Returns the `last` dict of `stats`, including `windowed`, the diffed
+    /// `a_window` as a `(start, end)` tuple (None when nothing was diffed),
+    /// `b_window_start`, and the `anchor` matching ended on: "at" (with
+    /// `anchor_pos` and `anchor_match_len`), "start_of_a", "no_match", "miss"
+    /// or "skipped". Fallback policies apply after this anchor.
+    fn last_call_info<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let last = dispatch!(Inner, &self.inner, s => s.last_call_info());
+        last.map(|call| call_stats_to_py(py, &call)).transpose()
+    }
+
+    /// Calls `callback(info)` after every prediction, e.g. to log or export
+    /// metrics without a second pass in Python; None removes it.
+    ///
+    /// `info` is the dict `last_call_info` returns, plus the prediction's
+    /// `start` offset in `a`, `match_len` and `chunk_len`. The callback runs on
+    /// the predicting thread; exceptions it raises are reported as unraisable.
+    #[pyo3(text_signature = "(callback)")]
+    fn set_hook(&mut self, callback: Option<PyObject>) {
+        dispatch!(Inner, &mut self.inner, s => set_hook_impl(s, callback))
+    }
+
+    /// How many matching blocks of each length the diffs found, as a dict
+    /// from length to count, e.g. to tune `min_match_len` and window sizes.
+    ///
+    /// Args:
+    ///     last (bool): Only the most recent diff instead of all diffs since
+    ///         the last `reset_stats`.
+    #[pyo3(signature = (last = false), text_signature = "(last=False)")]
+    fn match_length_histogram(&self, last: bool) -> BTreeMap<usize, u64> {
+        let histogram = match last {
+            true => dispatch!(Inner, &self.inner, s => s.last_match_lengths()),
+            false => dispatch!(Inner, &self.inner, s => s.match_lengths()),
+        };
+        histogram.counts().clone()
+    }
+
+    /// Clears the statistics returned by `stats` and `match_length_histogram`.
+    fn reset_stats(&self) {
+        dispatch!(Inner, &self.inner, s => s.reset_stats())
+    }
+
+    /// Forgets all appended tokens.
+    fn reset(&mut self) {
+        dispatch!(Inner, &mut self.inner, s => s.reset())
+    }
+
+    /// Saves the reference, options, appended tokens, anchors and statistics
+    /// to `path` in a compact binary format, so the session can be restored
+    /// with `load`, e.g. after a worker restart. The hook isn't saved.
+    ///
+    /// Raises:
+    ///     ValueError: When created with `whitespace_ids` or `normalize`, which
+    ///         can't be saved.
+    #[pyo3(text_signature = "(path)")]
+    fn save(&self, py: Python<'_>, path: std::path::PathBuf) -> PyResult<()> {
+        dispatch!(Inner, &self.inner, s => {
+            if s.normalizer().is_some() {
+                return Err(pyo3::exceptions::PyValueError::new_err("instances with whitespace_ids or normalize can't be saved"));
+            }
+            py.allow_threads(|| s.save(path)).map_err(state_err)
+        })
+    }
+
+    /// Restores an instance saved with `save`.
+    ///
+    /// Args:
+    ///     path (str | os.PathLike): The file written by `save`.
+    ///     dtype (str): The dtype of the saved instance; a wider one works too.
+    #[staticmethod]
+    #[pyo3(signature = (path, dtype = "int32"), text_signature = "(path, dtype='int32')")]
+    fn load(py: Python<'_>, path: std::path::PathBuf, dtype: &str) -> PyResult<Self> {
+        let dtype = DType::parse(dtype)?;
+        let inner = new_by_dtype!(Inner, dtype, T => py.allow_threads(|| StreamNextChunk::<T>::load(path)).map_err(state_err)?);
+        Ok(PyStreamNextChunk::new(inner))
+    }
+}
@@ -0,0 +1,86 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use diff::NgramNextChunk;
+
+use crate::nextchunk::PyPredictionResult;
+use crate::tokens::{with_tokens, DType, Output, PyToken};
+
+
+by_dtype! {
+    /// Concrete instantiations of the generic n-gram lookup selected by `dtype`.
+    enum Inner => NgramNextChunk
+}
+
+fn new_inner<T: PyToken>(a: &Bound<'_, PyAny>, max_ngram: usize) -> PyResult<NgramNextChunk<T>> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    Ok(NgramNextChunk::new(a, max_ngram))
+}
+
+fn next_chunk_impl<T: PyToken>(
+    py: Python<'_>,
+    ngram: &NgramNextChunk<T>,
+    current_b_py: &Bound<'_, PyAny>,
+    chunk_size: usize,
+    output: Output,
+) -> PyResult<PyPredictionResult> {
+    let result = with_tokens(current_b_py, |current_b| {
+        py.allow_threads(|| ngram.next_chunk_with_info(current_b, chunk_size))
+    })?;
+    PyPredictionResult::new(py, result, output)
+}
+
+
+/// Prompt-lookup decoding: predicts by looking up the last n tokens of `b` in
+/// an n-gram index over `a`. Cheaper than `StreamNextChunk` for short windows.
+#[pyclass(name = "NgramNextChunk", module = "stream_chunk_py", frozen)]
+pub struct PyNgramNextChunk {
+    inner: Inner,
+}
+
+#[pymethods]
+impl PyNgramNextChunk {
+    /// Args:
+    ///     a (list[int] | numpy.ndarray): The reference sequence.
+    ///     max_ngram (int): Longest n-gram to look up; shorter ones are tried on a miss.
+    ///     dtype (str): Token id type, one of "int32" (default), "uint32" or "int64".
+    #[new]
+    #[pyo3(signature = (a, max_ngram = 3, dtype = "int32"), text_signature = "(a, max_ngram=3, dtype='int32')")]
+    fn py_new(a: &Bound<'_, PyAny>, max_ngram: usize, dtype: &str) -> PyResult<Self> {
+        if max_ngram == 0 {
+            return Err(PyValueError::new_err("max_ngram must be at least 1"));
+        }
+        let inner = new_by_dtype!(Inner, DType::parse(dtype)?, T => new_inner::<T>(a, max_ngram)?);
+        Ok(PyNgramNextChunk { inner })
+    }
+
+    /// The token id type this instance was created with.
+    #[getter]
+    fn dtype(&self) -> &'static str {
+        self.inner.dtype().as_str()
+    }
+
+    #[getter]
+    fn max_ngram(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.max_ngram())
+    }
+
+    /// Predicts the next chunk of `a` following `current_b`.
+    #[pyo3(signature = (current_b, chunk_size, output = "list"), text_signature = "(current_b, chunk_size, output='list')")]
+    fn next_chunk(&self, py: Python<'_>, current_b: &Bound<'_, PyAny>, chunk_size: usize, output: &str) -> PyResult<PyObject> {
+        Ok(self.next_chunk_with_info(py, current_b, chunk_size, output)?.tokens(py))
+    }
+
+    /// Like `next_chunk`, but returns a `PredictionResult`; `match_len` is the matched n-gram size.
+    #[pyo3(signature = (current_b, chunk_size, output = "list"), text_signature = "(current_b, chunk_size, output='list')")]
+    fn next_chunk_with_info(
+        &self,
+        py: Python<'_>,
+        current_b: &Bound<'_, PyAny>,
+        chunk_size: usize,
+        output: &str,
+    ) -> PyResult<PyPredictionResult> {
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &self.inner, s => next_chunk_impl(py, s, current_b, chunk_size, output))
+    }
+}
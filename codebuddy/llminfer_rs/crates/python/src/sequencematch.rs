@@ -0,0 +1,148 @@
+use pyo3::prelude::*;
+
+use diff::{diff_json, lcs, matching_blocks, opcodes, similarity, DiffAlgorithm};
+
+use crate::tokens::{parse_algorithm, tokens_to_py, with_tokens, DType, Output, PyToken};
+
+
+fn similarity_impl<T: PyToken>(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, algorithm: DiffAlgorithm) -> PyResult<f64> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    with_tokens(b, |b| py.allow_threads(|| similarity(&a, b, algorithm)))
+}
+
+/// Similarity of two token sequences, `2 * M / (len(a) + len(b))` like
+/// `difflib.SequenceMatcher(None, a, b).ratio()`, without leaving Rust.
+#[pyfunction(name = "similarity")]
+#[pyo3(signature = (a, b, dtype = "int32", algorithm = "histogram"), text_signature = "(a, b, dtype='int32', algorithm='histogram')")]
+pub fn py_similarity(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, dtype: &str, algorithm: &str) -> PyResult<f64> {
+    let algorithm = parse_algorithm(algorithm)?;
+    match DType::parse(dtype)? {
+        DType::I32 => similarity_impl::<i32>(py, a, b, algorithm),
+        DType::U32 => similarity_impl::<u32>(py, a, b, algorithm),
+        DType::I64 => similarity_impl::<i64>(py, a, b, algorithm),
+    }
+}
+
+fn matching_blocks_impl<T: PyToken>(
+    py: Python<'_>,
+    a: &Bound<'_, PyAny>,
+    b: &Bound<'_, PyAny>,
+    algorithm: DiffAlgorithm,
+) -> PyResult<Vec<(usize, usize, usize)>> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    with_tokens(b, |b| py.allow_threads(|| matching_blocks(&a, b, algorithm)))
+}
+
+/// Drop-in for `difflib.SequenceMatcher(None, a, b).get_matching_blocks()`.
+///
+/// Returns:
+///     list[difflib.Match]: `(a, b, size)` blocks in increasing order, ending
+///     with the `(len(a), len(b), 0)` sentinel.
+#[pyfunction(name = "matching_blocks")]
+#[pyo3(signature = (a, b, dtype = "int32", algorithm = "histogram"), text_signature = "(a, b, dtype='int32', algorithm='histogram')")]
+pub fn py_matching_blocks<'py>(
+    py: Python<'py>,
+    a: &Bound<'py, PyAny>,
+    b: &Bound<'py, PyAny>,
+    dtype: &str,
+    algorithm: &str,
+) -> PyResult<Vec<Bound<'py, PyAny>>> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let blocks = match DType::parse(dtype)? {
+        DType::I32 => matching_blocks_impl::<i32>(py, a, b, algorithm),
+        DType::U32 => matching_blocks_impl::<u32>(py, a, b, algorithm),
+        DType::I64 => matching_blocks_impl::<i64>(py, a, b, algorithm),
+    }?;
+    // Return difflib's own namedtuple so `.a`/`.b`/`.size` keep working
+    let match_cls = py.import("difflib")?.getattr("Match")?;
+    blocks.into_iter().map(|block| match_cls.call1(block)).collect()
+}
+
+/// An opcode as handed to Python: `(tag, i1, i2, j1, j2)`.
+type PyOpcode = (&'static str, u32, u32, u32, u32);
+
+fn opcodes_impl<T: PyToken>(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, algorithm: DiffAlgorithm) -> PyResult<Vec<PyOpcode>> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    let ops = with_tokens(b, |b| py.allow_threads(|| opcodes(&a, b, algorithm)))?;
+    Ok(ops.into_iter().map(|op| (op.tag.as_str(), op.a.start, op.a.end, op.b.start, op.b.end)).collect())
+}
+
+/// Drop-in for `difflib.SequenceMatcher(None, a, b).get_opcodes()`.
+///
+/// Returns:
+///     list[tuple[str, int, int, int, int]]: `(tag, i1, i2, j1, j2)` with tag one of
+///     "equal", "insert", "delete" or "replace": `a[i1:i2]` becomes `b[j1:j2]`.
+#[pyfunction(name = "opcodes")]
+#[pyo3(signature = (a, b, dtype = "int32", algorithm = "histogram"), text_signature = "(a, b, dtype='int32', algorithm='histogram')")]
+pub fn py_opcodes(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, dtype: &str, algorithm: &str) -> PyResult<Vec<PyOpcode>> {
+    let algorithm = parse_algorithm(algorithm)?;
+    match DType::parse(dtype)? {
+        DType::I32 => opcodes_impl::<i32>(py, a, b, algorithm),
+        DType::U32 => opcodes_impl::<u32>(py, a, b, algorithm),
+        DType::I64 => opcodes_impl::<i64>(py, a, b, algorithm),
+    }
+}
+
+fn diff_json_impl<T: PyToken>(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, algorithm: DiffAlgorithm) -> PyResult<String> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    with_tokens(b, |b| py.allow_threads(|| diff_json(&a, b, algorithm)))
+}
+
+/// The diff of `a` and `b` as a JSON document, ready for `json.loads`.
+///
+/// Returns:
+///     str: `{"algorithm", "a_len", "b_len", "hunks"}`, each hunk being
+///     `{"op", "a_start", "a_end", "b_start", "b_end", "tokens"}` with op one of
+///     "equal", "insert" or "delete" (replacements become a delete then an insert).
+///     `tokens` holds the hunk's tokens from `a`, or from `b` for inserts.
+#[pyfunction(name = "diff_json")]
+#[pyo3(signature = (a, b, dtype = "int32", algorithm = "histogram"), text_signature = "(a, b, dtype='int32', algorithm='histogram')")]
+pub fn py_diff_json(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>, dtype: &str, algorithm: &str) -> PyResult<String> {
+    let algorithm = parse_algorithm(algorithm)?;
+    match DType::parse(dtype)? {
+        DType::I32 => diff_json_impl::<i32>(py, a, b, algorithm),
+        DType::U32 => diff_json_impl::<u32>(py, a, b, algorithm),
+        DType::I64 => diff_json_impl::<i64>(py, a, b, algorithm),
+    }
+}
+
+fn lcs_impl<T: PyToken>(
+    py: Python<'_>,
+    a: &Bound<'_, PyAny>,
+    b: &Bound<'_, PyAny>,
+    algorithm: DiffAlgorithm,
+    output: Output,
+) -> PyResult<(PyObject, Vec<(usize, usize)>)> {
+    let a = with_tokens(a, <[T]>::to_vec)?;
+    let result = with_tokens(b, |b| py.allow_threads(|| lcs(&a, b, algorithm)))?;
+    Ok((tokens_to_py(py, &result.tokens, output)?, result.pairs))
+}
+
+/// Common token subsequence of `a` and `b`, built from the diff's matches.
+///
+/// Exactly the longest one with `algorithm="myers_minimal"`.
+///
+/// Returns:
+///     tuple[list[int], list[tuple[int, int]]]: The subsequence and, for each of
+///     its tokens, the `(i, j)` positions it was taken from in `a` and `b`.
+#[pyfunction(name = "lcs")]
+#[pyo3(
+    signature = (a, b, dtype = "int32", algorithm = "histogram", output = "list"),
+    text_signature = "(a, b, dtype='int32', algorithm='histogram', output='list')"
+)]
+pub fn py_lcs(
+    py: Python<'_>,
+    a: &Bound<'_, PyAny>,
+    b: &Bound<'_, PyAny>,
+    dtype: &str,
+    algorithm: &str,
+    output: &str,
+) -> PyResult<(PyObject, Vec<(usize, usize)>)> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let output = Output::parse(output)?;
+    match DType::parse(dtype)? {
+        DType::I32 => lcs_impl::<i32>(py, a, b, algorithm, output),
+        DType::U32 => lcs_impl::<u32>(py, a, b, algorithm, output),
+        DType::I64 => lcs_impl::<i64>(py, a, b, algorithm, output),
+    }
+}
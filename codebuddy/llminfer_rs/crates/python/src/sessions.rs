@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::Duration;
+
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use diff::{Evicted, NextChunkOptions, SessionLimits, SessionPool, StreamNextChunk};
+
+use crate::nextchunk::memory_usage_to_py;
+use crate::tokens::{parse_algorithm, parse_matcher, tokens_to_py, with_tokens, DType, Output, PyToken};
+
+
+/// Caller-chosen session id.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, FromPyObject, IntoPyObject)]
+enum SessionId {
+    Int(i64),
+    Str(String),
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionId::Int(id) => write!(f, "{id}"),
+            SessionId::Str(id) => write!(f, "{id:?}"),
+        }
+    }
+}
+
+fn unknown(id: &SessionId) -> PyErr {
+    PyKeyError::new_err(format!("unknown session {id}"))
+}
+
+/// Stateful streamers by session id, in a [`SessionPool`] enforcing the
+/// limits, plus the references they share.
+struct Sessions<T: Eq + std::hash::Hash> {
+    options: NextChunkOptions,
+    on_evict: Option<PyObject>,
+    pool: SessionPool<SessionId, StreamNextChunk<T>>,
+    /// References of open sessions by content hash, so sessions over the same
+    /// reference share one buffer.
+    references: Mutex<HashMap<u64, Weak<[T]>>>,
+    hasher: RandomState,
+}
+
+impl<T: PyToken> Sessions<T> {
+    fn new(options: NextChunkOptions, limits: SessionLimits, on_evict: Option<PyObject>) -> Self {
+        Sessions { options, on_evict, pool: SessionPool::new(limits), references: Mutex::default(), hasher: RandomState::new() }
+    }
+
+    /// `tokens` as a buffer shared with the open sessions having the same
+    /// reference, copied only if there is none.
+    fn shared_reference(&self, tokens: &[T]) -> Arc<[T]> {
+        let hash = self.hasher.hash_one(tokens);
+        let mut references = self.references.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(shared) = references.get(&hash).and_then(Weak::upgrade).filter(|shared| **shared == *tokens) {
+            return shared;
+        }
+        references.retain(|_, reference| reference.strong_count() > 0);
+        let shared: Arc<[T]> = Arc::from(tokens);
+        references.insert(hash, Arc::downgrade(&shared));
+        shared
+    }
+
+    /// Passes each eviction to the `on_evict` callback. Errors are reported as
+    /// unraisable, since the call that evicted succeeded regardless.
+    fn report(&self, py: Python<'_>, evicted: Evicted<SessionId>) {
+        let Some(callback) = &self.on_evict else { return };
+        for (id, reason) in evicted {
+            if let Err(err) = callback.call1(py, (id, reason.as_str())) {
+                err.write_unraisable(py, Some(callback.bind(py)));
+            }
+        }
+    }
+
+    fn create(&self, py: Python<'_>, id: SessionId, reference: &Bound<'_, PyAny>) -> PyResult<()> {
+        let reference = with_tokens(reference, |tokens| self.shared_reference(tokens))?;
+        let (options, tokens) = (self.options.clone(), reference.len());
+        let streamer = py.allow_threads(|| StreamNextChunk::with_shared_slice(reference, options));
+        let evicted = self
+            .pool
+            .insert(id.clone(), streamer, tokens)
+            .map_err(|_| PyValueError::new_err(format!("session {id} already exists")))?;
+        self.report(py, evicted);
+        Ok(())
+    }
+
+    fn predict(&self, py: Python<'_>, id: &SessionId, new_tokens: &Bound<'_, PyAny>, n: usize, output: Output) -> PyResult<PyObject> {
+        let (streamer, evicted) = self.pool.get(id);
+        self.report(py, evicted);
+        let streamer = streamer.ok_or_else(|| unknown(id))?;
+        let new_tokens = with_tokens(new_tokens, <[T]>::to_vec)?;
+        let (chunk, tokens) = py.allow_threads(|| {
+            let mut streamer = streamer.lock().unwrap_or_else(PoisonError::into_inner);
+            streamer.append(&new_tokens);
+            let chunk = streamer.predict(n).to_vec();
+            (chunk, streamer.reference().len() + streamer.appended().len())
+        });
+        self.report(py, self.pool.set_tokens(id, tokens));
+        tokens_to_py(py, &chunk, output)
+    }
+
+    fn streamer(&self, id: &SessionId) -> PyResult<Arc<Mutex<StreamNextChunk<T>>>> {
+        self.pool.peek(id).ok_or_else(|| unknown(id))
+    }
+
+    fn drop(&self, id: &SessionId) -> PyResult<()> {
+        self.pool.remove(id).map(drop).ok_or_else(|| unknown(id))
+    }
+
+    fn evict(&self, py: Python<'_>) -> Vec<SessionId> {
+        let evicted = self.pool.evict();
+        let ids = evicted.iter().map(|(id, _)| id.clone()).collect();
+        self.report(py, evicted);
+        ids
+    }
+}
+
+by_dtype! {
+    /// Concrete instantiations of the generic session map selected by `dtype`.
+    enum Inner => Sessions
+}
+
+
+/// Many stateful `StreamNextChunk` streams keyed by session id (int or
+/// str), e.g. one per request of an inference server, without keeping a
+/// Python object per stream.
+///
+/// All sessions share the options given here, and sessions created with
+/// identical references share one copy of it. Methods may be called from
+/// any number of threads; predictions on different sessions run in parallel
+/// without the GIL.
+///
+/// So that leaked sessions can't grow a long-running server without bound,
+/// the manager can close sessions itself: those idle for longer than `ttl`,
+/// then the least recently used ones while over `max_sessions` or
+/// `max_tokens`. Limits are enforced on `create` and `predict` (and `evict`);
+/// the session being created or predicted is never evicted for capacity.
+#[pyclass(name = "SessionManager", module = "stream_chunk_py", frozen)]
+pub struct PySessionManager {
+    inner: Inner,
+}
+
+#[pymethods]
+impl PySessionManager {
+    /// Args:
+    ///     dtype, algorithm, matcher, min_match_len, max_mismatches: As for
+    ///         `StreamNextChunk`, applied to every session.
+    ///     max_sessions (int | None): Most sessions kept open.
+    ///     max_tokens (int | None): Most reference plus appended tokens kept
+    ///         over all sessions, a proxy for their memory.
+    ///     ttl (float | None): Seconds without `predict` after which a session
+    ///         is closed.
+    ///     memory_budget (int | None): As for `StreamNextChunk`, per session: caps
+    ///         the bytes of each session's interning tables and indexes, see
+    ///         `memory_usage`.
+    ///     on_evict (Callable[[int | str, str], None] | None): Called with the
+    ///         id and the reason, "expired" or "capacity", of every session the
+    ///         manager closes. Exceptions are reported as unraisable.
+    #[new]
+    #[pyo3(
+        signature = (dtype = "int32", algorithm = "histogram", matcher = "diff", min_match_len = 1, max_mismatches = 0, max_sessions = None, max_tokens = None, ttl = None, on_evict = None, memory_budget = None),
+        text_signature = "(dtype='int32', algorithm='histogram', matcher='diff', min_match_len=1, max_mismatches=0, max_sessions=None, max_tokens=None, ttl=None, on_evict=None, memory_budget=None)"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        dtype: &str,
+        algorithm: &str,
+        matcher: &str,
+        min_match_len: usize,
+        max_mismatches: usize,
+        max_sessions: Option<usize>,
+        max_tokens: Option<usize>,
+        ttl: Option<f64>,
+        on_evict: Option<PyObject>,
+        memory_budget: Option<usize>,
+    ) -> PyResult<Self> {
+        let options = NextChunkOptions {
+            algorithm: parse_algorithm(algorithm)?,
+            matcher: parse_matcher(matcher)?,
+            min_match_len,
+            max_mismatches,
+            memory_budget,
+            ..Default::default()
+        };
+        if max_sessions == Some(0) {
+            return Err(PyValueError::new_err("max_sessions must be positive"));
+        }
+        let ttl = ttl
+            .map(|secs| Duration::try_from_secs_f64(secs).map_err(|_| PyValueError::new_err(format!("invalid ttl {secs}"))))
+            .transpose()?;
+        let limits = SessionLimits { max_sessions, max_tokens, ttl };
+        let inner = new_by_dtype!(Inner, DType::parse(dtype)?, T => Sessions::<T>::new(options, limits, on_evict));
+        Ok(PySessionManager { inner })
+    }
+
+    /// The token id type of the sessions.
+    #[getter]
+    fn dtype(&self) -> &'static str {
+        self.inner.dtype().as_str()
+    }
+
+    /// Opens session `id` predicting from `reference`; raises `ValueError`
+    /// if it is already open.
+    #[pyo3(text_signature = "(id, reference)")]
+    fn create(&self, py: Python<'_>, id: SessionId, reference: &Bound<'_, PyAny>) -> PyResult<()> {
+        dispatch!(Inner, &self.inner, s => s.create(py, id, reference))
+    }
+
+    /// Appends `new_tokens` (possibly empty) to session `id` and predicts the
+    /// next `n` tokens, as `StreamNextChunk.append` then `predict` would.
+    /// Raises `KeyError` for unknown sessions.
+    #[pyo3(signature = (id, new_tokens, n, output = "list"), text_signature = "(id, new_tokens, n, output='list')")]
+    fn predict(&self, py: Python<'_>, id: SessionId, new_tokens: &Bound<'_, PyAny>, n: usize, output: &str) -> PyResult<PyObject> {
+        let output = Output::parse(output)?;
+        dispatch!(Inner, &self.inner, s => s.predict(py, &id, new_tokens, n, output))
+    }
+
+    /// `StreamNextChunk.memory_usage` of session `id`; raises `KeyError` for
+    /// unknown sessions.
+    #[pyo3(text_signature = "(id)")]
+    fn memory_usage<'py>(&self, py: Python<'py>, id: SessionId) -> PyResult<Bound<'py, PyDict>> {
+        let usage = dispatch!(Inner, &self.inner, s => s.streamer(&id)?.lock().unwrap_or_else(PoisonError::into_inner).memory_usage());
+        memory_usage_to_py(py, &usage)
+    }
+
+    /// Closes session `id`, freeing its streamer; raises `KeyError` for unknown sessions.
+    #[pyo3(text_signature = "(id)")]
+    fn drop(&self, id: SessionId) -> PyResult<()> {
+        dispatch!(Inner, &self.inner, s => s.drop(&id))
+    }
+
+    /// Applies the limits now, e.g. periodically to close expired sessions
+    /// without waiting for the next call, and returns the evicted ids.
+    #[pyo3(text_signature = "()")]
+    fn evict(&self, py: Python<'_>) -> Vec<SessionId> {
+        dispatch!(Inner, &self.inner, s => s.evict(py))
+    }
+
+    /// Ids of the open sessions, most recently used first.
+    fn ids(&self) -> Vec<SessionId> {
+        dispatch!(Inner, &self.inner, s => s.pool.ids())
+    }
+
+    fn __len__(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.pool.len())
+    }
+
+    /// Distinct reference buffers held by the open sessions.
+    fn shared_references(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => {
+            let references = s.references.lock().unwrap_or_else(PoisonError::into_inner);
+            references.values().filter(|reference| reference.strong_count() > 0).count()
+        })
+    }
+
+    fn __contains__(&self, id: SessionId) -> bool {
+        dispatch!(Inner, &self.inner, s => s.pool.contains(&id))
+    }
+}
@@ -0,0 +1,69 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use diff::{simulate_with_options, NextChunkOptions, SimulationReport};
+
+use crate::tokens::{parse_algorithm, parse_matcher, with_tokens, DType, PyToken};
+
+
+fn simulate_impl<T: PyToken>(
+    py: Python<'_>,
+    reference: &Bound<'_, PyAny>,
+    target: &Bound<'_, PyAny>,
+    chunk_size: usize,
+    options: NextChunkOptions,
+) -> PyResult<SimulationReport> {
+    let reference = with_tokens(reference, <[T]>::to_vec)?;
+    with_tokens(target, |target| py.allow_threads(|| simulate_with_options(&reference, target, chunk_size, options)))
+}
+
+/// Replays generating `target` with chunks predicted from `reference`, to
+/// evaluate prediction changes on recorded traces.
+///
+/// Each iteration predicts `chunk_size` tokens from the target so far,
+/// accepts their prefix matching the target and advances by that many
+/// tokens, or by one when nothing was accepted.
+///
+/// Returns:
+///     dict: `iterations`, `total_predicted`, `total_accepted`,
+///     `acceptance_rate`, the per-iteration `predicted`, `accepted` and
+///     `latency_s` lists, and `first_prediction_s` / `total_s` timings in seconds.
+#[pyfunction(name = "simulate")]
+#[pyo3(
+    signature = (reference, target, chunk_size, dtype = "int32", algorithm = "histogram", matcher = "diff", min_match_len = 1),
+    text_signature = "(reference, target, chunk_size, dtype='int32', algorithm='histogram', matcher='diff', min_match_len=1)"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn py_simulate<'py>(
+    py: Python<'py>,
+    reference: &Bound<'py, PyAny>,
+    target: &Bound<'py, PyAny>,
+    chunk_size: usize,
+    dtype: &str,
+    algorithm: &str,
+    matcher: &str,
+    min_match_len: usize,
+) -> PyResult<Bound<'py, PyDict>> {
+    let options = NextChunkOptions {
+        algorithm: parse_algorithm(algorithm)?,
+        matcher: parse_matcher(matcher)?,
+        min_match_len,
+        ..Default::default()
+    };
+    let report = match DType::parse(dtype)? {
+        DType::I32 => simulate_impl::<i32>(py, reference, target, chunk_size, options)?,
+        DType::U32 => simulate_impl::<u32>(py, reference, target, chunk_size, options)?,
+        DType::I64 => simulate_impl::<i64>(py, reference, target, chunk_size, options)?,
+    };
+    let dict = PyDict::new(py);
+    dict.set_item("iterations", report.iterations())?;
+    dict.set_item("total_predicted", report.total_predicted())?;
+    dict.set_item("total_accepted", report.total_accepted())?;
+    dict.set_item("acceptance_rate", report.acceptance_rate())?;
+    dict.set_item("predicted", report.steps.iter().map(|step| step.predicted).collect::<Vec<_>>())?;
+    dict.set_item("accepted", report.steps.iter().map(|step| step.accepted).collect::<Vec<_>>())?;
+    dict.set_item("latency_s", report.steps.iter().map(|step| step.latency.as_secs_f64()).collect::<Vec<_>>())?;
+    dict.set_item("first_prediction_s", report.first_prediction.as_secs_f64())?;
+    dict.set_item("total_s", report.total.as_secs_f64())?;
+    Ok(dict)
+}
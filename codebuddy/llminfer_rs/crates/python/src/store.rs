@@ -0,0 +1,129 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use diff::{NextChunkOptions, ReferenceStore, StreamNextChunk};
+
+use crate::nextchunk::{Inner as StreamerInner, PyStreamNextChunk};
+use crate::tokens::{parse_algorithm, tokens_to_py, with_tokens, DType, Output, PyToken};
+
+
+by_dtype! {
+    /// Concrete instantiations of the generic reference store selected by `dtype`.
+    enum Inner => ReferenceStore
+}
+
+fn add_impl<T: PyToken>(store: &mut ReferenceStore<T>, document_py: &Bound<'_, PyAny>) -> PyResult<usize> {
+    let document = with_tokens(document_py, <[T]>::to_vec)?;
+    Ok(store.add(document))
+}
+
+fn new_inner<T: PyToken>(
+    documents: Option<&Bound<'_, PyAny>>,
+    ngram_len: usize,
+    options: NextChunkOptions,
+) -> PyResult<ReferenceStore<T>> {
+    let mut store = ReferenceStore::new(ngram_len, options);
+    if let Some(documents) = documents {
+        for document in documents.try_iter()? {
+            add_impl(&mut store, &document?)?;
+        }
+    }
+    Ok(store)
+}
+
+fn retrieve_impl<T: PyToken>(py: Python<'_>, store: &ReferenceStore<T>, tail_py: &Bound<'_, PyAny>, k: usize) -> PyResult<Vec<(usize, usize)>> {
+    with_tokens(tail_py, |tail| py.allow_threads(|| store.retrieve(tail, k)))
+}
+
+fn stream_next_chunk_impl<T: PyToken>(
+    py: Python<'_>,
+    store: &ReferenceStore<T>,
+    tail_py: &Bound<'_, PyAny>,
+) -> PyResult<Option<(usize, StreamNextChunk<T>)>> {
+    with_tokens(tail_py, |tail| py.allow_threads(|| store.stream_next_chunk(tail)))
+}
+
+
+/// Many candidate reference documents (e.g. the source files of a
+/// repository) behind an n-gram inverted index, to find the one the output
+/// is copying from and speculate from it.
+#[pyclass(name = "ReferenceStore", module = "stream_chunk_py")]
+pub struct PyReferenceStore {
+    inner: Inner,
+}
+
+#[pymethods]
+impl PyReferenceStore {
+    /// Args:
+    ///     documents (Iterable[list[int] | numpy.ndarray] | None): Documents to index,
+    ///         with ids 0, 1, ... in order.
+    ///     ngram_len (int): Length of the indexed n-grams.
+    ///     dtype (str): Token id type, one of "int32" (default), "uint32" or "int64".
+    ///     algorithm (str): Diff algorithm of the streamers built, one of "histogram"
+    ///         (default), "myers" or "myers_minimal".
+    #[new]
+    #[pyo3(
+        signature = (documents = None, ngram_len = 4, dtype = "int32", algorithm = "histogram"),
+        text_signature = "(documents=None, ngram_len=4, dtype='int32', algorithm='histogram')"
+    )]
+    fn py_new(documents: Option<&Bound<'_, PyAny>>, ngram_len: usize, dtype: &str, algorithm: &str) -> PyResult<Self> {
+        if ngram_len == 0 {
+            return Err(PyValueError::new_err("ngram_len must be at least 1"));
+        }
+        let options = NextChunkOptions { algorithm: parse_algorithm(algorithm)?, ..Default::default() };
+        let inner = new_by_dtype!(Inner, DType::parse(dtype)?, T => new_inner::<T>(documents, ngram_len, options)?);
+        Ok(PyReferenceStore { inner })
+    }
+
+    /// The token id type this instance was created with.
+    #[getter]
+    fn dtype(&self) -> &'static str {
+        self.inner.dtype().as_str()
+    }
+
+    #[getter]
+    fn ngram_len(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.ngram_len())
+    }
+
+    fn __len__(&self) -> usize {
+        dispatch!(Inner, &self.inner, s => s.len())
+    }
+
+    /// Indexes `document` and returns its id.
+    #[pyo3(text_signature = "(document)")]
+    fn add(&mut self, document: &Bound<'_, PyAny>) -> PyResult<usize> {
+        dispatch!(Inner, &mut self.inner, s => add_impl(s, document))
+    }
+
+    /// Returns the document with id `id` as a list, None when there is none.
+    #[pyo3(text_signature = "(id)")]
+    fn document(&self, py: Python<'_>, id: usize) -> PyResult<Option<PyObject>> {
+        dispatch!(Inner, &self.inner, s => s.document(id).map(|document| tokens_to_py(py, document, Output::List)).transpose())
+    }
+
+    /// Up to `k` `(id, hits)` pairs of the documents sharing the most n-grams
+    /// with `tail`, the end of the sequence so far (e.g. its last 64 tokens),
+    /// most hits first.
+    #[pyo3(signature = (tail, k = 4), text_signature = "(tail, k=4)")]
+    fn retrieve(&self, py: Python<'_>, tail: &Bound<'_, PyAny>, k: usize) -> PyResult<Vec<(usize, usize)>> {
+        dispatch!(Inner, &self.inner, s => retrieve_impl(py, s, tail, k))
+    }
+
+    /// A `StreamNextChunk` over the document `retrieve` ranks best for
+    /// `tail`, with its id, or None when no document shares an n-gram with
+    /// it. Keep the streamer for the rest of the generation: building it
+    /// interns the document.
+    ///
+    /// Returns:
+    ///     tuple[int, StreamNextChunk] | None
+    #[pyo3(text_signature = "(tail)")]
+    fn stream_next_chunk(&self, py: Python<'_>, tail: &Bound<'_, PyAny>) -> PyResult<Option<(usize, PyStreamNextChunk)>> {
+        let found = match &self.inner {
+            Inner::I32(s) => stream_next_chunk_impl(py, s, tail)?.map(|(id, streamer)| (id, StreamerInner::I32(streamer))),
+            Inner::U32(s) => stream_next_chunk_impl(py, s, tail)?.map(|(id, streamer)| (id, StreamerInner::U32(streamer))),
+            Inner::I64(s) => stream_next_chunk_impl(py, s, tail)?.map(|(id, streamer)| (id, StreamerInner::I64(streamer))),
+        };
+        Ok(found.map(|(id, inner)| (id, PyStreamNextChunk::new(inner))))
+    }
+}
@@ -0,0 +1,77 @@
+use pyo3::prelude::*;
+
+use diff::{unified_diff, TextDiff};
+
+use crate::changes::{to_py_changes, Change};
+use crate::tokens::parse_algorithm;
+
+
+/// Line-based diff of two strings, for checking outputs at the text level.
+///
+/// All ranges are half-open line ranges.
+#[pyclass(name = "TextDiff", module = "stream_chunk_py", frozen)]
+pub struct PyTextDiff {
+    inner: TextDiff,
+}
+
+#[pymethods]
+impl PyTextDiff {
+    /// Args:
+    ///     before (str): The reference text.
+    ///     after (str): The text to compare against it.
+    ///     algorithm (str): Diff algorithm, one of "histogram" (default), "myers" or "myers_minimal".
+    #[new]
+    #[pyo3(signature = (before, after, algorithm = "histogram"), text_signature = "(before, after, algorithm='histogram')")]
+    fn py_new(py: Python<'_>, before: &str, after: &str, algorithm: &str) -> PyResult<Self> {
+        let algorithm = parse_algorithm(algorithm)?;
+        let inner = py.allow_threads(|| TextDiff::new(before, after, algorithm));
+        Ok(PyTextDiff { inner })
+    }
+
+    /// Number of lines in `before`.
+    #[getter]
+    fn before_lines(&self) -> u32 {
+        self.inner.before_lines()
+    }
+
+    /// Number of lines in `after`.
+    #[getter]
+    fn after_lines(&self) -> u32 {
+        self.inner.after_lines()
+    }
+
+    /// Changed lines as `((before_start, before_end), (after_start, after_end))`.
+    #[getter]
+    fn changes(&self) -> Vec<Change> {
+        to_py_changes(self.inner.changes())
+    }
+
+    /// Unchanged lines, in the same format as `changes`.
+    #[getter]
+    fn matches(&self) -> Vec<Change> {
+        to_py_changes(self.inner.matches())
+    }
+
+    /// Whether both texts have the same lines.
+    fn is_unchanged(&self) -> bool {
+        self.inner.is_unchanged()
+    }
+}
+
+
+/// Line-based unified diff hunks (`@@ -a,n +b,m @@`) of `a_text` against `b_text`.
+///
+/// Args:
+///     a_text (str): The reference text.
+///     b_text (str): The text to compare against it.
+///     context (int): Unchanged lines shown around each change.
+///     algorithm (str): Diff algorithm, one of "histogram" (default), "myers" or "myers_minimal".
+///
+/// Returns:
+///     str: The hunks without a `---`/`+++` header; empty when the lines are identical.
+#[pyfunction(name = "unified_diff")]
+#[pyo3(signature = (a_text, b_text, context = 3, algorithm = "histogram"), text_signature = "(a_text, b_text, context=3, algorithm='histogram')")]
+pub fn py_unified_diff(py: Python<'_>, a_text: &str, b_text: &str, context: u32, algorithm: &str) -> PyResult<String> {
+    let algorithm = parse_algorithm(algorithm)?;
+    Ok(py.allow_threads(|| unified_diff(a_text, b_text, context, algorithm)))
+}
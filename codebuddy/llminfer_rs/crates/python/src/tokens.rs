@@ -0,0 +1,277 @@
+use std::any::Any;
+use std::hash::Hash;
+
+use numpy::ndarray::ArrayView1;
+use numpy::{Element, PyArray1, PyReadonlyArray1};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+use pyo3::IntoPyObjectExt;
+use serde::Serialize;
+
+use diff::{AnchorStrategy, DiffAlgorithm, FallbackPolicy, MatcherBackend, SharedTokens, StreamNextChunk};
+
+use crate::arrow::{is_arrow_array, with_arrow_tokens};
+use crate::dlpack::{is_dlpack_tensor, with_dlpack_tokens};
+
+
+/// Token types the Python bindings can be instantiated with.
+pub trait PyToken:
+    'static + Eq + Hash + Copy + Send + Sync + Serialize + Element + for<'py> FromPyObject<'py> + for<'py> IntoPyObject<'py>
+{
+}
+
+impl<T> PyToken for T where
+    T: 'static + Eq + Hash + Copy + Send + Sync + Serialize + Element + for<'py> FromPyObject<'py> + for<'py> IntoPyObject<'py>
+{
+}
+
+/// How token sequences are handed back to Python.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    List,
+    Numpy,
+    /// Read-only numpy array borrowing the reference instead of copying it.
+    View,
+}
+
+impl Output {
+    pub fn parse(output: &str) -> PyResult<Self> {
+        match output {
+            "list" => Ok(Output::List),
+            "numpy" => Ok(Output::Numpy),
+            "view" => Ok(Output::View),
+            other => Err(PyValueError::new_err(format!(
+                "unsupported output {other:?}, expected 'list', 'numpy' or 'view'"
+            ))),
+        }
+    }
+}
+
+/// Whether `obj` could be a numpy array.
+///
+/// rust-numpy panics when numpy can't be imported, so only look for arrays
+/// once the caller's process has numpy loaded.
+fn maybe_ndarray(obj: &Bound<'_, PyAny>) -> bool {
+    if obj.is_instance_of::<PyList>() {
+        return false;
+    }
+    let py = obj.py();
+    py.import("sys")
+        .and_then(|sys| sys.getattr("modules"))
+        .and_then(|modules| modules.contains("numpy"))
+        .unwrap_or(false)
+}
+
+/// Calls `f` with the tokens in `obj`.
+///
+/// Contiguous numpy arrays, Arrow arrays (e.g. `pyarrow.Int32Array`) and
+/// CPU DLPack tensors (e.g. torch tensors) of the matching dtype are read in
+/// place; anything else (lists, tuples, arrays and tensors of another dtype)
+/// is extracted into a Vec first.
+pub fn with_tokens<T: PyToken, R>(obj: &Bound<'_, PyAny>, f: impl FnOnce(&[T]) -> R) -> PyResult<R> {
+    if is_arrow_array(obj) {
+        return with_arrow_tokens(obj, f);
+    }
+    if maybe_ndarray(obj) {
+        if let Ok(array) = obj.extract::<PyReadonlyArray1<'_, T>>() {
+            if let Ok(tokens) = array.as_slice() {
+                return Ok(f(tokens));
+            }
+            let tokens = array.as_array().to_vec();
+            return Ok(f(&tokens));
+        }
+    }
+    if is_dlpack_tensor(obj) {
+        return match with_dlpack_tokens(obj, f)? {
+            Ok(result) => Ok(result),
+            // Another dtype or strided: let the tensor convert itself
+            Err(f) => Ok(f(&obj.call_method0("tolist")?.extract::<Vec<T>>()?)),
+        };
+    }
+    let tokens: Vec<T> = obj.extract()?;
+    Ok(f(&tokens))
+}
+
+/// Converts predicted tokens to the requested Python representation.
+pub fn tokens_to_py<T: PyToken>(py: Python<'_>, tokens: &[T], output: Output) -> PyResult<PyObject> {
+    match output {
+        Output::List => tokens.to_vec().into_py_any(py),
+        Output::Numpy => PyArray1::from_slice(py, tokens).into_py_any(py),
+        Output::View => Err(PyValueError::new_err("output='view' is not supported here")),
+    }
+}
+
+/// Base object of `output="view"` arrays: a handle keeping the reference
+/// they point into alive, however the instance they came from changes.
+#[pyclass(name = "ReferenceBuffer", module = "stream_chunk_py", frozen)]
+struct PyReferenceBuffer {
+    _tokens: Box<dyn Any + Send + Sync>,
+}
+
+/// Moves `streamer`'s reference behind a shared handle before an
+/// `output="view"` call, so the views can keep it alive. Only done when views
+/// are asked for: modifying a shared reference copies it.
+pub fn share_for_view<T: PyToken>(streamer: &mut StreamNextChunk<T>, output: Output) {
+    if output == Output::View {
+        streamer.share_reference();
+    }
+}
+
+/// The handle on `streamer`'s reference that `output="view"` arrays keep as
+/// their base object; `None` for other outputs or while the reference isn't
+/// shared (see [`share_for_view`]), in which case the tokens are copied.
+pub fn view_base<T: PyToken>(streamer: &StreamNextChunk<T>, output: Output) -> Option<SharedTokens<T>> {
+    (output == Output::View).then(|| streamer.shared_reference()).flatten()
+}
+
+/// Like [`tokens_to_py`], but `Output::View` returns a read-only numpy array
+/// over `tokens` itself, kept alive by `base` from [`view_base`]. Tokens
+/// outside of `base` are copied instead.
+pub fn tokens_to_py_view<T: PyToken>(
+    py: Python<'_>,
+    tokens: &[T],
+    output: Output,
+    base: Option<SharedTokens<T>>,
+) -> PyResult<PyObject> {
+    if output != Output::View {
+        return tokens_to_py(py, tokens, output);
+    }
+    let base = base.filter(|base| {
+        let range = (**base).as_ref().as_ptr_range();
+        tokens.is_empty() || (range.start <= tokens.as_ptr() && tokens.as_ptr_range().end <= range.end)
+    });
+    let Some(base) = base else {
+        return tokens_to_py(py, tokens, Output::Numpy);
+    };
+    let base = Bound::new(py, PyReferenceBuffer { _tokens: Box::new(base) })?;
+    let view = ArrayView1::from(tokens);
+    // SAFETY: `tokens` lies in the shared reference, which is never written
+    // to and which `base` keeps alive as the array's base object
+    let array = unsafe { PyArray1::borrow_from_array(&view, base.into_any()) };
+    array.getattr("flags")?.setattr("writeable", false)?;
+    array.into_py_any(py)
+}
+
+/// Parses a diff algorithm name, raising `ValueError` for unknown names.
+pub fn parse_algorithm(algorithm: &str) -> PyResult<DiffAlgorithm> {
+    algorithm.parse().map_err(|e: diff::ParseOptionError| PyValueError::new_err(e.to_string()))
+}
+
+/// Parses a matcher backend name, raising `ValueError` for unknown names.
+pub fn parse_matcher(matcher: &str) -> PyResult<MatcherBackend> {
+    matcher.parse().map_err(|e: diff::ParseOptionError| PyValueError::new_err(e.to_string()))
+}
+
+/// Parses an anchor strategy name, raising `ValueError` for unknown names.
+pub fn parse_anchor_strategy(strategy: &str) -> PyResult<AnchorStrategy> {
+    strategy.parse().map_err(|e: diff::ParseOptionError| PyValueError::new_err(e.to_string()))
+}
+
+/// Parses a fallback policy: `None`, a policy name or an offset of `a`.
+pub fn parse_fallback(fallback: &Bound<'_, PyAny>) -> PyResult<Option<FallbackPolicy>> {
+    if fallback.is_none() {
+        return Ok(None);
+    }
+    if let Ok(name) = fallback.extract::<&str>() {
+        return name.parse().map(Some).map_err(|e: diff::ParseOptionError| PyValueError::new_err(e.to_string()));
+    }
+    let offset: usize = fallback
+        .extract()
+        .map_err(|_| PyValueError::new_err(format!("invalid fallback {fallback}, expected a policy name or an offset")))?;
+    Ok(Some(FallbackPolicy::Offset(offset)))
+}
+
+/// The Python form of a fallback policy, as taken by `parse_fallback`.
+pub fn fallback_to_py(py: Python<'_>, fallback: Option<FallbackPolicy>) -> PyResult<PyObject> {
+    match fallback {
+        None => Ok(py.None()),
+        Some(FallbackPolicy::Offset(offset)) => offset.into_py_any(py),
+        Some(policy) => policy.to_string().into_py_any(py),
+    }
+}
+
+
+/// Token id type selected by the `dtype` argument of the constructors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DType {
+    I32,
+    U32,
+    I64,
+}
+
+impl DType {
+    pub fn parse(dtype: &str) -> PyResult<Self> {
+        match dtype {
+            "int32" | "i32" => Ok(DType::I32),
+            "uint32" | "u32" => Ok(DType::U32),
+            "int64" | "i64" => Ok(DType::I64),
+            other => Err(PyValueError::new_err(format!(
+                "unsupported dtype {other:?}, expected one of 'int32', 'uint32', 'int64'"
+            ))),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DType::I32 => "int32",
+            DType::U32 => "uint32",
+            DType::I64 => "int64",
+        }
+    }
+}
+
+/// Declares an enum holding one instantiation of a generic type per [`DType`].
+macro_rules! by_dtype {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident => $ty:ident) => {
+        $(#[$meta])*
+        $vis enum $name {
+            I32($ty<i32>),
+            U32($ty<u32>),
+            I64($ty<i64>),
+        }
+
+        impl $name {
+            #[allow(dead_code)]
+            pub fn dtype(&self) -> $crate::tokens::DType {
+                match self {
+                    $name::I32(_) => $crate::tokens::DType::I32,
+                    $name::U32(_) => $crate::tokens::DType::U32,
+                    $name::I64(_) => $crate::tokens::DType::I64,
+                }
+            }
+        }
+    };
+}
+
+/// Builds the `$name` variant for `$dtype`, evaluating `$body` with `$t`
+/// aliased to the matching token type.
+macro_rules! new_by_dtype {
+    ($name:ident, $dtype:expr, $t:ident => $body:expr) => {
+        match $dtype {
+            $crate::tokens::DType::I32 => {
+                type $t = i32;
+                $name::I32($body)
+            }
+            $crate::tokens::DType::U32 => {
+                type $t = u32;
+                $name::U32($body)
+            }
+            $crate::tokens::DType::I64 => {
+                type $t = i64;
+                $name::I64($body)
+            }
+        }
+    };
+}
+
+/// Runs `$body` against whichever instantiation `$inner` holds.
+macro_rules! dispatch {
+    ($name:ident, $inner:expr, $s:ident => $body:expr) => {
+        match $inner {
+            $name::I32($s) => $body,
+            $name::U32($s) => $body,
+            $name::I64($s) => $body,
+        }
+    };
+}
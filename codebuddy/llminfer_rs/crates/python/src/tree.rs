@@ -0,0 +1,68 @@
+use pyo3::prelude::*;
+
+use diff::LabeledTokenTree;
+
+use crate::nextchunk::PyTokenTree;
+use crate::tokens::{with_tokens, DType, Output, PyToken};
+
+
+/// Outcome of `TokenTree.verify`.
+#[pyclass(name = "TreeVerification", module = "stream_chunk_py", frozen, get_all)]
+pub struct PyTreeVerification {
+    /// Nodes of the longest root-to-node path matching the accepted tokens, root first.
+    pub(crate) accepted_path: Vec<usize>,
+    /// The descendants of the last accepted node re-rooted past it, still to
+    /// verify next step; empty when the verifier left the tree.
+    pub(crate) surviving: Py<PyTokenTree>,
+    /// Node of the original tree each surviving node was.
+    pub(crate) surviving_nodes: Vec<usize>,
+    /// Indices in `paths` of the candidates with nothing left to verify.
+    pub(crate) retired: Vec<usize>,
+}
+
+#[pymethods]
+impl PyTreeVerification {
+    fn __repr__(&self) -> String {
+        format!("TreeVerification(accepted_path={:?}, retired={:?})", self.accepted_path, self.retired)
+    }
+}
+
+fn merge_candidates_impl<T: PyToken>(
+    py: Python<'_>,
+    candidates: Vec<(String, Bound<'_, PyAny>)>,
+    output: Output,
+) -> PyResult<PyTokenTree> {
+    let candidates = candidates
+        .into_iter()
+        .map(|(label, tokens)| Ok((label, with_tokens(&tokens, <[T]>::to_vec)?)))
+        .collect::<PyResult<Vec<(String, Vec<T>)>>>()?;
+    let tree = LabeledTokenTree::from_candidates(candidates.iter().map(|(label, tokens)| (label.clone(), tokens.as_slice())));
+    PyTokenTree::labeled(py, tree, output)
+}
+
+/// Merges drafts from several sources (e.g. `StreamNextChunk` and a small
+/// draft model) into one deduplicated `TokenTree`, so verification covers
+/// all of them in a single pass.
+///
+/// Args:
+///     candidates (Iterable[tuple[str, list[int] | numpy.ndarray]]): `(label, tokens)`
+///         pairs; several drafts may share a label.
+///
+/// Returns:
+///     TokenTree: The merged tree, whose `sources[i]` lists the labels of the
+///     drafts running through node `i`, in the order of `candidates`.
+#[pyfunction(name = "merge_candidates")]
+#[pyo3(signature = (candidates, dtype = "int32", output = "list"), text_signature = "(candidates, dtype='int32', output='list')")]
+pub fn py_merge_candidates(
+    py: Python<'_>,
+    candidates: Vec<(String, Bound<'_, PyAny>)>,
+    dtype: &str,
+    output: &str,
+) -> PyResult<PyTokenTree> {
+    let output = Output::parse(output)?;
+    match DType::parse(dtype)? {
+        DType::I32 => merge_candidates_impl::<i32>(py, candidates, output),
+        DType::U32 => merge_candidates_impl::<u32>(py, candidates, output),
+        DType::I64 => merge_candidates_impl::<i64>(py, candidates, output),
+    }
+}
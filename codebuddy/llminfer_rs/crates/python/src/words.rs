@@ -0,0 +1,68 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use regex::Regex;
+
+use diff::{NextChunkOptions, WordNextChunk, WordSplit};
+
+use crate::tokens::parse_algorithm;
+
+
+/// Word-level next-chunk prediction over plain text.
+///
+/// Words and the separators between them are both tokens, so
+/// `"".join(chunk)` is the predicted text. `current_b` should end on a word
+/// boundary.
+#[pyclass(name = "WordNextChunk", module = "stream_chunk_py", frozen)]
+pub struct PyWordNextChunk {
+    inner: WordNextChunk,
+}
+
+#[pymethods]
+impl PyWordNextChunk {
+    /// Args:
+    ///     text (str): The reference text.
+    ///     pattern (str | None): Regex matching the separators between words;
+    ///         None (default) splits on runs of whitespace.
+    ///     algorithm (str): Diff algorithm, one of "histogram" (default), "myers" or "myers_minimal".
+    ///     min_match_len (int): Predict nothing when the anchoring match is
+    ///         shorter than this many words (separators count).
+    #[new]
+    #[pyo3(
+        signature = (text, pattern = None, algorithm = "histogram", min_match_len = 1),
+        text_signature = "(text, pattern=None, algorithm='histogram', min_match_len=1)"
+    )]
+    fn py_new(text: String, pattern: Option<&str>, algorithm: &str, min_match_len: usize) -> PyResult<Self> {
+        let split = match pattern {
+            Some(pattern) => WordSplit::Regex(Regex::new(pattern).map_err(|e| PyValueError::new_err(e.to_string()))?),
+            None => WordSplit::Whitespace,
+        };
+        let options = NextChunkOptions { algorithm: parse_algorithm(algorithm)?, min_match_len, ..Default::default() };
+        Ok(PyWordNextChunk { inner: WordNextChunk::with_options(text, split, options) })
+    }
+
+    /// The separator regex, or None when splitting on whitespace.
+    #[getter]
+    fn pattern(&self) -> Option<&str> {
+        match self.inner.split() {
+            WordSplit::Whitespace => None,
+            WordSplit::Regex(regex) => Some(regex.as_str()),
+        }
+    }
+
+    /// The reference split into words and separators.
+    fn words(&self) -> Vec<&str> {
+        self.inner.words().collect()
+    }
+
+    /// Predicts the next `chunk_size` words (separators included) following `current_b`.
+    #[pyo3(text_signature = "(current_b, chunk_size)")]
+    fn next_chunk(&self, py: Python<'_>, current_b: &str, chunk_size: usize) -> Vec<&str> {
+        py.allow_threads(|| self.inner.next_chunk(current_b, chunk_size))
+    }
+
+    /// Like `next_chunk`, joined back into text.
+    #[pyo3(text_signature = "(current_b, chunk_size)")]
+    fn next_chunk_text(&self, py: Python<'_>, current_b: &str, chunk_size: usize) -> &str {
+        py.allow_threads(|| self.inner.next_chunk_text(current_b, chunk_size))
+    }
+}
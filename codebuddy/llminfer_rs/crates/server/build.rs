@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // protox compiles the proto in-process, so building needs no protoc install
+    let file_descriptors = protox::compile(["proto/next_chunk.proto"], ["proto"])?;
+    tonic_build::configure().compile_fds(file_descriptors)?;
+    println!("cargo:rerun-if-changed=proto/next_chunk.proto");
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+
+use crate::proto::next_chunk_server::NextChunk;
+use crate::proto::{
+    CloseSessionRequest, CloseSessionResponse, CreateSessionRequest, CreateSessionResponse, PredictRequest,
+    PredictResponse,
+};
+use crate::sessions::{session_options, SessionError, Sessions};
+
+
+impl From<SessionError> for Status {
+    fn from(err: SessionError) -> Self {
+        match err {
+            SessionError::Unknown(_) => Status::not_found(err.to_string()),
+        }
+    }
+}
+
+/// gRPC front end over [`Sessions`].
+#[derive(Clone, Default)]
+pub struct NextChunkService {
+    sessions: Arc<Sessions>,
+}
+
+impl NextChunkService {
+    pub fn new(sessions: Arc<Sessions>) -> Self {
+        NextChunkService { sessions }
+    }
+}
+
+#[tonic::async_trait]
+impl NextChunk for NextChunkService {
+    async fn create_session(
+        &self,
+        request: Request<CreateSessionRequest>,
+    ) -> Result<Response<CreateSessionResponse>, Status> {
+        let request = request.into_inner();
+        let options = session_options(&request.algorithm, request.min_match_len as usize, request.max_mismatches as usize)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let sessions = Arc::clone(&self.sessions);
+        // Interning / indexing a long reference is CPU work; keep it off the async workers
+        let session_id = tokio::task::spawn_blocking(move || sessions.create(request.reference, options))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(CreateSessionResponse { session_id }))
+    }
+
+    async fn predict(&self, request: Request<PredictRequest>) -> Result<Response<PredictResponse>, Status> {
+        let request = request.into_inner();
+        let sessions = Arc::clone(&self.sessions);
+        let prediction = tokio::task::spawn_blocking(move || {
+            sessions.predict(request.session_id, &request.new_tokens, request.chunk_size as usize)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))??;
+        Ok(Response::new(PredictResponse {
+            tokens: prediction.tokens,
+            start: prediction.start.map(|start| start as u64),
+            match_len: prediction.match_len as u64,
+        }))
+    }
+
+    async fn close_session(
+        &self,
+        request: Request<CloseSessionRequest>,
+    ) -> Result<Response<CloseSessionResponse>, Status> {
+        self.sessions.close(request.into_inner().session_id)?;
+        Ok(Response::new(CloseSessionResponse {}))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_service() {
+        let service = NextChunkService::default();
+        let create = CreateSessionRequest { reference: (0..100).collect(), algorithm: "myers".into(), ..Default::default() };
+        let id = service.create_session(Request::new(create)).await.unwrap().into_inner().session_id;
+
+        let predict = PredictRequest { session_id: id, new_tokens: vec![5, -1, 7, 8], chunk_size: 3 };
+        let response = service.predict(Request::new(predict)).await.unwrap().into_inner();
+        assert_eq!(response, PredictResponse { tokens: vec![9, 10, 11], start: Some(9), match_len: 2 });
+
+        service.close_session(Request::new(CloseSessionRequest { session_id: id })).await.unwrap();
+        let predict = PredictRequest { session_id: id, new_tokens: vec![], chunk_size: 3 };
+        let status = service.predict(Request::new(predict)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let create = CreateSessionRequest { algorithm: "patience".into(), ..Default::default() };
+        let status = service.create_session(Request::new(create)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::sessions::{session_options, Prediction, SessionError, Sessions};
+
+
+/// Predictions buffered per subscriber before slow SSE clients start missing some.
+const EVENT_BUFFER: usize = 64;
+
+#[derive(Deserialize)]
+struct CreateSession {
+    reference: Vec<i32>,
+    #[serde(default)]
+    algorithm: String,
+    #[serde(default)]
+    min_match_len: usize,
+    #[serde(default)]
+    max_mismatches: usize,
+}
+
+#[derive(Serialize)]
+struct SessionCreated {
+    session_id: u64,
+}
+
+#[derive(Deserialize)]
+struct PushTokens {
+    new_tokens: Vec<i32>,
+    chunk_size: usize,
+}
+
+/// Errors turned into plain-text HTTP responses.
+enum HttpError {
+    Session(SessionError),
+    BadRequest(String),
+    Internal(String),
+}
+
+impl From<SessionError> for HttpError {
+    fn from(err: SessionError) -> Self {
+        HttpError::Session(err)
+    }
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        match self {
+            HttpError::Session(err @ SessionError::Unknown(_)) => (StatusCode::NOT_FOUND, err.to_string()),
+            HttpError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            HttpError::Internal(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
+        }
+        .into_response()
+    }
+}
+
+#[derive(Clone)]
+struct HttpState {
+    sessions: Arc<Sessions>,
+    /// Broadcasts each session's predictions to its SSE subscribers.
+    events: Arc<Mutex<HashMap<u64, broadcast::Sender<Prediction>>>>,
+}
+
+impl HttpState {
+    fn events(&self, id: u64) -> Result<broadcast::Sender<Prediction>, SessionError> {
+        let events = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        events.get(&id).cloned().ok_or(SessionError::Unknown(id))
+    }
+}
+
+/// HTTP front end over `sessions`:
+///
+/// * `POST /sessions` with `{"reference": [..], "algorithm"?, "min_match_len"?,
+///   "max_mismatches"?}` creates a session and returns `{"session_id": ..}`;
+/// * `GET /sessions/{id}/events` streams the session's predictions as
+///   server-sent `prediction` events (`{"tokens", "start", "match_len"}`);
+/// * `POST /sessions/{id}/tokens` with `{"new_tokens": [..], "chunk_size": n}`
+///   appends the observed tokens, predicts, publishes the prediction to the
+///   event stream and also returns it;
+/// * `DELETE /sessions/{id}` closes the session and ends its event streams;
+/// * `GET /metrics` returns the prediction metrics of all sessions in the
+///   Prometheus text format.
+///
+/// Only sessions created over HTTP have an event stream.
+pub fn router(sessions: Arc<Sessions>) -> Router {
+    let state = HttpState { sessions, events: Arc::default() };
+    Router::new()
+        .route("/sessions", post(create_session))
+        .route("/sessions/:id", delete(close_session))
+        .route("/sessions/:id/tokens", post(push_tokens))
+        .route("/sessions/:id/events", get(events))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+/// Just the `GET /metrics` endpoint of [`router`], e.g. to scrape a server
+/// that only serves gRPC.
+pub fn metrics_router(sessions: Arc<Sessions>) -> Router {
+    let state = HttpState { sessions, events: Arc::default() };
+    Router::new().route("/metrics", get(metrics)).with_state(state)
+}
+
+async fn metrics(State(state): State<HttpState>) -> impl IntoResponse {
+    let text = state.sessions.metrics().render(state.sessions.len());
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], text)
+}
+
+async fn create_session(
+    State(state): State<HttpState>,
+    Json(request): Json<CreateSession>,
+) -> Result<Json<SessionCreated>, HttpError> {
+    let options = session_options(&request.algorithm, request.min_match_len, request.max_mismatches)
+        .map_err(|e| HttpError::BadRequest(e.to_string()))?;
+    let sessions = Arc::clone(&state.sessions);
+    let session_id = tokio::task::spawn_blocking(move || sessions.create(request.reference, options))
+        .await
+        .map_err(|e| HttpError::Internal(e.to_string()))?;
+    let (sender, _) = broadcast::channel(EVENT_BUFFER);
+    state.events.lock().unwrap_or_else(PoisonError::into_inner).insert(session_id, sender);
+    Ok(Json(SessionCreated { session_id }))
+}
+
+async fn push_tokens(
+    State(state): State<HttpState>,
+    Path(id): Path<u64>,
+    Json(request): Json<PushTokens>,
+) -> Result<Json<Prediction>, HttpError> {
+    let events = state.events(id)?;
+    let sessions = Arc::clone(&state.sessions);
+    let prediction = tokio::task::spawn_blocking(move || sessions.predict(id, &request.new_tokens, request.chunk_size))
+        .await
+        .map_err(|e| HttpError::Internal(e.to_string()))??;
+    // No subscribers is fine: the prediction is returned either way
+    let _ = events.send(prediction.clone());
+    Ok(Json(prediction))
+}
+
+async fn events(
+    State(state): State<HttpState>,
+    Path(id): Path<u64>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, HttpError> {
+    let receiver = state.events(id)?.subscribe();
+    // Predictions a lagging client missed are skipped rather than ending the stream
+    let stream = BroadcastStream::new(receiver).filter_map(|prediction| {
+        let event = Event::default().event("prediction").json_data(prediction.ok()?).ok()?;
+        Some(Ok(event))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+async fn close_session(State(state): State<HttpState>, Path(id): Path<u64>) -> Result<StatusCode, HttpError> {
+    state.sessions.close(id)?;
+    state.events.lock().unwrap_or_else(PoisonError::into_inner).remove(&id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn json_request(method: &str, uri: &str, body: serde_json::Value) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_http_sse() {
+        let app = router(Arc::new(Sessions::new()));
+        let created = app
+            .clone()
+            .oneshot(json_request("POST", "/sessions", serde_json::json!({"reference": (0..100).collect::<Vec<i32>>()})))
+            .await
+            .unwrap();
+        assert_eq!(created.status(), StatusCode::OK);
+        let id = json_body(created).await["session_id"].as_u64().unwrap();
+
+        let events = app
+            .clone()
+            .oneshot(Request::get(format!("/sessions/{id}/events")).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(events.status(), StatusCode::OK);
+        let mut events = events.into_body();
+
+        let pushed = app
+            .clone()
+            .oneshot(json_request("POST", &format!("/sessions/{id}/tokens"), serde_json::json!({"new_tokens": [0, 1], "chunk_size": 3})))
+            .await
+            .unwrap();
+        let expected = serde_json::json!({"tokens": [2, 3, 4], "start": 2, "match_len": 2});
+        assert_eq!(json_body(pushed).await, expected);
+
+        let frame = events.frame().await.unwrap().unwrap().into_data().unwrap();
+        let frame = String::from_utf8(frame.to_vec()).unwrap();
+        let data = frame.strip_prefix("event: prediction\ndata: ").and_then(|rest| rest.strip_suffix("\n\n")).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(data).unwrap(), expected);
+
+        let closed = app.clone().oneshot(Request::delete(format!("/sessions/{id}")).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(closed.status(), StatusCode::NO_CONTENT);
+        assert!(events.frame().await.is_none());
+        let missing = app
+            .clone()
+            .oneshot(json_request("POST", &format!("/sessions/{id}/tokens"), serde_json::json!({"new_tokens": [], "chunk_size": 3})))
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let metrics = app.oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(metrics.status(), StatusCode::OK);
+        let text = metrics.into_body().collect().await.unwrap().to_bytes();
+        let text = String::from_utf8(text.to_vec()).unwrap();
+        assert!(text.contains("llminfer_predictions_total 1\n") && text.contains("llminfer_open_sessions 0\n"));
+    }
+}
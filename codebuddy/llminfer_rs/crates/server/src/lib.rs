@@ -0,0 +1,17 @@
+//! Network front ends over stateful next-chunk sessions, for inference
+//! workers that can't link the library.
+
+mod grpc;
+mod http;
+mod metrics;
+mod sessions;
+
+/// Types generated from `proto/next_chunk.proto`.
+pub mod proto {
+    tonic::include_proto!("llminfer.nextchunk.v1");
+}
+
+pub use grpc::NextChunkService;
+pub use http::{metrics_router, router};
+pub use metrics::Metrics;
+pub use sessions::{session_options, Prediction, SessionError, Sessions};
@@ -0,0 +1,127 @@
+//! `llminfer-server [--grpc ADDR] [--http ADDR] [--metrics ADDR] [--max-sessions N]
+//! [--max-tokens N] [--session-ttl SECS]`: serves the `NextChunk` gRPC service
+//! and/or the HTTP + server-sent events front end over one set of sessions.
+//! Without addresses, serves gRPC on 0.0.0.0:50051.
+//!
+//! Prometheus metrics are served on `/metrics` of the HTTP front end, and on
+//! their own at the `--metrics` address.
+//!
+//! Sessions idle for `--session-ttl` seconds are closed, then the least
+//! recently used ones while over `--max-sessions` sessions or `--max-tokens`
+//! tokens in total; all unbounded by default.
+
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use diff::SessionLimits;
+use llminfer_server::proto::next_chunk_server::NextChunkServer;
+use llminfer_server::{metrics_router, router, NextChunkService, Sessions};
+
+
+const USAGE: &str = "usage: llminfer-server [--grpc ADDR] [--http ADDR] [--metrics ADDR] [--max-sessions N] [--max-tokens N] [--session-ttl SECS]";
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    grpc: Option<SocketAddr>,
+    http: Option<SocketAddr>,
+    metrics: Option<SocketAddr>,
+    limits: SessionLimits,
+}
+
+/// Parses the value following `flag`.
+fn parse_value<T: FromStr>(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    let value = args.next().ok_or_else(|| format!("{flag} needs a value"))?;
+    value.parse().map_err(|e| format!("{flag} {value}: {e}"))
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args { grpc: None, http: None, metrics: None, limits: SessionLimits::default() };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--grpc" => parsed.grpc = Some(parse_value(&arg, &mut args)?),
+            "--http" => parsed.http = Some(parse_value(&arg, &mut args)?),
+            "--metrics" => parsed.metrics = Some(parse_value(&arg, &mut args)?),
+            "--max-sessions" => parsed.limits.max_sessions = Some(parse_value(&arg, &mut args)?),
+            "--max-tokens" => parsed.limits.max_tokens = Some(parse_value(&arg, &mut args)?),
+            "--session-ttl" => {
+                let secs: f64 = parse_value(&arg, &mut args)?;
+                parsed.limits.ttl = Some(Duration::try_from_secs_f64(secs).map_err(|e| format!("{arg} {secs}: {e}"))?);
+            }
+            other => return Err(format!("unknown argument {other}")),
+        }
+    }
+    if parsed.limits.max_sessions == Some(0) {
+        return Err("--max-sessions must be positive".to_owned());
+    }
+    if parsed.grpc.is_none() && parsed.http.is_none() {
+        parsed.grpc = Some(([0, 0, 0, 0], 50051).into());
+    }
+    Ok(parsed)
+}
+
+async fn serve_grpc(addr: SocketAddr, sessions: Arc<Sessions>) -> Result<(), String> {
+    eprintln!("serving gRPC on {addr}");
+    let service = NextChunkServer::new(NextChunkService::new(sessions));
+    tonic::transport::Server::builder().add_service(service).serve(addr).await.map_err(|e| e.to_string())
+}
+
+async fn serve_http(addr: SocketAddr, sessions: Arc<Sessions>) -> Result<(), String> {
+    let listener = tokio::net::TcpListener::bind(addr).await.map_err(|e| format!("{addr}: {e}"))?;
+    eprintln!("serving HTTP on {addr}");
+    axum::serve(listener, router(sessions)).await.map_err(|e| e.to_string())
+}
+
+async fn serve_metrics(addr: SocketAddr, sessions: Arc<Sessions>) -> Result<(), String> {
+    let listener = tokio::net::TcpListener::bind(addr).await.map_err(|e| format!("{addr}: {e}"))?;
+    eprintln!("serving metrics on {addr}");
+    axum::serve(listener, metrics_router(sessions)).await.map_err(|e| e.to_string())
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let sessions = Arc::new(Sessions::with_limits(args.limits));
+    let grpc = async {
+        match args.grpc {
+            Some(addr) => serve_grpc(addr, Arc::clone(&sessions)).await,
+            None => Ok(()),
+        }
+    };
+    let http = async {
+        match args.http {
+            Some(addr) => serve_http(addr, Arc::clone(&sessions)).await,
+            None => Ok(()),
+        }
+    };
+    let metrics = async {
+        match args.metrics {
+            Some(addr) => serve_metrics(addr, Arc::clone(&sessions)).await,
+            None => Ok(()),
+        }
+    };
+    tokio::try_join!(grpc, http, metrics).map(drop)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {err}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
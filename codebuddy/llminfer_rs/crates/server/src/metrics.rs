@@ -0,0 +1,136 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use diff::{CallStats, Eviction};
+
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 12] = [0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0];
+
+/// A Prometheus histogram of durations with fixed buckets.
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the last one is `+Inf`.
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    sum_nanos: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, value: Duration) {
+        let bucket = LATENCY_BUCKETS.partition_point(|&bound| bound < value.as_secs_f64());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(value.as_nanos() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let bound = LATENCY_BUCKETS.get(i).map_or("+Inf".to_owned(), f64::to_string);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let sum = Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed)).as_secs_f64();
+        let _ = writeln!(out, "{name}_sum {sum}\n{name}_count {}", self.count.load(Ordering::Relaxed));
+    }
+}
+
+/// Prediction metrics of all sessions, rendered in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    predictions: AtomicU64,
+    empty_predictions: AtomicU64,
+    windowed_predictions: AtomicU64,
+    predicted_tokens: AtomicU64,
+    accepted_tokens: AtomicU64,
+    evicted_sessions: AtomicU64,
+    latency: Histogram,
+    diff_latency: Histogram,
+}
+
+impl Metrics {
+    /// Counts a prediction of `chunk_len` tokens made by the call `call`.
+    pub(crate) fn record_prediction(&self, chunk_len: usize, call: Option<CallStats>) {
+        self.predictions.fetch_add(1, Ordering::Relaxed);
+        if chunk_len == 0 {
+            self.empty_predictions.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(call) = call {
+            self.windowed_predictions.fetch_add(u64::from(call.windowed), Ordering::Relaxed);
+            self.latency.observe(call.wall);
+            if !call.fast_path {
+                self.diff_latency.observe(call.diff);
+            }
+        }
+    }
+
+    /// Counts a previous prediction of `predicted` tokens whose first
+    /// `accepted` matched the tokens then generated.
+    pub(crate) fn record_verification(&self, predicted: usize, accepted: usize) {
+        self.predicted_tokens.fetch_add(predicted as u64, Ordering::Relaxed);
+        self.accepted_tokens.fetch_add(accepted as u64, Ordering::Relaxed);
+    }
+
+    /// Counts the sessions closed for going over the session limits.
+    pub(crate) fn record_evictions<K>(&self, evicted: &[(K, Eviction)]) {
+        self.evicted_sessions.fetch_add(evicted.len() as u64, Ordering::Relaxed);
+    }
+
+    /// The metrics in the Prometheus text exposition format, plus the
+    /// `open_sessions` gauge.
+    pub fn render(&self, open_sessions: usize) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("llminfer_predictions_total", "Predictions made.", &self.predictions),
+            ("llminfer_empty_predictions_total", "Predictions that predicted no tokens.", &self.empty_predictions),
+            ("llminfer_windowed_predictions_total", "Predictions whose diff was windowed.", &self.windowed_predictions),
+            ("llminfer_predicted_tokens_total", "Tokens of predictions checked against the tokens generated next.", &self.predicted_tokens),
+            ("llminfer_accepted_tokens_total", "Leading predicted tokens that matched the tokens generated next.", &self.accepted_tokens),
+            ("llminfer_evicted_sessions_total", "Sessions closed for being idle or over the session limits.", &self.evicted_sessions),
+        ];
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}", counter.load(Ordering::Relaxed));
+        }
+        self.latency.render(&mut out, "llminfer_prediction_seconds", "Wall time of predictions.");
+        self.diff_latency.render(&mut out, "llminfer_diff_seconds", "Diff time of predictions that ran a diff.");
+        let name = "llminfer_open_sessions";
+        let _ = writeln!(out, "# HELP {name} Sessions currently open.\n# TYPE {name} gauge\n{name} {open_sessions}");
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        let call = CallStats { wall: Duration::from_micros(300), diff: Duration::from_micros(200), windowed: true, ..Default::default() };
+        metrics.record_prediction(3, Some(call));
+        metrics.record_prediction(0, Some(CallStats { fast_path: true, ..call }));
+        metrics.record_verification(3, 2);
+
+        let text = metrics.render(4);
+        for line in [
+            "llminfer_predictions_total 2",
+            "llminfer_empty_predictions_total 1",
+            "llminfer_windowed_predictions_total 2",
+            "llminfer_predicted_tokens_total 3",
+            "llminfer_accepted_tokens_total 2",
+            "llminfer_prediction_seconds_bucket{le=\"0.00025\"} 0",
+            "llminfer_prediction_seconds_bucket{le=\"0.0005\"} 2",
+            "llminfer_prediction_seconds_bucket{le=\"+Inf\"} 2",
+            "llminfer_prediction_seconds_count 2",
+            "llminfer_diff_seconds_bucket{le=\"0.00025\"} 1",
+            "llminfer_diff_seconds_count 1",
+            "llminfer_open_sessions 4",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {line:?} in\n{text}");
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::PoisonError;
+
+use diff::{DiffAlgorithm, NextChunkOptions, ParseOptionError, SessionLimits, SessionPool, StreamNextChunk};
+
+use crate::metrics::Metrics;
+
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SessionError {
+    #[error("unknown session {0}")]
+    Unknown(u64),
+}
+
+/// A predicted chunk, owned so it can outlive the session lock.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Prediction {
+    pub tokens: Vec<i32>,
+    /// Offset in the reference of the first predicted token, `None` when nothing was predicted.
+    pub start: Option<usize>,
+    /// Length of the match the prediction continues from.
+    pub match_len: usize,
+}
+
+/// Session tunables as received over the wire: an empty `algorithm` and a
+/// zero `min_match_len` select the defaults.
+pub fn session_options(algorithm: &str, min_match_len: usize, max_mismatches: usize) -> Result<NextChunkOptions, ParseOptionError> {
+    let algorithm = match algorithm {
+        "" => DiffAlgorithm::default(),
+        name => name.parse()?,
+    };
+    Ok(NextChunkOptions { algorithm, min_match_len: min_match_len.max(1), max_mismatches, ..Default::default() })
+}
+
+struct SessionState {
+    streamer: StreamNextChunk<i32>,
+    /// The last prediction, checked against the tokens appended next.
+    predicted: Vec<i32>,
+}
+
+/// Stateful [`StreamNextChunk`] sessions shared by the server front ends.
+///
+/// Sessions live in a [`SessionPool`]: the map lock is only held to look a
+/// session up and predictions lock just their own session, so different
+/// sessions predict concurrently. Sessions idle for longer than the TTL, then
+/// the least recently used ones while over capacity, are closed on `create`
+/// and `predict`, as if closed by their client.
+#[derive(Default)]
+pub struct Sessions {
+    next_id: AtomicU64,
+    pool: SessionPool<u64, SessionState>,
+    metrics: Metrics,
+}
+
+impl Sessions {
+    /// Sessions without limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sessions closed past `limits`; `max_tokens` counts reference plus
+    /// appended tokens.
+    pub fn with_limits(limits: SessionLimits) -> Self {
+        Sessions { pool: SessionPool::new(limits), ..Self::default() }
+    }
+
+    /// Creates a session predicting from `reference` and returns its id.
+    pub fn create(&self, reference: Vec<i32>, options: NextChunkOptions) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let tokens = reference.len();
+        let streamer = StreamNextChunk::with_options(reference, options);
+        // Ids are never reused, so `id` isn't open yet
+        let evicted = self.pool.insert(id, SessionState { streamer, predicted: Vec::new() }, tokens).unwrap_or_default();
+        self.metrics.record_evictions(&evicted);
+        id
+    }
+
+    /// Appends `new_tokens` to session `id` and predicts up to `chunk_size` tokens.
+    pub fn predict(&self, id: u64, new_tokens: &[i32], chunk_size: usize) -> Result<Prediction, SessionError> {
+        let (session, evicted) = self.pool.get(&id);
+        self.metrics.record_evictions(&evicted);
+        let session = session.ok_or(SessionError::Unknown(id))?;
+        let mut session = session.lock().unwrap_or_else(PoisonError::into_inner);
+        let SessionState { streamer, predicted } = &mut *session;
+        if !predicted.is_empty() {
+            let accepted = predicted.iter().zip(new_tokens).take_while(|(p, t)| p == t).count();
+            self.metrics.record_verification(predicted.len(), accepted);
+        }
+        streamer.append(new_tokens);
+        let result = streamer.predict_with_info(chunk_size);
+        let prediction = Prediction { tokens: result.tokens.to_vec(), start: result.start, match_len: result.match_len };
+        self.metrics.record_prediction(prediction.tokens.len(), streamer.last_call_info());
+        predicted.clone_from(&prediction.tokens);
+        let tokens = streamer.reference().len() + streamer.appended().len();
+        drop(session);
+        self.metrics.record_evictions(&self.pool.set_tokens(&id, tokens));
+        Ok(prediction)
+    }
+
+    /// Frees session `id`.
+    pub fn close(&self, id: u64) -> Result<(), SessionError> {
+        self.pool.remove(&id).map(drop).ok_or(SessionError::Unknown(id))
+    }
+
+    /// Number of open sessions.
+    pub fn len(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Prediction metrics over all sessions.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sessions() {
+        let sessions = Sessions::new();
+        let a = sessions.create((0..100).collect(), NextChunkOptions::default());
+        let b = sessions.create((100..200).collect(), NextChunkOptions::default());
+        assert_ne!(a, b);
+        assert_eq!(sessions.predict(a, &[0, 1], 2).unwrap().tokens, vec![2, 3]);
+        assert_eq!(sessions.predict(a, &[2, 3], 2).unwrap(), Prediction { tokens: vec![4, 5], start: Some(4), match_len: 4 });
+        assert_eq!(sessions.predict(b, &[], 2).unwrap().tokens, vec![100, 101]);
+        sessions.close(a).unwrap();
+        assert_eq!(sessions.predict(a, &[4], 2), Err(SessionError::Unknown(a)));
+        assert_eq!(sessions.close(a), Err(SessionError::Unknown(a)));
+        assert_eq!(sessions.len(), 1);
+    }
+
+    #[test]
+    fn test_limits() {
+        let sessions = Sessions::with_limits(SessionLimits { max_sessions: Some(2), ..Default::default() });
+        let a = sessions.create((0..100).collect(), NextChunkOptions::default());
+        let b = sessions.create((0..100).collect(), NextChunkOptions::default());
+        sessions.predict(a, &[0], 1).unwrap();
+        // `b` is the least recently used
+        let c = sessions.create((0..100).collect(), NextChunkOptions::default());
+        assert_eq!(sessions.predict(b, &[0], 1), Err(SessionError::Unknown(b)));
+        assert!(sessions.predict(a, &[1], 1).is_ok() && sessions.predict(c, &[], 1).is_ok());
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.metrics().render(sessions.len()).contains("llminfer_evicted_sessions_total 1"));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let sessions = Sessions::new();
+        let id = sessions.create((0..100).collect(), NextChunkOptions::default());
+        sessions.predict(id, &[0, 1], 3).unwrap();
+        // Two of the three predicted tokens were generated
+        sessions.predict(id, &[2, 3, -1], 3).unwrap();
+        let text = sessions.metrics().render(sessions.len());
+        for line in ["llminfer_predictions_total 2", "llminfer_predicted_tokens_total 3", "llminfer_accepted_tokens_total 2"] {
+            assert!(text.contains(line), "missing {line:?} in\n{text}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! JavaScript/TypeScript bindings (wasm-bindgen) over `int32` token ids.
+//!
+//! Build with `wasm-pack build crates/wasm --target web`; token sequences are
+//! passed and returned as `Int32Array`s.
+
+use wasm_bindgen::prelude::*;
+
+use diff::{DiffAlgorithm, ParseOptionError};
+
+
+fn parse_algorithm(algorithm: Option<String>) -> Result<DiffAlgorithm, JsError> {
+    match algorithm {
+        Some(algorithm) => algorithm.parse().map_err(|e: ParseOptionError| JsError::new(&e.to_string())),
+        None => Ok(DiffAlgorithm::default()),
+    }
+}
+
+
+/// Predicts the next chunk of a reference sequence from the tokens generated so far.
+#[wasm_bindgen]
+pub struct StreamNextChunk {
+    inner: diff::StreamNextChunk<i32>,
+}
+
+#[wasm_bindgen]
+impl StreamNextChunk {
+    /// `algorithm` is one of "histogram" (default), "myers" or "myers_minimal".
+    #[wasm_bindgen(constructor)]
+    pub fn new(a: &[i32], algorithm: Option<String>) -> Result<StreamNextChunk, JsError> {
+        let options = diff::NextChunkOptions { algorithm: parse_algorithm(algorithm)?, ..Default::default() };
+        Ok(StreamNextChunk { inner: diff::StreamNextChunk::with_options(a.to_vec(), options) })
+    }
+
+    /// Predicts up to `chunk_size` tokens of `a` following `current_b`.
+    #[wasm_bindgen(js_name = nextChunk)]
+    pub fn next_chunk(&self, current_b: &[i32], chunk_size: usize) -> Vec<i32> {
+        self.inner.next_chunk(current_b, chunk_size).to_vec()
+    }
+
+    /// Appends newly generated tokens to the internally tracked sequence.
+    pub fn append(&mut self, new_tokens: &[i32]) {
+        self.inner.append(new_tokens)
+    }
+
+    /// Predicts the next chunk for the tokens fed through `append`.
+    pub fn predict(&mut self, chunk_size: usize) -> Vec<i32> {
+        self.inner.predict(chunk_size).to_vec()
+    }
+
+    /// Forgets all appended tokens.
+    pub fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
+
+/// `2 * matches / (a.length + b.length)`, like difflib's `ratio()`.
+#[wasm_bindgen]
+pub fn similarity(a: &[i32], b: &[i32], algorithm: Option<String>) -> Result<f64, JsError> {
+    Ok(diff::similarity(a, b, parse_algorithm(algorithm)?))
+}
+
+/// Number of inserted plus deleted tokens turning `a` into `b`.
+#[wasm_bindgen(js_name = editDistance)]
+pub fn edit_distance(a: &[i32], b: &[i32], algorithm: Option<String>) -> Result<usize, JsError> {
+    Ok(diff::edit_distance(a, b, parse_algorithm(algorithm)?))
+}
+
+/// Matching blocks flattened as `[a_start, b_start, len, ...]` triples.
+#[wasm_bindgen(js_name = matchingBlocks)]
+pub fn matching_blocks(a: &[i32], b: &[i32], algorithm: Option<String>) -> Result<Vec<u32>, JsError> {
+    let blocks = diff::matching_blocks(a, b, parse_algorithm(algorithm)?);
+    Ok(blocks.into_iter().flat_map(|(i, j, n)| [i as u32, j as u32, n as u32]).collect())
+}
+
+/// Line-based unified diff of two texts with `context_len` lines of context.
+#[wasm_bindgen(js_name = unifiedDiff)]
+pub fn unified_diff(before: &str, after: &str, context_len: u32, algorithm: Option<String>) -> Result<String, JsError> {
+    Ok(diff::unified_diff(before, after, context_len, parse_algorithm(algorithm)?))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Also run on wasm32 by `wasm-pack test --node crates/wasm`, where calls
+    // are timed by another clock than natively
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    fn test_bindings() {
+        let a: Vec<i32> = (0..100).collect();
+        let mut s = StreamNextChunk::new(&a, None).unwrap();
+        assert_eq!(s.next_chunk(&[5, -1, 7, 8], 3), vec![9, 10, 11]);
+        s.append(&[0, 1, 2]);
+        assert_eq!(s.predict(2), vec![3, 4]);
+        s.reset();
+        assert_eq!(s.predict(2), vec![0, 1]);
+
+        assert_eq!(edit_distance(&[1, 2, 3], &[1, 3], Some("myers".into())).unwrap(), 1);
+        assert_eq!(matching_blocks(&[1, 2, 3], &[1, 3], None).unwrap(), vec![0, 0, 1, 2, 1, 1, 3, 2, 0]);
+        assert_eq!(similarity(&[1, 2], &[1, 2], None).unwrap(), 1.0);
+        assert!(unified_diff("a\nb\n", "a\nc\n", 3, None).unwrap().contains("-b\n+c\n"));
+    }
+}
@@ -0,0 +1,31 @@
+# ruff: noqa: E702
+
+import llminfer_rs; AdaptiveChunker = llminfer_rs.diff.AdaptiveChunker
+
+
+def test_adaptive_chunker():
+    c = AdaptiveChunker(list(range(100)), min_chunk=2, max_chunk=8)
+    assert (c.dtype, c.chunk_size) == ("int32", 2)
+    assert c.next_chunk([0, 1]) == [2, 3]
+    c.record(2, 2)
+    c.record(4, 4)
+    assert c.chunk_size == 8
+    c.record(8, 8)
+    assert c.chunk_size == 8
+    c.record(8, 1)
+    assert c.chunk_size == 4
+    assert c.totals == (22, 15)
+    c.append([10, 11])
+    assert c.predict() == [12, 13, 14, 15]
+    c.reset()
+    assert (c.chunk_size, c.totals) == (2, (0, 0))
+
+
+def test_verify_and_advance():
+    c = AdaptiveChunker(list(range(100)), min_chunk=2, max_chunk=8)
+    predicted = c.predict()
+    assert c.verify_and_advance(predicted, [0, 1]) == 2
+    assert (c.chunk_size, c.totals) == (4, (2, 2))
+    assert c.predict() == [2, 3, 4, 5]
+    assert c.verify_and_advance([2, 3, 4, 5], [2, 9, 4, 5]) == 1
+    assert (c.chunk_size, c.totals) == (2, (6, 3))
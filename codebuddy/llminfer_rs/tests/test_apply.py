@@ -0,0 +1,20 @@
+# ruff: noqa: E702
+import pytest
+
+import llminfer_rs; apply_edits = llminfer_rs.diff.apply_edits; edit_script = llminfer_rs.diff.edit_script
+
+
+def test_round_trip():
+    a = [1, 2, 3, 4, 5, 6]
+    b = [0, 1, 3, 4, 9, 9, 6, 7]
+    edits = edit_script(a, b)
+    assert all(len(edit) == 3 for edit in edits)
+    assert apply_edits(a, edits) == b
+    assert apply_edits(a, []) == a
+
+
+def test_invalid_edits():
+    with pytest.raises(ValueError, match="edit 1"):
+        apply_edits([1, 2, 3], [((1, 2), (1, 1), []), ((0, 1), (0, 0), [])])
+    with pytest.raises(ValueError, match="out of bounds"):
+        apply_edits([1, 2, 3], [((2, 4), (2, 2), [])])
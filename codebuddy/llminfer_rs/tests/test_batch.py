@@ -0,0 +1,45 @@
+# ruff: noqa: E702
+
+import pytest
+
+import llminfer_rs; BatchStreamNextChunk = llminfer_rs.diff.BatchStreamNextChunk
+
+
+def test_next_chunk_batch():
+    references = [list(range(i * 100, i * 100 + 50)) for i in range(6)]
+    batch = BatchStreamNextChunk(references)
+    assert len(batch) == 6
+
+    chunks = batch.next_chunk_batch([ref[: i + 1] for i, ref in enumerate(references)], 2)
+    assert chunks == [ref[i + 1 : i + 3] for i, ref in enumerate(references)]
+
+    assert batch.next_chunk_batch([[300, 301], []], 2, indices=[3, 0]) == [[302, 303], [0, 1]]
+
+    idx = batch.add([7, 8, 9])
+    assert idx == 6 and len(batch) == 7
+    assert batch.next_chunk_batch([[7]], 5, indices=[idx]) == [[8, 9]]
+
+
+def test_next_chunk_batch_errors():
+    batch = BatchStreamNextChunk([[1, 2, 3]], dtype="int64")
+    assert batch.dtype == "int64"
+    with pytest.raises(ValueError):
+        batch.next_chunk_batch([[1], [2]], 2)
+    with pytest.raises(IndexError):
+        batch.next_chunk_batch([[1]], 2, indices=[5])
+
+
+def test_next_chunk_batch_cancel():
+    import asyncio
+
+    CancellationToken = llminfer_rs.diff.CancellationToken
+    batch = BatchStreamNextChunk([list(range(50)), list(range(100, 150))])
+    cancel = CancellationToken()
+    assert not cancel.cancelled
+    assert batch.next_chunk_batch([[1], [101]], 2, cancel=cancel) == [[2, 3], [102, 103]]
+    cancel.cancel()
+    assert cancel.cancelled
+    with pytest.raises(asyncio.CancelledError):
+        batch.next_chunk_batch([[1], [101]], 2, cancel=cancel)
+    with pytest.raises(asyncio.CancelledError):
+        batch.next_chunk_batch([[1]], 2, indices=[0], cancel=cancel)
@@ -0,0 +1,24 @@
+# ruff: noqa: E702
+
+import llminfer_rs; StreamNextChunkBytes = llminfer_rs.diff.StreamNextChunkBytes
+
+SOURCE = 'fn main() {\n    println!("héllo");\n}\n'
+
+
+def test_next_chunk():
+    s = StreamNextChunkBytes(SOURCE.encode())
+    assert s.next_chunk(b"fn main() {\n    print", 7) == b'ln!("h\xc3'
+    # str is matched as its UTF-8 encoding
+    assert s.next_chunk('    println!("hé', 5) == b'llo")'
+    assert s.next_chunk(bytearray(b"xyz"), 3) == b"fn "
+
+
+def test_append_predict():
+    s = StreamNextChunkBytes(SOURCE, min_match_len=3)
+    s.append(b"fn main() {\n")
+    assert s.predict(8) == b"    prin"
+    s.append(b"    println!(")
+    assert s.predict(6) == '"héll'.encode()
+    s.reset()
+    s.append(b"}")
+    assert s.predict(4) == b""
@@ -0,0 +1,20 @@
+# ruff: noqa: E702
+
+import llminfer_rs; DetokenizedNextChunk = llminfer_rs.diff.DetokenizedNextChunk
+
+OLD = dict(enumerate(["fn", " ", "def", "(", ")", ":", "\n", "x"]))
+CURRENT = dict(enumerate(["fn", " def", " ", "def", "(", ")", ":", "\n", "x"]))
+
+
+def test_other_tokenizer_version():
+    s = DetokenizedNextChunk([1, 2, 3, 7, 4, 6, 1, 2, 3, 4, 5], CURRENT, a_vocab=OLD)
+    assert s.reference == b" def(x)\n def():"
+    # " def" is one token of the current vocabulary, none of a's
+    b = [0, 1, 4, 8, 5, 7, 1]
+    assert s.next_chunk(b, 2) == [4, 5]
+    assert s.next_chunk_bytes(b, 3) == b"():"
+
+
+def test_text_reference():
+    s = DetokenizedNextChunk(" def(x)\n def():", {i: t.encode() for i, t in CURRENT.items()})
+    assert s.next_chunk([1, 4], 3) == [8, 5, 7]
@@ -0,0 +1,104 @@
+# ruff: noqa: E702
+import pytest
+
+import llminfer_rs; diff = llminfer_rs.diff.diff
+
+
+def test_changes():
+    a = [1, 2, 3, 4, 5]
+    b = [1, 9, 3, 4, 5, 6]
+    assert diff(a, b) == [((1, 2), (1, 2)), ((5, 5), (5, 6))]
+    assert diff(a, b, algorithm="myers") == diff(a, b)
+    assert diff(a, a) == []
+    assert diff(a, [], dtype="int64") == [((0, 5), (0, 0))]
+    with pytest.raises(ValueError):
+        diff(a, b, dtype="float")
+
+
+def test_find_matches():
+    find_matches = llminfer_rs.diff.find_matches
+    assert find_matches([1, 2, 3, 4, 5], [0, 1, 2, 9, 4, 5]) == [((0, 2), (1, 3)), ((3, 5), (4, 6))]
+    assert find_matches([1, 2], [3], dtype="uint32") == []
+
+
+def test_text_diff():
+    TextDiff = llminfer_rs.diff.TextDiff
+    before = "fn main() {\n    foo();\n}\n"
+    after = "fn main() {\n    bar();\n    baz();\n}\n"
+    d = TextDiff(before, after)
+    assert (d.before_lines, d.after_lines) == (3, 4)
+    assert d.changes == [((1, 2), (1, 3))]
+    assert d.matches == [((0, 1), (0, 1)), ((2, 3), (3, 4))]
+    assert not d.is_unchanged()
+    assert TextDiff(before, before, algorithm="myers").is_unchanged()
+
+
+def test_unified_diff():
+    unified_diff = llminfer_rs.diff.unified_diff
+    before = "a\nb\nc\nd\n"
+    after = "a\nB\nc\nd\n"
+    assert unified_diff(before, after, context=1) == "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+    assert unified_diff(before, after).startswith("@@ -1,4 +1,4 @@\n")
+    assert unified_diff(before, before) == ""
+
+
+def test_similarity():
+    import difflib
+
+    similarity = llminfer_rs.diff.similarity
+    a, b = [1, 2, 3, 4], [1, 9, 3, 4, 5, 6]
+    assert similarity(a, b) == pytest.approx(difflib.SequenceMatcher(None, a, b).ratio())
+    assert similarity(a, a) == 1.0
+    assert similarity([], []) == 1.0
+
+
+def test_matching_blocks():
+    import difflib
+
+    matching_blocks = llminfer_rs.diff.matching_blocks
+    a, b = [1, 2, 3, 4, 5], [0, 1, 2, 9, 4, 5]
+    blocks = matching_blocks(a, b)
+    assert blocks == difflib.SequenceMatcher(None, a, b).get_matching_blocks()
+    assert (blocks[0].a, blocks[0].b, blocks[0].size) == (0, 1, 2)
+    assert matching_blocks([], []) == [(0, 0, 0)]
+
+
+def test_opcodes():
+    import difflib
+
+    opcodes = llminfer_rs.diff.opcodes
+    a, b = [1, 2, 3, 4, 5], [0, 1, 2, 9, 4, 5, 6]
+    assert opcodes(a, b) == difflib.SequenceMatcher(None, a, b).get_opcodes()
+    assert opcodes([1], []) == [("delete", 0, 1, 0, 0)]
+    assert opcodes([], []) == []
+
+
+def test_diff_json():
+    import json
+
+    diff_json = llminfer_rs.diff.diff_json
+    doc = json.loads(diff_json([1, 2, 3, 5], [1, 2, 4, 5], dtype="int64"))
+    assert (doc["algorithm"], doc["a_len"], doc["b_len"]) == ("histogram", 4, 4)
+    assert [(h["op"], h["tokens"]) for h in doc["hunks"]] == [
+        ("equal", [1, 2]), ("delete", [3]), ("insert", [4]), ("equal", [5]),
+    ]
+    assert doc["hunks"][2] == {"op": "insert", "a_start": 3, "a_end": 3, "b_start": 2, "b_end": 3, "tokens": [4]}
+    assert json.loads(diff_json([], []))["hunks"] == []
+
+
+def test_lcs():
+    lcs = llminfer_rs.diff.lcs
+    tokens, pairs = lcs([1, 2, 3, 4, 5], [0, 1, 2, 9, 4, 5, 6], algorithm="myers_minimal")
+    assert tokens == [1, 2, 4, 5]
+    assert pairs == [(0, 1), (1, 2), (3, 4), (4, 5)]
+    assert lcs([1, 2], [3]) == ([], [])
+
+
+def test_edit_distance():
+    edit_distance = llminfer_rs.diff.edit_distance
+    edit_distance_within = llminfer_rs.diff.edit_distance_within
+    a, b = [1, 2, 3, 4, 5], [0, 1, 2, 9, 4, 5, 6]
+    assert edit_distance(a, b, algorithm="myers_minimal") == 4
+    assert edit_distance(a, a) == 0
+    assert edit_distance_within(a, b, 4) == 4
+    assert edit_distance_within(a, b, 3) is None
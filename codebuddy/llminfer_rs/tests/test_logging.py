@@ -0,0 +1,32 @@
+# ruff: noqa: E702
+
+import logging
+
+import pytest
+
+import llminfer_rs; StreamNextChunk = llminfer_rs.diff.StreamNextChunk
+
+
+class _Records(logging.Handler):
+    def __init__(self):
+        super().__init__(level=1)
+        self.records = []
+
+    def emit(self, record):
+        self.records.append(record)
+
+
+def test_install_logging():
+    logger = logging.getLogger("llminfer_rs.test")
+    logger.setLevel(1)
+    handler = _Records(); logger.addHandler(handler)
+    try:
+        llminfer_rs.diff.install_logging("trace", logger="llminfer_rs.test")
+        StreamNextChunk(list(range(100))).next_chunk([5, -1, 7, 8], 2)
+    finally:
+        logger.removeHandler(handler)
+    messages = [r.getMessage() for r in handler.records]
+    assert any("next_chunk{b_len=4 chunk_size=2}: diffed b against a" in m for m in messages), messages
+    assert any("MatchCollector finished" in m for m in messages), messages
+    with pytest.raises(RuntimeError):
+        llminfer_rs.diff.install_logging()
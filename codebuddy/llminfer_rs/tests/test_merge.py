@@ -0,0 +1,16 @@
+# ruff: noqa: E702
+
+import llminfer_rs; merge3 = llminfer_rs.diff.merge3
+
+
+def test_clean_merge():
+    base = [1, 2, 3, 4, 5, 6, 7, 8]
+    merged, conflicts = merge3(base, [1, 20, 3, 4, 5, 6, 7, 8], [1, 2, 3, 4, 5, 6, 70, 8, 9])
+    assert merged == [1, 20, 3, 4, 5, 6, 70, 8, 9]
+    assert conflicts == []
+
+
+def test_conflict():
+    merged, conflicts = merge3([1, 2, 3, 4, 5], [1, 2, 30, 4, 5], [1, 2, 31, 32, 4, 5, 6], dtype="int64")
+    assert merged == [1, 2, 30, 4, 5, 6]
+    assert conflicts == [((2, 3), (2, 3), [30], [31, 32])]
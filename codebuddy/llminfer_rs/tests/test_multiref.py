@@ -0,0 +1,32 @@
+# ruff: noqa: E702
+
+import llminfer_rs; MultiRefStreamNextChunk = llminfer_rs.diff.MultiRefStreamNextChunk
+ReferenceStore = llminfer_rs.diff.ReferenceStore
+
+
+def test_best_reference_wins():
+    s = MultiRefStreamNextChunk([[1, 2, 3, 4, 5, 6], [9, 2, 3, 4, 7, 8], [0, 1, 2, 3, 4, 10, 11]])
+    assert len(s) == 3
+    assert s.reference(1) == [9, 2, 3, 4, 7, 8]
+    assert s.reference(3) is None
+
+    assert s.next_chunk([1, 2, 3, 4], 2) == ([5, 6], 0)
+    assert s.next_chunk([0, 1, 2, 3, 4], 2) == ([10, 11], 2)
+    assert s.next_chunk([1, 2, 99], 2) == ([], None)
+
+    result, ref = s.next_chunk_with_info([9, 2, 3, 4], 1)
+    assert ref == 1 and result.tokens == [7] and result.match_len == 4
+
+
+def test_reference_store():
+    store = ReferenceStore([[1, 2, 3, 4, 5, 6, 7], [9, 2, 3, 4, 8, 8, 2, 3, 4]], ngram_len=3)
+    assert store.add([5, 6, 7, 10, 11, 12]) == 2
+    assert len(store) == 3 and store.ngram_len == 3
+    assert store.document(1) == [9, 2, 3, 4, 8, 8, 2, 3, 4] and store.document(3) is None
+    assert store.retrieve([0, 2, 3, 4, 5, 6]) == [(0, 3), (1, 1)]
+    assert store.retrieve([5, 6, 7, 10], k=1) == [(2, 2)]
+
+    doc, s = store.stream_next_chunk([0, 6, 7, 10])
+    assert doc == 2
+    assert s.next_chunk([0, 6, 7, 10], 2) == [11, 12]
+    assert store.stream_next_chunk([42, 43, 44]) is None
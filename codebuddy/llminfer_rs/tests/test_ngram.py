@@ -0,0 +1,37 @@
+# ruff: noqa: E702
+import pytest
+
+import llminfer_rs; NgramNextChunk = llminfer_rs.diff.NgramNextChunk
+NgramPool = llminfer_rs.diff.NgramPool
+
+
+def test_lookup():
+    s = NgramNextChunk([1, 2, 3, 4, 9, 2, 3, 5, 6], max_ngram=3)
+    assert s.max_ngram == 3 and s.dtype == "int32"
+    assert s.next_chunk([], 2) == [1, 2]
+    assert s.next_chunk([7, 9, 2, 3], 2) == [5, 6]
+    r = s.next_chunk_with_info([8, 8, 2, 3], 3)
+    assert (r.tokens, r.start, r.match_len) == ([4, 9, 2], 3, 2)
+    assert s.next_chunk([42], 2) == []
+
+
+def test_invalid_max_ngram():
+    with pytest.raises(ValueError):
+        NgramNextChunk([1, 2], max_ngram=0)
+
+
+def test_ngram_pool():
+    pool = NgramPool([1, 2, 3, 4, 1, 2, 5, 6], key_len=2, continuation_len=3, max_per_key=2)
+    assert (pool.key_len, pool.continuation_len, pool.max_per_key, pool.dtype) == (2, 3, 2, "int32")
+    assert pool.candidates([9, 1, 2]) == [[5, 6], [3, 4, 1]]
+    assert pool.candidates([9, 1, 2], k=1) == [[5, 6]]
+    # verified output extends a continuation
+    pool.insert([1, 2, 5, 6, 7])
+    assert pool.candidates([1, 2]) == [[5, 6, 7], [3, 4, 1]]
+    tree = pool.candidate_tree([1, 2])
+    assert (tree.tokens, tree.parents) == ([5, 3, 6, 4, 7, 1], [-1, -1, 0, 1, 2, 3])
+    assert len(pool) > 0
+    pool.clear()
+    assert len(pool) == 0 and pool.candidates([1, 2]) == []
+    with pytest.raises(ValueError):
+        NgramPool(key_len=0)
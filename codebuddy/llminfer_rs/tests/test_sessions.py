@@ -0,0 +1,107 @@
+# ruff: noqa: E702
+
+from concurrent.futures import ThreadPoolExecutor
+
+import pytest
+
+import llminfer_rs; d = llminfer_rs.diff
+
+
+def test_session_manager():
+    m = d.SessionManager()
+    m.create(1, [1, 2, 3, 4, 5, 6])
+    m.create("b", [10, 20, 30, 40])
+    assert len(m) == 2 and 1 in m and "b" in m and 2 not in m
+    assert sorted(map(str, m.ids())) == ["1", "b"]
+    assert m.predict(1, [1, 2], 2) == [3, 4]
+    assert m.predict(1, [3, 4], 2) == [5, 6]
+    assert m.predict("b", [10], 2) == [20, 30]
+    m.drop(1)
+    assert len(m) == 1 and 1 not in m
+
+
+def test_session_manager_errors():
+    m = d.SessionManager(dtype="int64")
+    assert m.dtype == "int64"
+    m.create(1, [1, 2, 3])
+    with pytest.raises(ValueError):
+        m.create(1, [4])
+    with pytest.raises(KeyError):
+        m.predict(2, [1], 1)
+    with pytest.raises(KeyError):
+        m.drop(2)
+
+
+def test_session_manager_threads():
+    m = d.SessionManager()
+    for i in range(32):
+        m.create(i, list(range(i, i + 100)))
+
+    def run(i):
+        return [m.predict(i, [i + k], 1) for k in range(50)]
+
+    with ThreadPoolExecutor(max_workers=8) as pool:
+        results = list(pool.map(run, range(32)))
+    assert results == [[[i + k + 1] for k in range(50)] for i in range(32)]
+
+
+def test_session_manager_shared_references():
+    m = d.SessionManager()
+    for i in range(4):
+        m.create(i, list(range(100)))
+    m.create("other", list(range(50)))
+    assert m.shared_references() == 2
+    m.predict(0, [1, 2], 2)
+    assert m.predict(1, [5], 2) == [6, 7]
+    for i in range(4):
+        m.drop(i)
+    assert m.shared_references() == 1
+
+def test_session_manager_max_sessions():
+    evicted = []
+    m = d.SessionManager(max_sessions=2, on_evict=lambda id, reason: evicted.append((id, reason)))
+    m.create(1, [1, 2, 3])
+    m.create(2, [1, 2, 3])
+    m.predict(1, [1], 1)
+    m.create(3, [1, 2, 3])
+    assert evicted == [(2, "capacity")]
+    assert sorted(m.ids()) == [1, 3]
+    with pytest.raises(ValueError):
+        d.SessionManager(max_sessions=0)
+
+
+def test_session_manager_max_tokens():
+    evicted = []
+    m = d.SessionManager(max_tokens=10, on_evict=lambda id, reason: evicted.append((id, reason)))
+    m.create("a", [1, 2, 3, 4])
+    m.create("b", [1, 2, 3, 4])
+    m.predict("b", [1, 2, 3], 1)
+    assert evicted == [("a", "capacity")]
+    assert m.ids() == ["b"]
+
+
+def test_session_manager_ttl():
+    import time
+
+    evicted = []
+    m = d.SessionManager(ttl=0.2, on_evict=lambda id, reason: evicted.append((id, reason)))
+    m.create(1, [1, 2, 3])
+    assert m.evict() == []
+    time.sleep(0.3)
+    m.create(2, [1, 2, 3])
+    assert evicted == [(1, "expired")]
+    time.sleep(0.3)
+    assert m.evict() == [2]
+    assert len(m) == 0
+    with pytest.raises(KeyError):
+        m.predict(2, [], 1)
+
+
+def test_session_manager_memory_budget():
+    m = d.SessionManager(memory_budget=1000)
+    m.create(1, list(range(3000)))
+    usage = m.memory_usage(1)
+    assert usage["budget"] == 1000 and usage["windowed_only"]
+    assert m.predict(1, [-1] + list(range(10, 30)), 4) == [30, 31, 32, 33]
+    with pytest.raises(KeyError):
+        m.memory_usage(2)
@@ -0,0 +1,17 @@
+# ruff: noqa: E702
+
+import llminfer_rs; simulate = llminfer_rs.diff.simulate
+
+
+def test_simulate():
+    reference = list(range(100))
+    report = simulate(reference, reference, 10)
+    assert (report["iterations"], report["total_accepted"], report["acceptance_rate"]) == (10, 100, 1.0)
+    assert report["accepted"] == [10] * 10
+    assert report["total_s"] >= report["first_prediction_s"] >= report["latency_s"][0] >= 0
+    assert len(report["latency_s"]) == report["iterations"]
+
+    target = reference[:25] + [-1] + reference[26:]
+    report = simulate(reference, target, 10, dtype="int64", matcher="suffix_automaton")
+    assert report["accepted"][:4] == [10, 10, 5, 0]
+    assert report["total_accepted"] < 100 and len(report["predicted"]) == report["iterations"]
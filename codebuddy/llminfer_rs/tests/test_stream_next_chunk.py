@@ -1,9 +1,749 @@
 # ruff: noqa: E702
 
+import pytest
+
 import llminfer_rs; StreamNextChunk = llminfer_rs.diff.StreamNextChunk
+AcceptanceEstimator = llminfer_rs.diff.AcceptanceEstimator
 
 
 def test_simple():
     s = StreamNextChunk(list(range(8)))
     chunk = s.next_chunk([1, 2, 2, 3, 5], 30)
     assert chunk == [6, 7]
+
+
+def test_dtype():
+    big = 2**40
+    s = StreamNextChunk([big + i for i in range(8)], dtype="int64")
+    assert s.dtype == "int64"
+    assert s.next_chunk([big, big + 1], 3) == [big + 2, big + 3, big + 4]
+
+    s = StreamNextChunk([2**32 - 3, 2**32 - 2, 2**32 - 1], dtype="uint32")
+    assert s.next_chunk([2**32 - 3], 5) == [2**32 - 2, 2**32 - 1]
+
+
+def test_packed_int64_ids():
+    # (token, position) pairs packed into 64 bits, too wide for int32
+    packed = [(t << 32) | p for p, t in enumerate([5, 6, 7, 5, 6, 8, 9])]
+    with pytest.raises(OverflowError):
+        StreamNextChunk(packed)
+    s = StreamNextChunk(packed, dtype="int64")
+    assert s.next_chunk(packed[:5], 2) == packed[5:7]
+    s.append(packed[:2])
+    assert s.verify_and_advance(s.predict(3), packed[2:5]) == 3
+    assert s.predict(2) == packed[5:7]
+    assert s.next_chunk_candidates([-(1 << 40)], 2, 3) == []
+
+
+def test_append_predict():
+    s = StreamNextChunk(list(range(20)))
+    assert s.predict(3) == [0, 1, 2]
+    s.append([0, 1, 2])
+    assert s.predict(3) == [3, 4, 5]
+    s.append([3, 99])
+    assert s.predict(3) == []
+    s.append([5, 6])
+    assert s.predict(2) == [7, 8]
+    s.reset()
+    assert s.predict(1) == [0]
+
+
+def test_numpy_input_output():
+    np = pytest.importorskip("numpy")
+    s = StreamNextChunk(np.arange(8, dtype=np.int32))
+    assert s.next_chunk(np.array([1, 2, 2, 3, 5], dtype=np.int32), 30) == [6, 7]
+    # non-contiguous and mismatched-dtype arrays fall back to copying
+    assert s.next_chunk(np.arange(12, dtype=np.int32)[:6:2], 2) == [5, 6]
+    out = s.next_chunk(np.array([0, 1], dtype=np.int64), 3, output="numpy")
+    assert isinstance(out, np.ndarray) and out.dtype == np.int32
+    assert out.tolist() == [2, 3, 4]
+
+    s = StreamNextChunk(np.arange(2**40, 2**40 + 8, dtype=np.int64), dtype="int64")
+    s.append(np.array([2**40], dtype=np.int64))
+    assert s.predict(2, output="numpy").dtype == np.int64
+
+    with pytest.raises(ValueError):
+        s.predict(2, output="tuple")
+
+
+def test_next_chunk_into():
+    np = pytest.importorskip("numpy")
+    s = StreamNextChunk(list(range(8)))
+    out = np.full(4, -1, dtype=np.int32)
+    assert s.next_chunk_into([1, 2, 2, 3, 5], out) == 2
+    assert out.tolist() == [6, 7, -1, -1]
+    assert s.next_chunk_into([0, 1], out) == 4
+    assert out.tolist() == [2, 3, 4, 5]
+
+    with pytest.raises(TypeError):
+        s.next_chunk_into([0, 1], np.zeros(4, dtype=np.int64))
+    with pytest.raises(ValueError):
+        s.next_chunk_into([0, 1], np.zeros(8, dtype=np.int32)[::2])
+
+
+def test_next_chunk_depths():
+    a = list(range(200))
+    s = StreamNextChunk(a)
+    b = [999] + a[20:40]
+    assert s.next_chunk_depths(b, [32, 8, 128]) == [a[40:72], a[40:48], a[40:168]]
+    assert s.stats()["diff_calls"] == 1
+    assert s.next_chunk_depths(a[:150], [8, 128]) == [a[150:158], a[150:]]
+    assert s.next_chunk_depths(b, []) == []
+
+
+def test_arrow_input():
+    pa = pytest.importorskip("pyarrow")
+    s = StreamNextChunk(pa.array(range(8), type=pa.int32()))
+    assert s.next_chunk(pa.array([1, 2, 2, 3], type=pa.int32()), 3) == [4, 5, 6]
+    # sliced arrays are read from their offset
+    assert s.next_chunk(pa.array([9, 9, 0, 1], type=pa.int32()).slice(2), 2) == [2, 3]
+    s.append(pa.array([0, 1], type=pa.int32()))
+    assert s.predict(2) == [2, 3]
+
+    big = 2**40
+    s = StreamNextChunk(pa.array([big + i for i in range(8)], type=pa.int64()), dtype="int64")
+    assert s.next_chunk(pa.array([big], type=pa.int64()), 2) == [big + 1, big + 2]
+    with pytest.raises(ValueError, match="format"):
+        s.next_chunk(pa.array([1], type=pa.int32()), 2)
+    with pytest.raises(ValueError, match="nulls"):
+        s.next_chunk(pa.array([big, None], type=pa.int64()), 2)
+
+
+def test_torch_input():
+    torch = pytest.importorskip("torch")
+    s = StreamNextChunk(torch.arange(8, dtype=torch.int32))
+    assert s.next_chunk(torch.tensor([1, 2, 2, 3], dtype=torch.int32), 3) == [4, 5, 6]
+    # int64 (torch's default) and strided tensors are copied
+    assert s.next_chunk(torch.tensor([0, 1]), 2) == [2, 3]
+    assert s.next_chunk(torch.arange(12, dtype=torch.int32)[:6:2], 2) == [5, 6]
+    s.append(torch.tensor([0, 1], dtype=torch.int32))
+    assert s.predict(2) == [2, 3]
+
+    s = StreamNextChunk(torch.arange(2**40, 2**40 + 8), dtype="int64")
+    assert s.next_chunk(torch.tensor([2**40]), 2) == [2**40 + 1, 2**40 + 2]
+
+
+def test_view_output():
+    np = pytest.importorskip("numpy")
+    s = StreamNextChunk(list(range(100)))
+    view = s.next_chunk([10, 11], 5, output="view")
+    assert isinstance(view, np.ndarray) and view.tolist() == [12, 13, 14, 15, 16]
+    assert not view.flags.writeable and not view.flags.owndata
+    assert type(view.base).__name__ == "ReferenceBuffer"
+    assert s.next_chunk_with_info([10, 11], 2, output="view").tokens.tolist() == [12, 13]
+
+    s.append([50])
+    view = s.predict(3, output="view")
+    # views keep showing the reference they were taken from
+    s.extend_reference(list(range(100, 10000)))
+    assert view.tolist() == [51, 52, 53]
+    assert s.predict(3) == [51, 52, 53]
+    s.set_reference([7] * 100)
+    assert view.tolist() == [51, 52, 53]
+    del s  # the view keeps the reference alive
+    assert view.tolist() == [51, 52, 53]
+
+
+def test_concurrent_sessions():
+    from concurrent.futures import ThreadPoolExecutor
+
+    reference = list(range(5000))
+    streamers = [StreamNextChunk(reference) for _ in range(4)]
+
+    def run(s):
+        return s.next_chunk(reference[:2500] + [-1] + reference[2600:3000], 4)
+
+    with ThreadPoolExecutor(max_workers=4) as pool:
+        results = list(pool.map(run, streamers))
+    assert results == [[3000, 3001, 3002, 3003]] * 4
+
+
+@pytest.mark.asyncio
+async def test_async_next_chunk():
+    import asyncio
+
+    reference = list(range(5000))
+    s = StreamNextChunk(reference)
+    b = reference[:2500] + [-1] + reference[2600:3000]
+    assert await s.async_next_chunk(b, 4) == [3000, 3001, 3002, 3003]
+    results = await asyncio.gather(*(s.async_next_chunk(b[:n], 2, algorithm="myers") for n in (10, 20, 30)))
+    assert results == [[10, 11], [20, 21], [30, 31]]
+    with pytest.raises(TypeError):
+        await s.async_next_chunk(["x"], 2)
+
+
+def test_async_next_chunk_without_loop():
+    with pytest.raises(RuntimeError):
+        StreamNextChunk([1, 2, 3]).async_next_chunk([1], 2)
+
+
+def test_algorithm():
+    b = [0, 1, 2, 99, 4, 5]
+    s = StreamNextChunk(list(range(10)), algorithm="myers")
+    assert s.algorithm == "myers"
+    assert s.next_chunk(b, 2) == [6, 7]
+    for algorithm in ("histogram", "myers", "myers_minimal"):
+        assert s.next_chunk(b, 2, algorithm=algorithm) == [6, 7]
+    with pytest.raises(ValueError, match="patience"):
+        StreamNextChunk([1, 2], algorithm="patience")
+
+
+def test_next_chunk_with_info():
+    s = StreamNextChunk(list(range(50)))
+    r = s.next_chunk_with_info([7, 30, 31, 32], 3)
+    assert isinstance(r, llminfer_rs.diff.PredictionResult)
+    assert (r.tokens, r.start, r.match_len, r.windowed) == ([33, 34, 35], 33, 3, False)
+    assert "start=33" in repr(r)
+
+    r = s.next_chunk_with_info([1, 2, -5], 3)
+    assert r.tokens == [] and r.start is None
+
+    s.append([10, 11])
+    r = s.predict_with_info(2)
+    assert (r.tokens, r.start, r.match_len) == ([12, 13], 12, 2)
+
+
+def test_suffix_automaton_matcher():
+    s = StreamNextChunk(list(range(100)), matcher="suffix_automaton")
+    assert s.matcher == "suffix_automaton"
+    assert StreamNextChunk([1]).matcher == "diff"
+    r = s.next_chunk_with_info([5, -1, 40, 41], 3)
+    assert (r.tokens, r.start, r.match_len) == ([42, 43, 44], 42, 2)
+    s.append([7, 8])
+    assert s.predict(2) == [9, 10]
+    with pytest.raises(ValueError, match="regex"):
+        StreamNextChunk([1, 2], matcher="regex")
+
+
+def test_suffix_array_matcher():
+    s = StreamNextChunk(list(range(100)), matcher="suffix_array")
+    assert s.matcher == "suffix_array"
+    r = s.next_chunk_with_info([5, -1, 40, 41], 3)
+    assert (r.tokens, r.start, r.match_len) == ([42, 43, 44], 42, 2)
+    s.append([7, 8])
+    assert s.predict(2) == [9, 10]
+
+
+def test_hash_chain_matcher():
+    s = StreamNextChunk(list(range(100)), matcher="hash_chain")
+    assert s.matcher == "hash_chain"
+    r = s.next_chunk_with_info([5, -1, 40, 41, 42, 43], 3)
+    assert (r.tokens, r.start, r.match_len) == ([44, 45, 46], 44, 4)
+    assert s.next_chunk([5, -1, 40, 41], 3) == []
+    s.extend_reference([-1, 7])
+    s.append([97, 98, 99, -1])
+    assert s.predict(2) == [7]
+
+
+def test_landmarks():
+    s = StreamNextChunk(list(range(100)))
+    assert s.landmarks == []
+    s.landmarks = [(40, 44), (70, 73)]
+    assert s.landmarks == [(40, 44), (70, 73)]
+    s.append([0, 1, 2, -1])
+    s.append([-2, 40, 41, 42, 43, 44])
+    assert s.predict(2) == [45, 46]
+    assert s.last_call_info()["fast_path"]
+    with pytest.raises(ValueError, match="landmark"):
+        s.landmarks = [(90, 120)]
+
+
+def test_continuation_check_len():
+    assert StreamNextChunk([1]).continuation_check_len == 0
+    s = StreamNextChunk(list(range(100)), continuation_check_len=3)
+    assert s.continuation_check_len == 3
+    assert s.next_chunk([5, -1, 7, 8], 2) == [9, 10]
+    # Not an extension of the previous `b`, but it ends where that prediction did
+    assert s.next_chunk([7, -1, 7, 8, 9, 10], 2) == [11, 12]
+    last = s.last_call_info()
+    assert last["continuation_hit"] and last["fast_path"]
+    assert s.stats()["diff_calls"] == 1
+
+
+def test_prediction_cursor():
+    a = list(range(90))
+    b = a[:40]
+    b[20] = -1
+    s = StreamNextChunk(a)
+    r, cursor = s.next_chunk_from(b, 4)
+    assert r.tokens == [40, 41, 42, 43]
+    assert (cursor.b_len, cursor.pos, cursor.match_len, cursor.calls, cursor.diff_calls) == (40, 40, 19, 1, 1)
+    # Two branches from the same cursor
+    r, accepted = s.next_chunk_from(b + [40, 41, 42, 43], 4, cursor)
+    last = s.last_call_info()
+    assert r.tokens == [44, 45, 46, 47] and last["cursor_hit"] and not last["memo_hit"]
+    r, retried = s.next_chunk_from(b + [40, 41, -5, 60, 61], 4, cursor)
+    assert r.tokens == [62, 63, 64, 65] and retried.diff_calls == 2
+    r, accepted = s.next_chunk_from(b + [40, 41, 42, 43, 44, 45], 4, accepted)
+    assert r.tokens == [46, 47, 48, 49]
+    assert (accepted.calls, accepted.diff_calls, accepted.predicted_tokens) == (3, 1, 12)
+    assert repr(accepted) == "PredictionCursor(b_len=46, pos=46, match_len=25, calls=3)"
+
+
+def test_min_match_len():
+    b = [100, 101, 7, 8, -1, 30, 31]
+    assert StreamNextChunk(list(range(50))).next_chunk(b, 2) == [32, 33]
+    s = StreamNextChunk(list(range(50)), min_match_len=3)
+    assert s.min_match_len == 3
+    assert s.next_chunk(b, 2) == []
+    assert s.next_chunk([-1, 30, 31, 32], 2) == [33, 34]
+
+
+def test_max_mismatches():
+    b = [20, 21, 22, 23, 1000]
+    assert StreamNextChunk(list(range(50))).next_chunk(b, 2) == []
+    s = StreamNextChunk(list(range(50)), max_mismatches=1)
+    assert s.max_mismatches == 1
+    r = s.next_chunk_with_info(b, 2)
+    assert (r.tokens, r.match_len) == ([25, 26], 4)
+
+
+def test_escalation_budget_ms():
+    a = list(range(6000))
+    b = list(range(1000)) + list(range(4000, 5000))
+    s = StreamNextChunk(a, escalation_budget_ms=50.0)
+    assert s.next_chunk(b, 3) == [5000, 5001, 5002]
+    assert s.stats()["last"]["escalations"] >= 0
+    with pytest.raises(ValueError):
+        StreamNextChunk(a, escalation_budget_ms=-1.0)
+
+
+def test_last_call_info():
+    s = StreamNextChunk(list(range(50)))
+    assert s.last_call_info() is None
+    assert s.next_chunk([20, 21, 22, 1000], 2) == []
+    info = s.last_call_info()
+    assert (info["windowed"], info["a_window"], info["b_window_start"]) == (False, (0, 50), 0)
+    assert (info["anchor"], info["anchor_pos"]) == ("miss", None)
+    s.next_chunk([5, -1, 7, 8], 2)
+    info = s.last_call_info()
+    assert (info["anchor"], info["anchor_pos"], info["anchor_match_len"]) == ("at", 9, 2)
+    assert s.stats()["last"] == info
+
+
+def test_fallback():
+    a = list(range(50))
+    mid_change = [20, 21, 22, 1000]
+    assert StreamNextChunk(a).fallback is None
+    assert StreamNextChunk(a).next_chunk(mid_change, 2) == []
+    s = StreamNextChunk(a, fallback="start_of_a")
+    assert s.fallback == "start_of_a"
+    assert s.next_chunk(mid_change, 2) == [0, 1]
+    s.fallback = 10
+    assert s.fallback == 10
+    r = s.next_chunk_with_info(mid_change, 2)
+    assert (r.tokens, r.match_len) == ([10, 11], 0)
+    s.fallback = "last_position"
+    assert s.next_chunk(mid_change[:3], 2) == [23, 24]
+    assert s.next_chunk(mid_change, 2) == [24, 25]
+    s.fallback = None
+    assert s.next_chunk([100], 2) == [0, 1]
+    for invalid in ["nearest", -1]:
+        with pytest.raises(ValueError):
+            StreamNextChunk(a, fallback=invalid)
+
+
+def test_whitespace_ids():
+    # 1 and 2 are whitespace tokens of different widths, reformatted in b
+    a = [10, 1, 11, 1, 12, 2, 13, 14, 15]
+    b = [10, 2, 11, 2, 12, 1]
+    assert StreamNextChunk(a).next_chunk(b, 2) == []
+    s = StreamNextChunk(a, whitespace_ids=[1, 2])
+    assert s.next_chunk(b, 2) == [13, 14]
+    s.append(b)
+    assert s.predict(3) == [13, 14, 15]
+
+
+def test_normalize():
+    calls = []
+
+    def normalize(t):
+        calls.append(t)
+        return t - 100 if t >= 100 else t
+
+    s = StreamNextChunk(list(range(20)), normalize=normalize)
+    assert sorted(calls) == list(range(20))
+    assert s.next_chunk([105, 6, 107], 3) == [8, 9, 10]
+    # results are cached per token id
+    assert s.next_chunk([105, 6, 107], 3) == [8, 9, 10]
+    assert calls.count(105) == 1
+
+    # composes with whitespace_ids, which applies to the normalized ids
+    s = StreamNextChunk([1, 50, 2, 51, 3, 4], whitespace_ids=[50, 51], normalize=normalize)
+    assert s.next_chunk([101, 151, 2, 150, 103], 1) == [4]
+    with pytest.raises(ZeroDivisionError):
+        StreamNextChunk([1, 2], normalize=lambda t: t // 0)
+
+
+def test_set_reference():
+    s = StreamNextChunk([1, 2, 3, 4, 5], min_match_len=2)
+    s.append([1, 2])
+    s.set_reference(list(range(100, 200)))
+    assert s.min_match_len == 2
+    assert s.predict(2) == [100, 101]
+    assert s.next_chunk([150, 151], 2) == [152, 153]
+
+
+def test_extend_reference():
+    s = StreamNextChunk([1, 2, 3], min_match_len=2)
+    s.append([2, 3, 4])
+    assert s.predict(2) == []
+    s.extend_reference([4, 5, 6])
+    assert s.predict(2) == [5, 6]
+    assert s.next_chunk([1, 2, 3, 4], 2) == [5, 6]
+
+
+def test_next_chunk_candidates():
+    s = StreamNextChunk([1, 2, 3, 9, 5, 7, 3, 8, 4])
+    candidates = s.next_chunk_candidates([0, 1, 2, 3], 2, 5)
+    assert [(c.tokens, c.start, c.match_len) for c in candidates] == [([9, 5], 3, 3), ([8, 4], 7, 1)]
+    assert len(s.next_chunk_candidates([0, 1, 2, 3], 2, 1)) == 1
+    assert s.next_chunk_candidates([4], 2, 3) == []
+
+
+def test_next_chunk_tree():
+    s = StreamNextChunk([1, 2, 3, 4, 5, 0, 2, 3, 4, 6, 9, 3, 7])
+    tree = s.next_chunk_tree([1, 2, 3], 2, 3)
+    assert (tree.tokens, tree.parents, tree.depths) == ([4, 7, 5, 6], [-1, -1, 0, 0], [0, 0, 1, 1])
+    assert tree.paths == [[1], [0, 2], [0, 3]]
+    assert tree.attention_mask[3] == [True, False, False, True]
+    assert len(tree) == 4 and len(s.next_chunk_tree([8], 2, 3)) == 0
+    with pytest.raises(ValueError):
+        s.next_chunk_tree([1], 2, 3, output="view")
+    assert tree.sources is None
+
+
+def test_merge_candidates():
+    s = StreamNextChunk([1, 2, 3, 4, 5])
+    drafts = [("matcher", s.next_chunk([1, 2], 2)), ("model", [3, 9]), ("model", [3, 4, 7]), ("matcher", [8])]
+    tree = llminfer_rs.diff.merge_candidates(drafts)
+    assert (tree.tokens, tree.parents) == ([3, 8, 4, 9, 7], [-1, -1, 0, 0, 2])
+    assert tree.sources == [["matcher", "model"], ["matcher"], ["matcher", "model"], ["model"], ["model"]]
+    assert len(llminfer_rs.diff.merge_candidates([])) == 0
+
+
+def test_tree_verify():
+    drafts = [("matcher", [1, 2, 3]), ("model", [1, 2, 4, 5]), ("model", [5]), ("matcher", [1, 6])]
+    tree = llminfer_rs.diff.merge_candidates(drafts)
+    assert tree.paths == [[1], [0, 3], [0, 2, 4], [0, 2, 5, 6]]
+    v = tree.verify([1, 2])
+    assert (v.accepted_path, v.surviving_nodes, v.retired) == ([0, 2], [4, 5, 6], [0, 1])
+    assert (v.surviving.tokens, v.surviving.parents) == ([3, 4, 5], [-1, -1, 1])
+    assert v.surviving.sources == [["matcher"], ["model"], ["model"]]
+    # the verifier went past the tree
+    v = tree.verify([1, 2, 4, 5, 9])
+    assert v.accepted_path == [0, 2, 5, 6] and len(v.surviving) == 0 and v.retired == [0, 1, 2, 3]
+
+
+def test_next_chunk_with_estimate():
+    s = StreamNextChunk(list(range(100)))
+    est = AcceptanceEstimator()
+    r, n = s.next_chunk_with_estimate(list(range(40)), 8, est)
+    assert r.tokens == list(range(40, 48))
+    assert 0 <= n <= 8
+    for _ in range(20):
+        est.record(8, 8)
+    assert est.history > 0.95
+    _, n2 = s.next_chunk_with_estimate(list(range(40)), 8, est)
+    assert n2 > n
+    # No anchoring match: whatever is predicted is not expected to be accepted
+    _, n = s.next_chunk_with_estimate([-1, -2], 8, est)
+    assert n == 0
+
+
+def test_verify_and_advance():
+    s = StreamNextChunk([1, 2, 3, 4, 5, 6])
+    assert s.verify_and_advance(s.predict(3), [1, 2, 9]) == 2
+    assert s.verify_and_advance([3, 4], [9, 4]) == 0
+    assert s.predict(2) == []
+    stats = s.stats()
+    assert (stats["verifications"], stats["predicted_tokens"], stats["accepted_tokens"]) == (2, 5, 2)
+    assert stats["acceptance_rate"] == 0.4
+    assert StreamNextChunk([1]).stats()["acceptance_rate"] is None
+
+
+def test_stream():
+    reference = list(range(100))
+    target = reference[:25] + [-1] + reference[26:]
+    s = StreamNextChunk(reference)
+    steps = list(s.stream(iter(target), 10))
+    assert [accepted for _, accepted in steps][:4] == [10, 10, 5, 0]
+    assert steps[2][0] == list(range(20, 30))
+    assert s.predict(3) == []  # all of target was appended
+    report = llminfer_rs.diff.simulate(reference, target, 10)
+    assert [len(chunk) for chunk, _ in steps] == report["predicted"]
+    assert [accepted for _, accepted in steps] == report["accepted"]
+    assert s.stats()["accepted_tokens"] == report["total_accepted"]
+
+    # Goes on from the appended tokens; a chunk_size of 0 still advances
+    s = StreamNextChunk(reference)
+    s.append(reference[:90])
+    assert list(s.stream(reference[90:], 0)) == [([], 0)] * 10
+    assert list(s.stream([], 4)) == []
+
+
+@pytest.mark.filterwarnings("ignore::pytest.PytestUnraisableExceptionWarning")
+def test_set_hook():
+    calls = []
+    s = StreamNextChunk(list(range(50)))
+    s.set_hook(calls.append)
+    s.next_chunk([5, -1, 7, 8], 2)
+    s.append([0, 1])
+    s.predict(3)
+    assert [(c["anchor"], c["start"], c["match_len"], c["chunk_len"]) for c in calls] == [
+        ("at", 9, 2, 2),
+        ("at", 2, 2, 3),
+    ]
+    assert "wall_s" in calls[0] and calls[1] == s.last_call_info() | {"start": 2, "match_len": 2, "chunk_len": 3}
+
+    def fail(info):
+        raise ValueError("hook failure")
+
+    # a failing hook doesn't fail the prediction
+    s.set_hook(fail)
+    assert s.next_chunk([5, 6], 2) == [7, 8]
+    s.set_hook(None)
+    s.next_chunk([5, 6], 2)
+    assert len(calls) == 2
+
+
+def test_longest_match_at():
+    s = StreamNextChunk([1, 2, 3, 4, 5, 2, 3, 4, 9])
+    assert s.longest_match_at(10, [7, 7, 2, 3, 4]) == (4, 3)
+    assert s.longest_match_at(2, [7, 7, 2, 3, 4]) == (4, 2)
+    assert s.longest_match_at(10, [1, 7]) is None
+    s.append([1, 2, 3, 4, 9])
+    assert s.longest_match_at(10) == (9, 4)
+    assert s.stats()["calls"] == 0
+
+
+def test_match_length_histogram():
+    s = StreamNextChunk(list(range(100)))
+    assert s.match_length_histogram() == {}
+    s.next_chunk(list(range(10)) + [-1] + list(range(20, 25)), 2)
+    assert s.match_length_histogram(last=True) == {5: 1, 10: 1}
+    s.next_chunk([0, 1, 2, -1, 50, 51, 52, 53, 54], 2)
+    assert s.match_length_histogram(last=True) == {3: 1, 5: 1}
+    assert s.match_length_histogram() == {3: 1, 5: 2, 10: 1}
+    s.reset_stats()
+    assert s.match_length_histogram() == {}
+
+
+def test_boundary_equivalents():
+    a = list(range(100))
+    b = list(range(10)) + [1010]
+    assert StreamNextChunk(a).next_chunk(b, 3) == []
+    for table in ({1010: 10}, [(10, 1010)]):
+        s = StreamNextChunk(a, boundary_equivalents=table)
+        assert s.next_chunk(b, 3) == [11, 12, 13] and s.last_call_info()["boundary_skip"]
+    s.append(b)
+    assert s.predict(2) == [11, 12]
+
+
+def test_global_reanchor():
+    a = list(range(6000))
+    b = list(range(1000)) + list(range(4000, 5000))
+    s = StreamNextChunk(a, global_reanchor_after=2)
+    assert s.global_reanchor_after == 2
+    assert s.next_chunk(b, 3) == [5000, 5001, 5002]
+    # The windowed search found it, no full diff needed
+    assert s.stats()["global_reanchors"] == 0 and not s.last_call_info()["global_reanchor"]
+    assert StreamNextChunk(a).global_reanchor_after is None
+
+
+def test_multi_window_search():
+    a = list(range(6000))
+    b = list(range(1000)) + list(range(4000, 5000))
+    assert not StreamNextChunk(a).multi_window_search
+    s = StreamNextChunk(a, multi_window_search=True)
+    assert s.multi_window_search
+    assert s.next_chunk(b, 3) == [5000, 5001, 5002]
+
+
+def test_memoize_anchors():
+    a = list(range(3000))
+    b = a[:2000]
+    b[1000] = -1
+    assert not StreamNextChunk(a).memoize_anchors
+    s = StreamNextChunk(a, memoize_anchors=True)
+    assert s.memoize_anchors
+    for n in (1500, 1600):
+        assert s.next_chunk(b[:n], 4) == a[n:n + 4]
+    assert s.stats()["memo_hits"] == 1 and s.last_call_info()["memo_hit"]
+
+
+def test_junk_tokens():
+    # 0 is EOS, separating the documents of `a`
+    a = [1, 2, 3, 0, 4, 5, 6, 0, 7, 8]
+    s = StreamNextChunk(a, junk_tokens=[0])
+    assert s.junk_tokens == {0}
+    assert s.next_chunk([9, 0], 2) == []
+    assert s.next_chunk([1, 2], 3) == [3]
+    s.junk_tokens = []
+    assert s.next_chunk([1, 2], 3) == [3, 0, 4]
+    assert StreamNextChunk(a).junk_tokens == set()
+
+
+def test_coarse_block_len():
+    a = [(i * 7919) % 100_003 for i in range(120_000)]
+    b = a[50_000:51_000] + [-1] + a[51_000:52_000]
+    s = StreamNextChunk(a, coarse_block_len=64)
+    assert s.coarse_block_len == 64
+    assert s.next_chunk(b, 4) == a[52_000:52_004]
+    assert s.last_call_info()["coarse"]
+    assert StreamNextChunk(a).coarse_block_len is None
+
+
+def test_diff_segment_len():
+    a = list(range(3000))
+    b = a[2000:2050] + [-1] + a[2050:2100]
+    s = StreamNextChunk(a, diff_segment_len=500)
+    assert s.diff_segment_len == 500
+    assert s.next_chunk(b, 4) == a[2100:2104]
+    assert s.last_call_info()["segments"] > 1
+    assert StreamNextChunk(a).diff_segment_len is None
+
+
+def test_memory_budget():
+    a = list(range(3000))
+    usage = StreamNextChunk(a).memory_usage()
+    assert usage["budget"] is None and not usage["degraded"] and usage["used"] > usage["required"]
+    s = StreamNextChunk(a, memory_budget=usage["required"] + 1000)
+    assert s.memory_usage()["degraded"] and not s.memory_usage()["windowed_only"]
+    assert s.next_chunk(a[1000:1500], 4) == a[1500:1504]
+    s = StreamNextChunk(a, memory_budget=1000)
+    assert s.memory_usage()["windowed_only"]
+    assert s.next_chunk([-1] + a[10:30], 4) == a[30:34]
+    assert s.last_call_info()["windowed"]
+
+
+def test_deadline_ms():
+    a = list(range(3000))
+    b = [-1] + a[10:30]
+    s = StreamNextChunk(a, deadline_ms=0.0, fallback=7)
+    assert s.deadline_ms == 0.0 and StreamNextChunk(a).deadline_ms is None
+    assert s.next_chunk(b, 4) == a[7:11]
+    assert s.last_call_info()["timed_out"] and s.stats()["timeouts"] == 1
+    assert StreamNextChunk(a, deadline_ms=60_000).next_chunk(b, 4) == a[30:34]
+    with pytest.raises(ValueError):
+        StreamNextChunk(a, deadline_ms=-1)
+
+
+def test_anchor_strategy():
+    a = list(range(100))
+    b = a[10:30] + [500, 501] + a[60:63]
+    assert StreamNextChunk(a).anchor_strategy == "last"
+    assert StreamNextChunk(a).next_chunk(b, 3) == [63, 64, 65]
+    s = StreamNextChunk(a, anchor_strategy="longest")
+    assert s.anchor_strategy == "longest"
+    assert s.next_chunk(b, 3) == [35, 36, 37]
+
+    a = list(range(50)) + [7, 8, 9] + list(range(50, 60))
+    b = list(range(21)) + [7, 8, 9]
+    assert StreamNextChunk(a, anchor_strategy="longest_suffix").next_chunk(b, 3) == [10, 11, 12]
+    with pytest.raises(ValueError, match="first"):
+        StreamNextChunk(a, anchor_strategy="first")
+
+
+def test_anchor_weights():
+    # The model appended a novel token after a long match
+    a = list(range(100))
+    b = a[10:40] + [999]
+    assert StreamNextChunk(a).next_chunk(b, 3) == []
+    s = StreamNextChunk(a, anchor_strategy="weighted", anchor_weights=(2.0, 4.0, 0.5))
+    assert s.anchor_strategy == "weighted" and s.anchor_weights == (2.0, 4.0, 0.5)
+    assert s.next_chunk(b, 3) == [41, 42, 43]
+    assert StreamNextChunk(a).anchor_weights == (1.0, 8.0, 1.0)
+
+
+def test_autojunk():
+    # Half of `a` is the newline token 0, which alone anchors anywhere
+    a = [t for line in range(1000, 1150) for t in (line, 0)]
+    b = [5000, 6000, 0]
+    assert len(StreamNextChunk(a).next_chunk(b, 2)) == 2
+    s = StreamNextChunk(a, autojunk=0.1)
+    assert s.autojunk == 0.1 and StreamNextChunk(a).autojunk is None
+    assert s.next_chunk(b, 2) == []
+    assert s.next_chunk([5000, 1005, 0], 2) == [1006, 0]
+
+
+def test_stats():
+    s = StreamNextChunk(list(range(100)))
+    assert s.stats()["last"] is None
+    s.next_chunk([0, 1], 2)
+    s.next_chunk([5, -1, 7, 8], 2)
+    stats = s.stats()
+    assert (stats["calls"], stats["diff_calls"], stats["windowed_calls"]) == (2, 1, 0)
+    assert stats["wall_s"] >= stats["max_wall_s"] >= stats["last"]["wall_s"] >= 0
+    assert not stats["last"]["fast_path"] and stats["last"]["matches"] == 2
+    # b only grew since the last diff: anchored without diffing again
+    s.next_chunk([5, -1, 7, 8, 9, 10], 2)
+    stats = s.stats()
+    assert (stats["diff_calls"], stats["memo_hits"]) == (1, 1) and stats["last"]["memo_hit"]
+    assert stats["last"]["chunk_len"] == 2 and stats["empty_predictions"] == 0
+    s.next_chunk([98, 99], 2)
+    assert s.stats()["empty_predictions"] == 1 and s.stats()["window_misses"] == 0
+    s.reset_stats()
+    assert s.stats()["calls"] == 0
+
+
+def _write_npy(path, descr, tokens):
+    import struct
+
+    header = "{'descr': '%s', 'fortran_order': False, 'shape': (%d,), }" % (descr, len(tokens))
+    header += " " * (-(len(header) + 11) % 64) + "\n"
+    fmt = {"<i4": "<%di", "<i8": "<%dq"}[descr] % len(tokens)
+    path.write_bytes(b"\x93NUMPY\x01\x00" + struct.pack("<H", len(header)) + header.encode() + struct.pack(fmt, *tokens))
+
+
+def test_from_npy(tmp_path):
+    _write_npy(tmp_path / "a.npy", "<i4", list(range(8)))
+    s = StreamNextChunk.from_npy(tmp_path / "a.npy")
+    assert s.dtype == "int32"
+    assert s.next_chunk([1, 2, 3], 2) == [4, 5]
+
+    big = 2**40
+    _write_npy(tmp_path / "big.npy", "<i8", [big + i for i in range(8)])
+    s = StreamNextChunk.from_npy(str(tmp_path / "big.npy"), min_match_len=2)
+    assert s.dtype == "int64" and s.min_match_len == 2
+    assert s.next_chunk([big, big + 1], 2) == [big + 2, big + 3]
+    with pytest.raises(OverflowError):
+        StreamNextChunk.from_npy(tmp_path / "big.npy", dtype="int32")
+    assert StreamNextChunk.from_npy(tmp_path / "a.npy", dtype="int64").dtype == "int64"
+
+    (tmp_path / "bad.npy").write_bytes(b"not numpy")
+    with pytest.raises(ValueError):
+        StreamNextChunk.from_npy(tmp_path / "bad.npy")
+    with pytest.raises(OSError):
+        StreamNextChunk.from_npy(tmp_path / "missing.npy")
+
+
+def test_from_npy_numpy(tmp_path):
+    np = pytest.importorskip("numpy")
+    np.save(tmp_path / "a.npy", np.arange(8, dtype=np.int64).reshape(1, 8))
+    assert StreamNextChunk.from_npy(tmp_path / "a.npy").next_chunk([1, 2, 3], 2) == [4, 5]
+
+
+def test_save_load(tmp_path):
+    a = [i % 13 for i in range(300)]
+    s = StreamNextChunk(a, min_match_len=2, fallback="last_position")
+    s.next_chunk(a[:40], 3)
+    s.append(a[:60])
+    s.predict(3)
+    s.save(tmp_path / "state")
+
+    restored = StreamNextChunk.load(tmp_path / "state")
+    assert restored.min_match_len == 2 and restored.fallback == "last_position"
+    assert restored.stats()["calls"] == s.stats()["calls"]
+    restored.append(a[60:70]); s.append(a[60:70])
+    assert restored.predict(5) == s.predict(5)
+
+    big = StreamNextChunk([2**40, 1, 2], dtype="int64")
+    big.save(str(tmp_path / "big"))
+    assert StreamNextChunk.load(tmp_path / "big", dtype="int64").dtype == "int64"
+    with pytest.raises(OverflowError):
+        StreamNextChunk.load(tmp_path / "big")
+    with pytest.raises(ValueError):
+        StreamNextChunk(a, whitespace_ids=[0]).save(tmp_path / "normalized")
+    with pytest.raises(OSError):
+        StreamNextChunk.load(tmp_path / "missing")
@@ -0,0 +1,55 @@
+# ruff: noqa: E702
+
+from concurrent.futures import ThreadPoolExecutor
+
+import llminfer_rs; d = llminfer_rs.diff
+
+
+REFERENCE = list(range(5000))
+
+
+def _edited_prefix(end):
+    return [x for x in range(end) if x != 5]
+
+
+def test_shared_stream_next_chunk():
+    s = d.StreamNextChunk(REFERENCE)
+    ends = [10 + 37 * i for i in range(64)]
+
+    def run(end):
+        return s.next_chunk(_edited_prefix(end), 3), s.next_chunk_with_info(_edited_prefix(end), 3).start
+
+    with ThreadPoolExecutor(max_workers=8) as pool:
+        results = list(pool.map(run, ends))
+    assert results == [([end, end + 1, end + 2], end) for end in ends]
+    assert s.stats()["calls"] == 2 * len(ends)
+
+
+def test_shared_readonly_classes():
+    ngram = d.NgramNextChunk(REFERENCE)
+    multi = d.MultiRefStreamNextChunk([REFERENCE, [-x for x in REFERENCE]])
+    batch = d.BatchStreamNextChunk([REFERENCE, REFERENCE])
+
+    def run(end):
+        b = _edited_prefix(end)
+        return (
+            ngram.next_chunk(b, 2),
+            multi.next_chunk(b, 2),
+            batch.next_chunk_batch([b, b], 2),
+        )
+
+    with ThreadPoolExecutor(max_workers=8) as pool:
+        results = list(pool.map(run, range(20, 60)))
+    assert results == [([end, end + 1], ([end, end + 1], 0), [[end, end + 1]] * 2) for end in range(20, 60)]
+
+
+def test_stateful_per_thread():
+    # The stateful API needs exclusive access: one instance per thread
+    def run(end):
+        s = d.StreamNextChunk(REFERENCE)
+        s.append(_edited_prefix(end))
+        return s.predict(2)
+
+    with ThreadPoolExecutor(max_workers=8) as pool:
+        results = list(pool.map(run, range(20, 60)))
+    assert results == [[end, end + 1] for end in range(20, 60)]
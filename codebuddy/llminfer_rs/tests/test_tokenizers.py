@@ -0,0 +1,45 @@
+# ruff: noqa: E702
+
+import json
+
+import pytest
+
+import llminfer_rs; StreamNextChunk = llminfer_rs.diff.StreamNextChunk
+
+pytestmark = pytest.mark.skipif(
+    not hasattr(StreamNextChunk, "from_text"), reason="built without the tokenizers feature"
+)
+
+WORDS = ["[UNK]", "fn", "main", "(", ")", "{", "}", "let", "x", "=", "1", ";"]
+
+
+def _word_tokenizer(tmp_path):
+    """A whitespace word-level tokenizer.json mapping WORDS to their index."""
+    tokenizer = {
+        "version": "1.0",
+        "truncation": None,
+        "padding": None,
+        "added_tokens": [],
+        "normalizer": None,
+        "pre_tokenizer": {"type": "Whitespace"},
+        "post_processor": None,
+        "decoder": None,
+        "model": {"type": "WordLevel", "vocab": {w: i for i, w in enumerate(WORDS)}, "unk_token": "[UNK]"},
+    }
+    path = tmp_path / "tokenizer.json"
+    path.write_text(json.dumps(tokenizer))
+    return path
+
+
+def test_from_text(tmp_path):
+    s = StreamNextChunk.from_text("fn main ( ) { let x = 1 ; }", _word_tokenizer(tmp_path), min_match_len=2)
+    assert (s.dtype, s.min_match_len) == ("uint32", 2)
+    assert s.next_chunk_text("fn main ( ) {", 3) == [7, 8, 9]
+    assert s.next_chunk([1, 2, 3, 4, 5], 3) == [7, 8, 9]
+    # a single matching word is below min_match_len
+    assert s.next_chunk_text("x", 3) == []
+
+    with pytest.raises(OSError):
+        StreamNextChunk.from_text("fn", tmp_path / "missing.json")
+    with pytest.raises(ValueError):
+        StreamNextChunk([1, 2, 3], dtype="uint32").next_chunk_text("fn", 1)
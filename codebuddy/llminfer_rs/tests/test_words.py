@@ -0,0 +1,25 @@
+# ruff: noqa: E702
+
+import pytest
+
+import llminfer_rs; WordNextChunk = llminfer_rs.diff.WordNextChunk
+
+TEXT = "the quick brown fox jumps over the lazy dog"
+
+
+def test_whitespace_split():
+    w = WordNextChunk(TEXT)
+    assert w.pattern is None
+    assert "".join(w.words()) == TEXT
+    assert w.next_chunk("a quick brown ", 3) == ["fox", " ", "jumps"]
+    assert w.next_chunk_text("the quick brown fox jumps ", 5) == "over the lazy"
+    assert w.next_chunk(TEXT, 5) == []
+
+
+def test_regex_split():
+    w = WordNextChunk("let total = price * qty;\nreturn total;", pattern=r"\b", min_match_len=2)
+    assert w.pattern == r"\b"
+    assert w.next_chunk_text("let total = price * qty;\n", 4) == "return total;"
+    assert w.next_chunk("nothing in common", 4) == []
+    with pytest.raises(ValueError):
+        WordNextChunk(TEXT, pattern="(")